@@ -0,0 +1,412 @@
+//! Compatibility report (`cardinal-cli compat`)
+//!
+//! Three suites are run headlessly and summarized as pass/fail matrices:
+//!
+//! - Every opcode, in every mode, is run on a set of fixed stacks and
+//!   compared against a model written from the specification (see
+//!   [`crate::reference`]); this covers keep / return modes and the immediate
+//!   jumps, which the fuzz harness can't check against anything but Cardinal
+//!   itself.
+//! - The upstream `opctest` ROM, assembled from `uxn-tal/tal/validate`.
+//! - `devtest`, which checks the headless behavior of each Varvara device and
+//!   reports in the same style as `opctest`.
+//!
+//! Only `opctest` comes from upstream.  The model and `devtest` are this
+//! repository's own reading of the specification, so their rows are reported
+//! as local model self-checks and local checks rather than reference
+//! results.  Vectors the model can't check Cardinal on are counted as
+//! skipped, not passed.
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use uxn::{Backend, Device, Uxn, UxnRam};
+use varvara::{Machine, MemoryFiles};
+
+use crate::reference::{Stack, State};
+
+const OPCTEST: &str = include_str!("../../uxn-tal/tal/validate/opctest.tal");
+const DEVTEST: &str = include_str!("../tal/devtest.tal");
+
+/// Instruction budget for each ROM
+const CYCLES: u64 = 10_000_000;
+
+/// Number of pseudo-random vectors per opcode, in addition to edge cases
+const RANDOM_VECTORS: usize = 64;
+
+/// Mode suffixes, indexed by the opcode's top three bits
+const MODES: [&str; 8] = ["", "2", "r", "2r", "k", "2k", "kr", "2kr"];
+
+/// Reports Cardinal's compatibility with the upstream `opctest` ROM and
+/// local checks
+#[derive(clap::Args)]
+pub struct CompatArgs {
+    /// Write the report to a file rather than stdout
+    #[clap(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// Runs the `compat` subcommand, returning `true` if every check passed
+pub fn run(args: CompatArgs) -> Result<bool> {
+    let mut out = String::from("Cardinal compatibility report\n");
+    let mut failures = vec![];
+    let mut totals = vec![];
+
+    // Opcode modes
+    let modes = opcode_modes();
+    let n = vectors(0).len();
+    writeln!(
+        out,
+        "\nOpcode modes (local model self-checks, {n} vectors each)"
+    )
+    .unwrap();
+    write!(out, "{:8}", "").unwrap();
+    for m in MODES {
+        write!(out, "{:6}", if m.is_empty() { "base" } else { m }).unwrap();
+    }
+    out += "\n";
+    for base in 1..32 {
+        write!(out, "{:8}", uxn::op::NAMES[base]).unwrap();
+        for mode in 0..8 {
+            write!(out, "{:6}", modes[base | mode << 5].cell()).unwrap();
+        }
+        out += "\n";
+    }
+    out += "imm     ";
+    for mode in 0..8 {
+        let op = mode << 5;
+        write!(out, " {} {}", uxn::op::NAMES[op], modes[op].cell()).unwrap();
+    }
+    out += "\n";
+    failures.extend(modes.iter().filter_map(|m| m.failure.clone()));
+    let passed = modes.iter().filter(|m| m.passed()).count();
+    let skipped: usize = modes.iter().map(|m| m.skipped).sum();
+    totals.push(format!(
+        "{passed}/256 opcodes (local model self-checks, {skipped} vectors skipped)"
+    ));
+
+    // opctest
+    out += "\nopctest (upstream)\n";
+    let stdout = run_rom(OPCTEST, MemoryFiles::new()).context("failed to run opctest")?;
+    let (parts, missed) = opctest(&stdout);
+    for (name, ok) in &parts {
+        writeln!(out, "  {name:16}{}", cell(*ok)).unwrap();
+        if !ok {
+            failures.push(format!("opctest: {name} failed"));
+        }
+    }
+    if !missed.is_empty() {
+        writeln!(out, "  missed cases:   {}", missed.join(", ")).unwrap();
+        failures.extend(missed.iter().map(|m| format!("opctest: {m} failed")));
+    }
+    let passed = parts.iter().filter(|(_, ok)| *ok).count();
+    totals.push(format!("{passed}/{} opctest parts", parts.len()));
+
+    // devtest
+    out += "\nDevices (local checks, devtest)\n";
+    let files = MemoryFiles::from([("in.txt".to_owned(), b"hello".to_vec())]);
+    let stdout = run_rom(DEVTEST, files).context("failed to run devtest")?;
+    let checks = devtest(&stdout);
+    let mut device = "";
+    for (name, ok) in &checks {
+        let (dev, check) = name.split_once('/').unwrap_or((name, ""));
+        if dev != device {
+            if !device.is_empty() {
+                out += "\n";
+            }
+            write!(out, "  {dev:12}").unwrap();
+            device = dev;
+        }
+        write!(out, " {check} {}", cell(*ok)).unwrap();
+        if !ok {
+            failures.push(format!("devtest (local): {name} failed"));
+        }
+    }
+    out += "\n";
+    let passed = checks.iter().filter(|(_, ok)| *ok).count();
+    totals.push(format!("{passed}/{} device checks (local)", checks.len()));
+
+    if !failures.is_empty() {
+        out += "\nFailures\n";
+        for f in &failures {
+            writeln!(out, "  {f}").unwrap();
+        }
+    }
+    writeln!(out, "\nSummary: {}", totals.join(", ")).unwrap();
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, out).with_context(|| format!("failed to write {path:?}"))?
+        }
+        None => print!("{out}"),
+    }
+    Ok(failures.is_empty())
+}
+
+fn cell(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "FAIL"
+    }
+}
+
+/// Assembles and runs a test ROM, returning its stdout
+fn run_rom(src: &str, files: MemoryFiles) -> Result<String> {
+    let rom = uxn_tal::assemble(src).map_err(|e| anyhow::anyhow!("{e}"))?;
+    let mut m = Machine::new(Backend::Interpreter);
+    m.dev_mut().set_cycle_budget(Some(CYCLES));
+    m.dev_mut().file.set_memory_files(files);
+    m.load(&rom, &[]);
+    if m.dev().cycles_exhausted() {
+        anyhow::bail!("instruction budget exhausted");
+    }
+    let out = m.output();
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Parses `opctest` output into named parts and missed opcode cases
+///
+/// The bootstrap parts print `Ok1` through `Ok7`; later parts print
+/// `<name> passed!` or `<name> failed.`, and each failing opcode case prints
+/// `Opcode Failed -- <name> #<case>`.
+fn opctest(stdout: &str) -> (Vec<(String, bool)>, Vec<String>) {
+    let mut parts = vec![];
+    for i in 1..=7 {
+        let ok = stdout.lines().any(|l| l.trim() == format!("Ok{i}"));
+        parts.push((format!("Bootstrap {i}"), ok));
+    }
+    let mut missed = vec![];
+    for line in stdout.lines() {
+        if let Some(case) = line.strip_prefix("Opcode Failed -- ") {
+            missed.push(case.trim().to_owned());
+        } else if let Some((name, ok)) = result_line(line) {
+            parts.push((name.to_owned(), ok));
+        }
+    }
+    (parts, missed)
+}
+
+/// Parses `devtest` output into named checks
+fn devtest(stdout: &str) -> Vec<(String, bool)> {
+    stdout
+        .lines()
+        .filter_map(result_line)
+        .map(|(name, ok)| (name.to_owned(), ok))
+        .collect()
+}
+
+/// Parses a `<name> passed!` or `<name> failed.` line
+///
+/// The final `Result:` line is skipped, since it summarizes the others.
+fn result_line(line: &str) -> Option<(&str, bool)> {
+    let (name, ok) = if let Some(n) = line.strip_suffix(" passed!") {
+        (n, true)
+    } else {
+        (line.strip_suffix(" failed.")?, false)
+    };
+    (name != "Result:").then_some((name, ok))
+}
+
+/// Device which ignores every port, so `DEI` and `DEO` only touch memory
+struct Ports;
+
+impl Device for Ports {
+    fn dei(&mut self, _vm: &mut Uxn, _target: u8) {}
+    fn deo(&mut self, _vm: &mut Uxn, _target: u8) -> bool {
+        true
+    }
+}
+
+/// Small deterministic generator for test vectors
+struct XorShift(u32);
+
+impl XorShift {
+    fn next(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 24) as u8
+    }
+}
+
+/// Builds initial states for an opcode: edge cases, then pseudo-random
+fn vectors(op: u8) -> Vec<State> {
+    let mut rng = XorShift(0x9e37_79b9 ^ u32::from(op));
+    let mut fill = |f: &mut dyn FnMut(&mut XorShift) -> u8| {
+        let mut ram = Box::new([0u8; 65536]);
+        for b in ram.iter_mut() {
+            *b = rng.next() | 1;
+        }
+        ram[0x100] = op;
+        ram[0x101] = f(&mut rng);
+        ram[0x102] = f(&mut rng);
+        let mut dev = [0u8; 256];
+        dev.iter_mut().for_each(|b| *b = rng.next());
+        let wst: Vec<u8> = (0..8).map(|_| f(&mut rng)).collect();
+        let rst: Vec<u8> = (0..8).map(|_| f(&mut rng)).collect();
+        State {
+            wst: Stack::new(&wst),
+            rst: Stack::new(&rst),
+            ram,
+            dev,
+            pc: 0x100,
+        }
+    };
+    let mut out = vec![];
+    for v in [0x00, 0xff, 0x80, 0x7f, 0x01] {
+        out.push(fill(&mut |_| v));
+    }
+    for _ in 0..RANDOM_VECTORS {
+        out.push(fill(&mut |r| r.next()));
+    }
+    out
+}
+
+/// Result of checking one opcode against the model
+struct Mode {
+    /// Description of the first mismatch, if any
+    failure: Option<String>,
+    /// Vectors that were run
+    checked: usize,
+    /// Vectors the model couldn't check (see [`check_opcode`])
+    skipped: usize,
+}
+
+impl Mode {
+    /// Returns `true` if at least one vector ran and none of them failed
+    fn passed(&self) -> bool {
+        self.failure.is_none() && self.checked > 0
+    }
+
+    fn cell(&self) -> &'static str {
+        if self.checked == 0 && self.failure.is_none() {
+            "skip"
+        } else {
+            cell(self.passed())
+        }
+    }
+}
+
+/// Checks every opcode against the model in [`crate::reference`]
+///
+/// Checking an opcode stops at its first mismatch.
+fn opcode_modes() -> Vec<Mode> {
+    (0..=255u8)
+        .map(|op| {
+            let mut mode = Mode {
+                failure: None,
+                checked: 0,
+                skipped: 0,
+            };
+            for v in vectors(op) {
+                match check_opcode(v) {
+                    Some(Ok(())) => mode.checked += 1,
+                    None => mode.skipped += 1,
+                    Some(Err(e)) => {
+                        let name = uxn::op::NAMES[usize::from(op)];
+                        mode.failure = Some(format!("{name}: {e}"));
+                        break;
+                    }
+                }
+            }
+            mode
+        })
+        .collect()
+}
+
+/// Runs a single instruction in Cardinal and the model, returning a
+/// description of any difference, or `None` if the vector was skipped
+fn check_opcode(mut init: State) -> Option<std::result::Result<(), String>> {
+    // Place a `BRK` wherever the model says execution continues, so that
+    // Cardinal stops there too.  Vectors where that changes the instruction
+    // (by overwriting it or its immediate operand) or where the instruction
+    // overwrites the `BRK` are skipped.
+    let mut expected = init.clone();
+    let running = expected.step();
+    let target = expected.pc;
+    if running {
+        if target == 0x100 {
+            return None;
+        }
+        init.ram[usize::from(target)] = 0;
+        expected = init.clone();
+        expected.step();
+        if expected.pc != target || expected.ram[usize::from(target)] != 0 {
+            return None;
+        }
+    }
+
+    let mut ram = UxnRam::new();
+    ram.copy_from_slice(&init.ram[..]);
+    let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
+    vm.dev = init.dev;
+    for &b in init.wst.items() {
+        vm.stack_mut().push_byte(b);
+    }
+    for &b in init.rst.items() {
+        vm.ret_mut().push_byte(b);
+    }
+    // Stop after the instruction and the `BRK` that follows it
+    let pc = vm.run_until(&mut Ports, 0x100, |_, _, i| i >= 1);
+    let expected_pc = if running {
+        target.wrapping_add(1)
+    } else {
+        target
+    };
+
+    let input = format!(
+        "wst [{}] rst [{}]",
+        hex(init.wst.items()),
+        hex(init.rst.items())
+    );
+    if pc != Some(expected_pc) {
+        let pc = pc.map(|p| format!("{:04x}", p.wrapping_sub(1)));
+        return Some(Err(format!(
+            "with {input}: continued at {}, expected {:04x}",
+            pc.as_deref().unwrap_or("(not BRK)"),
+            target
+        )));
+    }
+    for (name, actual, expected) in [
+        ("wst", stack_items(vm.stack()), expected.wst.items()),
+        ("rst", stack_items(vm.ret()), expected.rst.items()),
+    ] {
+        if actual != expected {
+            return Some(Err(format!(
+                "with {input}: {name} [{}], expected [{}]",
+                hex(&actual),
+                hex(expected)
+            )));
+        }
+    }
+    if let Some(i) = (vm.ram[..] != expected.ram[..])
+        .then(|| (0..65536).find(|&i| vm.ram[i] != expected.ram[i]))
+        .flatten()
+    {
+        return Some(Err(format!(
+            "with {input}: ram[{i:04x}] = {:02x}, expected {:02x}",
+            vm.ram[i], expected.ram[i]
+        )));
+    }
+    if let Some(i) = (0..256).find(|&i| vm.dev[i] != expected.dev[i]) {
+        return Some(Err(format!(
+            "with {input}: dev[{i:02x}] = {:02x}, expected {:02x}",
+            vm.dev[i], expected.dev[i]
+        )));
+    }
+    Some(Ok(()))
+}
+
+/// Returns a Cardinal stack's items, from the bottom
+fn stack_items(s: &uxn::Stack) -> Vec<u8> {
+    let n = s.len();
+    (0..n).rev().map(|i| s.peek_byte_at(i)).collect()
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,3 +1,8 @@
+mod compat;
+mod reference;
+mod suite;
+mod term;
+
 use std::io::Read;
 use std::path::PathBuf;
 use std::sync::{
@@ -6,8 +11,8 @@ use std::sync::{
 };
 use std::time::Duration;
 
-use uxn::{Backend, Uxn, UxnRam};
-use varvara::Varvara;
+use uxn::Backend;
+use varvara::{Machine, PipeReader, Varvara};
 
 use anyhow::{Context, Result};
 use clap::Parser;
@@ -16,9 +21,14 @@ use log::info;
 /// Uxn runner
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// ROM to load and execute
-    rom: PathBuf,
+    #[clap(required = true)]
+    rom: Option<PathBuf>,
 
     /// Use the native Uxn implementation
     #[clap(long)]
@@ -28,49 +38,114 @@ struct Args {
     #[clap(long)]
     timeout: Option<f64>,
 
-    /// Arguments to pass into the VM
+    /// Draw the Screen device into the terminal, forwarding keyboard and mouse
+    /// input to the ROM (quit with Ctrl+C)
+    #[clap(long)]
+    tui: bool,
+
+    /// Characters used to draw pixels in `--tui` mode
+    #[clap(long, value_enum, default_value_t = term::Glyphs::HalfBlock)]
+    glyphs: term::Glyphs,
+
+    /// Color escapes used in `--tui` mode
+    #[clap(long, value_enum, default_value_t = term::ColorMode::TrueColor)]
+    colors: term::ColorMode,
+
+    /// Enable the network device, allowing connections to localhost
+    #[clap(long)]
+    net: bool,
+
+    /// Allow the network device to connect to the given host (implies `--net`)
+    #[clap(long, value_name = "HOST")]
+    net_allow: Vec<String>,
+
+    /// Allow the network device to connect to any host (implies `--net`)
+    #[clap(long)]
+    net_any: bool,
+
+    /// Run another ROM in the same process, feeding it the previous ROM's
+    /// stdout as its stdin (like `rom | next.rom`); may be repeated
+    #[clap(long, value_name = "ROM", conflicts_with = "tui")]
+    pipe: Vec<PathBuf>,
+
+    /// Arguments to pass into the VM (the first ROM, when using `--pipe`)
     #[arg(last = true)]
     args: Vec<String>,
 }
 
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run the ROM tests listed in a manifest, reporting results as TAP or
+    /// JUnit XML
+    Test(suite::TestArgs),
+    /// Run the upstream opctest ROM and local opcode and device checks,
+    /// printing a compatibility report
+    Compat(compat::CompatArgs),
+}
+
 fn main() -> Result<()> {
+    let args = Args::parse();
+    if let Some(command) = args.command {
+        env_logger::init_from_env(env_logger::Env::default().filter_or("UXN_LOG", "warn"));
+        let passed = match command {
+            Command::Test(t) => suite::run(t)?,
+            Command::Compat(c) => compat::run(c)?,
+        };
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    let rom = args.rom.as_ref().unwrap();
+
+    // Log messages would be drawn over the screen in `--tui` mode, so only
+    // show warnings by default.
     let env = env_logger::Env::default()
-        .filter_or("UXN_LOG", "info")
+        .filter_or("UXN_LOG", if args.tui { "warn" } else { "info" })
         .write_style_or("UXN_LOG", "always");
     env_logger::init_from_env(env);
 
-    let args = Args::parse();
-    let mut f =
-        std::fs::File::open(&args.rom).with_context(|| format!("failed to open {:?}", args.rom))?;
+    let backend = if args.native {
+        #[cfg(not(target_arch = "aarch64"))]
+        anyhow::bail!("no native implementation for this arch");
 
-    let mut rom = vec![];
-    f.read_to_end(&mut rom).context("failed to read file")?;
+        #[cfg(target_arch = "aarch64")]
+        Backend::Native
+    } else {
+        Backend::Interpreter
+    };
+
+    // Build every stage of the pipeline, connecting each one's stdout to the
+    // next before any ROM runs
+    let roms = std::iter::once(rom)
+        .chain(&args.pipe)
+        .map(read_rom)
+        .collect::<Result<Vec<_>>>()?;
+    let mut stages = vec![];
+    let mut pipes = vec![];
+    for i in 0..roms.len() {
+        let mut dev = Varvara::headless();
+        if args.net || args.net_any || !args.net_allow.is_empty() {
+            use varvara::net::{Net, NetPorts, Policy};
+            let policy = if args.net_any {
+                Policy::Any
+            } else if !args.net_allow.is_empty() {
+                Policy::Allow(args.net_allow.clone())
+            } else {
+                Policy::LocalhostOnly
+            };
+            dev.register::<NetPorts, _>(Net::new(policy))?;
+        }
+        if i + 1 < roms.len() {
+            pipes.push(dev.pipe_stdout());
+        }
+        stages.push(Machine::with_devices(backend, dev));
+    }
 
-    let mut ram = UxnRam::new();
-    let mut vm = Uxn::new(
-        &mut ram,
-        if args.native {
-            #[cfg(not(target_arch = "aarch64"))]
-            anyhow::bail!("no native implementation for this arch");
-
-            #[cfg(target_arch = "aarch64")]
-            Backend::Native
-        } else {
-            Backend::Interpreter
-        },
-    );
-    let mut dev = Varvara::default();
-    let data = vm.reset(&rom);
-    dev.reset(data);
-    dev.init_args(&mut vm, &args.args);
-
-    // Run the reset vector
+    // Run the reset vectors; only the first ROM receives arguments
     let start = std::time::Instant::now();
-    vm.run(&mut dev, 0x100);
+    for (i, (m, rom)) in stages.iter_mut().zip(&roms).enumerate() {
+        m.load(rom, if i == 0 { &args.args } else { &[] });
+    }
     info!("startup complete in {:?}", start.elapsed());
-
-    dev.output(&vm).check()?;
-    dev.send_args(&mut vm, &args.args).check()?;
+    pump(&mut stages, &mut pipes)?;
 
     // Set up timeout if specified
     let timeout_reached = Arc::new(AtomicBool::new(false));
@@ -82,53 +157,109 @@ fn main() -> Result<()> {
         });
     }
 
-    fn run_console_loop(
-        rx: std::sync::mpsc::Receiver<u8>,
-        dev: &mut Varvara,
-        vm: &mut Uxn,
-        timeout_reached: &Arc<AtomicBool>,
-    ) -> Result<()> {
-        loop {
-            if timeout_reached.load(Ordering::Relaxed) {
-                info!("Timeout reached, exiting");
-                break;
-            }
-            match rx.recv_timeout(Duration::from_millis(100)) {
-                Ok(c) => {
-                    dev.console(vm, c);
-                    dev.output(vm).check()?;
-                }
-                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                    continue;
-                }
+    if args.tui {
+        let opts = term::TermOptions {
+            glyphs: args.glyphs,
+            colors: args.colors,
+        };
+        let m = &mut stages[0];
+        if m.is_running() {
+            m.with(|runner| term::run(runner, opts, &timeout_reached))?;
+        }
+    } else {
+        run_console(&mut stages, &mut pipes, &timeout_reached)?;
+    }
+
+    // As in a shell pipeline, the exit code comes from the last ROM
+    if let Some(e) = stages.last().unwrap().exit() {
+        info!("requested exit ({e})");
+        std::process::exit(e);
+    }
+    Ok(())
+}
+
+fn read_rom(path: &PathBuf) -> Result<Vec<u8>> {
+    let mut f = std::fs::File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+    let mut rom = vec![];
+    f.read_to_end(&mut rom).context("failed to read file")?;
+    Ok(rom)
+}
+
+/// Moves piped output down the pipeline, then prints the results
+///
+/// `pipes[i]` carries the stdout of `stages[i]` to `stages[i + 1]`.  Only the
+/// last stage's stdout reaches the terminal; every stage's stderr is printed.
+fn pump(stages: &mut [Machine], pipes: &mut [PipeReader]) -> Result<()> {
+    for (i, pipe) in pipes.iter_mut().enumerate() {
+        stages[i + 1].with(|r| pipe.pump(r));
+    }
+    let last = stages.len() - 1;
+    for (i, m) in stages.iter_mut().enumerate() {
+        let mut out = m.output();
+        if i != last {
+            out.stdout.clear();
+        }
+        out.print()?;
+    }
+    Ok(())
+}
+
+/// Feeds stdin to the first stage's console device until the last stage
+/// finishes or the timeout is reached
+///
+/// At the end of stdin, the first stage is sent end-of-input (console type
+/// `0x04`, as in `uxncli`).  A stage is finished once it has exited, or has
+/// received end-of-input and processed every pending event; at that point its
+/// piped output has been delivered, and the next stage is sent end-of-input in
+/// turn, like closing a pipe between processes.
+fn run_console(
+    stages: &mut [Machine],
+    pipes: &mut [PipeReader],
+    timeout_reached: &Arc<AtomicBool>,
+) -> Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    varvara::spawn_console_worker(move |e| tx.send(e));
+
+    // Whether each stage has been sent end-of-input
+    let mut eof = vec![false; stages.len()];
+    loop {
+        if timeout_reached.load(Ordering::Relaxed) {
+            info!("Timeout reached, exiting");
+            return Ok(());
+        }
+        if !eof[0] {
+            match rx.recv_timeout(Duration::from_millis(10)) {
+                Ok(c) => stages[0].console(c),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => (),
                 Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
-                    // Input source disconnected, exit
-                    break;
+                    stages[0].console_eof();
+                    eof[0] = true;
                 }
             }
         }
-        Ok(())
-    }
+        let busy = stages.iter_mut().map(|m| m.poll()).collect::<Vec<_>>();
 
-    if std::io::IsTerminal::is_terminal(&std::io::stdin()) {
-        // Terminal: spawn console worker and run loop
-        let (tx, rx) = std::sync::mpsc::channel();
-        varvara::spawn_console_worker(move |e| tx.send(e));
-        run_console_loop(rx, &mut dev, &mut vm, &timeout_reached)?;
-    } else {
-        // Piped stdin: spawn console worker and run loop, then flush output and exit
-        let (tx, rx) = std::sync::mpsc::channel();
-        varvara::spawn_console_worker(move |e| tx.send(e));
-        run_console_loop(rx, &mut dev, &mut vm, &timeout_reached)?;
-        // After EOF, flush output briefly
-        for _ in 0..10 {
-            if timeout_reached.load(Ordering::Relaxed) {
-                info!("Timeout reached, exiting");
-                break;
+        // Close each pipe once its source has finished, after delivering the
+        // source's final output (including anything written in response to
+        // its own end-of-input)
+        for i in 1..stages.len() {
+            if !eof[i] && finished(&stages[i - 1], eof[i - 1], busy[i - 1]) {
+                stages[i].with(|r| pipes[i - 1].pump(r));
+                stages[i].console_eof();
+                eof[i] = true;
             }
-            std::thread::sleep(Duration::from_millis(10));
-            dev.output(&vm).check()?;
+        }
+        pump(stages, pipes)?;
+
+        let last = stages.len() - 1;
+        if finished(&stages[last], eof[last], busy[last]) {
+            return Ok(());
         }
     }
-    Ok(())
+}
+
+/// Checks whether a stage has exited, or reached the end of its input and
+/// gone idle
+fn finished(m: &Machine, eof: bool, busy: bool) -> bool {
+    !m.is_running() || (eof && !busy)
 }
@@ -0,0 +1,411 @@
+//! Reference model of the Uxn CPU, written from the specification
+//!
+//! This is deliberately simple and independent of `cardinal-uxn`: every
+//! instruction is decoded from its flag bits and evaluated on plain arrays, so
+//! it can be used to check the optimized implementations opcode-by-opcode.
+
+/// Circular 256-byte stack
+#[derive(Clone, PartialEq, Eq)]
+pub struct Stack {
+    pub data: [u8; 256],
+    /// Number of items, i.e. the index of the next push
+    pub ptr: u8,
+}
+
+impl Stack {
+    pub fn new(items: &[u8]) -> Self {
+        let mut s = Self {
+            data: [0; 256],
+            ptr: 0,
+        };
+        for &b in items {
+            s.push(b);
+        }
+        s
+    }
+
+    /// Returns live items, from the bottom of the stack
+    pub fn items(&self) -> &[u8] {
+        &self.data[..usize::from(self.ptr)]
+    }
+
+    fn push(&mut self, v: u8) {
+        self.data[usize::from(self.ptr)] = v;
+        self.ptr = self.ptr.wrapping_add(1);
+    }
+}
+
+/// Complete machine state
+#[derive(Clone)]
+pub struct State {
+    pub wst: Stack,
+    pub rst: Stack,
+    pub ram: Box<[u8; 65536]>,
+    pub dev: [u8; 256],
+    pub pc: u16,
+}
+
+/// Operand access for a single instruction, honoring its mode flags
+struct Operands<'a> {
+    state: &'a mut State,
+    short: bool,
+    keep: bool,
+    ret: bool,
+    /// Read position for pops, which only commits outside of keep mode
+    cursor: u8,
+}
+
+impl Operands<'_> {
+    fn src(&mut self) -> &mut Stack {
+        if self.ret {
+            &mut self.state.rst
+        } else {
+            &mut self.state.wst
+        }
+    }
+
+    fn dst(&mut self) -> &mut Stack {
+        if self.ret {
+            &mut self.state.wst
+        } else {
+            &mut self.state.rst
+        }
+    }
+
+    fn pop8(&mut self) -> u8 {
+        self.cursor = self.cursor.wrapping_sub(1);
+        let c = self.cursor;
+        self.src().data[usize::from(c)]
+    }
+
+    /// Pops a byte or short, depending on the short flag
+    fn pop(&mut self) -> u16 {
+        if self.short {
+            let lo = self.pop8();
+            let hi = self.pop8();
+            u16::from_be_bytes([hi, lo])
+        } else {
+            u16::from(self.pop8())
+        }
+    }
+
+    /// Finishes popping, before any values are pushed
+    fn commit(&mut self) {
+        if !self.keep {
+            let c = self.cursor;
+            self.src().ptr = c;
+        }
+    }
+
+    fn push(&mut self, v: u16) {
+        let short = self.short;
+        Self::push_to(self.src(), v, short);
+    }
+
+    /// Pushes a value to the opposite stack
+    fn push_other(&mut self, v: u16) {
+        let short = self.short;
+        Self::push_to(self.dst(), v, short);
+    }
+
+    fn push_to(s: &mut Stack, v: u16, short: bool) {
+        if short {
+            s.push((v >> 8) as u8);
+        }
+        s.push(v as u8);
+    }
+
+    /// Jumps to an absolute (short mode) or relative (byte mode) address
+    fn jump(&mut self, addr: u16) {
+        self.state.pc = if self.short {
+            addr
+        } else {
+            self.state.pc.wrapping_add(addr as u8 as i8 as u16)
+        };
+    }
+
+    fn load(&mut self, addr: u16, zero_page: bool) -> u16 {
+        let next = if zero_page {
+            u16::from((addr as u8).wrapping_add(1))
+        } else {
+            addr.wrapping_add(1)
+        };
+        let hi = self.state.ram[usize::from(addr)];
+        if self.short {
+            u16::from_be_bytes([hi, self.state.ram[usize::from(next)]])
+        } else {
+            u16::from(hi)
+        }
+    }
+
+    fn store(&mut self, addr: u16, v: u16, zero_page: bool) {
+        if self.short {
+            let next = if zero_page {
+                u16::from((addr as u8).wrapping_add(1))
+            } else {
+                addr.wrapping_add(1)
+            };
+            self.state.ram[usize::from(addr)] = (v >> 8) as u8;
+            self.state.ram[usize::from(next)] = v as u8;
+        } else {
+            self.state.ram[usize::from(addr)] = v as u8;
+        }
+    }
+}
+
+impl State {
+    fn read16(&self, addr: u16) -> u16 {
+        let hi = self.ram[usize::from(addr)];
+        let lo = self.ram[usize::from(addr.wrapping_add(1))];
+        u16::from_be_bytes([hi, lo])
+    }
+
+    /// Executes the instruction at `pc`
+    ///
+    /// Returns `false` if the instruction was `BRK`.  Device ports are plain
+    /// memory: `DEI` reads `dev` and `DEO` writes it.
+    pub fn step(&mut self) -> bool {
+        let op = self.ram[usize::from(self.pc)];
+        self.pc = self.pc.wrapping_add(1);
+        match op {
+            0x00 => return false,
+            // JCI, JMI, JSI
+            0x20 | 0x40 | 0x60 => {
+                let offset = self.read16(self.pc);
+                let next = self.pc.wrapping_add(2);
+                let taken = match op {
+                    0x20 => {
+                        self.wst.ptr = self.wst.ptr.wrapping_sub(1);
+                        self.wst.data[usize::from(self.wst.ptr)] != 0
+                    }
+                    0x60 => {
+                        self.rst.push((next >> 8) as u8);
+                        self.rst.push(next as u8);
+                        true
+                    }
+                    _ => true,
+                };
+                self.pc = if taken {
+                    next.wrapping_add(offset)
+                } else {
+                    next
+                };
+                return true;
+            }
+            _ => (),
+        }
+
+        let mut o = Operands {
+            short: op & 0x20 != 0,
+            ret: op & 0x40 != 0,
+            keep: op & 0x80 != 0,
+            cursor: 0,
+            state: self,
+        };
+        o.cursor = o.src().ptr;
+        match op & 0x1f {
+            // LIT, LIT2, LITr, LIT2r
+            0x00 => {
+                let pc = o.state.pc;
+                let v = o.load(pc, false);
+                o.state.pc = pc.wrapping_add(if o.short { 2 } else { 1 });
+                o.push(v);
+            }
+            // INC
+            0x01 => {
+                let a = o.pop();
+                o.commit();
+                o.push(a.wrapping_add(1));
+            }
+            // POP
+            0x02 => {
+                o.pop();
+                o.commit();
+            }
+            // NIP
+            0x03 => {
+                let a = o.pop();
+                o.pop();
+                o.commit();
+                o.push(a);
+            }
+            // SWP
+            0x04 => {
+                let a = o.pop();
+                let b = o.pop();
+                o.commit();
+                o.push(a);
+                o.push(b);
+            }
+            // ROT
+            0x05 => {
+                let a = o.pop();
+                let b = o.pop();
+                let c = o.pop();
+                o.commit();
+                o.push(b);
+                o.push(a);
+                o.push(c);
+            }
+            // DUP
+            0x06 => {
+                let a = o.pop();
+                o.commit();
+                o.push(a);
+                o.push(a);
+            }
+            // OVR
+            0x07 => {
+                let a = o.pop();
+                let b = o.pop();
+                o.commit();
+                o.push(b);
+                o.push(a);
+                o.push(b);
+            }
+            // EQU, NEQ, GTH, LTH
+            0x08..=0x0b => {
+                let a = o.pop();
+                let b = o.pop();
+                o.commit();
+                let f = match op & 0x1f {
+                    0x08 => b == a,
+                    0x09 => b != a,
+                    0x0a => b > a,
+                    _ => b < a,
+                };
+                o.short = false;
+                o.push(u16::from(f));
+            }
+            // JMP
+            0x0c => {
+                let a = o.pop();
+                o.commit();
+                o.jump(a);
+            }
+            // JCN
+            0x0d => {
+                let a = o.pop();
+                let cond = o.pop8();
+                o.commit();
+                if cond != 0 {
+                    o.jump(a);
+                }
+            }
+            // JSR
+            0x0e => {
+                let a = o.pop();
+                o.commit();
+                let pc = o.state.pc;
+                Operands::push_to(o.dst(), pc, true);
+                o.jump(a);
+            }
+            // STH
+            0x0f => {
+                let a = o.pop();
+                o.commit();
+                o.push_other(a);
+            }
+            // LDZ
+            0x10 => {
+                let addr = o.pop8();
+                o.commit();
+                let v = o.load(u16::from(addr), true);
+                o.push(v);
+            }
+            // STZ
+            0x11 => {
+                let addr = o.pop8();
+                let v = o.pop();
+                o.commit();
+                o.store(u16::from(addr), v, true);
+            }
+            // LDR
+            0x12 => {
+                let offset = o.pop8() as i8;
+                o.commit();
+                let addr = o.state.pc.wrapping_add(offset as u16);
+                let v = o.load(addr, false);
+                o.push(v);
+            }
+            // STR
+            0x13 => {
+                let offset = o.pop8() as i8;
+                let v = o.pop();
+                o.commit();
+                let addr = o.state.pc.wrapping_add(offset as u16);
+                o.store(addr, v, false);
+            }
+            // LDA
+            0x14 => {
+                let lo = o.pop8();
+                let hi = o.pop8();
+                o.commit();
+                let v = o.load(u16::from_be_bytes([hi, lo]), false);
+                o.push(v);
+            }
+            // STA
+            0x15 => {
+                let lo = o.pop8();
+                let hi = o.pop8();
+                let v = o.pop();
+                o.commit();
+                o.store(u16::from_be_bytes([hi, lo]), v, false);
+            }
+            // DEI
+            0x16 => {
+                let port = o.pop8();
+                o.commit();
+                let dev = &o.state.dev;
+                let hi = dev[usize::from(port)];
+                let v = if o.short {
+                    u16::from_be_bytes([hi, dev[usize::from(port.wrapping_add(1))]])
+                } else {
+                    u16::from(hi)
+                };
+                o.push(v);
+            }
+            // DEO
+            0x17 => {
+                let port = o.pop8();
+                let v = o.pop();
+                o.commit();
+                let dev = &mut o.state.dev;
+                if o.short {
+                    dev[usize::from(port)] = (v >> 8) as u8;
+                    dev[usize::from(port.wrapping_add(1))] = v as u8;
+                } else {
+                    dev[usize::from(port)] = v as u8;
+                }
+            }
+            // ADD, SUB, MUL, DIV, AND, ORA, EOR
+            0x18..=0x1e => {
+                let a = o.pop();
+                let b = o.pop();
+                o.commit();
+                let mask = if o.short { 0xffff } else { 0xff };
+                let v = match op & 0x1f {
+                    0x18 => b.wrapping_add(a),
+                    0x19 => b.wrapping_sub(a),
+                    0x1a => b.wrapping_mul(a),
+                    0x1b => b.checked_div(a).unwrap_or(0),
+                    0x1c => b & a,
+                    0x1d => b | a,
+                    _ => b ^ a,
+                };
+                o.push(v & mask);
+            }
+            // SFT
+            0x1f => {
+                let shift = o.pop8();
+                let v = o.pop();
+                o.commit();
+                let mask: u32 = if o.short { 0xffff } else { 0xff };
+                let v = ((u32::from(v) >> (shift & 0x0f)) << (shift >> 4)) & mask;
+                o.push(v as u16);
+            }
+            _ => unreachable!(),
+        }
+        true
+    }
+}
@@ -0,0 +1,392 @@
+//! Data-driven ROM tests (`cardinal-cli test`)
+//!
+//! A manifest is a TOML file listing test cases; paths are relative to the
+//! manifest, and `.tal` sources are assembled before running:
+//!
+//! ```toml
+//! # Defaults for every case
+//! cycles = 10_000_000   # instruction budget
+//! timeout = 5.0         # seconds
+//!
+//! [[test]]
+//! name = "hello"
+//! rom = "hello.tal"
+//! args = ["world"]
+//! stdin = "abc"         # or `stdin_file = "in.txt"`
+//! stdout = "Hello!"     # or `stdout_file = "out.txt"`; unchecked if absent
+//! stderr = ""           # or `stderr_file`; unchecked if absent
+//! exit = 0              # expected exit code (default 0)
+//! ```
+//!
+//! Each case runs headlessly: arguments are sent, then stdin, then
+//! end-of-input.  If the ROM has set a screen vector, frames are then run
+//! back-to-back (without waiting) until it exits.  A case fails if its
+//! output or exit code doesn't match, or if it spends its instruction budget
+//! or runs past its timeout.
+//!
+//! Results are written to stdout once every case has run; anything else is
+//! logged to stderr.
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use uxn::Backend;
+use varvara::{Machine, FRAME_TIME};
+
+/// Default instruction budget for each case
+const DEFAULT_CYCLES: u64 = 100_000_000;
+
+/// Default timeout for each case, in seconds
+const DEFAULT_TIMEOUT: f64 = 10.0;
+
+/// Result format
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Test Anything Protocol (version 13)
+    Tap,
+    /// JUnit XML
+    Junit,
+}
+
+/// Runs the ROM tests listed in a manifest
+#[derive(clap::Args)]
+pub struct TestArgs {
+    /// TOML manifest listing the tests
+    manifest: PathBuf,
+
+    /// Result format
+    #[clap(long, value_enum, default_value_t = Format::Tap)]
+    format: Format,
+
+    /// Write results to a file rather than stdout
+    #[clap(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Only run tests whose name contains this string
+    #[clap(long)]
+    filter: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    cycles: Option<u64>,
+    timeout: Option<f64>,
+    #[serde(default, rename = "test")]
+    tests: Vec<Case>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    rom: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    stdin: Option<String>,
+    stdin_file: Option<PathBuf>,
+    stdout: Option<String>,
+    stdout_file: Option<PathBuf>,
+    stderr: Option<String>,
+    stderr_file: Option<PathBuf>,
+    #[serde(default)]
+    exit: i32,
+    cycles: Option<u64>,
+    timeout: Option<f64>,
+}
+
+/// Everything needed to run a case, with files loaded
+struct Job {
+    rom: Vec<u8>,
+    args: Vec<String>,
+    stdin: Vec<u8>,
+    stdout: Option<Vec<u8>>,
+    stderr: Option<Vec<u8>>,
+    exit: i32,
+    cycles: u64,
+    timeout: Duration,
+}
+
+/// Observed behavior of a ROM
+struct Run {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    exit: Option<i32>,
+    exhausted: bool,
+}
+
+/// Result of a single case
+struct Report {
+    name: String,
+    time: Duration,
+    /// Reasons for failure; empty if the case passed
+    failures: Vec<String>,
+}
+
+/// Runs the `test` subcommand, returning `true` if every case passed
+pub fn run(args: TestArgs) -> Result<bool> {
+    let text = std::fs::read_to_string(&args.manifest)
+        .with_context(|| format!("failed to read {:?}", args.manifest))?;
+    let manifest: Manifest =
+        toml::from_str(&text).with_context(|| format!("failed to parse {:?}", args.manifest))?;
+    let dir = args.manifest.parent().unwrap_or(Path::new("."));
+
+    let mut reports = vec![];
+    for case in &manifest.tests {
+        if let Some(f) = &args.filter {
+            if !case.name.contains(f.as_str()) {
+                continue;
+            }
+        }
+        let start = Instant::now();
+        let failures = match case.job(&manifest, dir) {
+            Ok(job) => check(&job),
+            Err(e) => vec![format!("{e:#}")],
+        };
+        reports.push(Report {
+            name: case.name.clone(),
+            time: start.elapsed(),
+            failures,
+        });
+    }
+
+    let suite = args
+        .manifest
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let out = match args.format {
+        Format::Tap => tap(&reports),
+        Format::Junit => junit(&suite, &reports),
+    };
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, out).with_context(|| format!("failed to write {path:?}"))?
+        }
+        None => print!("{out}"),
+    }
+    Ok(reports.iter().all(|r| r.failures.is_empty()))
+}
+
+impl Case {
+    /// Loads the ROM and fixtures for this case
+    fn job(&self, manifest: &Manifest, dir: &Path) -> Result<Job> {
+        let path = dir.join(&self.rom);
+        let rom = if path.extension().is_some_and(|e| e == "tal") {
+            assemble(&path).with_context(|| format!("failed to assemble {path:?}"))?
+        } else {
+            std::fs::read(&path).with_context(|| format!("failed to read {path:?}"))?
+        };
+        let timeout = self.timeout.or(manifest.timeout).unwrap_or(DEFAULT_TIMEOUT);
+        Ok(Job {
+            rom,
+            args: self.args.clone(),
+            stdin: fixture(dir, &self.stdin, &self.stdin_file)?.unwrap_or_default(),
+            stdout: fixture(dir, &self.stdout, &self.stdout_file)?,
+            stderr: fixture(dir, &self.stderr, &self.stderr_file)?,
+            exit: self.exit,
+            cycles: self.cycles.or(manifest.cycles).unwrap_or(DEFAULT_CYCLES),
+            timeout: Duration::try_from_secs_f64(timeout)
+                .with_context(|| format!("invalid timeout {timeout}"))?,
+        })
+    }
+}
+
+/// Assembles a `.tal` case without printing the assembler's progress
+fn assemble(path: &Path) -> Result<Vec<u8>> {
+    let source = std::fs::read_to_string(path)?;
+    let mut asm = uxn_tal::Assembler::new();
+    asm.quiet = true;
+    asm.assemble(&source, Some(path.to_string_lossy().into_owned()))
+        .map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+/// Loads an inline or file-based fixture
+fn fixture(dir: &Path, text: &Option<String>, file: &Option<PathBuf>) -> Result<Option<Vec<u8>>> {
+    match (text, file) {
+        (Some(_), Some(_)) => anyhow::bail!("fixture given as both inline text and a file"),
+        (Some(t), None) => Ok(Some(t.as_bytes().to_vec())),
+        (None, Some(f)) => {
+            let path = dir.join(f);
+            let data = std::fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+            Ok(Some(data))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Runs a job, returning reasons for failure
+fn check(job: &Job) -> Vec<String> {
+    let Some(run) = execute(job) else {
+        return vec![format!("timed out after {:?}", job.timeout)];
+    };
+    let mut failures = vec![];
+    if run.exhausted {
+        failures.push(format!("instruction budget of {} exhausted", job.cycles));
+    }
+    for (label, expected, actual) in [
+        ("stdout", &job.stdout, &run.stdout),
+        ("stderr", &job.stderr, &run.stderr),
+    ] {
+        if let Some(expected) = expected.as_ref().filter(|e| *e != actual) {
+            failures.push(format!(
+                "{label} mismatch\n  expected: \"{}\"\n    actual: \"{}\"",
+                expected.escape_ascii(),
+                actual.escape_ascii()
+            ));
+        }
+    }
+    let exit = run.exit.unwrap_or(0);
+    if !run.exhausted && exit != job.exit {
+        failures.push(format!(
+            "exit code mismatch\n  expected: {}\n    actual: {exit}",
+            job.exit
+        ));
+    }
+    failures
+}
+
+/// Runs a job on a worker thread, returning `None` if it times out
+///
+/// A timed-out worker is stopped through the system's stop flag, which
+/// interrupts the vector it's running.
+fn execute(job: &Job) -> Option<Run> {
+    let (tx, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let (rom, args, stdin, cycles) = (
+        job.rom.clone(),
+        job.args.clone(),
+        job.stdin.clone(),
+        job.cycles,
+    );
+    let cancel = stop.clone();
+    std::thread::spawn(move || {
+        let mut m = Machine::new(Backend::Interpreter);
+        m.dev_mut().set_cycle_budget(Some(cycles));
+        m.dev_mut().set_stop_flag(Some(cancel));
+        m.load(&rom, &args);
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        let mut drain = |m: &mut Machine| {
+            let out = m.output();
+            stdout.extend_from_slice(&out.stdout);
+            stderr.extend_from_slice(&out.stderr);
+        };
+        drain(&mut m);
+        for c in stdin {
+            m.console(c);
+            drain(&mut m);
+        }
+        m.console_eof();
+        drain(&mut m);
+
+        // Run frames while the ROM has a screen vector (`Screen/vector`)
+        let screen = |m: &Machine| u16::from_be_bytes([m.vm().dev[0x20], m.vm().dev[0x21]]);
+        let mut now = Duration::ZERO;
+        while m.is_running() && screen(&m) != 0 {
+            m.advance(now);
+            now += FRAME_TIME;
+            drain(&mut m);
+        }
+        let _ = tx.send(Run {
+            stdout,
+            stderr,
+            exit: m.exit(),
+            exhausted: m.dev().cycles_exhausted(),
+        });
+    });
+    let out = rx.recv_timeout(job.timeout).ok();
+    stop.store(true, Ordering::Relaxed);
+    out
+}
+
+/// Formats results with the Test Anything Protocol
+fn tap(reports: &[Report]) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", reports.len());
+    for (i, r) in reports.iter().enumerate() {
+        let n = i + 1;
+        if r.failures.is_empty() {
+            writeln!(out, "ok {n} - {}", r.name).unwrap();
+        } else {
+            writeln!(out, "not ok {n} - {}", r.name).unwrap();
+            out += "  ---\n  message: |\n";
+            for line in r.failures.iter().flat_map(|f| f.lines()) {
+                writeln!(out, "    {line}").unwrap();
+            }
+            out += "  ...\n";
+        }
+    }
+    out
+}
+
+/// Formats results as JUnit XML
+fn junit(suite: &str, reports: &[Report]) -> String {
+    let failed = reports.iter().filter(|r| !r.failures.is_empty()).count();
+    let time: Duration = reports.iter().map(|r| r.time).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<testsuites tests=\"{}\" failures=\"{failed}\" time=\"{:.3}\">",
+        reports.len(),
+        time.as_secs_f64()
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failed}\" time=\"{:.3}\">",
+        xml_escape(suite),
+        reports.len(),
+        time.as_secs_f64()
+    )
+    .unwrap();
+    for r in reports {
+        write!(
+            out,
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            xml_escape(&r.name),
+            xml_escape(suite),
+            r.time.as_secs_f64()
+        )
+        .unwrap();
+        if r.failures.is_empty() {
+            out += "/>\n";
+        } else {
+            let first = r.failures[0].lines().next().unwrap_or_default();
+            writeln!(
+                out,
+                ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                xml_escape(first),
+                xml_escape(&r.failures.join("\n"))
+            )
+            .unwrap();
+        }
+    }
+    out += "  </testsuite>\n</testsuites>\n";
+    out
+}
+
+/// Escapes text for use in XML attributes and content
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            '\'' => out += "&apos;",
+            // Control characters aren't allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\t' | '\r') => {
+                write!(out, "\\x{:02x}", c as u32).unwrap()
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,483 @@
+//! Terminal renderer for graphical ROMs
+//!
+//! The Screen device is drawn with half-block or braille characters, and
+//! terminal key / mouse events are forwarded to the controller and mouse
+//! devices.
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Instant;
+
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        MouseButton, MouseEvent, MouseEventKind,
+    },
+    queue, terminal,
+};
+use log::info;
+use varvara::{FrameFormat, Key, MouseState, Runner};
+
+/// Characters used to draw pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Glyphs {
+    /// Upper half blocks (`▀`), giving two full-color pixels per cell
+    HalfBlock,
+    /// Braille dots, giving 2×4 pixels per cell in two colors
+    Braille,
+}
+
+/// Color escape sequences used for output
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    /// 24-bit `38;2;r;g;b` escapes
+    TrueColor,
+    /// xterm 256-color palette escapes
+    Ansi256,
+}
+
+/// Mapping between screen pixels and terminal cells
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Layout {
+    /// Number of screen pixels per terminal sub-pixel
+    scale: usize,
+    /// Terminal cells used for output, as `(columns, rows)`
+    cells: (u16, u16),
+}
+
+impl Glyphs {
+    /// Returns the number of sub-pixels in a single cell, as `(x, y)`
+    fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+
+    /// Picks an integer scale so that the screen fits into the terminal
+    fn layout(&self, screen: (u16, u16), term: (u16, u16)) -> Layout {
+        let (cw, ch) = self.cell_size();
+        let (w, h) = (screen.0 as usize, screen.1 as usize);
+        let tw = (term.0 as usize).max(1) * cw;
+        let th = (term.1 as usize).max(1) * ch;
+        let scale = w.div_ceil(tw).max(h.div_ceil(th)).max(1);
+        let cols = w.div_ceil(scale * cw).min(term.0 as usize);
+        let rows = h.div_ceil(scale * ch).min(term.1 as usize);
+        Layout {
+            scale,
+            cells: (cols as u16, rows as u16),
+        }
+    }
+}
+
+impl ColorMode {
+    /// Appends an SGR escape selecting the given foreground / background color
+    fn push(&self, out: &mut String, color: u32, background: bool) {
+        let [b, g, r, _] = color.to_le_bytes();
+        let base = if background { 48 } else { 38 };
+        match self {
+            ColorMode::TrueColor => {
+                let _ = write!(out, "\x1b[{base};2;{r};{g};{b}m");
+            }
+            ColorMode::Ansi256 => {
+                let _ = write!(out, "\x1b[{base};5;{}m", ansi256(r, g, b));
+            }
+        }
+    }
+}
+
+/// Finds the closest color in the xterm 256-color palette
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    // Levels used by the 6×6×6 color cube
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest = |v: u8| {
+        (0..LEVELS.len())
+            .min_by_key(|&i| (LEVELS[i] as i32 - v as i32).abs())
+            .unwrap() as u8
+    };
+    let (ri, gi, bi) = (nearest(r), nearest(g), nearest(b));
+    let cube = [
+        LEVELS[ri as usize],
+        LEVELS[gi as usize],
+        LEVELS[bi as usize],
+    ];
+
+    // Grayscale ramp from 8 to 238 in steps of 10
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = ((avg.saturating_sub(8) + 5) / 10).min(23) as u8;
+    let gray = 8 + gray_index * 10;
+
+    let dist = |c: [u8; 3]| {
+        [r, g, b]
+            .iter()
+            .zip(c)
+            .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    if dist([gray; 3]) < dist(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri + 6 * gi + bi
+    }
+}
+
+/// Renders an indexed frame into one string per terminal row
+///
+/// `pixels` holds one palette index per pixel, as returned by
+/// [`FrameFormat::Indexed`].
+fn render(
+    pixels: &[u8],
+    size: (u16, u16),
+    palette: &[u32; 4],
+    layout: Layout,
+    glyphs: Glyphs,
+    colors: ColorMode,
+) -> Vec<String> {
+    let (w, h) = (size.0 as usize, size.1 as usize);
+    let (cw, ch) = glyphs.cell_size();
+    let sample = |x: usize, y: usize| -> Option<u8> {
+        let (x, y) = (x * layout.scale, y * layout.scale);
+        (x < w && y < h).then(|| pixels[x + y * w] & 0b11)
+    };
+
+    let mut lines = Vec::with_capacity(layout.cells.1 as usize);
+    for row in 0..layout.cells.1 as usize {
+        let mut line = String::new();
+        let mut prev = None;
+        for col in 0..layout.cells.0 as usize {
+            let mut sub = [None; 8];
+            for dy in 0..ch {
+                for dx in 0..cw {
+                    sub[dx + dy * cw] = sample(col * cw + dx, row * ch + dy);
+                }
+            }
+            let (glyph, fg, bg) = match glyphs {
+                Glyphs::HalfBlock => {
+                    let top = sub[0].unwrap_or(0);
+                    let bottom = sub[1].unwrap_or(0);
+                    ('▀', top, bottom)
+                }
+                Glyphs::Braille => {
+                    let mut counts = [0usize; 4];
+                    for i in sub.iter().flatten() {
+                        counts[*i as usize] += 1;
+                    }
+                    let bg = (0..4).max_by_key(|&i| (counts[i], 4 - i)).unwrap();
+                    let fg = (0..4)
+                        .filter(|&i| i != bg)
+                        .max_by_key(|&i| (counts[i], 4 - i))
+                        .unwrap();
+
+                    // Braille dot numbering, indexed by sub-pixel position
+                    const DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+                    let mut bits = 0;
+                    for (i, s) in sub.iter().enumerate() {
+                        if matches!(s, Some(s) if *s as usize != bg) {
+                            bits |= DOTS[i];
+                        }
+                    }
+                    let glyph = char::from_u32(0x2800 + bits).unwrap();
+                    (glyph, fg as u8, bg as u8)
+                }
+            };
+            if prev != Some((fg, bg)) {
+                colors.push(&mut line, palette[fg as usize], false);
+                colors.push(&mut line, palette[bg as usize], true);
+                prev = Some((fg, bg));
+            }
+            line.push(glyph);
+        }
+        line.push_str("\x1b[0m");
+        lines.push(line);
+    }
+    lines
+}
+
+/// Converts a terminal key event into controller keys
+///
+/// Returns the modifier keys that are held and the key itself (if any).
+fn map_key(k: &KeyEvent) -> (Vec<Key>, Option<Key>) {
+    let mut mods = vec![];
+    if k.modifiers.contains(KeyModifiers::SHIFT) {
+        mods.push(Key::Shift);
+    }
+    if k.modifiers.contains(KeyModifiers::CONTROL) {
+        mods.push(Key::Ctrl);
+    }
+    if k.modifiers.contains(KeyModifiers::ALT) {
+        mods.push(Key::Alt);
+    }
+    let key = match k.code {
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        KeyCode::Left => Some(Key::Left),
+        KeyCode::Right => Some(Key::Right),
+        KeyCode::Home => Some(Key::Home),
+        KeyCode::End => Some(Key::End),
+        KeyCode::Enter => Some(Key::Char(b'\r')),
+        KeyCode::Tab => Some(Key::Char(b'\t')),
+        KeyCode::Backspace => Some(Key::Char(0x08)),
+        KeyCode::Delete => Some(Key::Char(0x7f)),
+        KeyCode::Esc => Some(Key::Char(0x1b)),
+        KeyCode::Char(c) if c.is_ascii() => Some(Key::Char(c as u8)),
+        _ => None,
+    };
+    (mods, key)
+}
+
+/// Restores the terminal state when dropped, then prints the console output
+/// held back while the screen was drawn
+struct TerminalGuard {
+    enhanced: bool,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl TerminalGuard {
+    fn new() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = std::io::stdout();
+        queue!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            event::EnableMouseCapture,
+            terminal::Clear(terminal::ClearType::All),
+        )?;
+        // Key release events are only available with the keyboard enhancement
+        // protocol; otherwise, we synthesize them after a frame.
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            queue!(
+                stdout,
+                event::PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                        | KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                )
+            )?;
+        }
+        stdout.flush()?;
+        Ok(Self {
+            enhanced,
+            stdout: vec![],
+            stderr: vec![],
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        if self.enhanced {
+            let _ = queue!(stdout, event::PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(
+            stdout,
+            event::DisableMouseCapture,
+            cursor::Show,
+            terminal::LeaveAlternateScreen,
+        );
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+
+        let _ = stdout.write_all(&self.stdout);
+        let _ = stdout.flush();
+        let _ = std::io::stderr().write_all(&self.stderr);
+    }
+}
+
+/// Options for the terminal renderer
+pub struct TermOptions {
+    /// Characters used to draw pixels
+    pub glyphs: Glyphs,
+    /// Color escapes used for output
+    pub colors: ColorMode,
+}
+
+/// Runs the ROM with its screen drawn into the terminal
+///
+/// Returns when the ROM exits (see [`Runner::exit`]), the user quits with
+/// Ctrl+C, or the timeout is reached.  The ROM's console output would be
+/// drawn over the screen, so it's held back and printed once the terminal is
+/// restored.
+pub fn run(
+    runner: &mut Runner,
+    opts: TermOptions,
+    timeout_reached: &Arc<AtomicBool>,
+) -> Result<()> {
+    let mut guard = TerminalGuard::new()?;
+    let mut stdout = std::io::stdout();
+    let mut pixels = vec![];
+    let mut prev_lines: Vec<String> = vec![];
+    let mut prev_layout = None;
+    let mut mouse = MouseState::default();
+    let mut held: Vec<Key> = vec![];
+    let start = Instant::now();
+
+    while runner.is_running() {
+        if timeout_reached.load(Ordering::Relaxed) {
+            info!("Timeout reached, exiting");
+            break;
+        }
+        let next_frame = start + runner.next_frame();
+        let Runner { vm, dev, .. } = &mut *runner;
+
+        let term = terminal::size()?;
+        let layout = opts.glyphs.layout(dev.screen.size(), term);
+        if prev_layout != Some(layout) {
+            queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+            prev_lines.clear();
+            prev_layout = Some(layout);
+        }
+
+        // Handle input until it's time for the next frame
+        let mut quit = false;
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            match event::read()? {
+                Event::Key(k) => {
+                    if k.code == KeyCode::Char('c') && k.modifiers == KeyModifiers::CONTROL {
+                        quit = true;
+                        break;
+                    }
+                    let (mods, key) = map_key(&k);
+                    let keys = mods.into_iter().chain(key);
+                    match k.kind {
+                        KeyEventKind::Press | KeyEventKind::Repeat => {
+                            let repeat = k.kind == KeyEventKind::Repeat;
+                            for key in keys {
+                                dev.pressed(vm, key, repeat);
+                                if !guard.enhanced && !held.contains(&key) {
+                                    held.push(key);
+                                }
+                            }
+                        }
+                        KeyEventKind::Release => {
+                            for key in keys {
+                                dev.released(vm, key);
+                            }
+                        }
+                    }
+                }
+                Event::Mouse(MouseEvent {
+                    kind, column, row, ..
+                }) => {
+                    let (cw, ch) = opts.glyphs.cell_size();
+                    let s = layout.scale;
+                    mouse.pos = (
+                        (column as usize * cw * s) as f32,
+                        (row as usize * ch * s) as f32,
+                    );
+                    let bit = |b: MouseButton| match b {
+                        MouseButton::Left => 1,
+                        MouseButton::Middle => 2,
+                        MouseButton::Right => 4,
+                    };
+                    mouse.scroll = (0.0, 0.0);
+                    match kind {
+                        MouseEventKind::Down(b) => mouse.buttons |= bit(b),
+                        MouseEventKind::Up(b) => mouse.buttons &= !bit(b),
+                        // Scroll values are divided by 5 in the mouse device,
+                        // so this gives a single tick per terminal event.
+                        MouseEventKind::ScrollUp => mouse.scroll.1 = -5.5,
+                        MouseEventKind::ScrollDown => mouse.scroll.1 = 5.5,
+                        MouseEventKind::ScrollLeft => mouse.scroll.0 = -5.5,
+                        MouseEventKind::ScrollRight => mouse.scroll.0 = 5.5,
+                        MouseEventKind::Drag(..) | MouseEventKind::Moved => (),
+                    }
+                    dev.mouse(
+                        vm,
+                        MouseState {
+                            pos: mouse.pos,
+                            scroll: mouse.scroll,
+                            buttons: mouse.buttons,
+                        },
+                    );
+                }
+                Event::Resize(..) => prev_layout = None,
+                _ => (),
+            }
+        }
+        if quit {
+            break;
+        }
+
+        runner.advance(start.elapsed());
+        for key in held.drain(..) {
+            runner.dev.released(&mut runner.vm, key);
+        }
+
+        let out = runner.output();
+        guard.stdout.extend_from_slice(&out.stdout);
+        guard.stderr.extend_from_slice(&out.stderr);
+        if out.exit.is_some() {
+            break;
+        }
+        let (size, palette) = (out.size, out.palette);
+
+        let Runner { vm, dev, .. } = &mut *runner;
+        dev.screen.frame_as(vm, FrameFormat::Indexed, &mut pixels);
+        let lines = render(&pixels, size, &palette, layout, opts.glyphs, opts.colors);
+        for (i, line) in lines.iter().enumerate() {
+            if prev_lines.get(i) != Some(line) {
+                queue!(stdout, cursor::MoveTo(0, i as u16))?;
+                stdout.write_all(line.as_bytes())?;
+            }
+        }
+        stdout.flush()?;
+        prev_lines = lines;
+    }
+    drop(guard);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_fits_terminal() {
+        let l = Glyphs::HalfBlock.layout((512, 320), (80, 24));
+        assert_eq!(l.scale, 7);
+        assert!(l.cells.0 <= 80 && l.cells.1 <= 24);
+
+        let l = Glyphs::Braille.layout((512, 320), (256, 80));
+        assert_eq!(l.scale, 1);
+        assert_eq!(l.cells, (256, 80));
+    }
+
+    #[test]
+    fn ansi256_colors() {
+        assert_eq!(ansi256(0, 0, 0), 16);
+        assert_eq!(ansi256(255, 255, 255), 231);
+        assert_eq!(ansi256(255, 0, 0), 196);
+        assert_eq!(ansi256(128, 128, 128), 244);
+    }
+
+    #[test]
+    fn render_braille() {
+        // 2×4 checkerboard of colors 0 and 1, forming a single cell
+        let pixels = [0, 1, 1, 0, 0, 1, 1, 0];
+        let palette = [0xFF000000, 0xFFFFFFFF, 0, 0];
+        let layout = Layout {
+            scale: 1,
+            cells: (1, 1),
+        };
+        let lines = render(
+            &pixels,
+            (2, 4),
+            &palette,
+            layout,
+            Glyphs::Braille,
+            ColorMode::TrueColor,
+        );
+        assert_eq!(lines.len(), 1);
+        // Tie between colors 0 and 1 picks 0 as the background, so the dots
+        // are drawn at the positions of color 1 (dots 4, 2, 6, 7).
+        assert!(lines[0].contains('\u{286a}'));
+    }
+}
@@ -0,0 +1,50 @@
+//! Helpers shared by the `cardinal-cli` integration tests
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Assembles the fixture `tests/<name>.tal`
+pub fn rom(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/{name}.tal", env!("CARGO_MANIFEST_DIR"));
+    let source = std::fs::read_to_string(&path).expect("failed to read fixture");
+    let mut asm = uxn_tal::Assembler::new();
+    asm.quiet = true;
+    asm.assemble(&source, Some(path))
+        .unwrap_or_else(|e| panic!("failed to assemble {name}.tal: {e}"))
+}
+
+/// Runs `cardinal-cli` with the given ROMs (piping all but the first) and
+/// stdin, returning the exit code, the last line of stdout (controller setup
+/// may log to stdout), and the elapsed time
+pub fn run(roms: &[&[u8]], stdin: &[u8]) -> (Option<i32>, Vec<u8>, Duration) {
+    let tmp = tempfile::tempdir().expect("failed to create tempdir");
+    let paths: Vec<_> = roms
+        .iter()
+        .enumerate()
+        .map(|(i, rom)| {
+            let path = tmp.path().join(format!("{i}.rom"));
+            std::fs::write(&path, rom).unwrap();
+            path
+        })
+        .collect();
+
+    let exe = assert_cmd::cargo::cargo_bin!("cardinal-cli");
+    let mut cmd = Command::new(exe);
+    cmd.arg(&paths[0]);
+    for p in &paths[1..] {
+        cmd.arg("--pipe").arg(p);
+    }
+    let start = Instant::now();
+    let mut child = cmd
+        .args(["--timeout", "10"])
+        .env("UXN_LOG", "off")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run cardinal-cli");
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let last = output.stdout.rsplit(|&c| c == b'\n').next().unwrap();
+    (output.status.code(), last.to_vec(), start.elapsed())
+}
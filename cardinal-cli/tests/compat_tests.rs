@@ -0,0 +1,57 @@
+//! Tests for the compatibility report (`cardinal-cli compat`)
+
+use std::process::Command;
+
+/// Runs the compatibility report, returning its text
+fn report() -> String {
+    let tmp = tempfile::tempdir().expect("failed to create tempdir");
+    let out = tmp.path().join("report.txt");
+    let exe = assert_cmd::cargo::cargo_bin!("cardinal-cli");
+    let status = Command::new(exe)
+        .arg("compat")
+        .arg("--output")
+        .arg(&out)
+        .status()
+        .expect("failed to run cardinal-cli");
+    let report = std::fs::read_to_string(&out).expect("failed to read report");
+    let failed = report.contains("\nFailures\n");
+    assert_eq!(status.code(), Some(i32::from(failed)), "{report}");
+    report
+}
+
+#[test]
+fn compat() {
+    let report = report();
+    let row = |name: &str| {
+        report
+            .lines()
+            .find(|l| l.split_whitespace().next() == Some(name))
+            .unwrap_or_else(|| panic!("missing row {name} in\n{report}"))
+            .to_owned()
+    };
+
+    // Every opcode has a row, with a cell for each mode
+    for name in ["INC", "JCN", "JSR", "STH", "LDA", "DEO", "SFT"] {
+        let row = row(name);
+        let cells: Vec<_> = row.split_whitespace().skip(1).collect();
+        assert_eq!(cells, ["ok"; 8], "{name}");
+    }
+    assert_eq!(
+        row("imm"),
+        "imm      BRK ok JCI ok JMI ok JSI ok LIT ok LIT2 ok LITr ok LIT2r ok"
+    );
+
+    for part in ["Bootstrap 1", "Bootstrap 7", "Opcodes", "Stack-wrap"] {
+        assert!(report.contains(&format!("  {part:16}ok\n")), "{part}");
+    }
+    // Only opctest is upstream; the other suites are this repository's own
+    assert!(report.contains("\nopctest (upstream)\n"));
+    assert!(report.contains("\nOpcode modes (local model self-checks, "));
+    assert!(report.contains("\nDevices (local checks, devtest)\n"));
+    for dev in ["Console", "Screen", "File"] {
+        assert!(!row(dev).contains("FAIL"), "{dev}");
+    }
+    // Vectors the model can't check are reported, not counted as passes
+    let summary = report.lines().find(|l| l.starts_with("Summary: ")).unwrap();
+    assert!(summary.contains(" vectors skipped)"), "{summary}");
+}
@@ -0,0 +1,33 @@
+//! Tests for the console protocol: end-of-input and exit codes
+
+use std::time::Duration;
+
+mod common;
+use common::{rom, run};
+
+#[test]
+fn eof() {
+    // The ROM sees end-of-input, and its exit code is returned
+    let (code, out, _) = run(&[&rom("count")], b"abc");
+    assert_eq!((code, out), (Some(3), b"3".to_vec()));
+
+    let (code, out, _) = run(&[&rom("count")], b"");
+    assert_eq!((code, out), (Some(0), b"0".to_vec()));
+}
+
+#[test]
+fn eof_through_pipe() {
+    // End-of-input reaches the last stage after the first stage's output,
+    // including the zero character which it echoes on its own end-of-input
+    let (code, out, _) = run(&[&rom("cat"), &rom("count")], b"abcd");
+    assert_eq!((code, out), (Some(5), b"5".to_vec()));
+}
+
+#[test]
+fn idle_after_eof() {
+    // A ROM which never exits finishes once input ends and it goes idle,
+    // well before the timeout
+    let (code, out, t) = run(&[&rom("cat")], b"hi\n");
+    assert_eq!((code, out), (Some(0), b"\0".to_vec()));
+    assert!(t < Duration::from_secs(5), "took {t:?}");
+}
@@ -0,0 +1,19 @@
+//! Tests for in-process ROM pipelines (`--pipe`)
+
+mod common;
+use common::{rom, run};
+
+#[test]
+fn pipeline() {
+    let (echo, shift) = (rom("echo"), rom("shift"));
+
+    // Only the last stage's stdout is printed, and its exit code is returned
+    let (code, out, _) = run(&[&echo, &shift], b"abq");
+    assert_eq!((code, out), (Some(2), b"bcr".to_vec()));
+
+    // The second stage exits on `q` after printing `r`, so the third never
+    // sees a `q`; it is still running when input ends, and shifts the zero
+    // character sent as end-of-input
+    let (code, out, _) = run(&[&echo, &shift, &shift], b"abq");
+    assert_eq!((code, out), (Some(0), b"cds\x01".to_vec()));
+}
@@ -0,0 +1,114 @@
+//! Tests for the manifest-driven ROM test runner (`cardinal-cli test`)
+
+use std::process::Command;
+
+/// Counts characters until the end of stdin, then prints the count and exits
+/// with it as the exit code (see `console_tests.rs`)
+const COUNT: [u8; 44] = [
+    0xa0, 0x01, 0x07, 0x80, 0x10, 0x37, 0x00, // |0100
+    0x80, 0x17, 0x16, 0x80, 0x04, 0x08, 0x20, 0x00, 0x0a, // type check
+    0xa0, 0x01, 0x2c, 0x14, 0x01, 0xa0, 0x01, 0x2c, 0x15, 0x00, // count
+    0xa0, 0x01, 0x2c, 0x14, 0x06, 0x80, 0x30, 0x18, 0x80, 0x18, 0x17, // print
+    0x80, 0x80, 0x1d, 0x80, 0x0f, 0x17, 0x00, // exit
+];
+
+const MANIFEST: &str = r#"
+timeout = 5.0
+
+[[test]]
+name = "hello"
+rom = "helloworld.tal"
+stdout = "Hello World!"
+
+[[test]]
+name = "count"
+rom = "count.rom"
+stdin_file = "in.txt"
+stdout = "4"
+exit = 4
+
+[[test]]
+name = "wrong count"
+rom = "count.rom"
+stdin = "ab"
+stdout = "3"
+
+[[test]]
+name = "budget"
+rom = "count.rom"
+stdin = "abcdef"
+cycles = 20
+exit = 6
+"#;
+
+/// Runs the test runner on [`MANIFEST`], returning the exit code and results
+///
+/// Results are written to a file with `--output`, or else read from stdout.
+fn run(format: &str, output: bool) -> (Option<i32>, String) {
+    let tmp = tempfile::tempdir().expect("failed to create tempdir");
+    let dir = tmp.path();
+    std::fs::copy("tests/helloworld.tal", dir.join("helloworld.tal")).unwrap();
+    std::fs::write(dir.join("count.rom"), COUNT).unwrap();
+    std::fs::write(dir.join("in.txt"), "abcd").unwrap();
+    std::fs::write(dir.join("suite.toml"), MANIFEST).unwrap();
+
+    let exe = assert_cmd::cargo::cargo_bin!("cardinal-cli");
+    let out = dir.join("results");
+    let mut cmd = Command::new(exe);
+    cmd.arg("test")
+        .arg(dir.join("suite.toml"))
+        .args(["--format", format]);
+    if output {
+        cmd.arg("--output").arg(&out);
+    }
+    let r = cmd.output().expect("failed to run cardinal-cli");
+    let results = if output {
+        std::fs::read_to_string(out).unwrap()
+    } else {
+        String::from_utf8(r.stdout).unwrap()
+    };
+    (r.status.code(), results)
+}
+
+#[test]
+fn tap() {
+    let (code, out) = run("tap", true);
+    assert_eq!(code, Some(1));
+    let lines: Vec<_> = out.lines().filter(|l| !l.starts_with(' ')).collect();
+    assert_eq!(
+        lines,
+        [
+            "TAP version 13",
+            "1..4",
+            "ok 1 - hello",
+            "ok 2 - count",
+            "not ok 3 - wrong count",
+            "not ok 4 - budget",
+        ]
+    );
+    assert!(out.contains("    stdout mismatch\n      expected: \"3\"\n        actual: \"2\""));
+    assert!(out.contains("    instruction budget of 20 exhausted"));
+}
+
+#[test]
+fn junit() {
+    let (code, out) = run("junit", true);
+    assert_eq!(code, Some(1));
+    assert!(out.starts_with("<?xml"));
+    assert!(out.contains(r#"<testsuite name="suite" tests="4" failures="2""#));
+    assert!(out.contains(r#"<testcase name="hello" classname="suite""#));
+    assert!(out.contains(r#"<failure message="stdout mismatch">"#));
+    assert!(out.contains(r#"<failure message="instruction budget of 20 exhausted">"#));
+}
+
+#[test]
+fn stdout_only_has_results() {
+    let (code, out) = run("tap", false);
+    assert_eq!(code, Some(1));
+    assert!(out.starts_with("TAP version 13\n"), "{out}");
+    assert!(out.lines().all(|l| l.starts_with("TAP")
+        || l.starts_with("1..")
+        || l.starts_with("ok")
+        || l.starts_with("not ok")
+        || l.starts_with(' ')));
+}
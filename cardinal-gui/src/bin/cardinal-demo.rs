@@ -495,7 +495,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create UxnModule
     let rom_path_arc = Arc::new(Mutex::new(rom_path.clone()));
-    let mut uxn_mod = UxnModule::new(rom_path.as_deref())?;
+    let uxn_mod = UxnModule::new(rom_path.as_deref())?;
     // --- ROM File Watcher ---
     let (reload_tx, reload_rx) = std::sync::mpsc::channel();
     if let Some(ref rom_path) = rom_path {
@@ -545,9 +545,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set up event channel for UxnApp
     let (_event_tx, event_rx) = mpsc::channel();
 
-    let vm = Arc::clone(&uxn_mod.uxn);
-    let mut vm = vm.lock().unwrap();
-    let mut dev = uxn_mod.varvara.take().expect("Varvara device missing");
+    // The app runs this machine until the process exits
+    let varvara::Runner {
+        vm: mut app_vm,
+        mut dev,
+        ..
+    } = uxn_mod.machine.leak();
     let _audio = audio_setup(dev.audio_streams());
 
     // --- Load .sym file if it exists next to the ROM ---
@@ -559,10 +562,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         if sym_path.exists() {
             match std::fs::read_to_string(&sym_path) {
                 Ok(_sym_contents) => {
-                    if let Some(ref mut varvara) = uxn_mod.varvara {
-                        let _ = varvara.load_symbols_into_self(sym_path.to_str().unwrap());
-                        println!("[DEBUG] Loaded symbols from {sym_path:?}");
-                    }
+                    let _ = dev.load_symbols_into_self(sym_path.to_str().unwrap());
+                    println!("[DEBUG] Loaded symbols from {sym_path:?}");
                 }
                 Err(e) => {
                     eprintln!("[DEBUG] Failed to read .sym file: {e}");
@@ -641,9 +642,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("Console stderr: {byte}");
     });
 
-    dev.audio(&mut vm);
-    let size = dev.output(&vm).size;
-    drop(vm); // Release lock
+    dev.audio(&mut app_vm);
+    let size = dev.output(&app_vm).size;
 
     let mut viewport = egui::ViewportBuilder::default()
         .with_inner_size([
@@ -683,14 +683,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         options,
         Box::new(move |cc| {
             let ctx = &cc.egui_ctx;
-            let vm = Arc::clone(&uxn_mod.uxn);
-            let mut vm = vm.lock().unwrap();
-            static mut RAM: [u8; 65536] = [0; 65536];
-            #[allow(static_mut_refs)]
-            let ram: &'static mut [u8; 65536] = unsafe { &mut RAM };
-            let new_uxn = uxn::Uxn::new(ram, uxn::Backend::Interpreter);
             let mut app = UxnApp::new_with_mode(
-                std::mem::replace(&mut *vm, new_uxn),
+                app_vm,
                 dev,
                 size,
                 scale,
@@ -92,6 +92,14 @@ pub struct CardinalViewportsApp<'a> {
     #[allow(dead_code)]
     last_usb_pedal: Option<u8>,
     pending_focus_panel: Option<usize>,
+    pipe_mode: PipeMode,
+    /// Console pipes and their destination panels, built for `pipes_mode`
+    /// and `pipes_panels` panels
+    pipes: Vec<(varvara::PipeReader, usize)>,
+    pipes_mode: PipeMode,
+    pipes_panels: usize,
+    /// Bytes the ring's closing link may still carry before it goes quiet
+    ring_budget: usize,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -102,6 +110,45 @@ enum WrapMode {
     AllMonitorsGeometric,
 }
 
+/// How panel consoles are connected to each other
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PipeMode {
+    /// Panels are independent
+    None,
+    /// Each panel's stdout feeds the next panel's stdin
+    Chain,
+    /// Like `Chain`, with the last panel feeding the first
+    Ring,
+}
+
+/// Bytes the closing link of a ring carries before it drops the rest
+///
+/// ROMs which echo their input would otherwise pass the same bytes around the
+/// ring forever.  The budget is restored once a frame passes without traffic
+/// on the closing link.
+const RING_BURST: usize = 4096;
+
+impl PipeMode {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(PipeMode::None),
+            "chain" => Some(PipeMode::Chain),
+            "ring" => Some(PipeMode::Ring),
+            _ => None,
+        }
+    }
+
+    /// Returns `(source, destination)` panel indices for `n` panels
+    fn links(self, n: usize) -> Vec<(usize, usize)> {
+        match self {
+            PipeMode::None => vec![],
+            PipeMode::Chain => (1..n).map(|i| (i - 1, i)).collect(),
+            PipeMode::Ring if n > 1 => (0..n).map(|i| (i, (i + 1) % n)).collect(),
+            PipeMode::Ring => vec![],
+        }
+    }
+}
+
 impl<'a> Default for CardinalViewportsApp<'a> {
     fn default() -> Self {
         let grid_cols = 2;
@@ -127,6 +174,11 @@ impl<'a> Default for CardinalViewportsApp<'a> {
             #[cfg(feature = "uses_usb")]
             last_usb_pedal: None,
             pending_focus_panel: None,
+            pipe_mode: PipeMode::None,
+            pipes: Vec::new(),
+            pipes_mode: PipeMode::None,
+            pipes_panels: 0,
+            ring_budget: RING_BURST,
         }
     }
 }
@@ -151,6 +203,38 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
                 }
             }
         }
+        // Reconnect panel consoles if the pipe mode or panel count changed.
+        // Dropping the old pipes unregisters their listeners.
+        if self.pipes_mode != self.pipe_mode || self.pipes_panels != self.uxn_panels.len() {
+            self.pipes.clear();
+            for (src, dst) in self.pipe_mode.links(self.uxn_panels.len()) {
+                let rx = self.uxn_panels[src]
+                    .borrow_mut()
+                    .stage
+                    .runner
+                    .dev
+                    .pipe_stdout();
+                self.pipes.push((rx, dst));
+            }
+            self.pipes_mode = self.pipe_mode;
+            self.pipes_panels = self.uxn_panels.len();
+            self.ring_budget = RING_BURST;
+        }
+        // In a ring, the last link feeds panel 0 and closes the loop
+        let closing = self.pipes.len().checked_sub(1);
+        let closing = closing.filter(|_| self.pipes_mode == PipeMode::Ring);
+        for (i, (rx, dst)) in self.pipes.iter_mut().enumerate() {
+            let runner = &mut self.uxn_panels[*dst].borrow_mut().stage.runner;
+            if closing == Some(i) {
+                let n = rx.pump_at_most(runner, self.ring_budget);
+                self.ring_budget -= n;
+                if n + rx.discard() == 0 {
+                    self.ring_budget = RING_BURST;
+                }
+            } else {
+                rx.pump(runner);
+            }
+        }
         // --- Ctrl+Q to exit the app ---
         if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Q)) {
             std::process::exit(0); // not nice to do this, sorry
@@ -196,6 +280,14 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
                 ui.horizontal(|ui| {
                     ui.checkbox(&mut self.all_panels_receive_input, "All panels receive keyboard input");
                     ui.checkbox(&mut self.all_panels_receive_mouse, "All panels receive mouse input");
+                    ui.separator();
+                    egui::ComboBox::from_label("Console Pipes")
+                        .selected_text(format!("{:?}", self.pipe_mode))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.pipe_mode, PipeMode::None, "None");
+                            ui.selectable_value(&mut self.pipe_mode, PipeMode::Chain, "Chain");
+                            ui.selectable_value(&mut self.pipe_mode, PipeMode::Ring, "Ring");
+                        });
                 });
             });
         });
@@ -486,6 +578,7 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
                     let mut panel = panel_rc.borrow_mut();
                     if let Some(varvara_controller) = panel
                         .stage
+                        .runner
                         .dev
                         .controller
                         .as_any()
@@ -523,10 +616,10 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
                     if let Some(panel_rc) = self.uxn_panels.get(idx) {
                         let panel = panel_rc.borrow();
                         use cardinal_gui::cardinal_orcas_symbols::cardinal_orcas_symbols::get_slice;
-                        let bang = get_slice(panel.stage.vm.ram(), "*");
-                        let x = get_slice(panel.stage.vm.ram(), "Mouse/x");
-                        let posx = get_slice(panel.stage.vm.ram(), "cursor/x");
-                        let grid = get_slice(panel.stage.vm.ram(), "grid/buf");
+                        let bang = get_slice(panel.stage.runner.vm.ram(), "*");
+                        let x = get_slice(panel.stage.runner.vm.ram(), "Mouse/x");
+                        let posx = get_slice(panel.stage.runner.vm.ram(), "cursor/x");
+                        let grid = get_slice(panel.stage.runner.vm.ram(), "grid/buf");
                         format!(
                             "{:?} bang  {:?} x {:?} posx {:?} grid {:?}",
                             panel.last_response_id(),
@@ -662,8 +755,12 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
                                     {
                                         let mut panel = panel_rc.borrow_mut();
                                         let stage = &mut panel.stage;
-                                        stage.dev.mouse.set_active();
-                                        stage.dev.mouse.update(&mut stage.vm, mouse_state);
+                                        stage.runner.dev.mouse.set_active();
+                                        stage
+                                            .runner
+                                            .dev
+                                            .mouse
+                                            .update(&mut stage.runner.vm, mouse_state);
                                     }
                                 }
                                 let mut no_mouse = filtered_input.clone();
@@ -697,7 +794,7 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
                                 let mut panel = panel_rc.borrow_mut();
                                 panel.handle_input(&filtered_input, rect);
                                 let stage = &mut panel.stage;
-                                stage.dev.redraw(&mut stage.vm);
+                                stage.runner.dev.redraw(&mut stage.runner.vm);
                                 stage.update_texture(ui.ctx());
                             }
                         }
@@ -719,12 +816,28 @@ impl<'a> eframe::App for CardinalViewportsApp<'a> {
     }
 }
 
-// Embed the ROM and .sym file as byte arrays
-const CARDINAL_ORCAS_ROM: &[u8] = include_bytes!("cardinal-orcas.rom");
-const CARDINAL_ORCAS_SYM: &[u8] = include_bytes!("cardinal-orcas.rom.sym");
+// Assemble the ROM and .sym file from roms/cardinal-orcas while building
+uxn_tal_macros::include_tal! {
+    #[allow(dead_code)]
+    mod orcas = "../roms/cardinal-orcas/cardinal-orcas.tal";
+}
+const CARDINAL_ORCAS_ROM: &[u8] = orcas::ROM;
+const CARDINAL_ORCAS_SYM: &[u8] = orcas::SYM;
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
+    // `--pipe none|chain|ring` connects panel consoles at startup
+    let mut pipe_mode = PipeMode::None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--pipe" {
+            let v = args.next().unwrap_or_default();
+            pipe_mode = PipeMode::parse(&v).unwrap_or_else(|| {
+                eprintln!("invalid --pipe mode {v:?} (expected none, chain or ring)");
+                std::process::exit(1);
+            });
+        }
+    }
     // Use grid size from app default
     let app_default = CardinalViewportsApp::default();
     let grid_cols = app_default.grid_cols;
@@ -784,7 +897,12 @@ fn main() {
     let mut app = eframe::create_native(
         "cardinal-orcas",
         options,
-        Box::new(|_cc| Ok(Box::new(CardinalViewportsApp::default()))),
+        Box::new(move |_cc| {
+            Ok(Box::new(CardinalViewportsApp {
+                pipe_mode,
+                ..CardinalViewportsApp::default()
+            }))
+        }),
         &event_loop,
     );
     event_loop.run_app(&mut app).expect("eframe app failed");
@@ -2,7 +2,7 @@ use anyhow::Context;
 use std::{io::Read, sync::mpsc};
 
 use uxn::{Backend, Uxn, UxnRam};
-use varvara::Varvara;
+use varvara::{Runner, Varvara};
 
 use anyhow::Result;
 use eframe::egui;
@@ -123,6 +123,10 @@ struct Args {
     #[clap(long)]
     mouse: Option<String>,
 
+    /// Accept new builds of the ROM from `uxntal --watch` on a local socket
+    #[clap(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = varvara::reload::DEFAULT_ADDR)]
+    listen: Option<String>,
+
     /// Arguments to pass into the VM
     #[arg(trailing_var_arg = true)]
     args: Vec<String>,
@@ -187,7 +191,7 @@ pub fn run() -> Result<()> {
     f.read_to_end(&mut rom).context("failed to read file")?;
 
     let ram = UxnRam::new();
-    let mut vm = Uxn::new(
+    let vm = Uxn::new(
         ram.leak(),
         if args.native {
             #[cfg(not(target_arch = "aarch64"))]
@@ -199,21 +203,20 @@ pub fn run() -> Result<()> {
             Backend::Interpreter
         },
     );
-    let mut dev = Varvara::default();
-
-    let extra = vm.reset(&rom);
-    dev.reset(extra);
-    dev.init_args(&mut vm, &args.args);
-
-    let _audio = audio_setup(dev.audio_streams());
+    let mut runner = Runner::new(vm, Varvara::default());
+    let _audio = audio_setup(runner.dev.audio_streams());
 
     // Run the reset vector
     let start = std::time::Instant::now();
-    vm.run(&mut dev, 0x100);
+    runner.load(&rom, &args.args);
     info!("startup complete in {:?}", start.elapsed());
 
-    dev.output(&vm).check()?;
-    dev.send_args(&mut vm, &args.args).check()?;
+    let out = runner.output();
+    out.print()?;
+    if let Some(e) = out.exit {
+        info!("requested exit ({e})");
+        std::process::exit(e);
+    }
 
     // // Hide the console after ROM is loaded, unless --debug is present
     // #[cfg(windows)]
@@ -223,7 +226,7 @@ pub fn run() -> Result<()> {
     //     }
     // }
 
-    let size @ (width, height) = dev.output(&vm).size;
+    let size @ (width, height) = runner.output().size;
     let scale = args.scale.unwrap_or(if width < 320 { 2.0 } else { 1.0 });
     info!("creating window with size ({width}, {height}) and scale {scale}");
     let rom_title = args
@@ -280,6 +283,14 @@ pub fn run() -> Result<()> {
     };
 
     let (tx, rx) = mpsc::channel();
+    if let Some(addr) = &args.listen {
+        let tx = tx.clone();
+        let addr = varvara::reload::spawn_listener(addr.as_str(), move |r| {
+            tx.send(crate::stage::Event::Reload(r))
+        })
+        .with_context(|| format!("could not listen on {addr}"))?;
+        println!("[cardinal-gui] Listening for reloads on {addr}");
+    }
     varvara::spawn_console_worker(move |c| tx.send(crate::stage::Event::Console(c)));
     let color_transform = args.color_transform.clone();
     let color_params = args.color_params.clone();
@@ -293,6 +304,8 @@ pub fn run() -> Result<()> {
     };
     let should_exit = Arc::new(AtomicBool::new(false));
     let should_exit_clone = should_exit.clone();
+    let exit_code = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let exit_code_clone = exit_code.clone();
     let timeout_opt = args.timeout;
     let run_result = eframe::run_native(
         "Varvara",
@@ -308,9 +321,8 @@ pub fn run() -> Result<()> {
                     ctx.request_repaint();
                 });
             }
-            Ok(Box::new(crate::stage::Stage::new(
-                vm,
-                dev,
+            let mut stage = crate::stage::Stage::with_runner(
+                runner,
                 rx,
                 crate::stage::StageConfig {
                     size,
@@ -333,10 +345,16 @@ pub fn run() -> Result<()> {
                     mouse_resize,
                 },
                 Some(should_exit_clone.clone()),
-            )))
+            );
+            stage.exit_code = exit_code_clone;
+            Ok(Box::new(stage))
         }),
     );
     run_result.map_err(|e| anyhow::anyhow!("got egui error: {e:?}"))?;
+    // Propagate the ROM's exit code once the window has closed
+    if let Some(e) = *exit_code.lock().unwrap() {
+        std::process::exit(e);
+    }
     // After eframe exits, check if we should exit due to timeout
     if should_exit.load(std::sync::atomic::Ordering::Relaxed) {
         std::process::exit(0);
@@ -6,8 +6,9 @@ pub enum MouseResizeMode {
 
 use log::{error, info};
 use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use uxn::Uxn;
-use varvara::{Key, Varvara};
+use varvara::{Key, Runner, Varvara};
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MouseDragMode {
     None,
@@ -42,6 +43,8 @@ pub enum Event {
     LoadRom(Vec<u8>),
     SetMuted(bool),
     Console(u8),
+    /// A new build from `uxntal --watch`
+    Reload(varvara::reload::Reload),
 }
 
 // Color transform trait and helpers
@@ -99,11 +102,11 @@ pub fn get_transform(name: &str, params: &[f32]) -> Box<dyn ColorTransform> {
 pub struct Stage<'a> {
     // zebra_offset moved to EffectsConfig
     pub should_exit: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
-    pub vm: Uxn<'a>,
-    pub dev: Varvara,
+    pub runner: Runner<'a>,
+    /// Exit code requested by the ROM, shared with the host
+    pub exit_code: Arc<Mutex<Option<i32>>>,
     pub scale: f32,
     pub size: (u16, u16),
-    pub next_frame: f64,
     pub scroll: (f32, f32),
     pub cursor_pos: Option<(f32, f32)>,
     pub texture: Option<egui::TextureHandle>,
@@ -128,6 +131,16 @@ impl<'a> Stage<'a> {
         event_rx: mpsc::Receiver<Event>,
         config: StageConfig,
         should_exit: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+    ) -> Self {
+        Self::with_runner(Runner::new(vm, dev), event_rx, config, should_exit)
+    }
+
+    /// Builds a stage around a runner which already has a ROM loaded
+    pub fn with_runner(
+        runner: Runner<'a>,
+        event_rx: mpsc::Receiver<Event>,
+        config: StageConfig,
+        should_exit: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
     ) -> Self {
         // let image = egui::ColorImage::new(
         //     [usize::from(config.size.0), usize::from(config.size.1)],
@@ -155,11 +168,10 @@ impl<'a> Stage<'a> {
         //     _ => None,
         // };
         Stage {
-            vm,
-            dev,
+            runner,
+            exit_code: Arc::new(Mutex::new(None)),
             scale: config.scale,
             size: config.size,
-            next_frame: 0.0,
             event_rx,
             resized: None,
             scroll: (0.0, 0.0),
@@ -183,20 +195,18 @@ impl<'a> Stage<'a> {
         self.resized = Some(f);
     }
     pub fn load_rom(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-        let _ = self.vm.reset(data);
-        self.dev.reset(data);
-        self.vm.run(&mut self.dev, 0x100);
-        let out = self.dev.output(&self.vm);
+        self.runner.load(data, &[]);
+        let out = self.runner.output();
         self.size = out.size;
         Ok(())
     }
     pub fn step(&mut self) {
-        // self.vm.run(&mut self.dev, 0x100);
-        self.dev.redraw(&mut self.vm);
+        // self.runner.vm.run(&mut self.runner.dev, 0x100);
+        self.runner.dev.redraw(&mut self.runner.vm);
     }
     pub fn update_texture(&mut self, ctx: &egui::Context) {
         // Prepare image and pixel coordinates for effect loop
-        let out = self.dev.output(&self.vm);
+        let out = self.runner.output();
         let mut image = egui::ColorImage::new(
             [out.size.0 as usize, out.size.1 as usize],
             vec![egui::Color32::BLACK; (out.size.0 as usize) * (out.size.1 as usize)],
@@ -426,41 +436,39 @@ impl<'a> Stage<'a> {
     pub fn handle_usb_input(&mut self) {
         #[cfg(all(feature = "uses_usb", not(target_arch = "wasm32")))]
         {
-            if let Some(controller_usb) = self.dev.controller_usb_mut() {
+            if let Some(controller_usb) = self.runner.dev.controller_usb_mut() {
                 let events = varvara::controller_usb::ControllerPollEvents::poll_usb_events(
                     controller_usb,
-                    &mut self.vm,
+                    &mut self.runner.vm,
                 );
                 for event in events {
-                    self.dev.process_event(&mut self.vm, event);
+                    self.runner.dev.process_event(&mut self.runner.vm, event);
                 }
             }
         }
         // let k = varvara::Key::Right;
         // let repeat = false;
-        // self.dev.pressed(&mut self.vm, k, repeat);
-        // self.dev.released(&mut self.vm, k);
+        // self.runner.dev.pressed(&mut self.runner.vm, k, repeat);
+        // self.runner.dev.released(&mut self.runner.vm, k);
         // let k = varvara::Key::Char(b'A');
-        //  self.dev
-        //      .pressed(&mut self.vm, varvara::Key::Char(b'a'), false);
-        // self.dev.char(&mut self.vm, b'a');
+        //  self.runner.dev
+        //      .pressed(&mut self.runner.vm, varvara::Key::Char(b'a'), false);
+        // self.runner.dev.char(&mut self.runner.vm, b'a');
     }
 
     /// Load symbols from a byte slice (for embedded .sym files)
     pub fn load_symbols(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
         let map = varvara::Varvara::parse_symbols_from_bytes(data)
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
-        self.dev.symbols = Some(map);
+        self.runner.dev.symbols = Some(map);
         Ok(())
     }
     /// Load a ROM from a file path and attempt to load a .sys symbol file if present
     pub fn load_rom_with_path<P: AsRef<std::path::Path>>(&mut self, path: P) {
         let path = path.as_ref();
         if let Ok(data) = std::fs::read(path) {
-            let _ = self.vm.reset(&data);
-            self.dev.reset(&data);
-            self.vm.run(&mut self.dev, 0x100);
-            let out = self.dev.output(&self.vm);
+            self.runner.load(&data, &[]);
+            let out = self.runner.output();
             self.size = out.size;
             // Look for a .sys file with the same file name as the ROM, e.g. orca.rom -> orca.rom.sys
             if let Some(file_name) = path.file_name() {
@@ -480,7 +488,7 @@ impl<'a> Stage<'a> {
                 let sys_path_str = sys_path.to_string_lossy().to_string();
                 println!("[DEBUG][Stage] Attempting to load symbols from {sys_path_str}");
                 if sys_path.exists() {
-                    let _ = self.dev.load_symbols_into_self(&sys_path_str);
+                    let _ = self.runner.dev.load_symbols_into_self(&sys_path_str);
                     println!("[DEBUG][Stage] Loaded symbols from {sys_path_str}");
                 }
             }
@@ -514,7 +522,7 @@ impl<'a> Stage<'a> {
                     scroll: (0.0, 0.0),
                     buttons,
                 };
-                self.dev.mouse(&mut self.vm, mouse_state);
+                self.runner.dev.mouse(&mut self.runner.vm, mouse_state);
 
                 // Window drag logic
                 // Drag logic is handled in update() via StartDrag
@@ -547,7 +555,7 @@ impl<'a> Stage<'a> {
                         scroll: (0.0, 0.0),
                         buttons,
                     };
-                    self.dev.mouse(&mut self.vm, mouse_state);
+                    self.runner.dev.mouse(&mut self.runner.vm, mouse_state);
                 }
             }
         }
@@ -560,9 +568,11 @@ impl<'a> Stage<'a> {
                 if let Some(varvara_key) = map_egui_key_to_varvara_key(*key) {
                     println!("[DEBUG][Stage] Forwarding key: {key:?} pressed={pressed} to VM as {varvara_key:?}");
                     if *pressed {
-                        self.dev.pressed(&mut self.vm, varvara_key, false);
+                        self.runner
+                            .dev
+                            .pressed(&mut self.runner.vm, varvara_key, false);
                     } else {
-                        self.dev.released(&mut self.vm, varvara_key);
+                        self.runner.dev.released(&mut self.runner.vm, varvara_key);
                     }
                 } else {
                     println!("[DEBUG][Stage] Ignored key: {key:?}");
@@ -603,9 +613,12 @@ impl<'a> Stage<'a> {
                     if c.is_ascii_graphic() || c == ' ' {
                         let byte = c as u8;
                         println!("[DEBUG][Stage] Forwarding char event: '{c}' (0x{byte:02x}) to VM (from egui::Event::Text)");
-                        self.dev
-                            .pressed(&mut self.vm, varvara::Key::Char(byte), false);
-                        self.dev.char(&mut self.vm, byte);
+                        self.runner.dev.pressed(
+                            &mut self.runner.vm,
+                            varvara::Key::Char(byte),
+                            false,
+                        );
+                        self.runner.dev.char(&mut self.runner.vm, byte);
                     } else {
                         println!(
                             "[DEBUG][Stage] Ignored char event: '{}' (0x{:02x}) (not printable)",
@@ -678,9 +691,11 @@ impl<'a> Stage<'a> {
                     if let Some(varvara_key) = map_egui_key_to_varvara_key(*key) {
                         println!("[DEBUG][Stage] Forwarding non-printable key: {key:?} pressed={pressed} to VM as {varvara_key:?}");
                         if *pressed {
-                            self.dev.pressed(&mut self.vm, varvara_key, false);
+                            self.runner
+                                .dev
+                                .pressed(&mut self.runner.vm, varvara_key, false);
                         } else {
-                            self.dev.released(&mut self.vm, varvara_key);
+                            self.runner.dev.released(&mut self.runner.vm, varvara_key);
                         }
                     } else {
                         println!("[DEBUG][Stage] Ignored key: {key:?}");
@@ -708,7 +723,7 @@ impl<'a> Stage<'a> {
                     scroll: (0.0, 0.0),
                     buttons,
                 };
-                self.dev.mouse(&mut self.vm, mouse_state);
+                self.runner.dev.mouse(&mut self.runner.vm, mouse_state);
             }
         }
         // Forward pointer button events (for clicks)
@@ -739,7 +754,7 @@ impl<'a> Stage<'a> {
                         buttons,
                     };
                     println!("[DEBUG][Stage] Forwarding pointer button: {button:?} pressed={pressed} at rel=({rel_x:.1},{rel_y:.1})");
-                    self.dev.mouse(&mut self.vm, mouse_state);
+                    self.runner.dev.mouse(&mut self.runner.vm, mouse_state);
                 }
             }
         }
@@ -750,7 +765,7 @@ impl<'a> Stage<'a> {
         // mark stopped to prevent further updates
         self.stopped = true;
         // mute audio to avoid audio continuing after close
-        self.dev.audio_set_muted(true);
+        self.runner.dev.audio_set_muted(true);
     }
 }
 
@@ -943,10 +958,21 @@ impl<'a> eframe::App for Stage<'a> {
                     self.update_texture(ctx);
                 }
                 Event::SetMuted(m) => {
-                    self.dev.audio_set_muted(m);
+                    self.runner.dev.audio_set_muted(m);
                 }
                 Event::Console(b) => {
-                    self.dev.console(&mut self.vm, b);
+                    self.runner.dev.console(&mut self.runner.vm, b);
+                }
+                Event::Reload(r) => {
+                    self.runner.reload(&r.rom, &r.keep);
+                    self.size = self.runner.output().size;
+                    if let Some(sym) = r.sym {
+                        if let Err(e) = self.load_symbols(&sym) {
+                            error!("could not load symbols: {e:?}");
+                        }
+                    }
+                    info!("reloaded rom ({} bytes)", r.rom.len());
+                    self.update_texture(ctx);
                 }
             }
         }
@@ -962,9 +988,10 @@ impl<'a> eframe::App for Stage<'a> {
             .map(|s| s.to_ascii_lowercase());
         let is_random = efx_name.as_deref() == Some("random");
         let is_sequential = efx_name.as_deref() == Some("sequential");
-        while input.time >= self.next_frame {
-            self.next_frame += 0.0166667;
-            self.dev.redraw(&mut self.vm);
+        if self
+            .runner
+            .advance(std::time::Duration::from_secs_f64(input.time))
+        {
             // Only cycle effect if efx is 'random' or 'sequential' and efxt dwell time has elapsed
             if is_random {
                 if self.config.effects.last_effect_switch == 0.0
@@ -1030,7 +1057,7 @@ impl<'a> eframe::App for Stage<'a> {
                     ];
                     for c in s.bytes() {
                         if RAW_CHARS.contains(&c) {
-                            self.dev.char(&mut self.vm, c);
+                            self.runner.dev.char(&mut self.runner.vm, c);
                         }
                     }
                 }
@@ -1046,7 +1073,7 @@ impl<'a> eframe::App for Stage<'a> {
                         key, pressed, input.modifiers.ctrl
                     );
                     if *pressed && *key == egui::Key::F2 {
-                        self.dev.system.debug(&mut self.vm);
+                        self.runner.dev.system.debug(&mut self.runner.vm);
                         #[cfg(target_os = "windows")]
                         unsafe {
                             winapi::um::winuser::MessageBeep(winapi::um::winuser::MB_OK);
@@ -1054,9 +1081,9 @@ impl<'a> eframe::App for Stage<'a> {
                     }
                     if let Some(k) = decode_key(*key, shift_held) {
                         if *pressed {
-                            self.dev.pressed(&mut self.vm, k, *repeat);
+                            self.runner.dev.pressed(&mut self.runner.vm, k, *repeat);
                         } else {
-                            self.dev.released(&mut self.vm, k);
+                            self.runner.dev.released(&mut self.runner.vm, k);
                         }
                     }
                 }
@@ -1073,9 +1100,9 @@ impl<'a> eframe::App for Stage<'a> {
             (input.modifiers.shift, varvara::Key::Shift),
         ] {
             if b {
-                self.dev.pressed(&mut self.vm, k, false)
+                self.runner.dev.pressed(&mut self.runner.vm, k, false)
             } else {
-                self.dev.released(&mut self.vm, k)
+                self.runner.dev.released(&mut self.runner.vm, k)
             }
         }
 
@@ -1098,18 +1125,18 @@ impl<'a> eframe::App for Stage<'a> {
             scroll: std::mem::take(&mut self.scroll),
             buttons,
         };
-        self.dev.mouse(&mut self.vm, m);
+        self.runner.dev.mouse(&mut self.runner.vm, m);
         let m = varvara::TrackerState {
             pos: self.cursor_pos.unwrap_or((0.0, 0.0)),
             scroll: std::mem::take(&mut self.scroll),
             buttons,
         };
-        self.dev.tracker(&mut self.vm, m);
+        self.runner.dev.tracker(&mut self.runner.vm, m);
 
         // Handle audio callback
-        self.dev.audio(&mut self.vm);
+        self.runner.dev.audio(&mut self.runner.vm);
 
-        let out = self.dev.output(&self.vm);
+        let out = self.runner.output();
 
         // Update our GUI based on current state
         if out.hide_mouse {
@@ -1347,7 +1374,13 @@ impl<'a> eframe::App for Stage<'a> {
         }
 
         // Update stdout / stderr / exiting
-        out.check().expect("failed to print output?");
+        out.print().expect("failed to print output?");
+        if let Some(e) = out.exit {
+            info!("requested exit ({e})");
+            *self.exit_code.lock().unwrap() = Some(e);
+            self.shutdown();
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
     }
 }
 
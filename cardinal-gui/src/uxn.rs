@@ -110,55 +110,32 @@ pub enum InjectEvent {
 pub mod uxn {
 
     use std::path::Path;
-    use std::sync::{Arc, Mutex};
-    use uxn::{Backend, Uxn};
-    use varvara::Varvara;
+    use uxn::Backend;
+    use varvara::Machine;
     /// UxnModule: Encapsulates a Uxn VM and its state for e_window
     pub struct UxnModule {
-        pub uxn: Arc<Mutex<Uxn<'static>>>,
-        pub varvara: Option<Varvara>,
+        pub machine: Machine,
     }
 
     impl UxnModule {
         /// Create a new UxnModule, optionally loading a ROM file
         pub fn new(rom_path: Option<&Path>) -> Result<Self, String> {
-            // Use a static RAM buffer for the Uxn VM
-            static mut RAM: [u8; 65536] = [0; 65536];
-            #[allow(static_mut_refs)]
-            let ram: &'static mut [u8; 65536] = unsafe { &mut RAM };
-            let mut uxn = Uxn::new(ram, Backend::Interpreter);
-            let mut varvara = Varvara::default();
-
-            // varvara.screen.buffer.resize(1024 * 768 * 4, 0);
-            // varvara.screen.changed = true;
+            let mut m = UxnModule {
+                machine: Machine::new(Backend::Interpreter),
+            };
             if let Some(path) = rom_path {
-                let rom = std::fs::read(path).map_err(|e| format!("Failed to read ROM: {e}"))?;
-                let _ = uxn.reset(&rom);
-                varvara.reset(&rom);
-
-                varvara.load_sym_with_rom_path(path);
+                m.load_rom(path)?;
             }
-
-            Ok(UxnModule {
-                uxn: Arc::new(Mutex::new(uxn)),
-                varvara: Some(varvara),
-            })
+            Ok(m)
         }
 
-        // /// Reset the Uxn VM (clears memory and state)
-        // pub fn reset(&self, rom: &[u8]) {
-        //     let mut uxn = self.uxn.lock().unwrap();
-        //     let _ = uxn.reset(rom);
-        // }
-
         /// Load a new ROM into the Uxn VM (resets VM)
         pub fn load_rom(&mut self, rom_path: &Path) -> Result<(), String> {
             let rom = std::fs::read(rom_path).map_err(|e| format!("Failed to read ROM: {e}"))?;
-            let mut uxn = self.uxn.lock().unwrap();
-            let _ = uxn.reset(&rom);
-            if let Some(varvara) = self.varvara.as_mut() {
-                varvara.load_sym_with_rom_path(rom_path);
-            }
+            self.machine.with(|r| {
+                let _ = r.vm.reset(&rom);
+            });
+            self.machine.dev_mut().load_sym_with_rom_path(rom_path);
             Ok(())
         }
     }
@@ -172,54 +149,41 @@ use ::uxn::{Backend, Uxn};
 use varvara::Key;
 use varvara::MouseState;
 use varvara::TrackerState;
-use varvara::Varvara;
+use varvara::{Machine, Runner, Varvara};
 /// UxnModule: Encapsulates a Uxn VM and its state for e_window
+///
+/// The module owns its RAM, so several can exist at once (and be moved to
+/// other threads); use [`Machine::leak`] to hand it to a [`UxnApp`].
 pub struct UxnModule {
-    pub uxn: Arc<Mutex<Uxn<'static>>>,
-    pub varvara: Option<Varvara>,
+    pub machine: Machine,
 }
 
 impl UxnModule {
     /// Create a new UxnModule, optionally loading a ROM file
     pub fn new(rom_path: Option<&Path>) -> Result<Self, String> {
-        // Use a static RAM buffer for the Uxn VM
-        static mut RAM: [u8; 65536] = [0; 65536];
-        #[allow(static_mut_refs)]
-        let ram: &'static mut [u8; 65536] = unsafe { &mut RAM };
-        let mut uxn = Uxn::new(ram, Backend::Interpreter);
-        let mut varvara = Varvara::default();
+        let mut m = UxnModule {
+            machine: Machine::new(Backend::Interpreter),
+        };
         if let Some(path) = rom_path {
-            let rom = std::fs::read(path).map_err(|e| format!("Failed to read ROM: {e}"))?;
-            let _ = uxn.reset(&rom);
-            varvara.load_sym_with_rom_path(path);
+            m.load_rom(path)?;
         }
-        Ok(UxnModule {
-            uxn: Arc::new(Mutex::new(uxn)),
-            varvara: Some(varvara),
-        })
+        Ok(m)
     }
 
-    // Step/run methods are not available in cardinal-uxn. Use run/reset as needed.
-
-    // No run_cycles method; use run/reset as needed.
-
     /// Reset the Uxn VM (clears memory and state)
-    pub fn reset(&self, rom: &[u8]) {
-        let mut uxn = self.uxn.lock().unwrap();
-        let _ = uxn.reset(rom);
+    pub fn reset(&mut self, rom: &[u8]) {
+        self.machine.with(|r| {
+            let _ = r.vm.reset(rom);
+        });
     }
 
     /// Load a new ROM into the Uxn VM (resets VM)
     pub fn load_rom(&mut self, rom_path: &Path) -> Result<(), String> {
         let rom = std::fs::read(rom_path).map_err(|e| format!("Failed to read ROM: {e}"))?;
-        if let Some(varvara) = self.varvara.as_mut() {
-            varvara.load_sym_with_rom_path(rom_path);
-        }
+        self.machine.dev_mut().load_sym_with_rom_path(rom_path);
         self.reset(&rom);
         Ok(())
     }
-
-    // No get_state method; UxnState is not available in cardinal-uxn.
 }
 
 // Optionally, add egui integration for UxnModule (UI panel, etc.)
@@ -245,11 +209,9 @@ pub enum Event {
 }
 
 pub struct UxnApp<'a> {
-    pub vm: Uxn<'a>,
-    pub dev: Varvara,
+    pub runner: Runner<'a>,
     scale: f32,
     size: (u16, u16),
-    next_frame: f64,
     scroll: (f32, f32),
     cursor_pos: Option<(f32, f32)>,
     texture: egui::TextureHandle,
@@ -273,6 +235,8 @@ pub struct UxnApp<'a> {
     first_update_done: bool,
     /// The current ROM label or filename (if available)
     current_rom_label: Option<String>,
+    /// ROM metadata most recently applied to the window title and icon
+    applied_metadata: Option<varvara::Metadata>,
     /// Queue for deferred input events (for orca injection)
     input_queue: std::collections::VecDeque<InjectEvent>,
     /// The last ROM file path loaded (if any)
@@ -303,8 +267,8 @@ impl<'a> UxnApp<'a> {
     }
     #[allow(clippy::too_many_arguments)]
     pub fn new_with_mode(
-        mut vm: Uxn<'a>,
-        mut dev: Varvara,
+        vm: Uxn<'a>,
+        dev: Varvara,
         mut size: (u16, u16),
         scale: f32,
         event_rx: mpsc::Receiver<Event>,
@@ -316,15 +280,16 @@ impl<'a> UxnApp<'a> {
         reload_rx: std::sync::mpsc::Receiver<()>,
         rom_path_arc: std::sync::Arc<std::sync::Mutex<Option<std::path::PathBuf>>>,
     ) -> Self {
+        let mut runner = Runner::new(vm, dev);
         if let Some(path) = rom_path_arc.lock().unwrap().as_ref() {
             if path.exists() {
-                dev.load_sym_with_rom_path(path);
+                runner.dev.load_sym_with_rom_path(path);
             }
         }
         // Run the VM and redraw once to initialize the framebuffer
-        vm.run(&mut dev, 0x100);
+        runner.vm.run(&mut runner.dev, 0x100);
 
-        dev.redraw(&mut vm);
+        runner.dev.redraw(&mut runner.vm);
 
         let w = 2048_usize; //size.0);
         let h = 2048_usize;
@@ -343,10 +308,8 @@ impl<'a> UxnApp<'a> {
             );
             // Load the first ROM immediately
             let rom = &auto_roms[0];
-            let _ = vm.reset(&rom.rom);
-            dev.reset(&rom.rom);
-            vm.run(&mut dev, 0x100);
-            dev.redraw(&mut vm);
+            runner.load(&rom.rom, &[]);
+            runner.dev.redraw(&mut runner.vm);
 
             auto_index = 0;
             auto_timer = 0.0;
@@ -358,11 +321,9 @@ impl<'a> UxnApp<'a> {
             }
         }
         UxnApp {
-            vm,
-            dev,
+            runner,
             scale,
             size,
-            next_frame: 0.0,
             event_rx,
             resized: None,
             scroll: (0.0, 0.0),
@@ -376,6 +337,7 @@ impl<'a> UxnApp<'a> {
             auto_roms,
             on_rom_change: None,
             current_rom_label,
+            applied_metadata: None,
             auto_rom_labels,
             on_first_update: None,
             first_update_done: false,
@@ -399,7 +361,10 @@ impl<'a> UxnApp<'a> {
 
     pub fn load_symbols(&mut self, path: &std::path::Path) -> Result<(), String> {
         println!("[UxnApp] Loading symbols from: {}", path.display());
-        let _ = self.dev.load_symbols_into_self(path.to_str().unwrap());
+        let _ = self
+            .runner
+            .dev
+            .load_symbols_into_self(path.to_str().unwrap());
         Ok(())
     }
 
@@ -407,10 +372,8 @@ impl<'a> UxnApp<'a> {
     pub fn reload_rom(&mut self, path: &std::path::Path) -> Result<(), String> {
         println!("[UxnApp] Reloading ROM from: {}", path.display());
         let rom = std::fs::read(path).map_err(|e| format!("Failed to read ROM: {e}"))?;
-        let _ = self.vm.reset(&rom);
-        self.dev.reset(&rom);
-        self.vm.run(&mut self.dev, 0x100);
-        self.dev.redraw(&mut self.vm);
+        self.runner.load(&rom, &[]);
+        self.runner.dev.redraw(&mut self.runner.vm);
 
         self.load_symbols(path)?;
 
@@ -433,32 +396,37 @@ impl<'a> UxnApp<'a> {
         let path = path.as_ref();
         let data = std::fs::read(path)?;
         self.last_rom_path = Some(path.to_path_buf());
-        let _ = self.vm.reset(&data);
-        self.dev.reset(&data);
-        self.vm.run(&mut self.dev, 0x100);
+        self.runner.load(&data, &[]);
         // Try to load .sym file if ROM was loaded from a file
         let sym_path = path.with_extension("sym");
         if sym_path.exists() {
-            let _ = self.dev.load_symbols_into_self(sym_path.to_str().unwrap());
+            let _ = self
+                .runner
+                .dev
+                .load_symbols_into_self(sym_path.to_str().unwrap());
         }
         // Optionally update the ROM label
         if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
             self.set_rom_label(name);
         }
-        let out = self.dev.output(&self.vm);
-        out.check()?;
+        self.print_output()
+    }
+
+    /// Prints console output, returning an error if the ROM has exited
+    fn print_output(&mut self) -> anyhow::Result<()> {
+        let out = self.runner.output();
+        out.print()?;
+        if let Some(e) = out.exit {
+            anyhow::bail!("ROM exited during startup ({e})");
+        }
         Ok(())
     }
 
     /// Backward-compatible load_rom for in-memory loads (no file path)
     fn load_rom(&mut self, data: &[u8]) -> anyhow::Result<()> {
         self.last_rom_path = None;
-        let _ = self.vm.reset(data);
-        self.dev.reset(data);
-        self.vm.run(&mut self.dev, 0x100);
-        let out = self.dev.output(&self.vm);
-        out.check()?;
-        Ok(())
+        self.runner.load(data, &[]);
+        self.print_output()
     }
 
     // Helper to get the last ROM path if available (for symbol loading)
@@ -588,15 +556,15 @@ impl eframe::App for UxnApp<'_> {
         // --- Per-frame input injection ---
         if let Some(event) = self.input_queue.pop_front() {
             match event {
-                InjectEvent::Char(c) => self.dev.char(&mut self.vm, c),
-                InjectEvent::KeyPress(k) => self.dev.pressed(&mut self.vm, k, false),
-                InjectEvent::KeyRelease(k) => self.dev.released(&mut self.vm, k),
+                InjectEvent::Char(c) => self.runner.dev.char(&mut self.runner.vm, c),
+                InjectEvent::KeyPress(k) => self.runner.dev.pressed(&mut self.runner.vm, k, false),
+                InjectEvent::KeyRelease(k) => self.runner.dev.released(&mut self.runner.vm, k),
                 InjectEvent::Sleep(ms) => {
                     std::thread::sleep(std::time::Duration::from_millis(ms));
                 }
                 InjectEvent::Chord(keys) => {
                     for k in &keys {
-                        self.dev.pressed(&mut self.vm, *k, false);
+                        self.runner.dev.pressed(&mut self.runner.vm, *k, false);
                     }
                 }
             }
@@ -635,7 +603,7 @@ impl eframe::App for UxnApp<'_> {
                 if rom.sym.is_some() {
                     // Load symbols if available
                     if let Some(sym) = &rom.sym {
-                        let _ = self.dev.load_symbols_from_vec(sym);
+                        let _ = self.runner.dev.load_symbols_from_vec(sym);
                     }
                 }
             }
@@ -648,15 +616,15 @@ impl eframe::App for UxnApp<'_> {
                     }
                 }
                 Event::SetMuted(m) => {
-                    self.dev.audio_set_muted(m);
+                    self.runner.dev.audio_set_muted(m);
                 }
                 Event::Console(b) => {
-                    self.dev.console(&mut self.vm, b);
+                    self.runner.dev.console(&mut self.runner.vm, b);
                 }
             }
         }
         // if let Some(ref mut varvara_controller) =
-        //     self.dev
+        //     self.runner.dev
         //         .controller
         //         .as_any()
         //         .downcast_mut::<varvara::controller::Controller>()
@@ -673,7 +641,7 @@ impl eframe::App for UxnApp<'_> {
         //     // Use canonical helper from varvara::controller
         //     varvara::controller::inject_pedal_keys(
         //         varvara_controller,
-        //         &mut self.vm,
+        //         &mut self.runner.vm,
         //         _prev,
         //         pedal_state,
         //     );
@@ -684,9 +652,10 @@ impl eframe::App for UxnApp<'_> {
         let mut events = Vec::new();
         #[cfg(all(feature = "uses_usb", not(target_arch = "wasm32")))]
         {
-            // Only borrow self.dev.controller mutably once in this block
+            // Only borrow self.runner.dev.controller mutably once in this block
             let mut last_pedal = None;
             if let Some(controller_usb) = self
+                .runner
                 .dev
                 .controller
                 .as_any()
@@ -694,7 +663,7 @@ impl eframe::App for UxnApp<'_> {
             {
                 events = varvara::controller_usb::ControllerPollEvents::poll_usb_events(
                     controller_usb,
-                    &mut self.vm,
+                    &mut self.runner.vm,
                 );
                 last_pedal = controller_usb.last_pedal;
             }
@@ -709,7 +678,7 @@ impl eframe::App for UxnApp<'_> {
                     PREV_PEDAL = pedal;
                 }
                 // Use shared controller pedal key injection logic
-                // Controller::inject_pedal_keys(&mut self.vm, prev, pedal);
+                // Controller::inject_pedal_keys(&mut self.runner.vm, prev, pedal);
             }
         }
         let pedal_data: Vec<u8> = events
@@ -717,17 +686,16 @@ impl eframe::App for UxnApp<'_> {
             .flat_map(|e: &varvara::Event| e.data.iter().map(|b| b.value))
             .collect();
         for value in pedal_data {
-            self.dev
-                .pressed(&mut self.vm, varvara::Key::Char(value), true);
-            self.dev.char(&mut self.vm, value);
+            self.runner
+                .dev
+                .pressed(&mut self.runner.vm, varvara::Key::Char(value), true);
+            self.runner.dev.char(&mut self.runner.vm, value);
         }
 
         ctx.request_repaint();
         ctx.input(|i| {
-            while i.time >= self.next_frame {
-                self.next_frame += 0.0166667;
-                self.dev.redraw(&mut self.vm);
-            }
+            self.runner
+                .advance(std::time::Duration::from_secs_f64(i.time));
             if i.raw.dropped_files.len() == 1 {
                 let target = &i.raw.dropped_files[0];
                 let r = if let Some(path) = &target.path {
@@ -747,7 +715,7 @@ impl eframe::App for UxnApp<'_> {
                 match e {
                     egui::Event::Text(s) => {
                         for c in s.bytes() {
-                            self.dev.char(&mut self.vm, c);
+                            self.runner.dev.char(&mut self.runner.vm, c);
                         }
                     }
                     egui::Event::Key {
@@ -758,9 +726,9 @@ impl eframe::App for UxnApp<'_> {
                     } => {
                         if let Some(k) = decode_key(*key, shift_held) {
                             if *pressed {
-                                self.dev.pressed(&mut self.vm, k, *repeat);
+                                self.runner.dev.pressed(&mut self.runner.vm, k, *repeat);
                             } else {
-                                self.dev.released(&mut self.vm, k);
+                                self.runner.dev.released(&mut self.runner.vm, k);
                             }
                         }
                     }
@@ -773,9 +741,9 @@ impl eframe::App for UxnApp<'_> {
                 (i.modifiers.shift, Key::Shift),
             ] {
                 if b {
-                    self.dev.pressed(&mut self.vm, k, false)
+                    self.runner.dev.pressed(&mut self.runner.vm, k, false)
                 } else {
-                    self.dev.released(&mut self.vm, k)
+                    self.runner.dev.released(&mut self.runner.vm, k)
                 }
             }
             let ptr = &i.pointer;
@@ -796,7 +764,7 @@ impl eframe::App for UxnApp<'_> {
                 scroll: std::mem::take(&mut self.scroll),
                 buttons,
             };
-            self.dev.mouse(&mut self.vm, m);
+            self.runner.dev.mouse(&mut self.runner.vm, m);
             let m = TrackerState {
                 pos: self
                     .cursor_pos
@@ -805,11 +773,30 @@ impl eframe::App for UxnApp<'_> {
                 scroll: std::mem::take(&mut self.scroll),
                 buttons,
             };
-            self.dev.tracker(&mut self.vm, m);
+            self.runner.dev.tracker(&mut self.runner.vm, m);
             i.time
         });
-        self.dev.audio(&mut self.vm);
-        let out = self.dev.output(&self.vm);
+        self.runner.dev.audio(&mut self.runner.vm);
+        let out = self.runner.output();
+        if out.metadata != self.applied_metadata.as_ref() {
+            self.applied_metadata = out.metadata.cloned();
+            if let Some(m) = out.metadata {
+                let title = match &m.version {
+                    Some(v) => format!("{} {v}", m.name),
+                    None => m.name.clone(),
+                };
+                ctx.send_viewport_cmd(egui::ViewportCommand::Title(title));
+                if let Some(icon) = &m.icon {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Icon(Some(std::sync::Arc::new(
+                        egui::IconData {
+                            rgba: icon.to_rgba(out.palette),
+                            width: u32::from(icon.width),
+                            height: u32::from(icon.height),
+                        },
+                    ))));
+                }
+            }
+        }
         if out.hide_mouse {
             ctx.set_cursor_icon(egui::CursorIcon::None);
         }
@@ -902,7 +889,12 @@ impl eframe::App for UxnApp<'_> {
                     ));
                 }
             });
-        out.check().ok();
+        out.print().ok();
+        if let Some(e) = out.exit {
+            info!("requested exit ({e})");
+            self.runner.dev.audio_set_muted(true);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
     }
 }
 
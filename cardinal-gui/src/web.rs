@@ -14,7 +14,7 @@ use web_sys::js_sys::Uint8Array;
 use crate::stage::{Event, Stage};
 use crate::uxn::audio_setup;
 use uxn::{Backend, Uxn, UxnRam};
-use varvara::Varvara;
+use varvara::{Runner, Varvara};
 
 pub fn run() -> Result<()> {
     eframe::WebLogger::init(log::LevelFilter::Debug).ok();
@@ -55,20 +55,20 @@ pub fn run() -> Result<()> {
     log::info!("[WASM] Creating UxnRam");
     let ram = UxnRam::new();
     log::info!("[WASM] Creating Uxn VM");
-    let mut vm = Uxn::new(ram.leak(), Backend::Interpreter);
+    let vm = Uxn::new(ram.leak(), Backend::Interpreter);
     log::info!("[WASM] Creating Varvara");
-    let mut dev = Varvara::new();
-    log::info!("[WASM] Resetting VM with ROM");
-    let extra = vm.reset(rom);
-    log::info!("[WASM] Resetting Varvara with extra");
-    dev.reset(extra);
+    let mut runner = Runner::new(vm, Varvara::new());
 
     log::info!("[WASM] Running reset vector");
-    vm.run(&mut dev, 0x100);
+    runner.load(rom, &[]);
     log::info!("[WASM] Checking output");
-    dev.output(&vm).check()?;
+    let out = runner.output();
+    out.print()?;
+    if let Some(e) = out.exit {
+        return Err(anyhow!("ROM exited during startup ({e})"));
+    }
 
-    let size @ (width, height) = dev.output(&vm).size;
+    let size @ (width, height) = runner.output().size;
     log::info!("[WASM] Output size: {width}x{height}");
     let options = eframe::WebOptions {
         #[cfg(not(target_arch = "wasm32"))]
@@ -197,7 +197,7 @@ pub fn run() -> Result<()> {
     std::mem::forget(a);
 
     let mut _audio = None;
-    let mut audio_data = Some(dev.audio_streams());
+    let mut audio_data = Some(runner.dev.audio_streams());
     let audio_check = document
         .get_element_by_id("audio-check")
         .ok_or_else(|| anyhow!("could not find audio-check"))?
@@ -246,9 +246,8 @@ pub fn run() -> Result<()> {
                 canvas,
                 options,
                 Box::new(move |_cc| {
-                    let mut s = Box::new(Stage::new(
-                        vm,
-                        dev,
+                    let mut s = Box::new(Stage::with_runner(
+                        runner,
                         rx,
                         crate::stage::StageConfig {
                             size,
@@ -11,7 +11,8 @@ use zerocopy::{BigEndian, U16};
 pub struct Console {
     stdout: Vec<u8>,
     stderr: Vec<u8>,
-    stdout_listeners: Vec<Box<dyn FnMut(u8) + Send>>,
+    /// Each returns `false` once it should be removed
+    stdout_listeners: Vec<Box<dyn FnMut(u8) -> bool + Send>>,
     stderr_listeners: Vec<Box<dyn FnMut(u8) + Send>>,
 }
 
@@ -37,6 +38,7 @@ pub enum Type {
     Stdin = 1,
     Argument = 2,
     ArgumentSpacer = 3,
+    /// End of the final argument, or (with a zero character) end of stdin
     ArgumentEnd = 4,
 }
 
@@ -63,7 +65,11 @@ where
         let mut i = std::io::stdin().lock();
         let mut buf = [0u8; 32];
         loop {
-            let n = i.read(&mut buf).unwrap();
+            // Stop at the end of input, which disconnects the receiver
+            let n = match i.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
             for &c in &buf[..n] {
                 if tx(c).is_err() {
                     return;
@@ -83,6 +89,17 @@ impl Console {
         }
     }
 
+    /// Clears buffered output, keeping registered listeners
+    ///
+    /// Listeners are kept because console pipes (see
+    /// [`Varvara::pipe_stdout`](crate::Varvara::pipe_stdout)) are opened
+    /// before a ROM is loaded, and loading resets the system.  A pipe's
+    /// listener is removed once its reader is dropped instead.
+    pub fn reset(&mut self) {
+        self.stdout.clear();
+        self.stderr.clear();
+    }
+
     /// Register a callback to receive bytes written to stderr
     pub fn register_stderr_listener<F>(&mut self, listener: F)
     where
@@ -91,9 +108,20 @@ impl Console {
         self.stderr_listeners.push(Box::new(listener));
     }
     /// Register a callback to receive bytes written to stdout
-    pub fn register_stdout_listener<F>(&mut self, listener: F)
+    pub fn register_stdout_listener<F>(&mut self, mut listener: F)
     where
         F: FnMut(u8) + Send + 'static,
+    {
+        self.register_stdout_listener_until(move |c| {
+            listener(c);
+            true
+        });
+    }
+    /// Register a callback to receive bytes written to stdout, which is
+    /// removed once it returns `false`
+    pub fn register_stdout_listener_until<F>(&mut self, listener: F)
+    where
+        F: FnMut(u8) -> bool + Send + 'static,
     {
         self.stdout_listeners.push(Box::new(listener));
     }
@@ -102,9 +130,8 @@ impl Console {
         match target {
             ConsolePorts::WRITE => {
                 self.stdout.push(v.write);
-                for listener in &mut self.stdout_listeners {
-                    listener(v.write);
-                }
+                self.stdout_listeners
+                    .retain_mut(|listener| listener(v.write));
             }
             ConsolePorts::ERROR => {
                 self.stderr.push(v.error);
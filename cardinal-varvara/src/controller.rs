@@ -94,7 +94,7 @@ impl Controller {
                 clear: true,
             }),
         };
-        println!(
+        log::debug!(
             "[og CONTROLLER][char] char: '{}' (0x{:02x}), vector: 0x{:04x}, addr: 0x{:02x}",
             c as char,
             c,
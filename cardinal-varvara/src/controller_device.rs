@@ -10,6 +10,11 @@ pub trait ControllerDevice: Send {
     fn pressed(&mut self, vm: &mut Uxn, k: super::controller::Key, repeat: bool) -> Option<Event>;
     /// Handles a key release event.
     fn released(&mut self, vm: &mut Uxn, k: super::controller::Key) -> Option<Event>;
+    /// Clears button state when a new ROM is loaded, keeping any input
+    /// threads running
+    ///
+    /// Does nothing by default, for devices that keep no state of their own.
+    fn reset(&mut self) {}
 }
 
 impl ControllerDevice for super::controller::Controller {
@@ -25,4 +30,7 @@ impl ControllerDevice for super::controller::Controller {
     fn released(&mut self, vm: &mut Uxn, k: super::controller::Key) -> Option<Event> {
         self.released(vm, k)
     }
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
 }
@@ -42,6 +42,9 @@ impl ControllerDevice for ControllerGilrs {
     fn released(&mut self, vm: &mut Uxn, k: Key) -> Option<Event> {
         self.controller.released(vm, k)
     }
+    fn reset(&mut self) {
+        self.controller = Controller::default();
+    }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
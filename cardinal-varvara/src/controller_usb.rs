@@ -72,6 +72,14 @@ impl ControllerDevice for ControllerUsb {
         }
         event
     }
+    fn reset(&mut self) {
+        self.controller = Controller::default();
+        self.last_pedal = None;
+        #[cfg(feature = "uses_gilrs")]
+        if let Some(gilrs) = &mut self.gilrs {
+            gilrs.reset();
+        }
+    }
     fn as_any(&mut self) -> &mut dyn Any {
         self
     }
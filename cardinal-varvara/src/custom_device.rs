@@ -0,0 +1,63 @@
+use crate::Event;
+use uxn::Uxn;
+
+/// Trait for devices which are registered into a free slot at runtime
+///
+/// Each device owns a single 16-byte page of device memory, which is chosen
+/// when it's registered with [`Varvara::register`](crate::Varvara::register).
+/// The CPU writes port values into device memory before calling
+/// [`deo`](CustomDevice::deo), and reads them back after calling
+/// [`dei`](CustomDevice::dei), so implementations typically use
+/// [`Uxn::dev`] / [`Uxn::dev_mut`] with a [`Ports`](uxn::Ports) type.
+pub trait CustomDevice: Send {
+    /// Returns a mutable reference to self as `Any` for downcasting.
+    fn as_any(&mut self) -> &mut dyn std::any::Any;
+
+    /// Handles a write to one of the device's ports
+    fn deo(&mut self, vm: &mut Uxn, target: u8);
+
+    /// Handles a read from one of the device's ports
+    fn dei(&mut self, vm: &mut Uxn, target: u8);
+
+    /// Polls for asynchronous work, returning an event to process
+    ///
+    /// `base` is the address at which the device is registered.  This is
+    /// called repeatedly by
+    /// [`Varvara::poll_devices`](crate::Varvara::poll_devices) until it
+    /// returns `None`, or a few thousand times per poll for a device that
+    /// always has an event ready.
+    fn update(&mut self, _vm: &mut Uxn, _base: u8) -> Option<Event> {
+        None
+    }
+
+    /// Resets the device's internal state
+    ///
+    /// This is called when the Varvara system is reset; registered devices
+    /// stay in their slots.
+    fn reset(&mut self) {}
+}
+
+/// Error returned when a custom device can't be registered
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// The base address isn't aligned to a 16-byte device page
+    Unaligned(u8),
+    /// The page is used by one of the built-in Varvara devices
+    Builtin(u8),
+    /// Another custom device is already registered at this page
+    Occupied(u8),
+}
+
+impl std::fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterError::Unaligned(b) => write!(f, "device base {b:#04x} is not page-aligned"),
+            RegisterError::Builtin(b) => write!(f, "device page {b:#04x} is a built-in device"),
+            RegisterError::Occupied(b) => {
+                write!(f, "device page {b:#04x} already has a custom device")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
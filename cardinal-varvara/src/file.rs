@@ -1,8 +1,9 @@
 use log::{error, trace, warn};
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{BTreeMap, HashSet, VecDeque},
     io::{Read, Write},
     mem::offset_of,
+    path::{Path, PathBuf},
 };
 use uxn::{Ports, Uxn, DEV_SIZE};
 use zerocopy::{BigEndian, U16};
@@ -93,8 +94,18 @@ enum Handle {
         path: std::path::PathBuf,
         file: std::fs::File,
     },
+    MemRead {
+        name: String,
+        pos: usize,
+    },
+    MemWrite {
+        name: String,
+    },
 }
 
+/// In-memory files, keyed by the name which the ROM uses to open them
+pub type MemoryFiles = BTreeMap<String, Vec<u8>>;
+
 pub struct File {
     f: Option<Handle>,
 
@@ -103,6 +114,12 @@ pub struct File {
 
     /// Log of missing files, to avoid spamming warnings
     missing_files: HashSet<String>,
+
+    /// In-memory files, used instead of the host filesystem if present
+    memory: Option<MemoryFiles>,
+
+    /// Directory which in-memory files are read from on first access
+    fallback: Option<PathBuf>,
 }
 
 impl Default for File {
@@ -117,9 +134,50 @@ impl File {
             f: None,
             buf: vec![],
             missing_files: HashSet::new(),
+            memory: None,
+            fallback: None,
         }
     }
 
+    /// Closes any open file, keeping in-memory files
+    pub fn reset(&mut self) {
+        self.f = None;
+        self.buf.clear();
+        self.missing_files.clear();
+    }
+
+    /// Replaces the host filesystem with a set of in-memory files
+    ///
+    /// The ROM can read, write, append to and delete these files, but can't
+    /// see anything else; directories are not supported.  Files written by
+    /// the ROM are available from [`File::memory_files`].
+    pub fn set_memory_files(&mut self, files: MemoryFiles) {
+        self.f = None;
+        self.memory = Some(files);
+    }
+
+    /// Reads in-memory files which aren't in the set from `dir`, when the ROM
+    /// first opens them
+    ///
+    /// Files are loaded on demand rather than up front, and anything the ROM
+    /// writes still only goes to memory.  Names are resolved relative to
+    /// `dir`, so they may lead outside it (e.g. `../lib.tal`).
+    pub fn set_memory_fallback(&mut self, dir: Option<PathBuf>) {
+        self.fallback = dir;
+    }
+
+    /// Returns the in-memory files, if the host filesystem has been replaced
+    pub fn memory_files(&self) -> Option<&MemoryFiles> {
+        self.memory.as_ref()
+    }
+
+    /// Removes and returns the in-memory files, restoring the host filesystem
+    pub fn take_memory_files(&mut self) -> Option<MemoryFiles> {
+        self.f = None;
+        self.fallback = None;
+        self.memory.take()
+    }
+
     /// Decodes a port address into an `(index, offset)` tuple
     fn decode_target(target: u8) -> (usize, u8) {
         let i = usize::from(target - FilePorts::BASE) / DEV_SIZE;
@@ -185,6 +243,12 @@ impl File {
         let Some(filename) = ports.filename(vm) else {
             return;
         };
+        if let Some(mem) = self.memory.as_mut() {
+            if mem.remove(&filename).is_some() {
+                FilePorts::dev_mut(vm, index).success.set(0);
+            }
+            return;
+        }
         let path = std::path::PathBuf::from(&filename);
         if !Self::is_path_local(&path) {
             return;
@@ -200,6 +264,33 @@ impl File {
         ports.success.set(0);
 
         let ports = FilePorts::dev(vm, index);
+        if let Some(mem) = self.memory.as_mut() {
+            if !matches!(self.f, Some(Handle::MemWrite { .. })) {
+                let Some(name) = ports.filename(vm) else {
+                    return;
+                };
+                if ports.append == 0x1 {
+                    load_fallback(mem, self.fallback.as_deref(), &name);
+                }
+                let data = mem.entry(name.clone()).or_default();
+                if ports.append != 0x1 {
+                    data.clear();
+                }
+                self.f = Some(Handle::MemWrite { name });
+            }
+            let Some(Handle::MemWrite { name }) = &self.f else {
+                unreachable!();
+            };
+            let data = mem.get_mut(name).unwrap();
+            let mut addr = ports.write.get();
+            for _ in 0..ports.length.get() {
+                data.push(vm.ram_read_byte(addr));
+                addr = addr.wrapping_add(1);
+            }
+            let n = ports.length.get();
+            FilePorts::dev_mut(vm, index).success.set(n);
+            return;
+        }
         if !matches!(self.f, Some(Handle::Write { .. })) {
             let Some(filename) = ports.filename(vm) else {
                 return;
@@ -275,6 +366,41 @@ impl File {
         let ports = FilePorts::dev_mut(vm, index);
         ports.success.set(0);
 
+        if let Some(mem) = self.memory.as_mut() {
+            if !matches!(self.f, Some(Handle::MemRead { .. })) {
+                let ports = FilePorts::dev(vm, index);
+                let Some(name) = ports.filename(vm) else {
+                    return;
+                };
+                load_fallback(mem, self.fallback.as_deref(), &name);
+                if !mem.contains_key(&name) {
+                    if self.missing_files.insert(name.to_owned()) {
+                        error!("{name:?} is missing");
+                    }
+                    return;
+                }
+                self.f = Some(Handle::MemRead { name, pos: 0 });
+            }
+            let Some(Handle::MemRead { name, pos }) = self.f.as_mut() else {
+                unreachable!();
+            };
+            // The file may have been deleted since it was opened
+            let data = mem
+                .get(name.as_str())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let ports = FilePorts::dev_mut(vm, index);
+            let start = (*pos).min(data.len());
+            let n = usize::from(ports.length.get()).min(data.len() - start);
+            *pos = start + n;
+            ports.success.set(n as u16);
+            let mut addr = ports.read.get();
+            for &b in &data[start..start + n] {
+                vm.ram_write_byte(addr, b);
+                addr = addr.wrapping_add(1);
+            }
+            return;
+        }
         if !matches!(self.f, Some(Handle::File { .. } | Handle::Dir { .. })) {
             let ports = FilePorts::dev(vm, index);
             let Some(filename) = ports.filename(vm) else {
@@ -333,7 +459,9 @@ impl File {
         let ports = FilePorts::dev_mut(vm, index);
         self.buf.resize(usize::from(ports.length.get()), 0u8);
         let n = match self.f.as_mut().unwrap() {
-            Handle::Write { .. } => unreachable!(),
+            Handle::Write { .. } | Handle::MemRead { .. } | Handle::MemWrite { .. } => {
+                unreachable!()
+            }
             Handle::File { path, file } => match file.read(&mut self.buf) {
                 Ok(n) => n,
                 Err(e) => {
@@ -398,3 +526,13 @@ impl File {
         }
     }
 }
+
+/// Reads `name` from `dir` into `mem`, unless it's already there
+fn load_fallback(mem: &mut MemoryFiles, dir: Option<&Path>, name: &str) {
+    if mem.contains_key(name) {
+        return;
+    }
+    if let Some(data) = dir.and_then(|d| std::fs::read(d.join(name)).ok()) {
+        mem.insert(name.to_owned(), data);
+    }
+}
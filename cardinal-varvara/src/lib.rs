@@ -8,7 +8,10 @@ use std::fs::File;
 use std::io::{self, Read};
 use std::{
     io::Write,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 /// Audio handler implementation
 mod audio;
@@ -27,9 +30,19 @@ pub mod controller_usb;
 #[path = "controller_usb_stub.rs"]
 pub mod controller_usb;
 
+mod custom_device;
 mod datetime;
 mod file;
+mod machine;
+mod metadata;
 mod mouse;
+/// Network socket device (enabled with the `network` feature)
+#[cfg(feature = "network")]
+pub mod net;
+mod pipe;
+/// Hot reloading of a running ROM
+pub mod reload;
+mod runner;
 mod screen;
 mod system;
 mod tracker;
@@ -39,11 +52,23 @@ pub use audio::StreamData;
 pub use audio::CHANNELS as AUDIO_CHANNELS;
 pub use console::spawn_worker as spawn_console_worker;
 pub use controller::Key;
+pub use custom_device::{CustomDevice, RegisterError};
+pub use file::MemoryFiles;
+pub use machine::Machine;
+pub use metadata::{Icon, Metadata};
 pub use mouse::MouseState;
+pub use pipe::PipeReader;
+pub use runner::{Runner, FRAME_TIME};
+pub use screen::{rgb565, FrameFormat};
 pub use tracker::TrackerState;
 
 use uxn::{Device, Ports, Uxn};
 
+/// Most events taken from each custom device by one call to
+/// [`Varvara::poll_devices`], so that a device which always has input
+/// (e.g. a fast network peer) can't stall the frame
+const MAX_DEVICE_EVENTS: usize = 4096;
+
 /// Holds ROM data and optional symbol information for Uxn.
 #[derive(Clone)]
 pub struct RomData {
@@ -82,6 +107,9 @@ pub struct Output<'a> {
     /// Current screen contents, as RGBA values
     pub frame: &'a [u8],
 
+    /// Current four-color palette, as `0xAARRGGBB` values
+    pub palette: [u32; 4],
+
     /// The system's mouse cursor should be hidden
     pub hide_mouse: bool,
 
@@ -93,6 +121,9 @@ pub struct Output<'a> {
 
     /// Request to exit with the given error code
     pub exit: Option<i32>,
+
+    /// Metadata provided by the ROM through the `System/metadata` port
+    pub metadata: Option<&'a Metadata>,
 }
 
 impl Output<'_> {
@@ -114,7 +145,8 @@ impl Output<'_> {
     /// Checks the results
     ///
     /// `stdout` and `stderr` are printed, and `exit(..)` is called if it has
-    /// been requested by the VM.
+    /// been requested by the VM.  Embedding hosts should use a [`Runner`]
+    /// instead, which returns the exit code as a value.
     pub fn check(&self) -> std::io::Result<()> {
         self.print()?;
         if let Some(e) = self.exit {
@@ -159,6 +191,12 @@ pub struct Varvara {
     pub symbols: Option<HashMap<u16, String>>,
     /// Last processed vector for deduplication
     pub last_vector: u16,
+    /// Custom devices, indexed by device page
+    devices: [Option<Box<dyn CustomDevice>>; 16],
+    /// Remaining instruction budget, if limited
+    cycles: Option<u64>,
+    /// Set by another thread to interrupt the running vector
+    stop: Option<Arc<AtomicBool>>,
 }
 
 impl Default for Varvara {
@@ -193,10 +231,17 @@ impl Device for Varvara {
             controller::ControllerPorts::BASE => (),
             a if audio::AudioPorts::matches(a) => self.audio.deo(vm, target),
 
-            // Default case
-            t => self.warn_missing(t),
+            // Custom devices or default case
+            t => match self.devices[usize::from(t >> 4)].as_mut() {
+                Some(d) => d.deo(vm, target),
+                None => self.warn_missing(t),
+            },
         }
-        !self.system.should_exit()
+        // As in the reference emulators, a write to `System/state` doesn't
+        // halt the CPU: the current vector runs to completion (e.g. drifblim
+        // sets its exit code before writing the ROM), and no further vectors
+        // are run by a `Runner`.
+        true
     }
     fn dei(&mut self, vm: &mut Uxn, target: u8) {
         match target & 0xF0 {
@@ -210,8 +255,11 @@ impl Device for Varvara {
             controller::ControllerPorts::BASE => (),
             a if audio::AudioPorts::matches(a) => self.audio.dei(vm, target),
 
-            // Default case
-            t => self.warn_missing(t),
+            // Custom devices or default case
+            t => match self.devices[usize::from(t >> 4)].as_mut() {
+                Some(d) => d.dei(vm, target),
+                None => self.warn_missing(t),
+            },
         }
     }
 }
@@ -325,31 +373,36 @@ impl Varvara {
             }
         };
         Self {
-            console: console::Console::new(),
-            system: system::System::new(),
-            datetime: datetime::Datetime,
-            audio: audio::Audio::new(),
-            screen: screen::Screen::new(),
-            mouse: mouse::Mouse::new(),
-            file: file::File::new(),
             controller,
-            tracker: tracker::Tracker::new(),
-            already_warned: [false; 16],
             uses_usb,
-            symbols: None,
-            last_vector: 0,
+            ..Self::headless()
         }
     }
 
     /// Builds a new instance of the Varvara peripherals (non-USB/wasm version).
     #[cfg(any(not(feature = "uses_usb"), target_arch = "wasm32"))]
     pub fn new() -> Self {
-        // #[cfg(all(feature = "uses_gilrs", not(target_arch = "wasm32")))]
-        // {
-        //     #[allow(unused_imports)]
-        //     use controller_gilrs::ControllerGilrs;
-        // }
         log::info!("[Varvara::new] (non-USB/wasm) constructing");
+        #[cfg(all(feature = "uses_gilrs", not(target_arch = "wasm32")))]
+        {
+            log::info!("[Varvara::new] (non-USB/wasm) ControllerGilrs");
+            Self {
+                controller: Box::new(ControllerGilrs::new(controller::Controller::default())),
+                ..Self::headless()
+            }
+        }
+        #[cfg(any(not(feature = "uses_gilrs"), target_arch = "wasm32"))]
+        {
+            log::info!("[Varvara::new] (non-USB/wasm) WASM stub controller");
+            Self::headless()
+        }
+    }
+
+    /// Builds the peripherals without USB or gamepad input threads
+    ///
+    /// For ROMs run without a window, e.g. by [`Machine`] or in tests;
+    /// keyboard input still reaches the controller device.
+    pub fn headless() -> Self {
         Self {
             console: console::Console::new(),
             system: system::System::new(),
@@ -358,107 +411,176 @@ impl Varvara {
             screen: screen::Screen::new(),
             mouse: mouse::Mouse::new(),
             file: file::File::new(),
-            controller: {
-                #[cfg(all(feature = "uses_gilrs", not(target_arch = "wasm32")))]
-                {
-                    log::info!("[Varvara::new] (non-USB/wasm) ControllerGilrs");
-                    Box::new(ControllerGilrs::new(controller::Controller::default()))
-                }
-                #[cfg(any(not(feature = "uses_gilrs"), target_arch = "wasm32"))]
-                {
-                    log::info!("[Varvara::new] (non-USB/wasm) WASM stub controller");
-                    Box::new(controller::Controller::default())
-                }
-            },
+            controller: Box::new(controller::Controller::default()),
             tracker: tracker::Tracker::new(),
             already_warned: [false; 16],
+            #[cfg(all(feature = "uses_usb", not(target_arch = "wasm32")))]
+            uses_usb: false,
             symbols: None,
             last_vector: 0,
+            devices: std::array::from_fn(|_| None),
+            cycles: None,
+            stop: None,
         }
     }
 
     /// Resets the CPU, loading extra data into expansion memory
-    #[cfg(all(feature = "uses_usb", not(target_arch = "wasm32")))]
+    ///
+    /// The controller keeps its input threads, which are only started when
+    /// the devices are built.
     pub fn reset(&mut self, extra: &[u8]) {
         self.system.reset(extra);
-        self.console = console::Console::new();
+        self.console.reset();
         self.audio.reset();
         self.screen = screen::Screen::new();
         self.mouse = mouse::Mouse::new();
-        self.file = file::File::new();
-
-        self.controller = if self.uses_usb {
-            #[cfg(all(
-                feature = "uses_usb",
-                not(target_arch = "wasm32"),
-                feature = "uses_gilrs"
-            ))]
-            {
-                Box::new(controller_usb::ControllerUsb {
-                    rx: controller_usb::spawn_usb_controller_thread(
-                        controller_usb::UsbDeviceConfig::default(),
-                    ),
-                    last_pedal: None,
-                    controller: controller::Controller::default(),
-                    gilrs: Some(ControllerGilrs::new(controller::Controller::default())),
-                })
-            }
-            #[cfg(all(
-                feature = "uses_usb",
-                not(target_arch = "wasm32"),
-                not(feature = "uses_gilrs")
-            ))]
-            {
-                Box::new(controller_usb::ControllerUsb {
-                    rx: controller_usb::spawn_usb_controller_thread(
-                        controller_usb::UsbDeviceConfig::default(),
-                    ),
-                    last_pedal: None,
-                    controller: controller::Controller::default(),
-                })
-            }
-            #[cfg(target_arch = "wasm32")]
-            {
-                // Always use stub controller for WASM
-                Box::new(controller::Controller::default())
-            }
-        } else {
-            #[cfg(all(feature = "uses_gilrs", not(target_arch = "wasm32")))]
-            {
-                Box::new(ControllerGilrs::new(controller::Controller::default()))
-            }
-            #[cfg(any(not(feature = "uses_gilrs"), target_arch = "wasm32"))]
-            {
-                // Always use stub controller for WASM
-                Box::new(controller::Controller::default())
-            }
-        };
+        self.file.reset();
+        self.controller.reset();
         self.tracker = tracker::Tracker::new();
         self.already_warned.fill(false);
+        for d in self.devices.iter_mut().flatten() {
+            d.reset();
+        }
     }
 
-    /// Resets the CPU, loading extra data into expansion memory.
-    #[cfg(any(not(feature = "uses_usb"), target_arch = "wasm32"))]
-    pub fn reset(&mut self, extra: &[u8]) {
-        self.system.reset(extra);
-        self.console = console::Console::new();
-        self.audio.reset();
-        self.screen = screen::Screen::new();
-        self.mouse = mouse::Mouse::new();
-        self.file = file::File::new();
+    /// Checks whether the given device page is used by a built-in device
+    fn is_builtin(page: u8) -> bool {
+        matches!(
+            page,
+            system::SystemPorts::BASE
+                | console::ConsolePorts::BASE
+                | datetime::DatetimePorts::BASE
+                | screen::ScreenPorts::BASE
+                | mouse::MousePorts::BASE
+                | tracker::TrackerPorts::BASE
+                | controller::ControllerPorts::BASE
+        ) || file::FilePorts::matches(page)
+            || audio::AudioPorts::matches(page)
+    }
 
-        self.controller = {
-            #[cfg(feature = "uses_gilrs")]
-            {
-                Box::new(ControllerGilrs::new(controller::Controller::default()))
-            }
-            #[cfg(not(feature = "uses_gilrs"))]
-            {
-                Box::new(controller::Controller::default())
+    /// Registers a custom device at the base address of its [`Ports`] type
+    pub fn register<P: Ports, D: CustomDevice + 'static>(
+        &mut self,
+        dev: D,
+    ) -> Result<(), RegisterError> {
+        self.register_at(P::BASE, Box::new(dev))
+    }
+
+    /// Registers a custom device at the given base address (e.g. `0x70`)
+    ///
+    /// The address must be the start of a device page which isn't used by a
+    /// built-in device or another custom device.
+    pub fn register_at(
+        &mut self,
+        base: u8,
+        dev: Box<dyn CustomDevice>,
+    ) -> Result<(), RegisterError> {
+        if base & 0x0F != 0 {
+            return Err(RegisterError::Unaligned(base));
+        } else if Self::is_builtin(base) {
+            return Err(RegisterError::Builtin(base));
+        }
+        let slot = &mut self.devices[usize::from(base >> 4)];
+        if slot.is_some() {
+            return Err(RegisterError::Occupied(base));
+        }
+        *slot = Some(dev);
+        Ok(())
+    }
+
+    /// Removes the custom device at the given base address, returning it
+    pub fn unregister(&mut self, base: u8) -> Option<Box<dyn CustomDevice>> {
+        self.devices[usize::from(base >> 4)].take()
+    }
+
+    /// Returns a mutable reference to the custom device at the given address
+    pub fn device_mut(&mut self, base: u8) -> Option<&mut dyn CustomDevice> {
+        match &mut self.devices[usize::from(base >> 4)] {
+            Some(d) => Some(d.as_mut()),
+            None => None,
+        }
+    }
+
+    /// Limits the number of instructions run by vectors, or removes the limit
+    ///
+    /// Once the budget is spent, the vector which spent it is interrupted and
+    /// no further vectors are run (see [`Varvara::cycles_exhausted`]).  The
+    /// budget is shared by every vector and is not restored by a reset.
+    pub fn set_cycle_budget(&mut self, cycles: Option<u64>) {
+        self.cycles = cycles;
+    }
+
+    /// Returns the remaining instruction budget, if limited
+    pub fn cycle_budget(&self) -> Option<u64> {
+        self.cycles
+    }
+
+    /// Checks whether the instruction budget has been spent
+    pub fn cycles_exhausted(&self) -> bool {
+        self.cycles == Some(0)
+    }
+
+    /// Sets a flag which another thread can raise to stop the system
+    ///
+    /// Once the flag is set, the running vector is interrupted and the
+    /// instruction budget is spent, so no further vectors are run.
+    pub fn set_stop_flag(&mut self, stop: Option<Arc<AtomicBool>>) {
+        self.stop = stop;
+    }
+
+    /// Runs a vector, subject to the instruction budget and stop flag
+    ///
+    /// Budgeted or stoppable vectors always use the interpreter, as with
+    /// [`Uxn::run_until`].
+    pub fn run(&mut self, vm: &mut Uxn, pc: u16) {
+        let stop = self.stop.clone();
+        let stopped = || stop.as_ref().is_some_and(|s| s.load(Ordering::Relaxed));
+        if self.cycles.is_none() && stop.is_none() {
+            vm.run(self, pc);
+            return;
+        }
+        let budget = self.cycles.unwrap_or(u64::MAX);
+        if budget == 0 || stopped() {
+            self.cycles = Some(0);
+            return;
+        }
+        let count = std::cell::Cell::new(0u64);
+        let done = vm.run_until(self, pc, |_, _, _| {
+            count.set(count.get() + 1);
+            count.get() >= budget || stopped()
+        });
+        if stopped() {
+            self.cycles = Some(0);
+            return;
+        }
+        if self.cycles.is_none() {
+            return;
+        }
+        // The final `BRK` isn't passed to the stop condition
+        let used = count.get() + u64::from(done.is_some());
+        self.cycles = Some(budget.saturating_sub(used));
+        if done.is_none() {
+            warn!("instruction budget exhausted");
+        }
+    }
+
+    /// Polls every custom device, processing any resulting events
+    ///
+    /// Each device is polled for at most 4096 events; any left over are
+    /// processed by the next call.  Returns the number of events processed.
+    pub fn poll_devices(&mut self, vm: &mut Uxn) -> usize {
+        let mut n = 0;
+        for i in 0..self.devices.len() {
+            let base = (i as u8) << 4;
+            for _ in 0..MAX_DEVICE_EVENTS {
+                let Some(e) = self.devices[i].as_mut().and_then(|d| d.update(vm, base)) else {
+                    break;
+                };
+                self.process_event(vm, e);
+                n += 1;
             }
-        };
-        self.tracker = tracker::Tracker::new();
-        self.already_warned.fill(false);
+        }
+        n
     }
 
     /// Checks whether the SHIFT key is currently down
@@ -492,19 +614,32 @@ impl Varvara {
     pub fn output(&mut self, vm: &Uxn) -> Output<'_> {
         Output {
             size: self.screen.size(),
+            palette: self.screen.palette(vm),
             frame: self.screen.frame(vm),
             hide_mouse: self.mouse.active(),
             stdout: self.console.stdout(),
             stderr: self.console.stderr(),
             exit: self.system.exit(),
+            metadata: self.system.metadata(),
         }
     }
 
+    /// Returns the metadata provided by the ROM, if any
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.system.metadata()
+    }
+
     /// Sends arguments to the console device
     ///
     /// Leaves the console type set to `stdin`, and returns the current output
     /// state of the system
     pub fn send_args(&mut self, vm: &mut Uxn, args: &[String]) -> Output<'_> {
+        self.push_args(vm, args);
+        self.output(vm)
+    }
+
+    /// Sends arguments to the console device, leaving the type set to `stdin`
+    pub(crate) fn push_args(&mut self, vm: &mut Uxn, args: &[String]) {
         for (i, a) in args.iter().enumerate() {
             self.console.set_type(vm, console::Type::Argument);
             for c in a.bytes() {
@@ -520,15 +655,14 @@ impl Varvara {
             self.process_event(vm, self.console.update(vm, b'\n'));
         }
         self.console.set_type(vm, console::Type::Stdin);
-        self.output(vm)
     }
 
     /// Send a character from the keyboard (controller) device
     /// Send a character from the keyboard (controller) device
     pub fn char(&mut self, vm: &mut Uxn, k: u8) {
-        println!("Sending character: {k}");
+        log::debug!("Sending character: {k}");
         let e = self.controller.char(vm, k);
-        println!("Processing event: {e:?}");
+        log::debug!("Processing event: {e:?}");
         self.process_event(vm, e);
     }
 
@@ -536,12 +670,12 @@ impl Varvara {
     /// Press a key on the controller device
     pub fn pressed(&mut self, vm: &mut Uxn, k: Key, repeat: bool) {
         // Only send pressed events for non-character keys
-        println!("Pressed key: {k:?}");
+        log::debug!("Pressed key: {k:?}");
         if let Key::Char(k) = k {
             // Do nothing, character keys are handled by char()
             self.char(vm, k);
         } else if let Some(e) = self.controller.pressed(vm, k, repeat) {
-            println!("Processing event: {e:?}");
+            log::debug!("Processing event: {e:?}");
             self.process_event(vm, e);
         }
     }
@@ -554,11 +688,24 @@ impl Varvara {
     }
 
     /// Send a character from the console device
+    ///
+    /// The console type is set to `stdin` before calling the vector.
     pub fn console(&mut self, vm: &mut Uxn, c: u8) {
+        self.console.set_type(vm, console::Type::Stdin);
         let e = self.console.update(vm, c);
         self.process_event(vm, e);
     }
 
+    /// Signals the end of stdin to the console device
+    ///
+    /// As in the reference `uxncli`, this calls the console vector with a
+    /// zero character and the type set to `end` (`0x04`).
+    pub fn console_eof(&mut self, vm: &mut Uxn) {
+        self.console.set_type(vm, console::Type::ArgumentEnd);
+        let e = self.console.update(vm, 0);
+        self.process_event(vm, e);
+    }
+
     /// Updates the mouse state
     pub fn mouse(&mut self, vm: &mut Uxn, m: MouseState) {
         if let Some(e) = self.mouse.update(vm, m) {
@@ -592,24 +739,26 @@ impl Varvara {
             let skip_print = skip_labels.contains(&label);
 
             if self.last_vector != e.vector && !skip_print {
-                println!(
+                log::debug!(
                     "[VARVARA][process_event] vector: 0x{:04x} [{}], data: {:?}",
-                    e.vector, label, e.data
+                    e.vector,
+                    label,
+                    e.data
                 );
                 self.last_vector = e.vector;
             }
 
             if let Some(d) = e.data {
                 if !skip_print {
-                    println!("[VARVARA][process_event] write_dev_mem addr: 0x{:02x}, value: 0x{:02x} ('{}')", d.addr, d.value, d.value as char);
+                    log::debug!("[VARVARA][process_event] write_dev_mem addr: 0x{:02x}, value: 0x{:02x} ('{}')", d.addr, d.value, d.value as char);
                 }
                 vm.write_dev_mem(d.addr, d.value);
             }
-            vm.run(self, e.vector);
+            self.run(vm, e.vector);
             if let Some(d) = e.data {
                 if d.clear {
                     if !skip_print {
-                        println!("[VARVARA][process_event] clear addr: 0x{:02x}", d.addr);
+                        log::debug!("[VARVARA][process_event] clear addr: 0x{:02x}", d.addr);
                     }
                     vm.write_dev_mem(d.addr, 0);
                 }
@@ -0,0 +1,146 @@
+use crate::{Output, Runner, Varvara};
+use std::mem::ManuallyDrop;
+use std::time::Duration;
+use uxn::{Backend, Uxn};
+
+/// Owning bundle of RAM, CPU and Varvara devices
+///
+/// [`Uxn`] borrows its RAM, which makes it awkward to store or move between
+/// threads; this type allocates the RAM itself and frees it when dropped.  It
+/// is `Send`, so machines can be built on one thread and run on another (e.g.
+/// in a worker pool).
+///
+/// Common operations are forwarded to the inner [`Runner`]; use
+/// [`Machine::with`] for full mutable access to the CPU.
+pub struct Machine {
+    /// Runner borrowing `ram`; dropped manually before `ram` is freed
+    runner: ManuallyDrop<Runner<'static>>,
+    /// RAM allocated with `Box::into_raw`, owned by this machine
+    ram: *mut [u8; 65536],
+}
+
+// SAFETY: `ram` is a unique heap allocation which is only reachable through
+// this machine, and the runner is itself `Send` (checked below).
+unsafe impl Send for Machine {}
+
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Runner<'static>>();
+};
+
+impl Machine {
+    /// Builds a new machine with zeroed RAM and [headless](Varvara::headless)
+    /// devices
+    pub fn new(backend: Backend) -> Self {
+        Self::with_devices(backend, Varvara::headless())
+    }
+
+    /// Builds a new machine with zeroed RAM and the given devices
+    pub fn with_devices(backend: Backend, dev: Varvara) -> Self {
+        let ram: Box<[u8; 65536]> = vec![0u8; 65536].into_boxed_slice().try_into().unwrap();
+        let ram = Box::into_raw(ram);
+        // SAFETY: `ram` is a fresh allocation which stays alive until `drop`,
+        // after the runner (and every borrow of the RAM) is gone.  The
+        // `'static` lifetime never escapes this type: the runner is only
+        // exposed through shared references or `with`, which shortens it.
+        let vm = Uxn::new(unsafe { &mut *ram }, backend);
+        Self {
+            runner: ManuallyDrop::new(Runner::new(vm, dev)),
+            ram,
+        }
+    }
+
+    /// Calls a function with mutable access to the runner
+    ///
+    /// The runner's lifetime is local to the closure, so the CPU can't be
+    /// swapped with one that outlives this machine's RAM.
+    pub fn with<R>(&mut self, f: impl for<'a> FnOnce(&mut Runner<'a>) -> R) -> R {
+        let runner: &mut Runner<'static> = &mut self.runner;
+        // SAFETY: shortening the lifetime is sound because the closure can't
+        // smuggle a `Runner<'a>` (or anything borrowing it) out, since `R`
+        // can't name `'a`.
+        f(unsafe { &mut *(runner as *mut Runner<'static>).cast::<Runner<'_>>() })
+    }
+
+    /// Returns the runner
+    pub fn runner(&self) -> &Runner<'_> {
+        &self.runner
+    }
+
+    /// Returns the CPU
+    pub fn vm(&self) -> &Uxn<'_> {
+        &self.runner.vm
+    }
+
+    /// Returns the devices
+    pub fn dev(&self) -> &Varvara {
+        &self.runner.dev
+    }
+
+    /// Returns the devices mutably
+    pub fn dev_mut(&mut self) -> &mut Varvara {
+        &mut self.runner.dev
+    }
+
+    /// Converts into a runner which borrows RAM for the rest of the program
+    ///
+    /// The RAM is leaked, as with [`UxnRam::leak`](uxn::UxnRam::leak); this is
+    /// intended for frontends which run a single machine until they exit.
+    pub fn leak(self) -> Runner<'static> {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the RAM is never freed and the
+        // runner is taken exactly once.
+        unsafe { ManuallyDrop::take(&mut this.runner) }
+    }
+
+    /// Loads a ROM; see [`Runner::load`]
+    pub fn load(&mut self, rom: &[u8], args: &[String]) {
+        self.runner.load(rom, args)
+    }
+
+    /// Checks whether the ROM is still running; see [`Runner::is_running`]
+    pub fn is_running(&self) -> bool {
+        self.runner.is_running()
+    }
+
+    /// Returns the exit code; see [`Runner::exit`]
+    pub fn exit(&self) -> Option<i32> {
+        self.runner.exit()
+    }
+
+    /// Sends a console character; see [`Runner::console`]
+    pub fn console(&mut self, c: u8) {
+        self.runner.console(c)
+    }
+
+    /// Signals the end of stdin; see [`Runner::console_eof`]
+    pub fn console_eof(&mut self) {
+        self.runner.console_eof()
+    }
+
+    /// Polls custom devices; see [`Runner::poll`]
+    pub fn poll(&mut self) -> bool {
+        self.runner.poll()
+    }
+
+    /// Advances the frame clock; see [`Runner::advance`]
+    pub fn advance(&mut self, now: Duration) -> bool {
+        self.runner.advance(now)
+    }
+
+    /// Returns the current output; see [`Runner::output`]
+    pub fn output(&mut self) -> Output<'_> {
+        self.runner.output()
+    }
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        // SAFETY: the runner is dropped exactly once, before the RAM that it
+        // borrows, and `ram` came from `Box::into_raw`.
+        unsafe {
+            ManuallyDrop::drop(&mut self.runner);
+            drop(Box::from_raw(self.ram));
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! ROM metadata, which a ROM exposes by writing its address to the
+//! `System/metadata` port.
+//!
+//! The metadata block begins with a version byte (`00`), followed by a
+//! null-terminated text body.  The first line of the text is the ROM's name
+//! (optionally followed by a version), and the remaining lines are a free-form
+//! description.  After the terminator comes a count byte and that many
+//! extended fields, each of which is a type byte followed by an address:
+//!
+//! | Type   | Contents                                      |
+//! |--------|-----------------------------------------------|
+//! | `0x83` | 24×24 icon, as 3×3 tiles in `chr` (2bpp) form  |
+//! | `0x88` | 64×64 icon, as 8×8 tiles in `icn` (1bpp) form  |
+use crate::system::SystemPorts;
+use uxn::{Device, Uxn};
+
+/// Maximum length of the text body, to avoid reading all of RAM
+const MAX_TEXT: usize = 1024;
+
+/// Instruction budget for [`Metadata::from_rom`]
+const PROBE_BUDGET: usize = 100_000;
+
+/// Metadata describing a ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// Name of the ROM (the first line of the text, without a version)
+    pub name: String,
+    /// Version, if the first line ends with something that looks like one
+    pub version: Option<String>,
+    /// Remaining lines of the text
+    pub description: String,
+    /// Application icon, if present
+    pub icon: Option<Icon>,
+}
+
+/// Application icon from ROM metadata
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Icon {
+    /// Width in pixels
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+    /// Row-major palette indices (`0..4`)
+    pub pixels: Vec<u8>,
+}
+
+impl Icon {
+    /// Decodes a square icon made of `tiles × tiles` sprites
+    fn read(vm: &Uxn, mut addr: u16, tiles: u16, two_bpp: bool) -> Self {
+        let size = tiles * 8;
+        let mut pixels = vec![0u8; usize::from(size) * usize::from(size)];
+        for ty in 0..tiles {
+            for tx in 0..tiles {
+                for y in 0..8 {
+                    let lo = vm.ram_read_byte(addr.wrapping_add(y));
+                    let hi = if two_bpp {
+                        vm.ram_read_byte(addr.wrapping_add(y + 8))
+                    } else {
+                        0
+                    };
+                    for x in 0..8 {
+                        let bit = 7 - x;
+                        let c = ((lo >> bit) & 1) | (((hi >> bit) & 1) << 1);
+                        let px = usize::from(tx * 8 + x);
+                        let py = usize::from(ty * 8 + y);
+                        pixels[py * usize::from(size) + px] = c;
+                    }
+                }
+                addr = addr.wrapping_add(if two_bpp { 16 } else { 8 });
+            }
+        }
+        Icon {
+            width: size,
+            height: size,
+            pixels,
+        }
+    }
+
+    /// Converts the icon to RGBA bytes using the given `0xAARRGGBB` palette
+    ///
+    /// Color 0 is treated as transparent.
+    pub fn to_rgba(&self, palette: [u32; 4]) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&c| {
+                let [_, r, g, b] = palette[usize::from(c & 3)].to_be_bytes();
+                [r, g, b, if c == 0 { 0 } else { 0xFF }]
+            })
+            .collect()
+    }
+}
+
+impl Metadata {
+    /// Parses the metadata block at the given address in RAM
+    ///
+    /// Returns `None` if the block has an unknown version
+    pub fn read(vm: &Uxn, addr: u16) -> Option<Self> {
+        let version = vm.ram_read_byte(addr);
+        if version != 0 {
+            log::warn!("unknown metadata version {version:#04x} at {addr:#06x}");
+            return None;
+        }
+        let mut text = vec![];
+        let mut pos = addr.wrapping_add(1);
+        loop {
+            let c = vm.ram_read_byte(pos);
+            pos = pos.wrapping_add(1);
+            if c == 0 {
+                break;
+            } else if text.len() == MAX_TEXT {
+                log::warn!("metadata text at {addr:#06x} is not terminated");
+                break;
+            }
+            text.push(c);
+        }
+        let text = String::from_utf8_lossy(&text);
+        let (first, description) = text.split_once('\n').unwrap_or((&text, ""));
+        let first = first.trim();
+        let (name, version) = match first.rsplit_once(' ') {
+            Some((n, v)) if Self::is_version(v) => (n.trim_end(), Some(v.to_owned())),
+            _ => (first, None),
+        };
+
+        let mut icon = None;
+        let count = vm.ram_read_byte(pos);
+        for i in 0..u16::from(count) {
+            let field = pos.wrapping_add(1 + i * 3);
+            let ty = vm.ram_read_byte(field);
+            let hi = vm.ram_read_byte(field.wrapping_add(1));
+            let lo = vm.ram_read_byte(field.wrapping_add(2));
+            let data = u16::from_be_bytes([hi, lo]);
+            match ty {
+                0x83 => icon = Some(Icon::read(vm, data, 3, true)),
+                0x88 if icon.is_none() => icon = Some(Icon::read(vm, data, 8, false)),
+                _ => (),
+            }
+        }
+
+        Some(Metadata {
+            name: name.to_owned(),
+            version,
+            description: description.trim().to_owned(),
+            icon,
+        })
+    }
+
+    /// Extracts metadata from a ROM file without running it to completion
+    ///
+    /// The reset vector is run (with all devices stubbed out) until it writes
+    /// to the `System/metadata` port, or until an instruction budget runs out.
+    pub fn from_rom(rom: &[u8]) -> Option<Self> {
+        let mut ram = uxn::UxnRam::new();
+        let mut vm = Uxn::new(&mut ram, uxn::Backend::Interpreter);
+        let _ = vm.reset(rom);
+        let mut probe = Probe(None);
+        vm.run_until(&mut probe, 0x100, |_, p, i| {
+            p.0.is_some() || i >= PROBE_BUDGET
+        });
+        probe.0.and_then(|addr| Self::read(&vm, addr))
+    }
+
+    /// Checks whether a word looks like a version (`1.2`, `v3`, etc)
+    fn is_version(s: &str) -> bool {
+        let s = s.strip_prefix(['v', 'V']).unwrap_or(s);
+        s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || ".-/".contains(c))
+    }
+}
+
+/// Device which records the address written to `System/metadata`
+struct Probe(Option<u16>);
+
+impl Device for Probe {
+    fn dei(&mut self, _vm: &mut Uxn, _target: u8) {}
+    fn deo(&mut self, vm: &mut Uxn, target: u8) -> bool {
+        if target == SystemPorts::METADATA {
+            self.0 = Some(vm.dev::<SystemPorts>().metadata_addr());
+        }
+        true
+    }
+}
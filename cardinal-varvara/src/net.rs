@@ -0,0 +1,411 @@
+//! Network socket device
+//!
+//! The device lets a ROM open a single TCP connection or UDP socket.  Incoming
+//! bytes are streamed into the `read` port one at a time, calling the vector
+//! for each, in the same way as the console device.
+//!
+//! | Port   | Name     | Description                                          |
+//! |--------|----------|------------------------------------------------------|
+//! | `0x0`  | `vector` | Called for each event                                |
+//! | `0x2`  | `read`   | Received byte (when `type` is [`EventType::Data`])   |
+//! | `0x3`  | `type`   | Event type, see [`EventType`]                        |
+//! | `0x4`  | `addr`   | Address of a null-terminated `host:port` string      |
+//! | `0x6`  | `open`   | Write `1` (TCP) or `2` (UDP) to connect, `0` to close|
+//! | `0x7`  | `status` | Read the current [`Status`]                          |
+//! | `0x8`  | `write`  | Appends a byte to the outgoing buffer                |
+//! | `0x9`  | `flush`  | Sends the outgoing buffer (as one datagram for UDP)  |
+use crate::{CustomDevice, Event, EventData};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::mem::offset_of;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{mpsc, Arc};
+use uxn::{Ports, Uxn};
+use zerocopy::{BigEndian, U16};
+
+#[derive(zerocopy::IntoBytes, zerocopy::FromBytes, zerocopy::KnownLayout, zerocopy::Immutable)]
+#[repr(C)]
+/// Port layout for the network device
+pub struct NetPorts {
+    vector: U16<BigEndian>,
+    read: u8,
+    type_: u8,
+    addr: U16<BigEndian>,
+    open: u8,
+    status: u8,
+    write: u8,
+    flush: u8,
+    _pad: [u8; 6],
+}
+
+impl Ports for NetPorts {
+    /// Default page for the network device, which is unused by Varvara
+    const BASE: u8 = 0x70;
+}
+
+impl NetPorts {
+    const READ: u8 = offset_of!(Self, read) as u8;
+    const OPEN: u8 = offset_of!(Self, open) as u8;
+    const STATUS: u8 = offset_of!(Self, status) as u8;
+    const WRITE: u8 = offset_of!(Self, write) as u8;
+    const FLUSH: u8 = offset_of!(Self, flush) as u8;
+}
+
+/// Value stored in the `type` port when the vector is called
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventType {
+    /// A byte was received and is stored in the `read` port
+    Data = 1,
+    /// The connection is open
+    Connected = 2,
+    /// The connection was closed by the remote peer
+    Closed = 3,
+    /// The connection failed or was denied by the [`Policy`]
+    Error = 4,
+}
+
+/// Value read from the `status` port
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// No connection is open
+    Closed = 0,
+    /// A connection is in progress
+    Connecting = 1,
+    /// The connection is open
+    Open = 2,
+}
+
+/// Permission policy deciding which hosts a ROM may connect to
+#[derive(Clone, Debug, Default)]
+pub enum Policy {
+    /// Only loopback addresses are allowed
+    #[default]
+    LocalhostOnly,
+    /// Loopback addresses and the listed hosts (or `host:port` pairs) are
+    /// allowed
+    Allow(Vec<String>),
+    /// Any host is allowed
+    Any,
+}
+
+impl Policy {
+    /// Checks whether `target` (a `host:port` string) may be looked up
+    ///
+    /// This runs before DNS resolution, so that denied hosts are never
+    /// resolved: only `localhost`, loopback addresses and, for
+    /// [`Policy::Allow`], the listed hosts pass.
+    pub fn may_resolve(&self, target: &str) -> bool {
+        let host = Self::host(target);
+        let loopback = host.eq_ignore_ascii_case("localhost")
+            || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+        match self {
+            Policy::LocalhostOnly => loopback,
+            Policy::Allow(hosts) => loopback || Self::listed(hosts, target),
+            Policy::Any => true,
+        }
+    }
+
+    /// Checks whether a connection to `target` (a `host:port` string) is allowed
+    pub fn check(&self, target: &str, addrs: &[SocketAddr]) -> bool {
+        let loopback = !addrs.is_empty() && addrs.iter().all(|a| a.ip().is_loopback());
+        match self {
+            Policy::LocalhostOnly => loopback,
+            Policy::Allow(hosts) => loopback || Self::listed(hosts, target),
+            Policy::Any => true,
+        }
+    }
+
+    /// Returns the host part of a `host:port` string, without brackets
+    fn host(target: &str) -> &str {
+        let host = target.rsplit_once(':').map(|(h, _)| h).unwrap_or(target);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    fn listed(hosts: &[String], target: &str) -> bool {
+        let host = Self::host(target);
+        hosts
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(host) || h.eq_ignore_ascii_case(target))
+    }
+}
+
+/// Open socket, one half of which is owned by each background thread
+enum Conn {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+/// Messages sent from the background thread to the device
+enum Message {
+    Connected,
+    Data(Vec<u8>),
+    Closed,
+    Error,
+}
+
+/// Network socket device
+pub struct Net {
+    policy: Policy,
+    /// Held while the connection is open; the UDP reader exits once the
+    /// device lets go of it
+    alive: Arc<()>,
+    rx: Option<mpsc::Receiver<Message>>,
+    /// Outgoing buffers, sent by the writer thread
+    tx: Option<mpsc::Sender<Vec<u8>>>,
+    status: Status,
+    outgoing: Vec<u8>,
+    incoming: VecDeque<u8>,
+}
+
+impl Net {
+    /// Builds a new network device with the given permission policy
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            alive: Arc::new(()),
+            rx: None,
+            tx: None,
+            status: Status::Closed,
+            outgoing: vec![],
+            incoming: VecDeque::new(),
+        }
+    }
+
+    /// Returns the current connection status
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Reads a null-terminated string from RAM
+    fn read_str(vm: &Uxn, mut addr: u16) -> String {
+        let mut out = vec![];
+        loop {
+            let c = vm.ram_read_byte(addr);
+            if c == 0 || out.len() >= 256 {
+                break;
+            }
+            out.push(c);
+            addr = addr.wrapping_add(1);
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    fn close(&mut self) {
+        // The writer thread sends anything already flushed, then shuts the
+        // socket down, which also ends the reader.  A thread that is still
+        // connecting finds both channels closed and gives up.
+        self.alive = Arc::new(());
+        self.tx = None;
+        self.rx = None;
+        self.status = Status::Closed;
+        self.outgoing.clear();
+        self.incoming.clear();
+    }
+
+    /// Starts connecting in a background thread, which then reads from the
+    /// socket until it's closed, and hands the other half of the socket to a
+    /// writer thread.
+    fn open(&mut self, target: String, udp: bool) {
+        self.close();
+        let (tx, rx) = mpsc::channel();
+        self.rx = Some(rx);
+        let (out_tx, out_rx) = mpsc::channel::<Vec<u8>>();
+        self.tx = Some(out_tx);
+        self.status = Status::Connecting;
+
+        // Each connection gets its own channels, so a stale thread can't
+        // clobber a newer connection.
+        let alive = self.alive.clone();
+        let policy = self.policy.clone();
+        std::thread::spawn(move || {
+            if !policy.may_resolve(&target) {
+                log::warn!("connection to {target:?} denied by policy");
+                let _ = tx.send(Message::Error);
+                return;
+            }
+            let addrs: Vec<SocketAddr> = match target.to_socket_addrs() {
+                Ok(a) => a.collect(),
+                Err(e) => {
+                    log::warn!("could not resolve {target:?}: {e}");
+                    let _ = tx.send(Message::Error);
+                    return;
+                }
+            };
+            if !policy.check(&target, &addrs) {
+                log::warn!("connection to {target:?} denied by policy");
+                let _ = tx.send(Message::Error);
+                return;
+            }
+            let mut reader = match Self::connect(&addrs, udp) {
+                Ok((w, r)) => {
+                    std::thread::spawn(move || Self::write(w, out_rx));
+                    r
+                }
+                Err(e) => {
+                    log::warn!("could not connect to {target:?}: {e}");
+                    let _ = tx.send(Message::Error);
+                    return;
+                }
+            };
+            if tx.send(Message::Connected).is_err() {
+                // The device was closed while we were connecting, so the
+                // writer has already shut the socket down
+                return;
+            }
+            let mut buf = [0u8; 2048];
+            loop {
+                let msg = match &mut reader {
+                    Conn::Tcp(s) => match s.read(&mut buf) {
+                        Ok(0) | Err(..) => Message::Closed,
+                        Ok(n) => Message::Data(buf[..n].to_vec()),
+                    },
+                    Conn::Udp(s) => match s.recv(&mut buf) {
+                        Err(e)
+                            if matches!(
+                                e.kind(),
+                                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                            ) =>
+                        {
+                            if Arc::strong_count(&alive) == 1 {
+                                break;
+                            }
+                            continue;
+                        }
+                        Err(..) => Message::Closed,
+                        Ok(n) => Message::Data(buf[..n].to_vec()),
+                    },
+                };
+                let done = matches!(msg, Message::Closed);
+                if tx.send(msg).is_err() || done {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Opens a socket, returning a writer and a reader for it
+    fn connect(addrs: &[SocketAddr], udp: bool) -> std::io::Result<(Conn, Conn)> {
+        if udp {
+            let any: SocketAddr = if addrs.first().is_some_and(|a| a.is_ipv6()) {
+                "[::]:0".parse().unwrap()
+            } else {
+                "0.0.0.0:0".parse().unwrap()
+            };
+            let s = UdpSocket::bind(any)?;
+            s.connect(addrs)?;
+            let r = s.try_clone()?;
+            // UDP sockets are never closed by the peer, so the reader polls
+            // to notice when the device has closed the socket.
+            r.set_read_timeout(Some(std::time::Duration::from_millis(100)))?;
+            Ok((Conn::Udp(s), Conn::Udp(r)))
+        } else {
+            let s = TcpStream::connect(addrs)?;
+            s.set_nodelay(true)?;
+            let r = s.try_clone()?;
+            Ok((Conn::Tcp(s), Conn::Tcp(r)))
+        }
+    }
+
+    /// Sends each flushed buffer until the device closes the connection,
+    /// then shuts a TCP socket down
+    fn write(mut conn: Conn, rx: mpsc::Receiver<Vec<u8>>) {
+        for data in rx {
+            let r = match &mut conn {
+                Conn::Tcp(s) => s.write_all(&data),
+                Conn::Udp(s) => s.send(&data).map(|_| ()),
+            };
+            if let Err(e) = r {
+                log::warn!("network write failed: {e}");
+            }
+        }
+        if let Conn::Tcp(s) = conn {
+            let _ = s.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    /// Hands the outgoing buffer to the writer thread, so a slow peer can't
+    /// block the VM
+    fn flush(&mut self) {
+        let data = std::mem::take(&mut self.outgoing);
+        if self.status != Status::Open {
+            return;
+        }
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(data);
+        }
+    }
+}
+
+impl CustomDevice for Net {
+    fn as_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        let base = target & 0xF0;
+        let v = vm.dev_at::<NetPorts>(base);
+        match target & 0x0F {
+            NetPorts::OPEN => match v.open {
+                0 => self.close(),
+                kind @ (1 | 2) => {
+                    let target = Self::read_str(vm, v.addr.get());
+                    self.open(target, kind == 2);
+                }
+                kind => log::warn!("invalid network socket kind {kind}"),
+            },
+            NetPorts::WRITE => self.outgoing.push(v.write),
+            NetPorts::FLUSH => self.flush(),
+            _ => (),
+        }
+    }
+
+    fn dei(&mut self, vm: &mut Uxn, target: u8) {
+        if target & 0x0F == NetPorts::STATUS {
+            vm.dev_mut_at::<NetPorts>(target & 0xF0).status = self.status as u8;
+        }
+    }
+
+    fn update(&mut self, vm: &mut Uxn, base: u8) -> Option<Event> {
+        let ty = if let Some(c) = self.incoming.pop_front() {
+            let p = vm.dev_mut_at::<NetPorts>(base);
+            p.type_ = EventType::Data as u8;
+            return Some(Event {
+                vector: p.vector.get(),
+                data: Some(EventData {
+                    addr: base | NetPorts::READ,
+                    value: c,
+                    clear: false,
+                }),
+            });
+        } else {
+            match self.rx.as_ref()?.try_recv().ok()? {
+                Message::Data(d) => {
+                    self.incoming.extend(d);
+                    return self.update(vm, base);
+                }
+                Message::Connected => {
+                    self.status = Status::Open;
+                    EventType::Connected
+                }
+                Message::Closed => {
+                    self.close();
+                    EventType::Closed
+                }
+                Message::Error => {
+                    self.close();
+                    EventType::Error
+                }
+            }
+        };
+        let p = vm.dev_mut_at::<NetPorts>(base);
+        p.type_ = ty as u8;
+        Some(Event {
+            vector: p.vector.get(),
+            data: None,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.close();
+    }
+}
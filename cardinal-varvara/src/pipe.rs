@@ -0,0 +1,78 @@
+//! Console bridges between VMs
+//!
+//! A pipe connects the `Console/write` port of one VM to the `Console/read`
+//! port of another, like a Unix pipe: every byte the source writes to stdout
+//! is delivered to the destination's console vector, as if it had been read
+//! from the destination's stdin.
+//!
+//! The two ends may live on different threads.  The source end is a console
+//! listener, so it survives a [`Varvara::reset`]; the destination is pumped
+//! explicitly by the host with [`PipeReader::pump`].
+use crate::{Runner, Varvara};
+use std::sync::mpsc;
+
+/// Receiving end of a console pipe
+pub struct PipeReader {
+    rx: mpsc::Receiver<u8>,
+    closed: bool,
+}
+
+impl Varvara {
+    /// Opens a pipe which receives every byte written to this system's stdout
+    ///
+    /// Stdout is still buffered and returned by [`Varvara::output`] as usual.
+    /// A system may feed any number of pipes; once a [`PipeReader`] is
+    /// dropped, its listener is removed the next time the system writes.
+    pub fn pipe_stdout(&mut self) -> PipeReader {
+        let (tx, rx) = mpsc::channel();
+        self.console
+            .register_stdout_listener_until(move |c| tx.send(c).is_ok());
+        PipeReader { rx, closed: false }
+    }
+}
+
+impl PipeReader {
+    /// Delivers pending bytes to the destination's console
+    ///
+    /// Returns the number of bytes delivered.  Bytes are consumed even if the
+    /// destination has exited.  Once the source is dropped and every byte has
+    /// been delivered, the destination is sent end-of-input (see
+    /// [`Runner::console_eof`]).
+    pub fn pump(&mut self, dst: &mut Runner) -> usize {
+        self.pump_at_most(dst, usize::MAX)
+    }
+
+    /// Delivers at most `max` pending bytes, as [`PipeReader::pump`]
+    ///
+    /// The rest are left for a later call.
+    pub fn pump_at_most(&mut self, dst: &mut Runner, max: usize) -> usize {
+        let mut n = 0;
+        while n < max {
+            match self.rx.try_recv() {
+                Ok(c) => {
+                    dst.console(c);
+                    n += 1;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    if !self.closed {
+                        dst.console_eof();
+                        self.closed = true;
+                    }
+                    break;
+                }
+            }
+        }
+        n
+    }
+
+    /// Drops pending bytes without delivering them, returning how many
+    pub fn discard(&mut self) -> usize {
+        self.rx.try_iter().count()
+    }
+
+    /// Checks whether the source has been dropped and every byte delivered
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
@@ -0,0 +1,182 @@
+//! Hot reloading of a running ROM
+//!
+//! `uxntal --watch` reassembles a ROM whenever its sources change and sends
+//! each build to an emulator started with `cardinal-gui --listen`, which
+//! swaps it in with [`Runner::reload`](crate::Runner::reload).
+//!
+//! A request is one TCP connection carrying a [`Reload`]:
+//!
+//! | Bytes  | Contents                                              |
+//! |--------|-------------------------------------------------------|
+//! | 4      | `UXNR`                                                |
+//! | 1      | Version, currently `1`                                |
+//! | 4 + n  | Big-endian ROM length, then the ROM                   |
+//! | 1      | `1` if a symbol file follows, else `0`                |
+//! | 4 + n  | Big-endian `.sym` length, then the file, if present   |
+//! | 2 + 4n | Number of RAM ranges to keep, then each first and last address |
+//!
+//! and the listener answers with a single `1` byte once the build has been
+//! queued.  Anyone who can connect can replace the running program, so
+//! only loopback connections are accepted.
+use log::warn;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// Address used by `uxntal --watch` and `cardinal-gui --listen` by default
+pub const DEFAULT_ADDR: &str = "127.0.0.1:30072";
+
+const MAGIC: &[u8; 4] = b"UXNR";
+const VERSION: u8 = 1;
+const ACK: u8 = 1;
+const MAX_SYM: usize = 16 << 20;
+
+/// How long the listener waits on a client before moving on to the next
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A new build of the running ROM
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reload {
+    /// ROM contents, starting at `0x0100`
+    pub rom: Vec<u8>,
+    /// Symbol file, in the `.sym` format
+    pub sym: Option<Vec<u8>>,
+    /// RAM to carry over from the running program
+    pub keep: Vec<RangeInclusive<u16>>,
+}
+
+impl Reload {
+    /// Serializes the request
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.extend_from_slice(&(self.rom.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.rom);
+        match &self.sym {
+            Some(sym) => {
+                out.push(1);
+                out.extend_from_slice(&(sym.len() as u32).to_be_bytes());
+                out.extend_from_slice(sym);
+            }
+            None => out.push(0),
+        }
+        out.extend_from_slice(&(self.keep.len() as u16).to_be_bytes());
+        for r in &self.keep {
+            out.extend_from_slice(&r.start().to_be_bytes());
+            out.extend_from_slice(&r.end().to_be_bytes());
+        }
+        out
+    }
+
+    /// Reads a request written by [`Reload::encode`]
+    pub fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(invalid("not a reload request"));
+        }
+        let rom = read_block(r, 0x10000 - 0x100, "ROM too large")?;
+        let sym = match read_u8(r)? {
+            0 => None,
+            1 => Some(read_block(r, MAX_SYM, "symbol file too large")?),
+            _ => return Err(invalid("bad symbol flag")),
+        };
+        let mut count = [0u8; 2];
+        r.read_exact(&mut count)?;
+        let mut keep = Vec::new();
+        for _ in 0..u16::from_be_bytes(count) {
+            let mut range = [0u8; 4];
+            r.read_exact(&mut range)?;
+            let start = u16::from_be_bytes([range[0], range[1]]);
+            let end = u16::from_be_bytes([range[2], range[3]]);
+            keep.push(start..=end);
+        }
+        Ok(Self { rom, sym, keep })
+    }
+
+    /// Sends the request to a listening emulator, waiting until it has been
+    /// accepted
+    pub fn send<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.write_all(&self.encode())?;
+        let mut ack = [0u8];
+        stream.read_exact(&mut ack)?;
+        if ack[0] != ACK {
+            return Err(io::Error::other("reload was not accepted"));
+        }
+        Ok(())
+    }
+}
+
+/// Parses RAM ranges such as `0000-00ff,8000`, in hex
+pub fn parse_ranges(s: &str) -> Option<Vec<RangeInclusive<u16>>> {
+    s.split(',')
+        .map(|r| {
+            let (start, end) = r.split_once('-').unwrap_or((r, r));
+            let start = u16::from_str_radix(start.trim(), 16).ok()?;
+            let end = u16::from_str_radix(end.trim(), 16).ok()?;
+            (start <= end).then_some(start..=end)
+        })
+        .collect()
+}
+
+/// Spawns a thread which accepts reload requests on `addr`, passing each to
+/// `tx`
+///
+/// Returns the address actually bound, e.g. when `addr` asks for port 0.
+/// The thread stops once `tx` returns an error.
+pub fn spawn_listener<A, F, E>(addr: A, mut tx: F) -> io::Result<SocketAddr>
+where
+    A: ToSocketAddrs,
+    F: FnMut(Reload) -> Result<(), E> + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            if !stream.peer_addr().is_ok_and(|a| a.ip().is_loopback()) {
+                continue;
+            }
+            // An idle client mustn't hold up later reloads
+            if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+                continue;
+            }
+            match Reload::decode(&mut stream) {
+                Ok(reload) => {
+                    if tx(reload).is_err() {
+                        return;
+                    }
+                    let _ = stream.write_all(&[ACK]);
+                }
+                Err(e) => warn!("ignoring reload request: {e}"),
+            }
+        }
+    });
+    Ok(local)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut b = [0u8];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+/// Reads a length-prefixed block of at most `max` bytes
+fn read_block<R: Read>(r: &mut R, max: usize, too_large: &str) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max {
+        return Err(invalid(too_large));
+    }
+    let mut data = vec![0u8; len];
+    r.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,166 @@
+use crate::{Output, Varvara};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use uxn::Uxn;
+
+/// Interval between screen vector calls (60 Hz)
+pub const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+/// Host-driven runner which owns a CPU and its Varvara devices
+///
+/// The host supplies time and input; the runner calls vectors and reports the
+/// results.  Unlike [`Output::check`], it never terminates the process: when
+/// the ROM writes to `System/state`, the exit code is latched, no further
+/// vectors are run, and the code is returned by [`Runner::exit`].
+pub struct Runner<'a> {
+    /// CPU state
+    pub vm: Uxn<'a>,
+    /// Varvara devices
+    pub dev: Varvara,
+    exit: Option<i32>,
+    next_frame: Duration,
+    args: Vec<String>,
+}
+
+impl<'a> Runner<'a> {
+    /// Builds a runner from an existing CPU and device set
+    ///
+    /// The CPU is left as-is; call [`Runner::load`] to load a ROM.
+    pub fn new(vm: Uxn<'a>, dev: Varvara) -> Self {
+        Self {
+            vm,
+            dev,
+            exit: None,
+            next_frame: Duration::ZERO,
+            args: vec![],
+        }
+    }
+
+    /// Loads a ROM, runs its reset vector, and sends arguments to the console
+    ///
+    /// Any previous exit code is cleared and the frame clock restarts at zero.
+    pub fn load(&mut self, rom: &[u8], args: &[String]) {
+        let extra = self.vm.reset(rom);
+        self.dev.reset(extra);
+        self.exit = None;
+        self.next_frame = Duration::ZERO;
+        self.args = args.to_vec();
+
+        self.dev.init_args(&mut self.vm, args);
+        self.dev.run(&mut self.vm, 0x100);
+        self.latch();
+        if self.is_running() && !args.is_empty() {
+            self.dev.push_args(&mut self.vm, args);
+            self.latch();
+        }
+    }
+
+    /// Replaces the ROM with a new build, keeping parts of RAM
+    ///
+    /// The new ROM is loaded as by [`Runner::load`] with the arguments the
+    /// last ROM was loaded with, then the `keep` ranges are copied back from before the reload, so state
+    /// set up by the reset vector can be carried over from the old program.
+    pub fn reload(&mut self, rom: &[u8], keep: &[RangeInclusive<u16>]) {
+        let saved: Vec<(usize, Vec<u8>)> = keep
+            .iter()
+            .map(|r| {
+                let r = *r.start() as usize..=*r.end() as usize;
+                (*r.start(), self.vm.ram()[r].to_vec())
+            })
+            .collect();
+        let args = std::mem::take(&mut self.args);
+        self.load(rom, &args);
+        for (start, data) in saved {
+            self.vm.ram_mut()[start..start + data.len()].copy_from_slice(&data);
+        }
+    }
+
+    /// Returns `true` if the ROM hasn't requested an exit or spent its
+    /// instruction budget (see [`Varvara::set_cycle_budget`])
+    pub fn is_running(&self) -> bool {
+        self.exit.is_none() && !self.dev.cycles_exhausted()
+    }
+
+    /// Returns the exit code requested by the ROM, if any
+    pub fn exit(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Returns the host time at which the next screen frame is due
+    pub fn next_frame(&self) -> Duration {
+        self.next_frame
+    }
+
+    /// Sends a character to the console device
+    pub fn console(&mut self, c: u8) {
+        if self.is_running() {
+            self.dev.console(&mut self.vm, c);
+            self.latch();
+        }
+    }
+
+    /// Signals the end of stdin to the console device
+    pub fn console_eof(&mut self) {
+        if self.is_running() {
+            self.dev.console_eof(&mut self.vm);
+            self.latch();
+        }
+    }
+
+    /// Processes pending events from registered custom devices
+    ///
+    /// Returns `true` if any events were processed, i.e. the ROM isn't idle.
+    pub fn poll(&mut self) -> bool {
+        if self.is_running() {
+            let n = self.dev.poll_devices(&mut self.vm);
+            self.latch();
+            n > 0
+        } else {
+            false
+        }
+    }
+
+    /// Advances the clock to `now`, running a frame if one is due
+    ///
+    /// `now` is measured from an arbitrary origin chosen by the host, which
+    /// must be monotonic.  A frame polls custom devices then calls the screen
+    /// vector.  If the host falls more than a frame behind, the clock skips
+    /// ahead rather than running a burst of frames.
+    ///
+    /// Returns `true` if a frame was run.
+    pub fn advance(&mut self, now: Duration) -> bool {
+        if !self.is_running() || now < self.next_frame {
+            return false;
+        }
+        self.next_frame += FRAME_TIME;
+        if self.next_frame < now {
+            self.next_frame = now + FRAME_TIME;
+        }
+        self.poll();
+        if self.is_running() {
+            self.dev.redraw(&mut self.vm);
+            self.latch();
+        }
+        true
+    }
+
+    /// Returns the current output state of the system
+    ///
+    /// `stdout` and `stderr` are drained; `exit` is sticky once the ROM has
+    /// requested an exit.
+    pub fn output(&mut self) -> Output<'_> {
+        let mut out = self.dev.output(&self.vm);
+        if let Some(e) = out.exit {
+            self.exit.get_or_insert(e);
+        }
+        out.exit = self.exit;
+        out
+    }
+
+    /// Checks whether the ROM has requested an exit since the last call
+    fn latch(&mut self) {
+        if self.exit.is_none() {
+            self.exit = self.dev.system.exit();
+        }
+    }
+}
@@ -129,6 +129,49 @@ impl Auto {
     }
 }
 
+/// Pixel layout used by [`Screen::frame_as`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Four bytes per pixel in BGRA order (the same layout as [`Screen::frame`])
+    Bgra,
+    /// One byte per pixel, holding the palette index (0-3)
+    Indexed,
+    /// Palette indices packed four pixels per byte, leftmost pixel in the
+    /// high bits
+    ///
+    /// Each row is padded to a whole number of bytes.
+    Packed2bpp,
+    /// One bit per pixel, leftmost pixel in the high bit; a bit is set when the
+    /// pixel's palette index is nonzero
+    ///
+    /// Each row is padded to a whole number of bytes.
+    Packed1bpp,
+    /// Two bytes per pixel, little-endian RGB565
+    Rgb565,
+}
+
+impl FrameFormat {
+    /// Returns the number of bytes used to store a single row of pixels
+    pub fn stride(&self, width: u16) -> usize {
+        let width = width as usize;
+        match self {
+            FrameFormat::Bgra => width * 4,
+            FrameFormat::Indexed => width,
+            FrameFormat::Packed2bpp => width.div_ceil(4),
+            FrameFormat::Packed1bpp => width.div_ceil(8),
+            FrameFormat::Rgb565 => width * 2,
+        }
+    }
+}
+
+/// Converts a palette color (as returned by [`Screen::palette`]) to RGB565
+pub fn rgb565(color: u32) -> u16 {
+    let r = ((color >> 16) & 0xFF) as u16;
+    let g = ((color >> 8) & 0xFF) as u16;
+    let b = (color & 0xFF) as u16;
+    ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3)
+}
+
 pub struct Screen {
     /// Screen buffer
     pixels: Vec<ScreenPixel>,
@@ -187,13 +230,21 @@ impl Screen {
         (self.width, self.height)
     }
 
-    /// Gets the current frame, returning a `(buffer, width, height)` tuple
-    pub fn frame(&mut self, vm: &Uxn) -> &[u8] {
+    /// Returns the four-color palette as `0xAARRGGBB` values
+    ///
+    /// The palette is read from the system device's `red` / `green` / `blue`
+    /// ports, so it reflects the most recent writes from the ROM.
+    pub fn palette(&mut self, vm: &Uxn) -> [u32; 4] {
         let prev_colors = self.colors;
         let sys = vm.dev::<crate::system::SystemPorts>();
         self.colors = [0, 1, 2, 3].map(|i| sys.color(i));
         self.changed |= prev_colors != self.colors;
+        self.colors
+    }
 
+    /// Gets the current frame, returning a `(buffer, width, height)` tuple
+    pub fn frame(&mut self, vm: &Uxn) -> &[u8] {
+        self.palette(vm);
         if std::mem::take(&mut self.changed) {
             for (p, o) in self.pixels.iter().zip(self.buffer.chunks_mut(4)) {
                 o.copy_from_slice(&self.colors[(p.get() & 0b11) as usize].to_le_bytes());
@@ -202,6 +253,57 @@ impl Screen {
         &self.buffer
     }
 
+    /// Renders the current frame into `out` using the given pixel format
+    ///
+    /// `out` is cleared and resized to `format.stride(width) * height` bytes.
+    /// Unlike [`Screen::frame`], this doesn't touch the cached BGRA buffer, so
+    /// it's cheap to call for hosts which never need RGBA output.
+    pub fn frame_as(&mut self, vm: &Uxn, format: FrameFormat, out: &mut Vec<u8>) {
+        let colors = self.palette(vm);
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let stride = format.stride(self.width);
+        out.clear();
+        out.resize(stride * height, 0);
+
+        let rows = self.pixels.chunks(width.max(1)).take(height);
+        for (row, o) in rows.zip(out.chunks_mut(stride.max(1))) {
+            for (x, p) in row.iter().enumerate() {
+                let i = p.get() & 0b11;
+                match format {
+                    FrameFormat::Bgra => {
+                        let c = colors[i as usize].to_le_bytes();
+                        o[x * 4..][..4].copy_from_slice(&c);
+                    }
+                    FrameFormat::Indexed => o[x] = i,
+                    FrameFormat::Packed2bpp => {
+                        o[x / 4] |= i << (6 - (x % 4) * 2);
+                    }
+                    FrameFormat::Packed1bpp => {
+                        if i != 0 {
+                            o[x / 8] |= 0x80 >> (x % 8);
+                        }
+                    }
+                    FrameFormat::Rgb565 => {
+                        let c = rgb565(colors[i as usize]).to_le_bytes();
+                        o[x * 2..][..2].copy_from_slice(&c);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the `(foreground, background)` color indices at a position
+    ///
+    /// Returns `None` if the position is outside of the screen.
+    pub fn layers_at(&self, x: u16, y: u16) -> Option<(u8, u8)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = x as usize + y as usize * self.width as usize;
+        self.pixels.get(i).map(|p| (p.fg, p.bg))
+    }
+
     fn set_pixel(&mut self, layer: Layer, x: u16, y: u16, color: u8) {
         if x >= self.width || y >= self.height {
             return;
@@ -1,3 +1,4 @@
+use crate::Metadata;
 use log::warn;
 use std::mem::offset_of;
 use uxn::{Ports, Uxn};
@@ -5,6 +6,7 @@ use zerocopy::KnownLayout;
 use zerocopy::{BigEndian, FromBytes, FromZeros, IntoBytes, U16};
 pub struct System {
     exit: Option<i32>,
+    metadata: Option<Metadata>,
     banks: [Box<[u8; 65536]>; 15],
 }
 
@@ -56,10 +58,16 @@ impl Ports for SystemPorts {
 impl SystemPorts {
     const EXPANSION: u8 = (offset_of!(Self, expansion) + 1) as u8;
     const WST: u8 = offset_of!(Self, wst) as u8;
+    pub(crate) const METADATA: u8 = (offset_of!(Self, metadata) + 1) as u8;
     const RST: u8 = offset_of!(Self, rst) as u8;
     const DEBUG: u8 = offset_of!(Self, debug) as u8;
     const STATE: u8 = offset_of!(Self, state) as u8;
 
+    /// Returns the address stored in the `metadata` port
+    pub(crate) fn metadata_addr(&self) -> u16 {
+        self.metadata.get()
+    }
+
     /// Looks up the color for the given index
     pub fn color(&self, i: u8) -> u32 {
         let i = 3 - i;
@@ -80,7 +88,11 @@ mod expansion {
 impl System {
     pub fn new() -> Self {
         let banks = [(); 15].map(|_| Box::new([0u8; 65536]));
-        Self { banks, exit: None }
+        Self {
+            banks,
+            exit: None,
+            metadata: None,
+        }
     }
 
     /// Resets the peripheral, loading the given data into expansion memory
@@ -92,6 +104,7 @@ impl System {
             b[n..].fill(0u8);
         }
         self.exit = None;
+        self.metadata = None;
     }
 
     /// Triggers a debug output for the system ports
@@ -154,6 +167,9 @@ impl System {
                     _ => warn!("invalid expansion opcode {op}"),
                 }
             }
+            SystemPorts::METADATA => {
+                self.metadata = Metadata::read(vm, v.metadata.get());
+            }
             SystemPorts::WST => {
                 let wst = v.wst;
                 vm.stack_mut().set_len(wst)
@@ -177,10 +193,8 @@ impl System {
                     println!("<");
                 }
             }
-            SystemPorts::STATE => {
-                if v.state != 0 {
-                    self.exit = Some((v.state & !0x80) as i32);
-                }
+            SystemPorts::STATE if v.state != 0 => {
+                self.exit = Some((v.state & !0x80) as i32);
             }
             _ => (),
         }
@@ -200,9 +214,9 @@ impl System {
         }
     }
 
-    /// Returns `true` if the exit flag is set
-    pub fn should_exit(&self) -> bool {
-        self.exit.is_some()
+    /// Returns the metadata most recently provided by the ROM
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
     }
 
     /// Clears and returns the exit code (if present)
@@ -0,0 +1,16 @@
+//! Fixtures shared by the `cardinal-varvara` integration tests
+
+/// Echoes console input, exiting with code 1 on `q`
+///
+/// ```tal
+/// |0100 ;on-console #10 DEO2 BRK
+/// @on-console
+///     #12 DEI DUP #18 DEO
+///     LIT "q EQU ?{ BRK } #81 #0f DEO BRK
+/// ```
+pub const ECHO: [u8; 28] = [
+    0xa0, 0x01, 0x07, 0x80, 0x10, 0x37, 0x00, // |0100
+    0x80, 0x12, 0x16, 0x06, 0x80, 0x18, 0x17, // echo
+    0x80, b'q', 0x08, 0x20, 0x00, 0x01, 0x00, // LIT "q EQU ?{ BRK }
+    0x80, 0x81, 0x80, 0x0f, 0x17, 0x00, 0x00, // exit
+];
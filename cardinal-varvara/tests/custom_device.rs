@@ -0,0 +1,138 @@
+use cardinal_varvara::{CustomDevice, Event, RegisterError, Varvara};
+use uxn::{Backend, Ports, Uxn, UxnRam};
+use zerocopy::{BigEndian, U16};
+
+/// Test device which adds the two bytes written to it
+#[derive(zerocopy::Immutable, zerocopy::IntoBytes, zerocopy::FromBytes, zerocopy::KnownLayout)]
+#[repr(C)]
+struct AdderPorts {
+    vector: U16<BigEndian>,
+    a: u8,
+    b: u8,
+    sum: u8,
+    _padding: [u8; 11],
+}
+
+impl Ports for AdderPorts {
+    const BASE: u8 = 0x70;
+}
+
+#[derive(Default)]
+struct Adder {
+    writes: Vec<u8>,
+    pending: bool,
+}
+
+impl CustomDevice for Adder {
+    fn as_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn deo(&mut self, _vm: &mut Uxn, target: u8) {
+        self.writes.push(target);
+    }
+    fn dei(&mut self, vm: &mut Uxn, target: u8) {
+        if target == 0x74 {
+            let p = vm.dev_mut::<AdderPorts>();
+            p.sum = p.a.wrapping_add(p.b);
+        }
+    }
+    fn update(&mut self, vm: &mut Uxn, base: u8) -> Option<Event> {
+        std::mem::take(&mut self.pending).then(|| Event {
+            data: None,
+            vector: vm.dev_at::<AdderPorts>(base).vector.get(),
+        })
+    }
+    fn reset(&mut self) {
+        self.writes.clear();
+    }
+}
+
+#[test]
+fn custom_device() {
+    // |0100 #0203 #72 DEO2 #74 DEI #00 STZ ;on-poll #70 DEO2 BRK
+    // @on-poll #ff #01 STZ BRK
+    let rom = [
+        0xa0, 0x02, 0x03, 0x80, 0x72, 0x37, // #0203 #72 DEO2
+        0x80, 0x74, 0x16, 0x80, 0x00, 0x11, // #74 DEI #00 STZ
+        0xa0, 0x01, 0x13, 0x80, 0x70, 0x37, // ;on-poll #70 DEO2
+        0x00, // BRK
+        0x80, 0xff, 0x80, 0x01, 0x11, 0x00, // @on-poll #ff #01 STZ BRK
+    ];
+    let mut ram = UxnRam::new();
+    let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    dev.register::<AdderPorts, _>(Adder::default()).unwrap();
+    let extra = vm.reset(&rom);
+    dev.reset(extra);
+    vm.run(&mut dev, 0x100);
+
+    assert_eq!(vm.ram_read_byte(0x00), 5);
+    let adder = dev.device_mut(0x70).unwrap();
+    let adder = adder.as_any().downcast_mut::<Adder>().unwrap();
+    assert_eq!(adder.writes, [0x72, 0x73, 0x70, 0x71]);
+
+    // Events from custom devices call their vectors
+    assert_eq!(vm.ram_read_byte(0x01), 0);
+    dev.poll_devices(&mut vm);
+    assert_eq!(vm.ram_read_byte(0x01), 0);
+    let adder = dev.device_mut(0x70).unwrap();
+    adder.as_any().downcast_mut::<Adder>().unwrap().pending = true;
+    dev.poll_devices(&mut vm);
+    assert_eq!(vm.ram_read_byte(0x01), 0xff);
+
+    // Devices stay registered across a reset
+    dev.reset(&[]);
+    assert!(dev.device_mut(0x70).is_some());
+}
+
+#[test]
+fn register_errors() {
+    let mut dev = Varvara::headless();
+    assert_eq!(
+        dev.register_at(0x71, Box::new(Adder::default())),
+        Err(RegisterError::Unaligned(0x71))
+    );
+    for page in [
+        0x00, 0x10, 0x20, 0x30, 0x60, 0x80, 0x90, 0xa0, 0xb0, 0xc0, 0xf0,
+    ] {
+        assert_eq!(
+            dev.register_at(page, Box::new(Adder::default())),
+            Err(RegisterError::Builtin(page))
+        );
+    }
+    assert!(dev.register_at(0xd0, Box::new(Adder::default())).is_ok());
+    assert_eq!(
+        dev.register_at(0xd0, Box::new(Adder::default())),
+        Err(RegisterError::Occupied(0xd0))
+    );
+    assert!(dev.unregister(0xd0).is_some());
+    assert!(dev.register_at(0xd0, Box::new(Adder::default())).is_ok());
+}
+
+/// Test device which always has another event ready
+struct Flood;
+
+impl CustomDevice for Flood {
+    fn as_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn deo(&mut self, _vm: &mut Uxn, _target: u8) {}
+    fn dei(&mut self, _vm: &mut Uxn, _target: u8) {}
+    fn update(&mut self, _vm: &mut Uxn, _base: u8) -> Option<Event> {
+        Some(Event::default())
+    }
+}
+
+#[test]
+fn poll_is_bounded() {
+    let mut ram = UxnRam::new();
+    let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    dev.register_at(0x70, Box::new(Flood)).unwrap();
+    let extra = vm.reset(&[0x00]);
+    dev.reset(extra);
+
+    // Each poll returns, leaving the rest of the events for the next one
+    assert_eq!(dev.poll_devices(&mut vm), 4096);
+    assert_eq!(dev.poll_devices(&mut vm), 4096);
+}
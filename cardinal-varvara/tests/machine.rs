@@ -0,0 +1,71 @@
+use cardinal_varvara::Machine;
+use uxn::Backend;
+
+/// Prints its first argument, then exits with its length
+///
+/// ```tal
+/// |0100 ;on-console #10 DEO2 BRK
+/// @on-console
+///     #17 DEI #04 EQU ?{ #12 DEI #18 DEO #00 LDZ INC #00 STZ BRK }
+///     #00 LDZ #80 ORA #0f DEO BRK
+/// ```
+const ROM: [u8; 37] = [
+    0xa0, 0x01, 0x07, 0x80, 0x10, 0x37, 0x00, // |0100
+    0x80, 0x17, 0x16, 0x80, 0x04, 0x08, 0x20, 0x00, 0x0e, // #17 DEI #04 EQU ?{
+    0x80, 0x12, 0x16, 0x80, 0x18, 0x17, // #12 DEI #18 DEO
+    0x80, 0x00, 0x10, 0x01, 0x80, 0x00, 0x11, 0x00, // count BRK }
+    0x80, 0x00, 0x10, 0x80, 0x80, 0x1d, 0x80, // #00 LDZ #80 ORA
+];
+
+fn rom() -> Vec<u8> {
+    let mut rom = ROM.to_vec();
+    rom.extend([0x0f, 0x17, 0x00]); // #0f DEO BRK
+    rom
+}
+
+#[test]
+fn worker_pool() {
+    let rom = rom();
+    let handles: Vec<_> = (0..16)
+        .map(|i| {
+            // Build on this thread, run on another
+            let mut m = Machine::new(Backend::Interpreter);
+            let rom = rom.clone();
+            std::thread::spawn(move || {
+                let arg = "x".repeat(i + 1);
+                m.load(&rom, std::slice::from_ref(&arg));
+                let out = m.output();
+                assert_eq!(out.stdout, arg.as_bytes());
+                m.exit()
+            })
+        })
+        .collect();
+    for (i, h) in handles.into_iter().enumerate() {
+        assert_eq!(h.join().unwrap(), Some(i as i32 + 1));
+    }
+}
+
+#[test]
+fn with_runner() {
+    let mut m = Machine::new(Backend::Interpreter);
+    m.load(&rom(), &[]);
+    assert!(m.is_running());
+    m.with(|r| {
+        r.vm.ram_write_byte(0x00, 4);
+        r.console(b'!');
+    });
+    assert_eq!(m.vm().ram_read_byte(0x00), 5);
+    assert_eq!(m.output().stdout, b"!");
+
+    // Machines can be reused and dropped repeatedly without leaking RAM
+    for _ in 0..20 {
+        let mut m = Machine::new(Backend::Interpreter);
+        m.load(&rom(), &["a".to_owned()]);
+        assert_eq!(m.exit(), Some(1));
+    }
+
+    // Leaking hands out a runner which outlives the machine
+    let mut r = m.leak();
+    r.console(b'?');
+    assert_eq!(r.output().stdout, b"?");
+}
@@ -0,0 +1,78 @@
+use cardinal_varvara::{Metadata, Varvara};
+use uxn::{Backend, Uxn, UxnRam};
+
+/// Builds a ROM which points `System/metadata` at the given text, optionally
+/// followed by a 24×24 icon
+///
+/// ```tal
+/// |0100 ;meta #06 DEO2 BRK
+/// @meta 00 "text 00 01 83 =icon
+/// @icon ...
+/// ```
+fn rom(text: &str, icon: Option<&[u8; 144]>) -> Vec<u8> {
+    let meta = 0x107u16;
+    let mut rom = vec![0xa0, (meta >> 8) as u8, meta as u8, 0x80, 0x06, 0x37, 0x00];
+    rom.push(0x00);
+    rom.extend(text.bytes());
+    rom.push(0x00);
+    match icon {
+        Some(icon) => {
+            let addr = 0x100 + rom.len() as u16 + 4;
+            rom.extend([0x01, 0x83, (addr >> 8) as u8, addr as u8]);
+            rom.extend(icon);
+        }
+        None => rom.push(0x00),
+    }
+    rom
+}
+
+#[test]
+fn system_port() {
+    let mut icon = [0u8; 144];
+    icon[0] = 0b1000_0000; // top-left pixel of the first tile, low plane
+    icon[16 + 8] = 0b0000_0001; // top-right pixel of the second tile, high plane
+    let rom = rom("Demo 1.2\nA test ROM\nby someone", Some(&icon));
+
+    let mut ram = UxnRam::new();
+    let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    let extra = vm.reset(&rom);
+    dev.reset(extra);
+    assert!(dev.output(&vm).metadata.is_none());
+    vm.run(&mut dev, 0x100);
+
+    let out = dev.output(&vm);
+    let m = out.metadata.unwrap();
+    assert_eq!(m.name, "Demo");
+    assert_eq!(m.version.as_deref(), Some("1.2"));
+    assert_eq!(m.description, "A test ROM\nby someone");
+    let icon = m.icon.as_ref().unwrap();
+    assert_eq!((icon.width, icon.height), (24, 24));
+    assert_eq!(icon.pixels[0], 1);
+    assert_eq!(icon.pixels[15], 2);
+    assert_eq!(icon.pixels.iter().filter(|&&p| p != 0).count(), 2);
+
+    let rgba = icon.to_rgba([0xff000000, 0xff112233, 0xff445566, 0xff778899]);
+    assert_eq!(&rgba[..8], &[0x11, 0x22, 0x33, 0xff, 0, 0, 0, 0]);
+
+    // Metadata is cleared on reset
+    dev.reset(&[]);
+    assert!(dev.metadata().is_none());
+}
+
+#[test]
+fn from_rom() {
+    let m = Metadata::from_rom(&rom("Plain name", None)).unwrap();
+    assert_eq!(m.name, "Plain name");
+    assert_eq!(m.version, None);
+    assert_eq!(m.description, "");
+    assert_eq!(m.icon, None);
+
+    let m = Metadata::from_rom(&rom("Tool v0.3-beta\nDoes things", None)).unwrap();
+    assert_eq!(m.name, "Tool");
+    assert_eq!(m.version.as_deref(), Some("v0.3-beta"));
+
+    // ROMs which never write the port have no metadata
+    assert_eq!(Metadata::from_rom(&[0x00]), None);
+    assert_eq!(Metadata::from_rom(&[0x40, 0xff, 0xfd]), None); // JMI to self
+}
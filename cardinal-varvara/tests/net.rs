@@ -0,0 +1,183 @@
+#![cfg(feature = "network")]
+use cardinal_varvara::net::{Net, NetPorts, Policy};
+use cardinal_varvara::Varvara;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::time::{Duration, Instant};
+use uxn::{Backend, Device, Uxn, UxnRam};
+
+/// Network event vector, which records each event into the zero page
+///
+/// ```tal
+/// @on-net
+///     .Net/type DEI #01 STZ
+///     .Net/read DEI #00 LDZ #10 ADD STZ
+///     #00 LDZ INC #00 STZ BRK
+/// ```
+const ROM: [u8; 24] = [
+    0x80, 0x73, 0x16, 0x80, 0x01, 0x11, // .Net/type DEI #01 STZ
+    0x80, 0x72, 0x16, 0x80, 0x00, 0x10, 0x80, 0x10, 0x18, 0x11, // read
+    0x80, 0x00, 0x10, 0x01, 0x80, 0x00, 0x11, 0x00, // count
+];
+
+/// Address at which the `host:port` string is stored
+const TARGET: u16 = 0x200;
+
+fn with_net<F: FnOnce(&mut Uxn, &mut Varvara)>(policy: Policy, f: F) {
+    let mut ram = UxnRam::new();
+    let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    dev.register::<NetPorts, _>(Net::new(policy)).unwrap();
+    let extra = vm.reset(&ROM);
+    dev.reset(extra);
+    vm.write_dev_mem(0x70, 0x01);
+    vm.write_dev_mem(0x71, 0x00);
+    f(&mut vm, &mut dev);
+}
+
+fn open(vm: &mut Uxn, dev: &mut Varvara, target: &str, kind: u8) {
+    for (i, b) in target.bytes().chain([0]).enumerate() {
+        vm.ram_write_byte(TARGET + i as u16, b);
+    }
+    let [hi, lo] = TARGET.to_be_bytes();
+    vm.write_dev_mem(0x74, hi);
+    vm.write_dev_mem(0x75, lo);
+    vm.write_dev_mem(0x76, kind);
+    assert!(dev.deo(vm, 0x76));
+}
+
+fn send(vm: &mut Uxn, dev: &mut Varvara, data: &[u8]) {
+    for &b in data {
+        vm.write_dev_mem(0x78, b);
+        assert!(dev.deo(vm, 0x78));
+    }
+    assert!(dev.deo(vm, 0x79));
+}
+
+/// Polls the device until the predicate is true, panicking after a timeout
+fn wait_for<F: Fn(&Uxn) -> bool>(vm: &mut Uxn, dev: &mut Varvara, f: F) {
+    let start = Instant::now();
+    while !f(vm) {
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+        dev.poll_devices(vm);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn tcp_echo() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 64];
+        while let Ok(n @ 1..) = s.read(&mut buf) {
+            s.write_all(&buf[..n]).unwrap();
+        }
+    });
+
+    with_net(Policy::LocalhostOnly, |vm, dev| {
+        open(vm, dev, &addr.to_string(), 1);
+        wait_for(vm, dev, |vm| vm.ram_read_byte(0x01) == 2);
+        assert_eq!(vm.ram_read_byte(0x00), 1);
+
+        send(vm, dev, b"hi!");
+        wait_for(vm, dev, |vm| vm.ram_read_byte(0x00) == 4);
+        assert_eq!(vm.ram_read_byte(0x01), 1);
+        let echoed: Vec<u8> = (0x11..0x14).map(|i| vm.ram_read_byte(i)).collect();
+        assert_eq!(echoed, b"hi!");
+
+        // Closing the connection from our side doesn't call the vector
+        vm.write_dev_mem(0x76, 0);
+        assert!(dev.deo(vm, 0x76));
+        dev.poll_devices(vm);
+        assert_eq!(vm.ram_read_byte(0x00), 4);
+    });
+}
+
+#[test]
+fn udp_echo() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        let (n, from) = server.recv_from(&mut buf).unwrap();
+        server.send_to(&buf[..n], from).unwrap();
+    });
+
+    with_net(Policy::LocalhostOnly, |vm, dev| {
+        open(vm, dev, &addr.to_string(), 2);
+        wait_for(vm, dev, |vm| vm.ram_read_byte(0x01) == 2);
+
+        send(vm, dev, b"ping");
+        wait_for(vm, dev, |vm| vm.ram_read_byte(0x00) == 5);
+        let echoed: Vec<u8> = (0x11..0x15).map(|i| vm.ram_read_byte(i)).collect();
+        assert_eq!(echoed, b"ping");
+    });
+}
+
+#[test]
+fn denied_by_policy() {
+    with_net(Policy::LocalhostOnly, |vm, dev| {
+        open(vm, dev, "10.255.255.1:9", 1);
+        wait_for(vm, dev, |vm| vm.ram_read_byte(0x00) == 1);
+        assert_eq!(vm.ram_read_byte(0x01), 4);
+    });
+}
+
+#[test]
+fn policy() {
+    let local: SocketAddr = "127.0.0.1:80".parse().unwrap();
+    let remote: SocketAddr = "93.184.216.34:80".parse().unwrap();
+
+    assert!(Policy::LocalhostOnly.check("localhost:80", &[local]));
+    assert!(!Policy::LocalhostOnly.check("example.com:80", &[remote]));
+    assert!(!Policy::LocalhostOnly.check("mixed:80", &[local, remote]));
+
+    let allow = Policy::Allow(vec!["example.com".to_owned(), "other.org:8080".to_owned()]);
+    assert!(allow.check("localhost:80", &[local]));
+    assert!(allow.check("EXAMPLE.com:443", &[remote]));
+    assert!(allow.check("other.org:8080", &[remote]));
+    assert!(!allow.check("other.org:80", &[remote]));
+    assert!(!allow.check("evil.net:80", &[remote]));
+
+    assert!(Policy::Any.check("evil.net:80", &[remote]));
+}
+
+#[test]
+fn denied_before_lookup() {
+    assert!(Policy::LocalhostOnly.may_resolve("localhost:80"));
+    assert!(Policy::LocalhostOnly.may_resolve("[::1]:80"));
+    assert!(!Policy::LocalhostOnly.may_resolve("example.com:80"));
+    assert!(!Policy::LocalhostOnly.may_resolve("10.0.0.1:80"));
+
+    let allow = Policy::Allow(vec!["example.com".to_owned()]);
+    assert!(allow.may_resolve("127.0.0.1:80"));
+    assert!(allow.may_resolve("example.com:443"));
+    assert!(!allow.may_resolve("evil.net:80"));
+
+    assert!(Policy::Any.may_resolve("evil.net:80"));
+}
+
+#[test]
+fn flush_before_connected() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut out = vec![];
+        s.read_to_end(&mut out).unwrap();
+        out
+    });
+
+    with_net(Policy::LocalhostOnly, |vm, dev| {
+        open(vm, dev, &addr.to_string(), 1);
+        // Dropped, since the connection isn't open yet
+        send(vm, dev, b"early");
+        wait_for(vm, dev, |vm| vm.ram_read_byte(0x01) == 2);
+        send(vm, dev, b"late");
+        vm.write_dev_mem(0x76, 0);
+        assert!(dev.deo(vm, 0x76));
+    });
+    assert_eq!(server.join().unwrap(), b"late");
+}
@@ -0,0 +1,49 @@
+use cardinal_varvara::{Runner, Varvara};
+use uxn::{Backend, Uxn, UxnRam};
+
+mod common;
+use common::ECHO;
+
+#[test]
+fn chain() {
+    let mut ram_a = UxnRam::new();
+    let mut ram_b = UxnRam::new();
+    let mut a = Runner::new(
+        Uxn::new(&mut ram_a, Backend::Interpreter),
+        Varvara::headless(),
+    );
+    let mut b = Runner::new(
+        Uxn::new(&mut ram_b, Backend::Interpreter),
+        Varvara::headless(),
+    );
+
+    // The pipe is opened before loading, and survives the reset
+    let mut pipe = a.dev.pipe_stdout();
+    a.load(&ECHO, &[]);
+    b.load(&ECHO, &[]);
+
+    for c in b"hi" {
+        a.console(*c);
+    }
+    assert_eq!(pipe.pump_at_most(&mut b, 1), 1);
+    assert_eq!(pipe.pump(&mut b), 1);
+    assert_eq!(pipe.pump(&mut b), 0);
+    assert_eq!(a.output().stdout, b"hi");
+    assert_eq!(b.output().stdout, b"hi");
+
+    // Discarded bytes never reach the destination
+    a.console(b'!');
+    assert_eq!(pipe.discard(), 1);
+    assert_eq!(pipe.pump(&mut b), 0);
+    a.output();
+    assert!(b.output().stdout.is_empty());
+
+    // Exiting the source passes its final byte along, then closes the pipe
+    a.console(b'q');
+    assert_eq!(a.exit(), Some(1));
+    assert!(!pipe.is_closed());
+    drop(a);
+    assert_eq!(pipe.pump(&mut b), 1);
+    assert!(pipe.is_closed());
+    assert_eq!(b.exit(), Some(1));
+}
@@ -0,0 +1,73 @@
+use cardinal_varvara::reload::{parse_ranges, spawn_listener, Reload};
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc;
+
+fn reload() -> Reload {
+    Reload {
+        rom: vec![0xa0, 0x01, 0x07, 0x00],
+        sym: Some(b"\x07\x01on-frame\0".to_vec()),
+        keep: vec![0x00..=0xff, 0x8000..=0x9fff],
+    }
+}
+
+#[test]
+fn round_trip() {
+    let r = reload();
+    let data = r.encode();
+    assert_eq!(&data[..5], b"UXNR\x01");
+    assert_eq!(Reload::decode(&mut data.as_slice()).unwrap(), r);
+
+    let r = Reload::default();
+    assert_eq!(Reload::decode(&mut r.encode().as_slice()).unwrap(), r);
+}
+
+#[test]
+fn decode_errors() {
+    let data = reload().encode();
+    assert!(Reload::decode(&mut &data[..data.len() - 1]).is_err());
+    assert!(Reload::decode(&mut &b"UXNR\x02"[..]).is_err());
+
+    // ROMs can't be larger than page memory
+    let mut data = b"UXNR\x01".to_vec();
+    data.extend_from_slice(&0x10000u32.to_be_bytes());
+    data.resize(data.len() + 0x10000, 0);
+    let err = Reload::decode(&mut data.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(
+        parse_ranges("0000-00ff, 8000-9FFF,c000"),
+        Some(vec![0x00..=0xff, 0x8000..=0x9fff, 0xc000..=0xc000])
+    );
+    assert_eq!(parse_ranges("00ff-0000"), None);
+    assert_eq!(parse_ranges("0000-"), None);
+    assert_eq!(parse_ranges("10000"), None);
+    assert_eq!(parse_ranges(""), None);
+}
+
+#[test]
+fn listener() {
+    let (tx, rx) = mpsc::channel();
+    let addr = spawn_listener("127.0.0.1:0", move |r| tx.send(r)).unwrap();
+    reload().send(addr).unwrap();
+    assert_eq!(rx.try_recv().unwrap(), reload());
+
+    // Once the receiver is gone, requests are no longer accepted
+    drop(rx);
+    assert!(reload().send(addr).is_err());
+}
+
+#[test]
+fn idle_client() {
+    let (tx, rx) = mpsc::channel();
+    let addr = spawn_listener("127.0.0.1:0", move |r| tx.send(r)).unwrap();
+
+    // A client which stops partway through doesn't block the next one
+    let mut idle = TcpStream::connect(addr).unwrap();
+    idle.write_all(b"UX").unwrap();
+    reload().send(addr).unwrap();
+    assert_eq!(rx.try_recv().unwrap(), reload());
+}
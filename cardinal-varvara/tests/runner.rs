@@ -0,0 +1,191 @@
+use cardinal_varvara::{Runner, Varvara, FRAME_TIME};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uxn::{Backend, Uxn, UxnRam};
+
+mod common;
+use common::ECHO;
+
+/// Counts screen frames in the zero page, exiting with code 5 on the third
+///
+/// ```tal
+/// |0100 ;on-frame #20 DEO2 BRK
+/// @on-frame
+///     #00 LDZ INC DUP #00 STZ
+///     #03 EQU ?{ BRK } #85 #0f DEO BRK
+/// ```
+const FRAMES: [u8; 29] = [
+    0xa0, 0x01, 0x07, 0x80, 0x20, 0x37, 0x00, // |0100
+    0x80, 0x00, 0x10, 0x01, 0x06, 0x80, 0x00, 0x11, // count
+    0x80, 0x03, 0x08, 0x20, 0x00, 0x01, 0x00, // #03 EQU ?{ BRK }
+    0x80, 0x85, 0x80, 0x0f, 0x17, 0x00, 0x00, // exit
+];
+
+fn runner<'a>(ram: &'a mut UxnRam, rom: &[u8]) -> Runner<'a> {
+    let vm = Uxn::new(ram, Backend::Interpreter);
+    let mut r = Runner::new(vm, Varvara::headless());
+    r.load(rom, &[]);
+    r
+}
+
+#[test]
+fn frames_and_exit() {
+    let mut ram = UxnRam::new();
+    let mut r = runner(&mut ram, &FRAMES);
+    assert!(r.is_running());
+
+    // The first frame is due immediately, then every FRAME_TIME
+    assert!(r.advance(Duration::ZERO));
+    assert!(!r.advance(FRAME_TIME / 2));
+    assert!(r.advance(FRAME_TIME));
+    assert_eq!(r.vm.ram_read_byte(0x00), 2);
+    assert_eq!(r.exit(), None);
+
+    // Falling behind runs a single frame, which exits
+    assert!(r.advance(FRAME_TIME * 10));
+    assert_eq!(r.vm.ram_read_byte(0x00), 3);
+    assert_eq!(r.exit(), Some(5));
+    assert!(!r.is_running());
+
+    // Once exited, no more vectors run, and the exit code is sticky
+    assert!(!r.advance(FRAME_TIME * 20));
+    assert_eq!(r.vm.ram_read_byte(0x00), 3);
+    assert_eq!(r.output().exit, Some(5));
+    assert_eq!(r.output().exit, Some(5));
+
+    // Loading a ROM clears the exit code
+    r.load(&FRAMES, &[]);
+    assert!(r.is_running());
+    assert_eq!(r.output().exit, None);
+}
+
+#[test]
+fn console() {
+    let mut ram = UxnRam::new();
+    let mut r = runner(&mut ram, &ECHO);
+    for c in b"hi" {
+        r.console(*c);
+    }
+    assert_eq!(r.output().stdout, b"hi");
+    r.console(b'q');
+    assert_eq!(r.exit(), Some(1));
+    r.console(b'!');
+    let out = r.output();
+    assert_eq!(out.stdout, b"q");
+    assert_eq!(out.exit, Some(1));
+}
+
+#[test]
+fn exit_during_reset() {
+    // |0100 #0a #18 DEO #82 #0f DEO BRK
+    let rom = [
+        0x80, 0x0a, 0x80, 0x18, 0x17, 0x80, 0x82, 0x80, 0x0f, 0x17, 0x00,
+    ];
+    let mut ram = UxnRam::new();
+    let vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut r = Runner::new(vm, Varvara::headless());
+    r.load(&rom, &["ignored".to_owned()]);
+    assert_eq!(r.exit(), Some(2));
+    let out = r.output();
+    assert_eq!(out.stdout, b"\n");
+    assert_eq!(out.exit, Some(2));
+}
+
+#[test]
+fn exit_finishes_vector() {
+    // Writing to `System/state` doesn't halt the CPU mid-vector
+    // |0100 #80 #0f DEO LIT "! #18 DEO BRK
+    let rom = [
+        0x80, 0x80, 0x80, 0x0f, 0x17, 0x80, b'!', 0x80, 0x18, 0x17, 0x00,
+    ];
+    let mut ram = UxnRam::new();
+    let mut r = runner(&mut ram, &rom);
+    assert_eq!(r.exit(), Some(0));
+    assert_eq!(r.output().stdout, b"!");
+}
+
+#[test]
+fn cycle_budget() {
+    let mut ram = UxnRam::new();
+    let vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    // 4 instructions for the reset vector, 10 per echoed character, and 5 to
+    // echo a third character before its vector is interrupted
+    dev.set_cycle_budget(Some(4 + 10 * 2 + 5));
+    let mut r = Runner::new(vm, dev);
+    r.load(&ECHO, &[]);
+    assert_eq!(r.dev.cycle_budget(), Some(20 + 5));
+
+    for c in b"abcd" {
+        r.console(*c);
+    }
+    assert!(!r.is_running());
+    assert!(r.dev.cycles_exhausted());
+    let out = r.output();
+    assert_eq!(out.stdout, b"abc");
+    assert_eq!(out.exit, None);
+}
+
+#[test]
+fn stop_flag() {
+    let mut ram = UxnRam::new();
+    let vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    let stop = Arc::new(AtomicBool::new(false));
+    dev.set_stop_flag(Some(stop.clone()));
+    let mut r = Runner::new(vm, dev);
+    let raise = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    // |0100 @loop !loop
+    r.load(&[0x40, 0xff, 0xfd], &[]);
+    raise.join().unwrap();
+    assert!(!r.is_running());
+    assert!(r.dev.cycles_exhausted());
+}
+
+#[test]
+fn reload_keeps_ram() {
+    let mut ram = UxnRam::new();
+    let mut r = runner(&mut ram, &FRAMES);
+    r.advance(Duration::ZERO);
+    r.advance(FRAME_TIME);
+    assert_eq!(r.vm.ram_read_byte(0x00), 2);
+
+    // The frame counter survives a reload which keeps the zero page, so the
+    // next frame exits
+    r.reload(&FRAMES, &[0x00..=0xff]);
+    assert!(r.is_running());
+    assert_eq!(r.vm.ram_read_byte(0x00), 2);
+    r.advance(Duration::ZERO);
+    assert_eq!(r.exit(), Some(5));
+
+    // Without it, the counter starts over
+    r.reload(&FRAMES, &[]);
+    assert!(r.is_running());
+    assert_eq!(r.vm.ram_read_byte(0x00), 0);
+}
+
+#[test]
+fn reload_keeps_args() {
+    let mut ram = UxnRam::new();
+    let vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut r = Runner::new(vm, Varvara::headless());
+    r.load(&ECHO, &["hi".to_string()]);
+    assert_eq!(r.output().stdout, b"hi\n");
+    r.reload(&ECHO, &[]);
+    assert_eq!(r.output().stdout, b"hi\n");
+}
+
+#[test]
+fn load_keeps_controller() {
+    let mut ram = UxnRam::new();
+    let mut r = runner(&mut ram, &FRAMES);
+    let before = &*r.dev.controller as *const _ as *const u8;
+    r.load(&FRAMES, &[]);
+    let after = &*r.dev.controller as *const _ as *const u8;
+    assert_eq!(before, after, "reset replaced the controller");
+}
@@ -0,0 +1,442 @@
+//! Conformance tests for the Varvara screen device
+//!
+//! Each test builds a tiny ROM which drives the screen ports, then checks the
+//! exact color stored in each layer.
+use cardinal_varvara::Varvara;
+use uxn::{Backend, Uxn, UxnRam};
+
+mod op {
+    pub const BRK: u8 = 0x00;
+    pub const DEI2: u8 = 0x36;
+    pub const DEO: u8 = 0x17;
+    pub const DEO2: u8 = 0x37;
+    pub const LIT: u8 = 0x80;
+    pub const LIT2: u8 = 0xa0;
+}
+
+mod port {
+    pub const WIDTH: u8 = 0x22;
+    pub const HEIGHT: u8 = 0x24;
+    pub const AUTO: u8 = 0x26;
+    pub const X: u8 = 0x28;
+    pub const Y: u8 = 0x2a;
+    pub const ADDR: u8 = 0x2c;
+    pub const PIXEL: u8 = 0x2e;
+    pub const SPRITE: u8 = 0x2f;
+}
+
+/// Address at which sprite data is stored
+const SPRITES: u16 = 0x8000;
+
+/// Address of the snippet used to read back port values
+const PEEK: u16 = 0xf000;
+
+/// Blending table from the Varvara specification, indexed by `[data][color]`
+const BLEND: [[u8; 16]; 4] = [
+    [0, 0, 0, 0, 1, 0, 1, 1, 2, 2, 0, 2, 3, 3, 3, 0],
+    [0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3],
+    [1, 2, 3, 1, 1, 2, 3, 1, 1, 2, 3, 1, 1, 2, 3, 1],
+    [2, 3, 1, 2, 2, 3, 1, 2, 2, 3, 1, 2, 2, 3, 1, 2],
+];
+
+/// Builder for a ROM which writes a sequence of values to screen ports
+#[derive(Default)]
+struct Rom(Vec<u8>);
+
+impl Rom {
+    fn size(w: u16, h: u16) -> Self {
+        Self::default().deo2(port::WIDTH, w).deo2(port::HEIGHT, h)
+    }
+    fn deo(mut self, port: u8, v: u8) -> Self {
+        self.0.extend([op::LIT, v, op::LIT, port, op::DEO]);
+        self
+    }
+    fn deo2(mut self, port: u8, v: u16) -> Self {
+        let [hi, lo] = v.to_be_bytes();
+        self.0.extend([op::LIT2, hi, lo, op::LIT, port, op::DEO2]);
+        self
+    }
+    fn pos(self, x: u16, y: u16) -> Self {
+        self.deo2(port::X, x).deo2(port::Y, y)
+    }
+    fn pixel(self, v: u8) -> Self {
+        self.deo(port::PIXEL, v)
+    }
+    fn sprite(self, v: u8) -> Self {
+        self.deo(port::SPRITE, v)
+    }
+}
+
+/// Running machine, with helpers to inspect the screen
+struct Machine<'a> {
+    vm: Uxn<'a>,
+    dev: Varvara,
+}
+
+impl Machine<'_> {
+    /// Reads a two-byte screen port
+    fn port2(&mut self, port: u8) -> u16 {
+        for (i, b) in [op::LIT, port, op::DEI2, op::BRK].into_iter().enumerate() {
+            self.vm.ram_write_byte(PEEK + i as u16, b);
+        }
+        self.vm.run(&mut self.dev, PEEK);
+        let st = self.vm.stack_mut();
+        let v = u16::from_le_bytes([st.peek_byte_at(0), st.peek_byte_at(1)]);
+        let n = st.len();
+        st.set_len(n - 2);
+        v
+    }
+
+    /// Returns the `(foreground, background)` colors at a position
+    fn at(&self, x: u16, y: u16) -> (u8, u8) {
+        self.dev
+            .screen
+            .layers_at(x, y)
+            .expect("position out of bounds")
+    }
+
+    fn fg(&self, x: u16, y: u16) -> u8 {
+        self.at(x, y).0
+    }
+
+    fn bg(&self, x: u16, y: u16) -> u8 {
+        self.at(x, y).1
+    }
+}
+
+/// Runs the ROM with the given sprite data, then calls `f` on the result
+fn run<F: FnOnce(&mut Machine)>(rom: Rom, sprites: &[u8], f: F) {
+    let mut ram = UxnRam::new();
+    let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
+    let mut dev = Varvara::headless();
+    let mut code = rom.0;
+    code.push(op::BRK);
+    let extra = vm.reset(&code);
+    dev.reset(extra);
+    for (i, b) in sprites.iter().enumerate() {
+        vm.ram_write_byte(SPRITES + i as u16, *b);
+    }
+    vm.run(&mut dev, 0x100);
+    f(&mut Machine { vm, dev });
+}
+
+#[test]
+fn resize() {
+    run(Rom::size(24, 10), &[], |m| {
+        assert_eq!(m.dev.screen.size(), (24, 10));
+        assert_eq!(m.port2(port::WIDTH), 24);
+        assert_eq!(m.port2(port::HEIGHT), 10);
+        assert_eq!(m.dev.screen.layers_at(23, 9), Some((0, 0)));
+        assert_eq!(m.dev.screen.layers_at(24, 9), None);
+        assert_eq!(m.dev.screen.layers_at(23, 10), None);
+    });
+}
+
+#[test]
+fn pixel_layers() {
+    let rom = Rom::size(16, 16).pos(3, 4).pixel(0x02).pixel(0x41);
+    run(rom, &[], |m| {
+        assert_eq!(m.at(3, 4), (1, 2));
+        for y in 0..16 {
+            for x in 0..16 {
+                if (x, y) != (3, 4) {
+                    assert_eq!(m.at(x, y), (0, 0), "unexpected pixel at {x}, {y}");
+                }
+            }
+        }
+        // Without auto-increment, the position is unchanged
+        assert_eq!(m.port2(port::X), 3);
+        assert_eq!(m.port2(port::Y), 4);
+    });
+}
+
+#[test]
+fn pixel_auto() {
+    for (auto, dx, dy) in [(0x01, 1, 0), (0x02, 0, 1), (0x03, 1, 1)] {
+        let rom = Rom::size(16, 16)
+            .deo(port::AUTO, auto)
+            .pos(2, 5)
+            .pixel(0x03)
+            .pixel(0x03)
+            .pixel(0x03);
+        run(rom, &[], |m| {
+            for i in 0..3 {
+                assert_eq!(m.bg(2 + i * dx, 5 + i * dy), 3, "auto {auto:#x}, step {i}");
+            }
+            assert_eq!(m.bg(2 + 3 * dx, 5 + 3 * dy), 0, "auto {auto:#x}");
+            assert_eq!(m.port2(port::X), 2 + 3 * dx);
+            assert_eq!(m.port2(port::Y), 5 + 3 * dy);
+        });
+    }
+}
+
+#[test]
+fn pixel_fill_quadrants() {
+    type Quadrant = fn(u16, u16) -> bool;
+    let cases: [(u8, Quadrant); 4] = [
+        (0x80, |x, y| x >= 5 && y >= 6),
+        (0x90, |x, y| x < 5 && y >= 6),
+        (0xa0, |x, y| x >= 5 && y < 6),
+        (0xb0, |x, y| x < 5 && y < 6),
+    ];
+    for (mode, inside) in cases {
+        for (layer, color) in [(0x00, 2), (0x40, 3)] {
+            let rom = Rom::size(16, 16)
+                .deo(port::AUTO, 0x03)
+                .pos(5, 6)
+                .pixel(mode | layer | color);
+            run(rom, &[], |m| {
+                for y in 0..16 {
+                    for x in 0..16 {
+                        let c = if inside(x, y) { color } else { 0 };
+                        let expected = if layer != 0 { (c, 0) } else { (0, c) };
+                        assert_eq!(m.at(x, y), expected, "mode {mode:#x} at {x}, {y}");
+                    }
+                }
+                // Fills never auto-increment the position
+                assert_eq!(m.port2(port::X), 5);
+                assert_eq!(m.port2(port::Y), 6);
+            });
+        }
+    }
+}
+
+#[test]
+fn sprite_1bpp() {
+    let cross = [0x81, 0x42, 0x24, 0x18, 0x18, 0x24, 0x42, 0x81];
+    // Color 1 is opaque, so unset bits overwrite the background
+    // Color 5 is transparent, so unset bits leave the background alone
+    for (color, clear) in [(1, 0), (5, 2)] {
+        let rom = Rom::size(16, 16)
+            .pos(0, 0)
+            .pixel(0x82)
+            .pos(4, 2)
+            .deo2(port::ADDR, SPRITES)
+            .sprite(color);
+        run(rom, &cross, |m| {
+            for dy in 0..8 {
+                for dx in 0..8 {
+                    let set = cross[dy as usize] & (0x80 >> dx) != 0;
+                    let expected = if set { BLEND[1][color as usize] } else { clear };
+                    assert_eq!(m.bg(4 + dx, 2 + dy), expected, "color {color}");
+                }
+            }
+            // Pixels outside of the sprite keep the fill color
+            assert_eq!(m.bg(3, 2), 2);
+            assert_eq!(m.bg(12, 2), 2);
+            assert_eq!(m.bg(4, 10), 2);
+        });
+    }
+}
+
+#[test]
+fn sprite_flip() {
+    let corner = [0x80, 0, 0, 0, 0, 0, 0, 0];
+    for (flip, dx, dy) in [(0x00, 0, 0), (0x10, 7, 0), (0x20, 0, 7), (0x30, 7, 7)] {
+        let rom = Rom::size(16, 16)
+            .pos(2, 3)
+            .deo2(port::ADDR, SPRITES)
+            .sprite(0x40 | flip | 0x01);
+        run(rom, &corner, |m| {
+            for y in 0..16 {
+                for x in 0..16 {
+                    let expected = u8::from((x, y) == (2 + dx, 3 + dy));
+                    assert_eq!(m.fg(x, y), expected, "flip {flip:#x} at {x}, {y}");
+                    assert_eq!(m.bg(x, y), 0);
+                }
+            }
+        });
+    }
+}
+
+#[test]
+fn sprite_2bpp() {
+    // Low and high planes, giving data values 0, 1, 2, 3, 0, 1, 2, 3 per row
+    let mut data = [0x55; 16];
+    data[8..].fill(0x33);
+    let rom = Rom::size(16, 16)
+        .pos(0, 0)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x81);
+    run(rom, &data, |m| {
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(m.bg(x, y), (x % 4) as u8, "at {x}, {y}");
+            }
+        }
+        assert_eq!(m.bg(0, 8), 0);
+    });
+}
+
+#[test]
+fn sprite_blending() {
+    let mut data = [0x55; 16];
+    data[8..].fill(0x33);
+    for layer in [0x00, 0x40] {
+        for color in 0..16u8 {
+            let rom = Rom::size(16, 16)
+                .pos(0, 0)
+                .pixel(0x82 | layer)
+                .deo2(port::ADDR, SPRITES)
+                .sprite(0x80 | layer | color);
+            run(rom, &data, |m| {
+                for x in 0..8 {
+                    let d = (x % 4) as usize;
+                    let transparent = d == 0 && color % 5 == 0;
+                    let c = if transparent {
+                        2
+                    } else {
+                        BLEND[d][color as usize]
+                    };
+                    let expected = if layer != 0 { (c, 0) } else { (0, c) };
+                    assert_eq!(m.at(x, 0), expected, "color {color}, data {d}");
+                }
+            });
+        }
+    }
+}
+
+#[test]
+fn sprite_auto() {
+    // Three 1bpp sprites, each with a single solid row at a different height
+    let mut data = [0u8; 24];
+    for i in 0..3 {
+        data[i * 8 + i] = 0xff;
+    }
+
+    // auto x: sprites are drawn in a column, and x advances once at the end
+    let rom = Rom::size(32, 32)
+        .deo(port::AUTO, 0x25)
+        .pos(0, 0)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x01);
+    run(rom, &data, |m| {
+        for i in 0..3 {
+            for x in 0..8 {
+                assert_eq!(m.bg(x, i * 9), 1, "sprite {i}");
+                assert_eq!(m.bg(x, i * 9 + 1), 0, "sprite {i}");
+            }
+        }
+        assert_eq!(m.bg(8, 0), 0);
+        assert_eq!(m.port2(port::X), 8);
+        assert_eq!(m.port2(port::Y), 0);
+        assert_eq!(m.port2(port::ADDR), SPRITES + 24);
+    });
+
+    // auto y: sprites are drawn in a row, and y advances once at the end
+    let rom = Rom::size(32, 32)
+        .deo(port::AUTO, 0x26)
+        .pos(0, 0)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x01);
+    run(rom, &data, |m| {
+        for i in 0..3 {
+            for x in 0..8 {
+                assert_eq!(m.bg(i * 8 + x, i), 1, "sprite {i}");
+                assert_eq!(m.bg(i * 8 + x, 0), u8::from(i == 0), "sprite {i}");
+            }
+        }
+        assert_eq!(m.bg(0, 8), 0);
+        assert_eq!(m.port2(port::X), 0);
+        assert_eq!(m.port2(port::Y), 8);
+        assert_eq!(m.port2(port::ADDR), SPRITES + 24);
+    });
+
+    // Without the addr flag, the same sprite is drawn repeatedly
+    let rom = Rom::size(32, 32)
+        .deo(port::AUTO, 0x21)
+        .pos(0, 0)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x01);
+    run(rom, &data, |m| {
+        for i in 0..3 {
+            assert_eq!(m.bg(0, i * 8), 1, "sprite {i}");
+            assert_eq!(m.bg(0, i * 8 + 1), 0, "sprite {i}");
+        }
+        assert_eq!(m.port2(port::ADDR), SPRITES);
+    });
+
+    // 2bpp sprites advance the address by 16 bytes each
+    let rom = Rom::size(32, 32)
+        .deo(port::AUTO, 0x25)
+        .pos(0, 0)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x81);
+    run(rom, &[0; 48], |m| {
+        assert_eq!(m.port2(port::ADDR), SPRITES + 48);
+    });
+
+    // Flipped sprites move the position backwards
+    let rom = Rom::size(32, 32)
+        .deo(port::AUTO, 0x03)
+        .pos(16, 16)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x31);
+    run(rom, &data, |m| {
+        assert_eq!(m.port2(port::X), 8);
+        assert_eq!(m.port2(port::Y), 8);
+    });
+}
+
+#[test]
+fn out_of_bounds() {
+    let solid = [0xff; 8];
+
+    // Pixels past the edge are ignored
+    let rom = Rom::size(16, 16)
+        .pos(16, 0)
+        .pixel(0x01)
+        .pos(0, 16)
+        .pixel(0x01);
+    run(rom, &[], |m| {
+        for y in 0..16 {
+            for x in 0..16 {
+                assert_eq!(m.at(x, y), (0, 0));
+            }
+        }
+    });
+
+    // Fills starting past the edge draw nothing
+    let rom = Rom::size(16, 16).pos(20, 20).pixel(0x81);
+    run(rom, &[], |m| {
+        assert_eq!(m.bg(15, 15), 0);
+    });
+
+    // Sprites at negative coordinates are clipped, not wrapped
+    let rom = Rom::size(16, 16)
+        .pos(0xfffc, 0xfffa)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x01);
+    run(rom, &solid, |m| {
+        for y in 0..16 {
+            for x in 0..16 {
+                let expected = u8::from(x < 4 && y < 2);
+                assert_eq!(m.bg(x, y), expected, "at {x}, {y}");
+            }
+        }
+    });
+
+    // Sprites at the right / bottom edges are clipped
+    let rom = Rom::size(16, 16)
+        .pos(12, 12)
+        .deo2(port::ADDR, SPRITES)
+        .sprite(0x01);
+    run(rom, &solid, |m| {
+        for y in 0..16 {
+            for x in 0..16 {
+                let expected = u8::from(x >= 12 && y >= 12);
+                assert_eq!(m.bg(x, y), expected, "at {x}, {y}");
+            }
+        }
+    });
+
+    // Auto-increment wraps around the 16-bit position
+    let rom = Rom::size(16, 16)
+        .deo(port::AUTO, 0x03)
+        .pos(0xffff, 0xffff)
+        .pixel(0x01);
+    run(rom, &[], |m| {
+        assert_eq!(m.port2(port::X), 0);
+        assert_eq!(m.port2(port::Y), 0);
+    });
+}
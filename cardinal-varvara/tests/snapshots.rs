@@ -12,7 +12,7 @@ struct Snapshot {
 fn get_snapshot(rom: &[u8]) -> Result<Snapshot, std::io::Error> {
     let mut ram = UxnRam::new();
     let mut vm = Uxn::new(&mut ram, Backend::Interpreter);
-    let mut dev = Varvara::default();
+    let mut dev = Varvara::headless();
     let data = vm.reset(rom);
     dev.reset(data);
     vm.run(&mut dev, 0x100); // init vector
@@ -165,3 +165,71 @@ mod snapshots {
         run_and_check("screen");
     }
 }
+
+mod formats {
+    use super::*;
+    use cardinal_varvara::{rgb565, FrameFormat};
+
+    fn run_rom(name: &str) -> (Uxn<'static>, Varvara) {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set");
+        let rom_path = Path::new(&manifest_dir)
+            .parent()
+            .expect("missing parent directory")
+            .join(format!("roms/{name}.rom"));
+        let rom = std::fs::read(rom_path).expect("could not read ROM file");
+        let mut vm = Uxn::new(UxnRam::new().leak(), Backend::Interpreter);
+        let mut dev = Varvara::headless();
+        let data = vm.reset(&rom);
+        dev.reset(data);
+        vm.run(&mut dev, 0x100);
+        dev.redraw(&mut vm);
+        (vm, dev)
+    }
+
+    #[test]
+    fn alternate_formats_match_bgra() {
+        let (vm, mut dev) = run_rom("screen.pixel");
+        let out = dev.output(&vm);
+        let (width, height) = out.size;
+        let (width, height) = (width as usize, height as usize);
+        let palette = out.palette;
+        let bgra = out.frame[..width * height * 4].to_vec();
+
+        let mut buf = vec![];
+        dev.screen.frame_as(&vm, FrameFormat::Bgra, &mut buf);
+        assert_eq!(buf, bgra);
+
+        let mut indexed = vec![];
+        dev.screen.frame_as(&vm, FrameFormat::Indexed, &mut indexed);
+        assert_eq!(indexed.len(), width * height);
+        for (i, c) in indexed.iter().zip(bgra.chunks(4)) {
+            assert_eq!(palette[*i as usize].to_le_bytes(), c);
+        }
+
+        dev.screen.frame_as(&vm, FrameFormat::Packed2bpp, &mut buf);
+        let stride = FrameFormat::Packed2bpp.stride(width as u16);
+        assert_eq!(buf.len(), stride * height);
+        for (y, row) in indexed.chunks(width).enumerate() {
+            for (x, i) in row.iter().enumerate() {
+                let b = buf[y * stride + x / 4];
+                assert_eq!((b >> (6 - (x % 4) * 2)) & 0b11, *i);
+            }
+        }
+
+        dev.screen.frame_as(&vm, FrameFormat::Packed1bpp, &mut buf);
+        let stride = FrameFormat::Packed1bpp.stride(width as u16);
+        assert_eq!(buf.len(), stride * height);
+        for (y, row) in indexed.chunks(width).enumerate() {
+            for (x, i) in row.iter().enumerate() {
+                let b = buf[y * stride + x / 8];
+                assert_eq!((b & (0x80 >> (x % 8))) != 0, *i != 0);
+            }
+        }
+
+        dev.screen.frame_as(&vm, FrameFormat::Rgb565, &mut buf);
+        assert_eq!(buf.len(), width * height * 2);
+        for (i, c) in indexed.iter().zip(buf.chunks(2)) {
+            assert_eq!(rgb565(palette[*i as usize]).to_le_bytes(), c);
+        }
+    }
+}
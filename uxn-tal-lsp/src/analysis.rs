@@ -0,0 +1,374 @@
+//! Per-document analysis: symbol index, diagnostics, hover and completion
+//!
+//! The index is built from the `uxn_tal` lexer's tokens, which carry line,
+//! column and scope information; addresses come from the `Assembler` symbol
+//! table after a full assembly, and diagnostics from every error the
+//! assembler reports.
+//!
+//! Lines and columns are converted to zero-based LSP positions by counting
+//! characters, which matches UTF-16 offsets for the ASCII source that Uxntal
+//! programs are written in.
+use std::collections::HashMap;
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    Position, Range, TextEdit,
+};
+use uxn_tal::devicemap::DEVICES_DEFAULT;
+use uxn_tal::lexer::{Lexer, Token, TokenWithPos};
+use uxn_tal::stack_check;
+use uxn_tal::{Assembler, Severity};
+
+/// Namespace of a symbol
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// Label or sublabel, named by its full `parent/child` path
+    Label,
+    /// Macro defined with `%`
+    Macro,
+}
+
+/// Occurrence of a label or macro in the source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// Resolved name: sublabels and scoped references use the full path
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Range of the name, excluding any rune
+    pub range: Range,
+}
+
+/// Results of analyzing a single document
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub definitions: Vec<Symbol>,
+    pub references: Vec<Symbol>,
+    /// Label addresses, if the document assembled
+    pub addresses: HashMap<String, u16>,
+}
+
+impl Analysis {
+    /// Lexes, indexes and assembles a document
+    ///
+    /// `path` is used to resolve includes, and to tell errors in this document
+    /// apart from errors in included files.
+    pub fn new(source: &str, path: Option<&str>) -> Self {
+        let mut out = Self::default();
+        // Lexer errors are reported again by the assembler below
+        let (tokens, _) = Lexer::new(source.to_owned(), path.map(str::to_owned))
+            .with_quiet(true)
+            .tokenize_all();
+        out.index(&tokens);
+
+        // The assembler is not hardened against half-typed input, so a panic
+        // is reported as a diagnostic rather than taking down the server.
+        let mut asm = Assembler::new();
+        asm.quiet = true;
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            asm.assemble_with_diagnostics(source, path.map(str::to_owned))
+        }));
+        match r {
+            Ok(Ok(_)) => {
+                out.addresses = asm
+                    .symbols
+                    .iter()
+                    .map(|(name, s)| (name.clone(), s.address))
+                    .collect();
+                let warnings = std::panic::catch_unwind(|| stack_check::check(source, path));
+                for d in warnings.iter().flatten() {
+                    out.diagnostics.push(diagnostic(d, source, path));
+                }
+            }
+            Ok(Err(diagnostics)) => {
+                out.diagnostics = diagnostics
+                    .iter()
+                    .map(|d| diagnostic(d, source, path))
+                    .collect();
+            }
+            Err(_) => out.diagnostics.push(Diagnostic {
+                range: Range::default(),
+                severity: Some(DiagnosticSeverity::ERROR),
+                message: "assembler panicked on this input".to_owned(),
+                ..Default::default()
+            }),
+        }
+        out
+    }
+
+    /// Collects definitions, then resolves references against them
+    fn index(&mut self, tokens: &[TokenWithPos]) {
+        let mut refs = vec![];
+        for t in tokens {
+            let main = t.scope.as_deref().map(|s| s.split('/').next().unwrap());
+            match &t.token {
+                Token::LabelDef(_, name) => self.define(name, SymbolKind::Label, t),
+                Token::SublabelDef(name) if !name.is_empty() => {
+                    let full = match main {
+                        Some(m) => format!("{m}/{name}"),
+                        None => name.clone(),
+                    };
+                    self.definitions.push(Symbol {
+                        name: full,
+                        kind: SymbolKind::Label,
+                        range: name_range(t, name),
+                    });
+                }
+                Token::MacroDef(name) => self.define(name, SymbolKind::Macro, t),
+                Token::SublabelRef(name) => refs.push((t, format!("&{name}"))),
+                Token::RelativeRef(name) => refs.push((t, format!("/{name}"))),
+                Token::LabelRef(_, name)
+                | Token::ConditionalRef(name)
+                | Token::RawAddressRef(name)
+                | Token::JSRRef(name)
+                | Token::HyphenRef(name)
+                | Token::PaddingLabel(name)
+                | Token::RelativePaddingLabel(name)
+                | Token::DotRef(name)
+                | Token::SemicolonRef(name)
+                | Token::EqualsRef(name)
+                | Token::CommaRef(name)
+                | Token::UnderscoreRef(name)
+                | Token::QuestionRef(name)
+                | Token::ExclamationRef(name) => refs.push((t, name.clone())),
+                _ => (),
+            }
+        }
+
+        for (t, raw) in refs {
+            let scope = t.scope.as_deref().unwrap_or_default();
+            let is_macro = matches!(&t.token, Token::LabelRef(_, n)
+                if self.definitions.iter().any(|d| d.kind == SymbolKind::Macro && &d.name == n));
+            let (name, kind) = if is_macro {
+                (raw.clone(), SymbolKind::Macro)
+            } else {
+                (self.resolve(&raw, scope), SymbolKind::Label)
+            };
+            let text = raw.trim_start_matches(['&', '/']);
+            self.references.push(Symbol {
+                name,
+                kind,
+                range: name_range(t, text),
+            });
+        }
+    }
+
+    fn define(&mut self, name: &str, kind: SymbolKind, t: &TokenWithPos) {
+        self.definitions.push(Symbol {
+            name: name.to_owned(),
+            kind,
+            range: name_range(t, name),
+        });
+    }
+
+    /// Resolves a reference to the full name of a label
+    ///
+    /// `&child` and `/child` refer to a sublabel of the enclosing label;
+    /// other names are tried as written, then relative to the scope.
+    fn resolve(&self, raw: &str, scope: &str) -> String {
+        let main = scope.split('/').next().unwrap();
+        if let Some(child) = raw.strip_prefix(['&', '/']) {
+            return format!("{main}/{child}");
+        }
+        let known = |n: &str| self.label(n).is_some();
+        [
+            raw.to_owned(),
+            format!("{main}/{raw}"),
+            format!("{scope}/{raw}"),
+        ]
+        .into_iter()
+        .find(|n| known(n))
+        .unwrap_or_else(|| raw.to_owned())
+    }
+
+    fn label(&self, name: &str) -> Option<&Symbol> {
+        self.definitions
+            .iter()
+            .find(|d| d.kind == SymbolKind::Label && d.name == name)
+    }
+
+    /// Returns the symbol (definition or reference) under the cursor
+    pub fn symbol_at(&self, pos: Position) -> Option<&Symbol> {
+        self.definitions
+            .iter()
+            .chain(&self.references)
+            .find(|s| contains(s.range, pos))
+    }
+
+    /// Returns the definition of the symbol under the cursor
+    pub fn definition(&self, pos: Position) -> Option<&Symbol> {
+        let s = self.symbol_at(pos)?;
+        self.definitions
+            .iter()
+            .find(|d| d.kind == s.kind && d.name == s.name)
+    }
+
+    /// Returns every reference to the symbol under the cursor
+    pub fn references(&self, pos: Position, include_declaration: bool) -> Vec<Range> {
+        let Some(s) = self.symbol_at(pos) else {
+            return vec![];
+        };
+        let defs = self.definitions.iter().filter(|_| include_declaration);
+        defs.chain(&self.references)
+            .filter(|r| r.kind == s.kind && r.name == s.name)
+            .map(|r| r.range)
+            .collect()
+    }
+
+    /// Returns Markdown describing the symbol under the cursor
+    pub fn hover(&self, pos: Position) -> Option<(String, Range)> {
+        let s = self.symbol_at(pos)?;
+        let text = match s.kind {
+            SymbolKind::Macro => format!("macro `%{}`", s.name),
+            SymbolKind::Label => {
+                let addr = self
+                    .addresses
+                    .get(&s.name)
+                    .copied()
+                    .or_else(|| device_field(&s.name).map(|(addr, _)| addr));
+                match addr {
+                    Some(a) if a < 0x100 => format!("`@{}` at `|{a:02x}`", s.name),
+                    Some(a) => format!("`@{}` at `|{a:04x}`", s.name),
+                    None if self.label(&s.name).is_some() => {
+                        format!("`@{}` (address unknown until the file assembles)", s.name)
+                    }
+                    None => format!("`{}` is not defined", s.name),
+                }
+            }
+        };
+        Some((text, s.range))
+    }
+
+    /// Returns completions for the word ending at `pos` in `line`
+    ///
+    /// Device fields from the default device map are offered as
+    /// `Device/field`, along with this document's labels and macros.
+    pub fn completions(&self, line: &str, pos: Position) -> Vec<CompletionItem> {
+        let before: String = line.chars().take(pos.character as usize).collect();
+        let word = before
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(|c| ".,;:=_-!?|$&/@%#~".contains(c));
+        let start = Position::new(pos.line, pos.character - word.chars().count() as u32);
+        let range = Range::new(start, pos);
+        let item = |label: String, kind, detail: String| CompletionItem {
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                label.clone(),
+            ))),
+            label,
+            kind: Some(kind),
+            detail: Some(detail),
+            ..Default::default()
+        };
+
+        let mut out = vec![];
+        for dev in DEVICES_DEFAULT.iter() {
+            for f in &dev.fields {
+                if f.name == "pad" {
+                    continue;
+                }
+                let addr = dev.get_field_address(&f.name).unwrap_or(dev.address);
+                out.push(item(
+                    format!("{}/{}", dev.name, f.name),
+                    CompletionItemKind::FIELD,
+                    format!(
+                        "|{addr:02x}, {} byte{}",
+                        f.size,
+                        if f.size == 1 { "" } else { "s" }
+                    ),
+                ));
+            }
+        }
+        for d in &self.definitions {
+            if out.iter().any(|i| i.label == d.name) {
+                continue;
+            }
+            let (kind, detail) = match d.kind {
+                SymbolKind::Macro => (CompletionItemKind::SNIPPET, "macro".to_owned()),
+                SymbolKind::Label => match self.addresses.get(&d.name) {
+                    Some(a) => (CompletionItemKind::REFERENCE, format!("|{a:04x}")),
+                    None => (CompletionItemKind::REFERENCE, "label".to_owned()),
+                },
+            };
+            out.push(item(d.name.clone(), kind, detail));
+        }
+        if let Some((prefix, _)) = word.rsplit_once('/') {
+            let prefix = format!("{prefix}/");
+            out.retain(|i| i.label.starts_with(&prefix));
+        }
+        out
+    }
+}
+
+/// Converts an assembler diagnostic to an LSP one; errors in included files
+/// are shown at the start of the document
+fn diagnostic(d: &uxn_tal::Diagnostic, source: &str, path: Option<&str>) -> Diagnostic {
+    let (range, message) = match &d.span {
+        Some(span) if span.path.is_empty() || Some(span.path.as_str()) == path => (
+            word_range(source, span.line, span.column),
+            d.message.clone(),
+        ),
+        Some(span) => (
+            Range::default(),
+            format!("{}:{}: {}", span.path, span.line, d.message),
+        ),
+        None => (Range::default(), first_line(&d.message)),
+    };
+    Diagnostic {
+        range,
+        severity: Some(match d.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Note => DiagnosticSeverity::INFORMATION,
+        }),
+        source: Some("uxntal".to_owned()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// Looks up `Device/field` in the default device map
+fn device_field(name: &str) -> Option<(u16, u8)> {
+    let (dev, field) = name.split_once('/')?;
+    let dev = DEVICES_DEFAULT.iter().find(|d| d.name == dev)?;
+    let f = dev.fields.iter().find(|f| f.name == field)?;
+    Some((dev.get_field_address(field)?, f.size))
+}
+
+/// Returns the range of `name` at the end of a token, skipping its rune
+fn name_range(t: &TokenWithPos, name: &str) -> Range {
+    let line = t.line.saturating_sub(1) as u32;
+    let end = t.end_pos as u32;
+    let start = (end + 1).saturating_sub(name.chars().count() as u32).max(1) - 1;
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+/// Returns the range of the whitespace-delimited word at a one-based line
+/// and column
+fn word_range(source: &str, line: usize, column: usize) -> Range {
+    let l = line.saturating_sub(1);
+    let text = source.lines().nth(l).unwrap_or_default();
+    let chars: Vec<char> = text.chars().collect();
+    let mut start = column.saturating_sub(1).min(chars.len());
+    while start > 0 && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+    let mut end = start;
+    while end < chars.len() && !chars[end].is_whitespace() {
+        end += 1;
+    }
+    Range::new(
+        Position::new(l as u32, start as u32),
+        Position::new(l as u32, end as u32),
+    )
+}
+
+fn contains(r: Range, p: Position) -> bool {
+    r.start <= p && p <= r.end
+}
+
+fn first_line(s: &str) -> String {
+    s.lines().next().unwrap_or_default().to_owned()
+}
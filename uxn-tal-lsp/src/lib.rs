@@ -0,0 +1,10 @@
+//! Language server for Uxntal
+//!
+//! Offers diagnostics from the `uxn_tal` assembler, go-to-definition and
+//! find-references for labels, sublabels and macros, hover with resolved
+//! label addresses, and completion of device fields.
+pub mod analysis;
+pub mod server;
+
+pub use analysis::Analysis;
+pub use server::{capabilities, Server};
@@ -0,0 +1,39 @@
+//! `uxntal-lsp`: language server for Uxntal, speaking LSP over stdio
+use std::io::{self, BufReader};
+
+use anyhow::Result;
+use lsp_server::{Connection, Message};
+use uxn_tal_lsp::{capabilities, Server};
+
+fn main() -> Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().filter_or("UXN_LOG", "warn"));
+
+    let (server, client) = Connection::memory();
+    // The reader stops after the `exit` notification, but may also be left
+    // blocked on stdin if the client disappears, so it is never joined.
+    std::thread::spawn(move || -> io::Result<()> {
+        let mut stdin = BufReader::new(io::stdin());
+        while let Some(msg) = Message::read(&mut stdin)? {
+            let exit = matches!(&msg, Message::Notification(n) if n.method == "exit");
+            if client.sender.send(msg).is_err() || exit {
+                break;
+            }
+        }
+        Ok(())
+    });
+    // The assembler is run quietly, so nothing else writes to stdout
+    let writer = std::thread::spawn(move || -> io::Result<()> {
+        let mut out = io::stdout();
+        for msg in &client.receiver {
+            msg.write(&mut out)?;
+        }
+        Ok(())
+    });
+
+    let caps = serde_json::to_value(capabilities())?;
+    server.initialize(caps)?;
+    Server::default().run(&server)?;
+    drop(server);
+    writer.join().unwrap()?;
+    Ok(())
+}
@@ -0,0 +1,215 @@
+//! Language server main loop
+//!
+//! Documents are synchronized in full on every change and re-analyzed
+//! immediately; Uxntal files are small enough that incremental updates would
+//! not be worth the complexity.
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::{debug, warn};
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, References, Request as _};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams, ReferenceParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+
+use crate::analysis::Analysis;
+
+/// Characters after which clients should ask for completions
+const TRIGGERS: &[&str] = &[".", ",", ";", "=", "-", "_", "!", "?", "|", "&", "/"];
+
+/// Returns the capabilities advertised during initialization
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(TRIGGERS.iter().map(|s| s.to_string()).collect()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Open document
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+/// Language server state
+#[derive(Default)]
+pub struct Server {
+    docs: HashMap<String, Document>,
+}
+
+impl Server {
+    /// Runs the server on an initialized connection until shutdown
+    pub fn run(&mut self, conn: &Connection) -> Result<()> {
+        for msg in &conn.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if conn.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    let resp = self.request(req);
+                    conn.sender.send(resp.into())?;
+                }
+                Message::Notification(n) => {
+                    if let Some(p) = self.notification(n) {
+                        let n = Notification::new(PublishDiagnostics::METHOD.to_owned(), p);
+                        conn.sender.send(n.into())?;
+                    }
+                }
+                Message::Response(r) => debug!("ignoring response {:?}", r.id),
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a document notification, returning diagnostics to publish
+    fn notification(&mut self, n: Notification) -> Option<PublishDiagnosticsParams> {
+        let (uri, text, version) = match n.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: lsp_types::DidOpenTextDocumentParams = parse(n.params)?;
+                let d = p.text_document;
+                (d.uri, d.text, Some(d.version))
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: lsp_types::DidChangeTextDocumentParams = parse(n.params)?;
+                let text = p.content_changes.into_iter().last()?.text;
+                (p.text_document.uri, text, Some(p.text_document.version))
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: lsp_types::DidCloseTextDocumentParams = parse(n.params)?;
+                self.docs.remove(p.text_document.uri.as_str());
+                return Some(PublishDiagnosticsParams::new(
+                    p.text_document.uri,
+                    vec![],
+                    None,
+                ));
+            }
+            m => {
+                debug!("ignoring notification {m}");
+                return None;
+            }
+        };
+        let analysis = Analysis::new(&text, file_path(&uri).as_deref());
+        let diagnostics = analysis.diagnostics.clone();
+        self.docs
+            .insert(uri.as_str().to_owned(), Document { text, analysis });
+        Some(PublishDiagnosticsParams::new(uri, diagnostics, version))
+    }
+
+    fn request(&mut self, req: Request) -> Response {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            GotoDefinition::METHOD => self.definition(req).map(to_value),
+            References::METHOD => self.references(req).map(to_value),
+            HoverRequest::METHOD => self.hover(req).map(to_value),
+            Completion::METHOD => self.completion(req).map(to_value),
+            m => {
+                return Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unsupported method {m}"),
+                )
+            }
+        };
+        match result {
+            Some(v) => Response::new_ok(id, v),
+            None => null(id),
+        }
+    }
+
+    fn doc(&self, uri: &Uri) -> Option<&Document> {
+        self.docs.get(uri.as_str())
+    }
+
+    fn definition(&self, req: Request) -> Option<GotoDefinitionResponse> {
+        let p: GotoDefinitionParams = parse(req.params)?;
+        let p = p.text_document_position_params;
+        let d = self.doc(&p.text_document.uri)?;
+        let def = d.analysis.definition(p.position)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            p.text_document.uri,
+            def.range,
+        )))
+    }
+
+    fn references(&self, req: Request) -> Option<Vec<Location>> {
+        let p: ReferenceParams = parse(req.params)?;
+        let uri = p.text_document_position.text_document.uri;
+        let d = self.doc(&uri)?;
+        let ranges = d.analysis.references(
+            p.text_document_position.position,
+            p.context.include_declaration,
+        );
+        Some(
+            ranges
+                .into_iter()
+                .map(|r| Location::new(uri.clone(), r))
+                .collect(),
+        )
+    }
+
+    fn hover(&self, req: Request) -> Option<Hover> {
+        let p: HoverParams = parse(req.params)?;
+        let p = p.text_document_position_params;
+        let d = self.doc(&p.text_document.uri)?;
+        let (text, range) = d.analysis.hover(p.position)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: text,
+            }),
+            range: Some(range),
+        })
+    }
+
+    fn completion(&self, req: Request) -> Option<CompletionResponse> {
+        let p: CompletionParams = parse(req.params)?;
+        let p = p.text_document_position;
+        let d = self.doc(&p.text_document.uri)?;
+        let line = d.text.lines().nth(p.position.line as usize).unwrap_or("");
+        let items = d.analysis.completions(line, p.position);
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+/// Returns the local path of a `file://` URI
+fn file_path(uri: &Uri) -> Option<String> {
+    if uri.scheme()?.as_str() != "file" {
+        return None;
+    }
+    let path = uri.path().as_estr().decode().into_string().ok()?;
+    // `file:///C:/dir` has the path `/C:/dir` on Windows
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] if cfg!(windows) => path[1..].to_owned(),
+        _ => path.into_owned(),
+    };
+    Some(path)
+}
+
+fn parse<T: serde::de::DeserializeOwned>(params: serde_json::Value) -> Option<T> {
+    serde_json::from_value(params)
+        .map_err(|e| warn!("invalid params: {e}"))
+        .ok()
+}
+
+fn to_value<T: serde::Serialize>(v: T) -> serde_json::Value {
+    serde_json::to_value(v).unwrap()
+}
+
+fn null(id: RequestId) -> Response {
+    Response::new_ok(id, serde_json::Value::Null)
+}
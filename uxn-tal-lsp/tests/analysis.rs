@@ -0,0 +1,121 @@
+use lsp_types::{DiagnosticSeverity, Position, Range};
+use uxn_tal_lsp::analysis::SymbolKind;
+use uxn_tal_lsp::Analysis;
+
+const SOURCE: &str = "\
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+
+%EMIT { .Console/write DEO }
+
+|0100
+
+@on-reset ( -> )
+	;hello print
+	BRK
+
+@print ( str* -- )
+	&while
+		LDAk EMIT
+		INC2 LDAk ?&while
+	POP2
+	JMP2r
+
+@hello \"Hi $1
+";
+
+fn pos(line: u32, character: u32) -> Position {
+    Position::new(line, character)
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(pos(line, start), pos(line, end))
+}
+
+#[test]
+fn index() {
+    let a = Analysis::new(SOURCE, None);
+    assert!(a.diagnostics.is_empty(), "{:?}", a.diagnostics);
+
+    let print = a.definition(pos(7, 9)).expect("no definition for print");
+    assert_eq!(print.name, "print");
+    assert_eq!(print.range, range(10, 1, 6));
+
+    // `?&while` resolves to the sublabel of the enclosing label
+    let w = a.definition(pos(13, 14)).expect("no definition for &while");
+    assert_eq!(w.name, "print/while");
+    assert_eq!(w.range, range(11, 2, 7));
+
+    let emit = a.definition(pos(12, 8)).expect("no definition for EMIT");
+    assert_eq!(emit.kind, SymbolKind::Macro);
+    assert_eq!(emit.range, range(2, 1, 5));
+
+    assert_eq!(a.references(pos(10, 2), false), [range(7, 8, 13)]);
+    assert_eq!(
+        a.references(pos(13, 14), true),
+        [range(11, 2, 7), range(13, 14, 19)]
+    );
+}
+
+#[test]
+fn hover() {
+    let a = Analysis::new(SOURCE, None);
+    let (text, r) = a.hover(pos(7, 3)).unwrap();
+    assert_eq!(text, "`@hello` at `|0112`");
+    assert_eq!(r, range(7, 2, 7));
+
+    let (text, _) = a.hover(pos(2, 17)).unwrap();
+    assert_eq!(text, "`@Console/write` at `|18`");
+}
+
+#[test]
+fn completion() {
+    let a = Analysis::new(SOURCE, None);
+    let line = "\t.Screen/";
+    let items = a.completions(line, pos(3, 9));
+    let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+    assert!(labels.contains(&"Screen/width"), "{labels:?}");
+    assert!(
+        labels.iter().all(|l| l.starts_with("Screen/")),
+        "{labels:?}"
+    );
+    let width = items.iter().find(|i| i.label == "Screen/width").unwrap();
+    assert_eq!(width.detail.as_deref(), Some("|22, 2 bytes"));
+
+    let labels: Vec<_> = a
+        .completions("\t;", pos(3, 2))
+        .into_iter()
+        .map(|i| i.label)
+        .collect();
+    for l in ["hello", "print/while", "EMIT", "Console/write"] {
+        assert!(labels.iter().any(|x| x == l), "{l} missing from {labels:?}");
+    }
+}
+
+#[test]
+fn diagnostics() {
+    let a = Analysis::new("|0100 #zz BRK\n", None);
+    assert_eq!(a.diagnostics.len(), 1);
+    assert_eq!(a.diagnostics[0].range.start.line, 0);
+
+    let a = Analysis::new("|0100\n\t;missing BRK\n", None);
+    assert_eq!(a.diagnostics.len(), 1, "{:?}", a.diagnostics);
+    let d = &a.diagnostics[0];
+    assert_eq!(d.range.start.line, 1, "{d:?}");
+    assert!(d.message.contains("missing"), "{d:?}");
+
+    let a = Analysis::new("|0100\n\t;one #zz\n\t;two BRK\n", None);
+    let lines: Vec<_> = a.diagnostics.iter().map(|d| d.range.start.line).collect();
+    assert_eq!(lines, [1, 1, 2], "{:?}", a.diagnostics);
+
+    assert!(Analysis::new(SOURCE, None).diagnostics.is_empty());
+    let a = Analysis::new(
+        "|0100 @f ( a -- )
+	DUP JMP2r
+",
+        None,
+    );
+    assert_eq!(a.diagnostics.len(), 1, "{:?}", a.diagnostics);
+    let d = &a.diagnostics[0];
+    assert_eq!(d.severity, Some(DiagnosticSeverity::WARNING));
+    assert_eq!(d.range, range(1, 5, 10));
+}
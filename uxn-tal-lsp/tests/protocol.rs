@@ -0,0 +1,91 @@
+//! Drives the `uxntal-lsp` binary over stdio, which also checks that the
+//! assembler's own output doesn't leak into the protocol stream
+use std::io::BufReader;
+use std::process::{Command, Stdio};
+
+use lsp_server::{Message, Notification, Request, RequestId};
+use serde_json::{json, Value};
+
+#[test]
+fn stdio() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_uxntal-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start uxntal-lsp");
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut send = |m: Message| m.write(&mut stdin).unwrap();
+    let mut recv = || Message::read(&mut stdout).unwrap().expect("server hung up");
+    let request = |id: i32, method: &str, params: Value| {
+        Message::Request(Request::new(RequestId::from(id), method.to_owned(), params))
+    };
+    let notify = |method: &str, params: Value| {
+        Message::Notification(Notification::new(method.to_owned(), params))
+    };
+    let uri = "file:///tmp/hello.tal";
+
+    send(request(1, "initialize", json!({ "capabilities": {} })));
+    let Message::Response(r) = recv() else {
+        panic!("expected initialize response");
+    };
+    let caps = &r.result.unwrap()["capabilities"];
+    assert_eq!(caps["hoverProvider"], true);
+    assert_eq!(caps["definitionProvider"], true);
+    send(notify("initialized", json!({})));
+
+    let text = "|0100 ;hello BRK\n@hello \"Hi $1\n\t;nowhere\n";
+    send(notify(
+        "textDocument/didOpen",
+        json!({ "textDocument": {
+            "uri": uri, "languageId": "uxntal", "version": 1, "text": text
+        }}),
+    ));
+    let Message::Notification(n) = recv() else {
+        panic!("expected diagnostics");
+    };
+    assert_eq!(n.method, "textDocument/publishDiagnostics");
+    let diags = n.params["diagnostics"].as_array().unwrap();
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0]["range"]["start"]["line"], 2);
+
+    let text = "|0100 ;hello BRK\n@hello \"Hi $1\n";
+    send(notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": text }]
+        }),
+    ));
+    let Message::Notification(n) = recv() else {
+        panic!("expected diagnostics");
+    };
+    assert_eq!(n.params["diagnostics"], json!([]));
+
+    let at = json!({ "textDocument": { "uri": uri }, "position": { "line": 0, "character": 8 } });
+    send(request(2, "textDocument/hover", at.clone()));
+    let Message::Response(r) = recv() else {
+        panic!("expected hover response");
+    };
+    assert_eq!(
+        r.result.unwrap()["contents"]["value"],
+        "`@hello` at `|0104`"
+    );
+
+    send(request(3, "textDocument/definition", at));
+    let Message::Response(r) = recv() else {
+        panic!("expected definition response");
+    };
+    let loc = r.result.unwrap();
+    assert_eq!(loc["uri"], uri);
+    assert_eq!(loc["range"]["start"], json!({ "line": 1, "character": 1 }));
+
+    send(request(4, "shutdown", Value::Null));
+    let Message::Response(r) = recv() else {
+        panic!("expected shutdown response");
+    };
+    assert_eq!(r.id, RequestId::from(4));
+    send(notify("exit", Value::Null));
+    assert!(child.wait().unwrap().success());
+}
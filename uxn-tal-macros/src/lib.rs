@@ -0,0 +1,216 @@
+//! Assembles Uxntal at compile time, embedding the ROM in the program
+//!
+//! [`include_tal!`] assembles a file and [`tal!`] a string literal.  Given
+//! just the source they expand to the ROM, as a `&'static [u8]`:
+//!
+//! ```ignore
+//! const ROM: &[u8] = uxn_tal_macros::include_tal!("roms/hello.tal");
+//! ```
+//!
+//! or, given a module name, to a module holding the ROM, its symbol file and
+//! a constant for each label:
+//!
+//! ```ignore
+//! uxn_tal_macros::tal! {
+//!     /// Prints a greeting
+//!     pub mod hello = r#"
+//!         |0100 ;text print-str BRK
+//!         @print-str ( str* -- ) ...
+//!         @text "hello 00
+//!     "#;
+//! }
+//!
+//! runner.load(hello::ROM, &[]);
+//! let text = hello::labels::TEXT.slice(runner.vm.ram());
+//! assert_eq!(hello::label("print-str"), Some(hello::labels::PRINT_STR));
+//! ```
+//!
+//! Paths given to `include_tal!` are relative to the crate's `Cargo.toml`.
+//! `~include`s are relative to that file's directory, as they are for
+//! `uxntal`, or to the crate's for `tal!`.  Every file read is tracked, so
+//! editing one rebuilds the crate.
+//!
+//! Assembler errors become compile errors on the string literal, each with
+//! the file, line and column it refers to.
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::quote;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use syn::parse::{Parse, ParseStream};
+use syn::{Attribute, Ident, LitStr, Token, Visibility};
+use uxn_tal::{label_extents, rust_identifier, Assembler};
+
+/// Assembles a TAL string literal
+///
+/// See the [crate] documentation.
+#[proc_macro]
+pub fn tal(input: TokenStream) -> TokenStream {
+    expand(input, false)
+}
+
+/// Assembles a TAL file, relative to the crate's `Cargo.toml`
+///
+/// See the [crate] documentation.
+#[proc_macro]
+pub fn include_tal(input: TokenStream) -> TokenStream {
+    expand(input, true)
+}
+
+/// `[attributes] [visibility] mod name = "..."`, or just `"..."`
+struct Input {
+    module: Option<(Vec<Attribute>, Visibility, Ident)>,
+    literal: LitStr,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let module = if input.peek(LitStr) {
+            None
+        } else {
+            let attrs = input.call(Attribute::parse_outer)?;
+            let vis = input.parse()?;
+            input.parse::<Token![mod]>()?;
+            let name = input.parse()?;
+            input.parse::<Token![=]>()?;
+            Some((attrs, vis, name))
+        };
+        let literal = input.parse()?;
+        if module.is_some() {
+            input.parse::<Option<Token![;]>>()?;
+        }
+        Ok(Self { module, literal })
+    }
+}
+
+fn expand(input: TokenStream, from_file: bool) -> TokenStream {
+    let input = syn::parse_macro_input!(input as Input);
+    match assemble(&input, from_file) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn assemble(input: &Input, from_file: bool) -> syn::Result<TokenStream2> {
+    let span = input.literal.span();
+    let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let (source, path, include_dir) = if from_file {
+        let path = crate_dir.join(input.literal.value());
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| syn::Error::new(span, format!("couldn't read {}: {e}", path.display())))?;
+        let dir = path
+            .parent()
+            .map_or_else(|| crate_dir.clone(), Path::to_owned);
+        (source, path.display().to_string(), dir)
+    } else {
+        (input.literal.value(), "tal!".to_owned(), crate_dir)
+    };
+
+    let mut asm = Assembler::new();
+    asm.quiet = true;
+    asm.include_dir = Some(include_dir);
+    let result = asm.assemble_with_diagnostics(&source, Some(path.clone()));
+
+    // Rebuild when any file read changes, even if assembly failed
+    let tracked = asm
+        .sources
+        .keys()
+        .filter(|p| from_file || **p != path)
+        .map(|p| {
+            let p = Path::new(p)
+                .canonicalize()
+                .unwrap_or_else(|_| PathBuf::from(p));
+            let p = p.display().to_string();
+            quote!(
+                const _: &[u8] = include_bytes!(#p);
+            )
+        })
+        .collect::<TokenStream2>();
+
+    let rom = result.map_err(|diagnostics| {
+        diagnostics
+            .iter()
+            .map(|d| {
+                // rustc says it's an error itself
+                let text = d.to_string();
+                let prefix = format!("{}: ", d.severity);
+                syn::Error::new(span, text.strip_prefix(&prefix).unwrap_or(&text))
+            })
+            .reduce(|mut all, e| {
+                all.combine(e);
+                all
+            })
+            .unwrap_or_else(|| syn::Error::new(span, "assembly failed"))
+    })?;
+    let rom_bytes = Literal::byte_string(&rom);
+
+    let Some((attrs, vis, name)) = &input.module else {
+        return Ok(quote!({
+            #tracked
+            #rom_bytes as &[u8]
+        }));
+    };
+
+    let sym = Literal::byte_string(&asm.generate_symbol_file());
+    let mut consts = Vec::new();
+    let mut all = Vec::new();
+    let mut taken = HashSet::new();
+    for (label, address, size) in label_extents(&asm) {
+        let value = quote!(Label { name: #label, address: #address, size: #size });
+        // Lambdas have no name to refer to them by
+        if !label.starts_with('λ') {
+            let id = rust_identifier(label);
+            // Later labels which only differ in punctuation are left out
+            if let Ok(id) = syn::parse_str::<Ident>(&id) {
+                if taken.insert(id.to_string()) {
+                    let doc = format!("`{label}`");
+                    consts.push(quote!(#[doc = #doc] pub const #id: Label = #value;));
+                }
+            }
+        }
+        all.push(value);
+    }
+    Ok(quote! {
+        #(#attrs)*
+        #vis mod #name {
+            /// A label in [`ROM`]
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+            pub struct Label {
+                /// Name in the source, e.g. `on-reset/loop`
+                pub name: &'static str,
+                /// Address in memory
+                pub address: u16,
+                /// Bytes up to the next label, or the end of the ROM
+                pub size: u16,
+            }
+
+            impl Label {
+                /// Returns the label's bytes in `ram`, the whole 64K of
+                /// memory
+                pub fn slice<'a>(&self, ram: &'a [u8]) -> &'a [u8] {
+                    &ram[self.address as usize..][..self.size as usize]
+                }
+            }
+
+            /// The assembled ROM, which is loaded at `0x0100`
+            pub const ROM: &[u8] = #rom_bytes;
+            /// The symbol file, as written next to a ROM as `.rom.sym`
+            pub const SYM: &[u8] = #sym;
+            /// Every label, in the order they're defined
+            pub const LABELS: &[Label] = &[#(#all),*];
+
+            /// Labels by name, e.g. `on-reset/loop` as `ON_RESET_LOOP`
+            pub mod labels {
+                use super::Label;
+                #(#consts)*
+            }
+
+            /// Finds a label by its name in the source
+            pub fn label(name: &str) -> Option<Label> {
+                LABELS.iter().find(|l| l.name == name).copied()
+            }
+
+            #tracked
+        }
+    })
+}
@@ -0,0 +1,63 @@
+use uxn_tal::inproc::run_rom;
+use uxn_tal::Assembler;
+use uxn_tal_macros::{include_tal, tal};
+
+const HELLO: &[u8] = include_tal!("tests/tal/hello.tal");
+
+include_tal! {
+    /// The same program, with its labels
+    mod hello = "tests/tal/hello.tal";
+}
+
+tal! {
+    mod counter = r#"
+        |00 @count $1
+        |0100 @on-reset ( -> )
+            .count LDZ INC .count STZ
+            BRK
+        @on-frame-2 ( -> ) BRK
+        @on/frame2 ( -> ) BRK
+    "#
+}
+
+#[test]
+fn embeds_the_rom() {
+    assert_eq!(run_rom(HELLO, &[], Default::default()).stdout, b"hello\n");
+    assert_eq!(hello::ROM, HELLO);
+    assert_eq!(
+        tal!("|0100 #01 #02 ADD BRK"),
+        [0x80, 0x01, 0x80, 0x02, 0x18, 0x00]
+    );
+}
+
+#[test]
+fn matches_the_assembler() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tal");
+    let source = std::fs::read_to_string(format!("{dir}/hello.tal")).unwrap();
+    let mut asm = Assembler::new();
+    asm.include_dir = Some(dir.into());
+    assert_eq!(asm.assemble(&source, None).unwrap(), hello::ROM);
+    assert_eq!(asm.generate_symbol_file(), hello::SYM);
+}
+
+#[test]
+fn labels() {
+    use hello::labels::*;
+    assert_eq!(ON_RESET.address, 0x0100);
+    assert_eq!(CONSOLE_WRITE.address, 0x18);
+    assert_eq!(PRINT_STR_LOOP.name, "print-str/loop");
+    assert_eq!(hello::label("text"), Some(TEXT));
+    assert_eq!(hello::label("nope"), None);
+
+    // A label's bytes run up to the next one, or the end of the ROM
+    let mut ram = vec![0; 0x10000];
+    ram[0x100..][..HELLO.len()].copy_from_slice(HELLO);
+    assert_eq!(TEXT.slice(&ram), b"hello");
+    assert_eq!(PRINT_STR.size, TEXT.address - PRINT_STR.address);
+
+    // The first of two labels with the same constant name wins
+    assert_eq!(counter::labels::COUNT.address, 0x00);
+    assert_eq!(counter::labels::ON_FRAME_2.name, "on-frame-2");
+    assert_eq!(counter::LABELS.len(), 4);
+    assert!(counter::label("on/frame2").is_some());
+}
@@ -2,14 +2,38 @@
 
 use crate::devicemap::Device;
 use crate::devicemap::DEVICES_DEFAULT; // NEW: bring in default devices
+use crate::diagnostic::{Diagnostic, Span};
 use crate::error::{AssemblerError, Result};
 use crate::lexer::{Lexer, TokenWithPos};
+use crate::object::{RelocKind, Relocation};
 use crate::opcodes::Opcodes;
+use crate::optimizer::{self, Facts, Flow};
 use crate::parser::{AstNode, Parser};
 use crate::rom::Rom;
 use crate::runes::Rune;
-use std::collections::HashMap;
+use crate::source_map::SourceMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prints progress or tracing output to stdout, unless the assembler is
+/// [quiet](Assembler::quiet)
+macro_rules! say {
+    ($asm:expr, $($arg:tt)*) => {
+        if !$asm.quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Like [`say!`], but to stderr
+macro_rules! esay {
+    ($asm:expr, $($arg:tt)*) => {
+        if !$asm.quiet {
+            eprintln!($($arg)*);
+        }
+    };
+}
 
 /// Macro definition
 #[derive(Debug, Clone)]
@@ -46,6 +70,20 @@ pub struct Assembler {
     pub drif_mode: bool,                // Enable drifblim-compatible mode
     pub after_unreferenced_sublabel: bool, // Track if we're after a sublabel with no incoming references
     pub verbose: u8,                       // 0=none, 1=normal, 2=debug
+    pub sources: HashMap<String, String>,  // path -> contents, for error snippets
+    pub source_map: SourceMap,             // address -> originating token
+    pub used_symbols: HashSet<String>,     // symbols resolved by at least one reference
+    pub reference_targets: Vec<(u16, String)>, // (reference address, symbol it resolved to)
+    pub optimize: bool,                    // Run the peephole optimizer (see optimizer.rs)
+    pub relocatable: bool,                 // Leave unknown labels to the linker (see object.rs)
+    pub include_dir: Option<PathBuf>, // Resolve relative ~include paths here, not the working dir
+    pub quiet: bool, // Print nothing, e.g. when embedded in a build or a language server
+    pub(crate) pinned: bool, // Source padded to an absolute address past 0x0100
+    pub(crate) reset: bool, // Source pads to |0100, so it has a reset vector
+    pub(crate) relocations: Vec<Relocation>, // references for the linker to patch
+    errors: Vec<AssemblerError>, // recoverable errors, reported once assembly ends
+    facts: Option<Facts>, // set while assembling with the optimizer
+    flow: Flow,      // whether emitted code can run into the next node
 }
 
 /// Represents a forward reference that needs to be resolved
@@ -147,6 +185,20 @@ impl Assembler {
             drif_mode,
             after_unreferenced_sublabel: false,
             verbose,
+            sources: HashMap::new(),
+            source_map: SourceMap::new(),
+            used_symbols: HashSet::new(),
+            reference_targets: Vec::new(),
+            optimize: false,
+            relocatable: false,
+            include_dir: None,
+            quiet: false,
+            pinned: false,
+            reset: false,
+            relocations: Vec::new(),
+            errors: Vec::new(),
+            facts: None,
+            flow: Flow::default(),
         }
     }
 
@@ -156,18 +208,130 @@ impl Assembler {
             self.symbols.insert(name.to_string(), sym);
             self.symbol_order.push(name.to_string());
         } else if self.verbose >= 2 {
-            eprintln!("DEBUG: Symbol '{}' already exists at address {:04X}, not overwriting with new address {:04X}", name, self.symbols[name].address, sym.address);
+            esay!(self, "DEBUG: Symbol '{}' already exists at address {:04X}, not overwriting with new address {:04X}", name, self.symbols[name].address, sym.address);
         }
     }
 
+    /// Moves to an absolute address, noting whether the code can still be
+    /// relocated; every unit starts at 0x0100, so padding there doesn't count
+    fn pad_to(&mut self, address: u16) -> Result<()> {
+        self.pinned |= address > 0x0100;
+        self.reset |= address == 0x0100;
+        self.rom.pad_to(address)
+    }
+
     /// Update effective length if current position has non-zero content
     fn update_effective_length(&mut self) {
         self.effective_length = self.effective_length.max(self.rom.position().into());
     }
 
-    /// Assemble TAL source code into a ROM
+    /// Assemble TAL source code into a ROM, returning the first error
     pub fn assemble(&mut self, source: &str, path: Option<String>) -> Result<Vec<u8>> {
+        let rom = self.assemble_recovering(source, path);
+        let mut errors = std::mem::take(&mut self.errors);
+        match rom {
+            Ok(rom) if errors.is_empty() => Ok(rom),
+            Ok(_) => Err(errors.remove(0)),
+            Err(e) => {
+                errors.push(e);
+                Err(errors.remove(0))
+            }
+        }
+    }
+
+    /// Assemble TAL source code into a ROM, continuing past errors where
+    /// possible so that all of them are reported at once
+    pub fn assemble_with_diagnostics(
+        &mut self,
+        source: &str,
+        path: Option<String>,
+    ) -> std::result::Result<Vec<u8>, Vec<Diagnostic>> {
+        let rom = self.assemble_recovering(source, path);
+        let mut errors = std::mem::take(&mut self.errors);
+        match rom {
+            Ok(rom) if errors.is_empty() => Ok(rom),
+            Ok(_) => Err(errors.iter().map(Diagnostic::from).collect()),
+            Err(e) => {
+                errors.push(e);
+                Err(errors.iter().map(Diagnostic::from).collect())
+            }
+        }
+    }
+
+    /// Runs every pass, collecting recoverable errors in `self.errors`; an
+    /// `Err` means assembly could not continue at all
+    fn assemble_recovering(&mut self, source: &str, path: Option<String>) -> Result<Vec<u8>> {
+        self.facts = None;
+        let mut rom = self.assemble_pass(source, path.clone())?;
+        let unoptimized = rom.len();
+        if self.optimize && !self.relocatable && self.errors.is_empty() && !rom.is_empty() {
+            rom = self.assemble_optimized(source, path.clone(), rom)?;
+            if rom.is_empty() {
+                esay!(
+                    self,
+                    "Warning: {} is empty after optimizing",
+                    path.as_deref().unwrap_or("(input)")
+                );
+            }
+        }
+        if !rom.is_empty() {
+            say!(self,
+                "Assembled {} in {} bytes({:.2}% used), {} labels, {} macros. (effective_length=0x{:04X})",
+                path.unwrap_or_else(|| "(input)".to_string()),
+                rom.len(),
+                rom.len() as f64 / 652.80,
+                self.symbols.len(),
+                self.macros.len(),
+                self.effective_length
+            );
+            if self.optimize && self.verbose >= 1 {
+                say!(self, "Optimized from {} bytes", unoptimized);
+            }
+        }
+        Ok(rom)
+    }
+
+    /// Reassembles with the optimizer until it finds no more routines to
+    /// remove, falling back to the last pass that assembled cleanly
+    fn assemble_optimized(
+        &mut self,
+        source: &str,
+        path: Option<String>,
+        rom: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let mut facts = Facts::default();
+        facts.learn(self);
+        let mut best = (None, rom);
+        for _ in 0..8 {
+            self.facts = Some(facts.clone());
+            match self.assemble_pass(source, path.clone()) {
+                Ok(rom) if self.errors.is_empty() => best = (Some(facts.clone()), rom),
+                _ => break,
+            }
+            if !facts.learn(self) {
+                return Ok(best.1);
+            }
+        }
+        self.errors.clear();
+        self.facts = best.0;
+        self.assemble_pass(source, path)
+    }
+
+    /// Runs every pass once
+    fn assemble_pass(&mut self, source: &str, path: Option<String>) -> Result<Vec<u8>> {
         // Clear previous state
+        self.rom = Rom::new();
+        self.errors.clear();
+        self.sources.clear();
+        self.source_map = SourceMap::new();
+        self.used_symbols.clear();
+        self.reference_targets.clear();
+        self.flow = Flow::default();
+        self.pinned = false;
+        self.reset = false;
+        self.relocations.clear();
+        self.sources
+            .insert(path.clone().unwrap_or_default(), source.to_string());
         self.symbols.clear();
         self.symbol_order.clear();
         self.current_label = None;
@@ -180,14 +344,18 @@ impl Assembler {
         self.last_top_label = None;
 
         // Tokenize
-        let mut lexer = Lexer::new(source.to_string(), path.clone());
-        let tokens = lexer.tokenize()?;
+        let mut lexer = Lexer::new(source.to_string(), path.clone()).with_quiet(self.quiet);
+        let (tokens, errors) = lexer.tokenize_all();
+        self.errors.extend(errors);
 
         // Parse
         // Use "(input)" as the default path if none is provided
         let mut parser =
-            Parser::new_with_source(tokens, path.clone().unwrap_or_default(), source.to_string());
-        let ast = parser.parse()?;
+            Parser::new_with_source(tokens, path.clone().unwrap_or_default(), source.to_string())
+                .with_quiet(self.quiet);
+        let (ast, errors) = parser.parse_all();
+        self.errors.extend(errors);
+        let node_tokens = parser.node_tokens().to_vec();
 
         // First pass: collect labels and generate code
 
@@ -197,12 +365,15 @@ impl Assembler {
         // --- Ensure ROM pointer starts at 0x0100 (Varvara/uxn convention) ---
         self.rom.pad_to(0x0100)?;
 
-        self.first_pass(&ast)?;
+        self.first_pass(&ast, &node_tokens)?;
 
         // Second pass: resolve references and emit metadata header if needed
         self.second_pass()?;
         if self.verbose >= 2 {
-            println!("DEBUG: Resolved {} references", self.references.len());
+            say!(self, "DEBUG: Resolved {} references", self.references.len());
+        }
+        if !self.errors.is_empty() {
+            return Ok(Vec::new());
         }
 
         // Apply drifblim optimizations if in drif mode
@@ -213,7 +384,8 @@ impl Assembler {
         let end = self.effective_length;
         if end <= page_start {
             if self.verbose >= 2 {
-                println!(
+                say!(
+                    self,
                     "DEBUG: No non-zero bytes beyond PAGE (effective_length=0x{:04X})",
                     end
                 );
@@ -234,24 +406,24 @@ impl Assembler {
             prog.resize(end_rel, 0);
         }
         let result = &prog[..end_rel];
-        println!(
-            "Assembled {} in {} bytes({:.2}% used), {} labels, {} macros. (effective_length=0x{:04X})",
-            path.clone().unwrap_or_else(|| "(input)".to_string()),
-            result.len(),
-            result.len() as f64 / 652.80,
-            self.symbols.len(),
-            self.macros.len(),
-            end
-        );
         Ok(result.to_vec())
     }
 
-    fn first_pass(&mut self, ast: &[AstNode]) -> Result<()> {
+    fn first_pass(&mut self, ast: &[AstNode], node_tokens: &[TokenWithPos]) -> Result<()> {
+        let optimized;
+        let (ast, node_tokens) = match &self.facts {
+            Some(facts) => {
+                optimized = optimizer::optimize(ast, node_tokens, facts, false);
+                (&optimized.0[..], &optimized.1[..])
+            }
+            None => (ast, node_tokens),
+        };
         let mut current_scope: Option<String> = None;
         let mut last_top_label: Option<String> = None;
         let mut i = 0;
         while i < ast.len() {
-            match &ast[i] {
+            let start = self.rom.position();
+            let result = match &ast[i] {
                 AstNode::LabelDef(_rune, label) => {
                     let address = self.rom.position();
                     let label_clone = label.clone();
@@ -279,6 +451,7 @@ impl Assembler {
                         self.current_label = Some(label_clone.clone());
                         self.last_top_label = Some(label_clone);
                     }
+                    Ok(())
                 }
                 AstNode::SublabelDef(_tok) => {
                     // Don't define sublabels in first_pass - let process_node handle them
@@ -286,29 +459,33 @@ impl Assembler {
                     // But first update instance variables from local tracking
                     self.current_label = current_scope.clone();
                     self.last_top_label = last_top_label.clone();
-                    self.process_node(&ast[i])?;
+                    self.process_node(&ast[i])
                 }
                 AstNode::Padding(pad_addr) => {
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(
+                            self,
                             "DEBUG: [first_pass] Processing Padding to 0x{:04X}",
                             pad_addr
                         );
                     }
-                    self.rom.pad_to(*pad_addr)?;
+                    self.pad_to(*pad_addr)
                 }
                 AstNode::RelativePadding(count) => {
                     let old_pos = self.rom.position();
                     let new_pos = old_pos + count;
                     if self.verbose >= 2 {
-                        eprintln!("DEBUG: [first_pass] Processing RelativePadding({}) from 0x{:04X} to 0x{:04X}", count, old_pos, new_pos);
+                        esay!(self, "DEBUG: [first_pass] Processing RelativePadding({}) from 0x{:04X} to 0x{:04X}", count, old_pos, new_pos);
                     }
-                    self.rom.pad_to(new_pos)?;
-                }
-                _ => {
-                    self.process_node(&ast[i])?;
+                    self.rom.pad_to(new_pos)
                 }
+                _ => self.process_node(&ast[i]),
+            };
+            if let Err(e) = result {
+                self.errors.push(e);
             }
+            self.map_node(&ast[i], start, &node_tokens[i]);
+            self.flow.step(&ast[i]);
             i += 1;
         }
         Ok(())
@@ -359,9 +536,13 @@ impl Assembler {
                 let id = match self.lambda_stack.pop() {
                     Some(id) => id,
                     None => {
-                        eprintln!("Unmatched '}}' at line {}. Current macro table:", tok.line);
+                        esay!(
+                            self,
+                            "Unmatched '}}' at line {}. Current macro table:",
+                            tok.line
+                        );
                         for (name, mac) in &self.macros {
-                            eprintln!("Macro '{}': {:?}", name, mac.body);
+                            esay!(self, "Macro '{}': {:?}", name, mac.body);
                         }
                         return Err(AssemblerError::SyntaxError {
                             path: path.clone(),
@@ -394,7 +575,7 @@ impl Assembler {
                         },
                     );
                 } else if self.verbose >= 2 {
-                    eprintln!("DEBUG: Not inserting lambda label '{}' at address {:04X} because a named label already exists here", name, addr);
+                    esay!(self, "DEBUG: Not inserting lambda label '{}' at address {:04X} because a named label already exists here", name, addr);
                 }
             }
             AstNode::Padding(pad_addr) => {
@@ -402,7 +583,7 @@ impl Assembler {
                 if *pad_addr == 0x0100 && self.last_top_label.is_none() {
                     self.current_label = None;
                 }
-                self.rom.pad_to(*pad_addr)?;
+                self.pad_to(*pad_addr)?;
                 // Don't update effective_length for padding - only update when actual content is written
             }
             AstNode::Byte(byte) => {
@@ -451,7 +632,7 @@ impl Assembler {
                 // In drif mode, check if this instruction is reachable
                 if self.drif_mode && self.is_unreachable_instruction() {
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(self,
                             "DEBUG: Drif mode - skipping unreachable instruction: '{}' at address {:04X}",
                             inst.opcode,
                             self.rom.position()
@@ -461,7 +642,8 @@ impl Assembler {
                 }
 
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: Processing instruction: '{}' at address {:04X}",
                         inst.opcode,
                         self.rom.position()
@@ -471,7 +653,8 @@ impl Assembler {
                 if inst.opcode.eq_ignore_ascii_case("BRK") {
                     self.rom.write_byte(0x00)?;
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(
+                            self,
                             "DEBUG: Wrote opcode 0x00 (BRK) at {:04X}",
                             self.rom.position() - 1
                         );
@@ -491,7 +674,8 @@ impl Assembler {
                         );
                         self.rom.write_byte(final_opcode)?;
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: Wrote opcode 0x{:02X} ({}) at {:04X}",
                                 final_opcode,
                                 inst.opcode,
@@ -511,7 +695,8 @@ impl Assembler {
                     }
                     Err(_) => {
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: Creating JSR reference for unknown opcode: '{}'",
                                 inst.opcode
                             );
@@ -527,7 +712,8 @@ impl Assembler {
                         });
                         self.rom.write_byte(0x60)?; // JSR opcode
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: Wrote JSR opcode 0x60 at {:04X}",
                                 self.rom.position() - 1
                             );
@@ -535,7 +721,8 @@ impl Assembler {
                         self.update_effective_length();
                         self.rom.write_short(0xffff)?; // Placeholder
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: Wrote JSR placeholder 0xFFFF at {:04X}-{:04X}",
                                 self.rom.position() - 2,
                                 self.rom.position() - 1
@@ -549,7 +736,7 @@ impl Assembler {
                 self.line_number = token.line;
                 // DEBUG: Log when a bare label reference is encountered
                 if self.verbose >= 2 {
-                    println!(
+                    say!(self,
                         "DEBUG: AstNode::LabelRef encountered at line {}, emitting JSR to label {:?} at address {:04X}",
                         token.line,
                         token,
@@ -648,7 +835,7 @@ impl Assembler {
                 let label_clone = label.clone();
 
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(self,
                             "DEBUG: [process_node] Defining label '{}' at address 0x{:04X} (line: {}, file: {})",
                             label_clone,
                             self.rom.position(),
@@ -667,7 +854,8 @@ impl Assembler {
                     },
                 );
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: Symbol table now contains: {:?}",
                         self.symbols.keys().collect::<Vec<_>>()
                     );
@@ -685,7 +873,8 @@ impl Assembler {
                 }
 
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: Defined label '{}' at address {:0.4X}",
                         label_clone,
                         self.rom.position()
@@ -709,7 +898,7 @@ impl Assembler {
                         if !has_direct_ref && has_referenced_sublabel {
                             self.after_unreferenced_sublabel = true;
                             if self.verbose >= 2 {
-                                eprintln!("DEBUG: Drif mode - parent label '{}' is unreferenced but has referenced sublabels; marking subsequent code unreachable", label_clone);
+                                esay!(self, "DEBUG: Drif mode - parent label '{}' is unreferenced but has referenced sublabels; marking subsequent code unreachable", label_clone);
                             }
                         } else {
                             self.after_unreferenced_sublabel = false;
@@ -759,9 +948,11 @@ impl Assembler {
                     };
                     let insert_address = self.rom.position();
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(
+                            self,
                             "DEBUG: [SUBLABEL INSERT] About to insert '{}' with address {:04X}",
-                            full_name, insert_address
+                            full_name,
+                            insert_address
                         );
                     }
                     self.insert_symbol_if_new(
@@ -773,7 +964,8 @@ impl Assembler {
                         },
                     );
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(
+                            self,
                             "DEBUG: [SUBLABEL VERIFY] After inserting '{}', symbol table has: {:?}",
                             full_name,
                             self.symbols.get(&full_name)
@@ -798,7 +990,8 @@ impl Assembler {
                 }
 
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: Defined sublabel '{}' at address {:04X}",
                         full_name,
                         self.rom.position()
@@ -812,7 +1005,7 @@ impl Assembler {
                     if !has_references {
                         self.after_unreferenced_sublabel = true;
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(self,
                                 "DEBUG: Drif mode - sublabel '{}' has no references, marking subsequent code as unreachable",
                                 full_name
                             );
@@ -967,7 +1160,11 @@ impl Assembler {
                     label
                 };
                 if self.verbose >= 2 {
-                    eprintln!("DEBUG: [PaddingLabel] Resolving padding label '{}'", label);
+                    esay!(
+                        self,
+                        "DEBUG: [PaddingLabel] Resolving padding label '{}'",
+                        label
+                    );
                 }
                 // --- PATCH: try current scope, then <main_scope>/<label> ---
                 let mut found = self.symbols.get(&label);
@@ -978,9 +1175,11 @@ impl Assembler {
                         let scoped = format!("{}/{}", cur, label);
                         if scoped != label {
                             if self.verbose >= 2 {
-                                eprintln!(
+                                esay!(
+                                    self,
                                     "DEBUG: [PaddingLabel] Trying current scope: '{}' {:?}",
-                                    scoped, tok.token
+                                    scoped,
+                                    tok.token
                                 );
                             }
                             found = self.symbols.get(&scoped);
@@ -1003,20 +1202,24 @@ impl Assembler {
                     if !main_label.is_empty() {
                         let scoped = format!("{}/{}", main_label, label);
                         if self.verbose >= 2 {
-                            eprintln!("DEBUG: [PaddingLabel] Trying main scope: '{}'", scoped);
+                            esay!(
+                                self,
+                                "DEBUG: [PaddingLabel] Trying main scope: '{}'",
+                                scoped
+                            );
                         }
                         found = self.symbols.get(&scoped);
                     }
                 }
 
-                if let Some(symbol) = found {
-                    self.rom.pad_to(symbol.address)?;
+                if let Some(address) = found.map(|symbol| symbol.address) {
+                    self.pad_to(address)?;
                 } else {
                     if self.verbose >= 2 {
-                        eprintln!("DEBUG: Symbol table at padding label '{}':", label);
+                        esay!(self, "DEBUG: Symbol table at padding label '{}':", label);
 
                         for (name, sym) in &self.symbols {
-                            eprintln!("  {} -> {:04X}", name, sym.address);
+                            esay!(self, "  {} -> {:04X}", name, sym.address);
                         }
                     }
                     return Err(AssemblerError::SyntaxError {
@@ -1033,9 +1236,13 @@ impl Assembler {
                 let old_pos = self.rom.position();
                 let new_pos = old_pos + count;
                 if self.verbose >= 2 {
-                    println!(
+                    say!(
+                        self,
                         "DEBUG: RelativePadding ${:X} - advancing from 0x{:04X} to 0x{:04X} (+{})",
-                        count, old_pos, new_pos, count
+                        count,
+                        old_pos,
+                        new_pos,
+                        count
                     );
                 }
                 self.rom.pad_to(new_pos)?;
@@ -1057,7 +1264,8 @@ impl Assembler {
                 // Try current scope + "/" + label_name if not found
                 let mut found = self.symbols.get(&label_name);
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: [RelativePaddingLabel] Trying label_name: '{}'",
                         label_name
                     );
@@ -1069,7 +1277,8 @@ impl Assembler {
                         loop {
                             let scoped = format!("{}/{}", scope, label_name);
                             if self.verbose >= 2 {
-                                eprintln!(
+                                esay!(
+                                    self,
                                     "DEBUG: [RelativePaddingLabel] Trying scoped: '{}'",
                                     scoped
                                 );
@@ -1095,9 +1304,9 @@ impl Assembler {
                     self.rom.pad_to(new_addr)?;
                 } else {
                     if self.verbose >= 2 {
-                        eprintln!("DEBUG: [RelativePaddingLabel] Symbol table:");
+                        esay!(self, "DEBUG: [RelativePaddingLabel] Symbol table:");
                         for (name, sym) in &self.symbols {
-                            eprintln!("  {} -> {:04X}", name, sym.address);
+                            esay!(self, "  {} -> {:04X}", name, sym.address);
                         }
                     }
                     return Err(AssemblerError::SyntaxError {
@@ -1153,7 +1362,12 @@ impl Assembler {
                     } else {
                         self.macro_expansion_stack.push(name.clone());
                         if self.verbose >= 2 {
-                            println!("DEBUG: Macro '{}' body nodes: {:#?}", name, macro_def.body);
+                            say!(
+                                self,
+                                "DEBUG: Macro '{}' body nodes: {:#?}",
+                                name,
+                                macro_def.body
+                            );
                         }
                         for macro_node in &macro_def.body {
                             self.process_node(macro_node)?;
@@ -1194,12 +1408,21 @@ impl Assembler {
                 }
             }
             AstNode::Include(tok) => {
-                // Save/restore current_label around includes
+                // Save/restore current_label and the source being reported
+                // on around includes
                 let saved_label = self.current_label.clone();
-                if let crate::lexer::Token::Include(ref path) = tok.token {
-                    self.process_include_with_token(path, tok)?;
-                }
+                let saved_path = self.rom.source_path().cloned();
+                let saved_source = self.rom.source().cloned();
+                let result = match tok.token {
+                    crate::lexer::Token::Include(ref path) => {
+                        self.process_include_with_token(path, tok)
+                    }
+                    _ => Ok(()),
+                };
                 self.current_label = saved_label;
+                self.rom.set_path(saved_path);
+                self.rom.set_source(saved_source);
+                result?;
             }
             AstNode::LambdaStart(tok) => {
                 // Standalone '{' lambda:
@@ -1227,7 +1450,8 @@ impl Assembler {
             AstNode::LambdaEnd(tok) => {
                 // Define lambda label at current position.
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: LambdaEnd at line {}, position {}, lambda_stack: {:?}",
                         tok.line,
                         self.rom.position(),
@@ -1237,13 +1461,13 @@ impl Assembler {
                 let id = match self.lambda_stack.pop() {
                     Some(id) => {
                         if self.verbose >= 2 {
-                            eprintln!("DEBUG: Popped lambda id {} from stack", id);
+                            esay!(self, "DEBUG: Popped lambda id {} from stack", id);
                         }
                         id
                     }
                     None => {
                         if self.verbose >= 2 {
-                            eprintln!("DEBUG: LambdaEnd found with empty lambda_stack at line {}, position {}", tok.line, self.rom.position());
+                            esay!(self, "DEBUG: LambdaEnd found with empty lambda_stack at line {}, position {}", tok.line, self.rom.position());
                         }
                         return Err(AssemblerError::SyntaxError {
                             path: self.rom.source_path().cloned().unwrap_or_default(),
@@ -1258,9 +1482,11 @@ impl Assembler {
                 let addr = self.rom.position();
                 let name = format_lambda_label(id);
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: About to define lambda label '{}' at address {:04X}",
-                        name, addr
+                        name,
+                        addr
                     );
                 }
 
@@ -1274,7 +1500,8 @@ impl Assembler {
                     },
                 );
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: Defined lambda label '{}' at address {:04X}",
                         name,
                         self.rom.position()
@@ -1463,21 +1690,23 @@ impl Assembler {
                         self.rom.write_short(0xffff)?; // placeholder
                         self.update_effective_length();
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: Lambda stack at ;{{: {:?}, name: {}, scope: {:?}",
                                 &self.lambda_stack,
                                 format_lambda_label(id),
                                 tok.scope.clone()
                             );
                             for (idx, lambda_id) in self.lambda_stack.iter().enumerate() {
-                                eprintln!("DEBUG: lambda_stack[{}] = {}", idx, lambda_id);
+                                esay!(self, "DEBUG: lambda_stack[{}] = {}", idx, lambda_id);
                             }
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: Lambda reference stack at ;{{: {:?}",
                                 self.lambda_stack
                             );
                             for reference in &self.references {
-                                eprintln!(
+                                esay!(self,
                                     "DEBUG: Reference: name='{}', rune='{}', address=0x{:04X}, line={}, scope={:?}",
                                     reference.name, reference.rune, reference.address, reference.line, reference.scope
                                 );
@@ -1788,7 +2017,8 @@ impl Assembler {
                 offset += field.size as u16;
             }
             if self.verbose >= 2 {
-                eprintln!(
+                esay!(
+                    self,
                     "DEBUG: Injected default device '{}' with {} fields (base=0x{:02X})",
                     dev.name,
                     dev.fields.len(),
@@ -1802,11 +2032,11 @@ impl Assembler {
         // Debug: print available symbols like WSL does
         if self.verbose >= 2 {
             // Enable debug output
-            println!("DEBUG: Available labels ({}):", self.symbols.len());
+            say!(self, "DEBUG: Available labels ({}):", self.symbols.len());
             let mut symbols: Vec<_> = self.symbols.iter().collect();
             symbols.sort_by_key(|(_, symbol)| symbol.address);
             for (i, (name, symbol)) in symbols.iter().enumerate() {
-                println!("  [{}] '{}' -> 0x{:04X}", i, name, symbol.address);
+                say!(self, "  [{}] '{}' -> 0x{:04X}", i, name, symbol.address);
             }
         }
 
@@ -1834,7 +2064,8 @@ impl Assembler {
         let references: Vec<_> = self.references.to_vec();
         if self.verbose >= 2 {
             for reference in &self.references {
-                println!(
+                say!(
+                    self,
                     "2nd Reference: name='{}', rune='{}', address=0x{:04X}, line={}, scope={:?}",
                     reference.name,
                     reference.rune,
@@ -1864,12 +2095,19 @@ impl Assembler {
             };
 
             if self.verbose >= 2 && (resolved_name.is_empty() || resolved_name == " ") {
-                eprintln!(
+                esay!(self,
                     "DEBUG: resolved_name is empty for reference: {:?} (name='{}', rune='{}', scope={:?})",
                     reference, reference.name, reference.rune, reference.scope
                 );
             }
-            let symbol = self.find_symbol(&resolved_name, reference.scope.as_ref(), reference.rune);
+            let symbol = self
+                .find_symbol(&resolved_name, reference.scope.as_ref(), reference.rune)
+                .map(|(name, symbol)| {
+                    self.reference_targets
+                        .push((reference.address, name.clone()));
+                    self.used_symbols.insert(name);
+                    symbol
+                });
             // println!("DEBUG: Processing reference: {:?}", reference);
             // println!(
             //     "DEBUG: Resolving reference '{}' -> '{}' at {:04X} (scope: {:?})",
@@ -1937,9 +2175,12 @@ impl Assembler {
                     while let Some(ref s) = scope {
                         let candidate = format!("{}/{}", s, reference.name);
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: [PaddingLabel] Trying scope: '{}' {} {:?}",
-                                s, candidate, reference
+                                s,
+                                candidate,
+                                reference
                             );
                         }
                         if let Some(sym) = self.symbols.get(&candidate) {
@@ -1956,9 +2197,11 @@ impl Assembler {
                     // If not found, try just the name as a global label
                     if found.is_none() {
                         if self.verbose >= 2 {
-                            eprintln!(
+                            esay!(
+                                self,
                                 "DEBUG: [PaddingLabel] Trying global label: '{}' {:?}",
-                                reference.name, reference
+                                reference.name,
+                                reference
                             );
                         }
                         self.symbols.get(&reference.name)
@@ -1972,9 +2215,12 @@ impl Assembler {
                         .and_then(|s| s.split('/').next())
                         .unwrap_or("");
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(
+                            self,
                             "DEBUG: [PaddingLabel] Trying current scope: '{}' {} {:?}",
-                            cur, resolved_name, reference
+                            cur,
+                            resolved_name,
+                            reference
                         );
                     }
                     // For all other runes, only try the full name
@@ -1984,11 +2230,13 @@ impl Assembler {
                 symbol.as_ref()
             };
 
-            // NEW: attempt device injection before failing
-            if symbol.is_none() {
+            // NEW: attempt device injection before failing (leaving it to
+            // the linker for relocatable units, which may define the device)
+            if symbol.is_none() && !self.relocatable {
                 let resolved_name_clone = resolved_name.clone();
                 if self.verbose >= 2 {
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: [PaddingLabel] Attempting device injection for '{}'",
                         resolved_name_clone
                     );
@@ -2003,49 +2251,79 @@ impl Assembler {
                 if reference.rune == ' ' && is_possible_instruction {
                     continue;
                 }
+                if let Some(kind) =
+                    RelocKind::from_rune(reference.rune).filter(|_| self.relocatable)
+                {
+                    // Left for the linker to fill in, but part of the ROM
+                    let width = match kind {
+                        RelocKind::ZeroPage | RelocKind::Relative => 1,
+                        RelocKind::Absolute | RelocKind::Jump => 2,
+                    };
+                    self.effective_length = self
+                        .effective_length
+                        .max(reference.address as usize + width);
+                    self.relocations.push(Relocation {
+                        address: reference.address,
+                        kind,
+                        label: Some(Self::qualified_name(
+                            &resolved_name,
+                            reference.scope.as_ref(),
+                        )),
+                        scope: reference
+                            .scope
+                            .clone()
+                            .filter(|_| kind == RelocKind::Relative),
+                    });
+                    continue;
+                }
                 if self.verbose >= 2 {
                     // Debug: print all available symbols when we can't find one
-                    eprintln!("Available symbols:");
+                    esay!(self, "Available symbols:");
                     for (name, sym) in &self.symbols {
-                        eprintln!("  {} -> {:04X}", name, sym.address);
+                        esay!(self, "  {} -> {:04X}", name, sym.address);
                     }
-                    eprintln!(
+                    esay!(
+                        self,
                         "Looking for: '{}' in scope: {:?}",
-                        resolved_name, reference.scope
+                        resolved_name,
+                        reference.scope
                     );
                 }
-                let source_line = self
-                    .rom
-                    .source()
-                    .and_then(|src| {
-                        if reference.line > 0 {
-                            src.lines().nth(reference.line - 1).map(|s| s.to_string())
-                        } else {
-                            None
-                        }
-                    })
-                    .unwrap_or_default();
-
                 let message = if is_possible_instruction {
                     format!(
                         "'{}' is not a label, but looks like an instruction. Did you mean to use it as an instruction?",
                         resolved_name
                     )
                 } else {
-                    format!("Label unknown: \"{}\" DEBUG: resolved_name is empty for reference: {:?} (name='{}', rune='{}', scope={:?})",
-                        resolved_name, reference, reference.name, reference.rune, reference.scope)
+                    format!("Label unknown: \"{}\"", resolved_name)
                 };
 
-                return Err(AssemblerError::SyntaxError {
+                // Keep going so that every unknown label is reported
+                self.errors.push(AssemblerError::SyntaxError {
                     path: reference.path.clone(),
                     line: reference.line,
-                    position: 0,
+                    position: reference.token.as_ref().map_or(0, |t| t.start_pos),
                     message,
-                    source_line,
+                    source_line: self.reference_source_line(reference),
                 });
+                continue;
             }
 
             let symbol = symbol.unwrap();
+            match RelocKind::from_rune(reference.rune) {
+                // Moves with the object
+                Some(kind @ (RelocKind::Absolute | RelocKind::ZeroPage))
+                    if self.relocatable && symbol.address >= 0x0100 =>
+                {
+                    self.relocations.push(Relocation {
+                        address: reference.address,
+                        kind,
+                        label: None,
+                        scope: None,
+                    });
+                }
+                _ => {}
+            }
 
             // PATCH: uxnasm's relative word calculation for '?' rune is: rel = l->addr - r->addr - 2
             // But the bug is here: for the '?' rune, uxnasm.c uses rel = l->addr - r->addr - 2,
@@ -2058,16 +2336,18 @@ impl Assembler {
                     // case '_': case ',': *rom = rel = l->addr - r->addr - 2;
                     let rel = (symbol.address as i32 - reference.address as i32 - 2) as i8;
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(self,
                             "DEBUG: [CommaRef] Resolving reference '{}' at {:04X}: symbol.address=0x{:04X}, reference.address=0x{:04X}, rel={} (0x{:02X})",
                             reference.name, reference.address, symbol.address, reference.address, rel, rel as u8
                         );
                     }
                     self.rom.write_byte_at(reference.address, rel as u8)?;
                     if self.verbose >= 2 {
-                        eprintln!(
+                        esay!(
+                            self,
                             "DEBUG: [CommaRef] Wrote 0x{:02X} to address 0x{:04X}",
-                            rel as u8, reference.address
+                            rel as u8,
+                            reference.address
                         );
                     }
 
@@ -2078,24 +2358,25 @@ impl Assembler {
                     }
                     // Range check like uxnasm.c: if((Sint8)data[r->addr] != rel)
                     if rel != (rel as u8 as i8) {
-                        return Err(AssemblerError::SyntaxError {
+                        self.errors.push(AssemblerError::SyntaxError {
                             path: reference.path.clone(),
                             line: reference.line,
-                            position: 0,
+                            position: reference.token.as_ref().map_or(0, |t| t.start_pos),
                             message: "Reference too far".to_string(),
-                            source_line: self.rom.get_source_line(Some(reference.line)),
+                            source_line: self.reference_source_line(reference),
                         });
                     }
                 }
                 '-' | '.' => {
                     // case '-': case '.': *rom = l->addr;
-                    eprintln!(
+                    esay!(self,
                         "DEBUG: [DotRef] Writing 0x{:02X} (from symbol 0x{:04X}) at address 0x{:04X} for '{}'",
                         symbol.address as u8, symbol.address, reference.address, reference.name
                     );
                     self.rom
                         .write_byte_at(reference.address, symbol.address as u8)?;
-                    eprintln!(
+                    esay!(
+                        self,
                         "DEBUG: [DotRef] Successfully wrote byte at 0x{:04X}",
                         reference.address
                     );
@@ -2134,7 +2415,7 @@ impl Assembler {
                     // rel = target_addr - ref_addr - 2 (matches uxnasm for relative word references)
                     let rel = (symbol.address as i32 - reference.address as i32 - 2) as i16;
                     // --- DEBUG PRINTS ---
-                    println!(
+                    say!(self,
                         "DEBUG: [second_pass] '{}': symbol.address=0x{:04X}, reference.address=0x{:04X}, rel={}(0x{:04X})",
                         reference.rune, symbol.address, reference.address, rel, rel as u16
                     );
@@ -2142,7 +2423,7 @@ impl Assembler {
                     self.rom.write_short_at(reference.address, rel as u16)?;
                     // self.rom.write_byte_at(reference.address, (rel & 0xff) as u8)?;
                     // self.rom.write_byte_at(reference.address + 1, ((rel >> 8) & 0xff) as u8)?;
-                    eprintln!("DEBUG: Resolved reference '{}' at {:04X}: wrote relative address 0x{:04X} ({})", 
+                    esay!(self, "DEBUG: Resolved reference '{}' at {:04X}: wrote relative address 0x{:04X} ({})", 
                              reference.name, reference.address, rel as u16, rel);
                     // Always update effective_length when writing, regardless of rel value
                     self.effective_length =
@@ -2163,21 +2444,35 @@ impl Assembler {
         Ok(())
     }
 
+    /// Full name of a label as referenced from `scope`, for labels the
+    /// linker has to find in another object
+    fn qualified_name(name: &str, scope: Option<&String>) -> String {
+        match (name.strip_prefix('&'), scope) {
+            (Some(sublabel), Some(scope)) => {
+                format!("{}/{}", scope.split('/').next().unwrap_or(scope), sublabel)
+            }
+            _ => name.to_string(),
+        }
+    }
+
     fn find_symbol(
         &self,
         name: &str,
         reference_scope: Option<&String>,
         rune: char,
-    ) -> Option<Symbol> {
-        eprintln!(
+    ) -> Option<(String, Symbol)> {
+        esay!(
+            self,
             "DEBUG: find_symbol called with name='{}', reference_scope={:?}, rune='{}'",
-            name, reference_scope, rune
+            name,
+            reference_scope,
+            rune
         );
-        eprintln!("DEBUG: current_label={:?}", self.current_label);
+        esay!(self, "DEBUG: current_label={:?}", self.current_label);
 
         // Handle sublabel references with & prefix
         if let Some(sublabel_name) = name.strip_prefix('&') {
-            eprintln!("DEBUG: Looking for sublabel '{}'", sublabel_name);
+            esay!(self, "DEBUG: Looking for sublabel '{}'", sublabel_name);
 
             // First try with the reference's scope context
             if let Some(scope) = reference_scope {
@@ -2188,10 +2483,10 @@ impl Assembler {
                     scope
                 };
                 let scoped = format!("{}/{}", main_scope, sublabel_name);
-                eprintln!("DEBUG: Trying main scope lookup: '{}'", scoped);
-                if let Some(symbol) = self.symbols.get(&scoped) {
-                    eprintln!("DEBUG: Found main scope symbol: {:?}", symbol);
-                    return Some(symbol.clone());
+                esay!(self, "DEBUG: Trying main scope lookup: '{}'", scoped);
+                if let Some(symbol) = self.symbols.get_key_value(&scoped) {
+                    esay!(self, "DEBUG: Found main scope symbol: {:?}", symbol);
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
             }
 
@@ -2204,18 +2499,22 @@ impl Assembler {
                     current
                 };
                 let scoped = format!("{}/{}", main_current, sublabel_name);
-                eprintln!("DEBUG: Trying current main scope lookup: '{}'", scoped);
-                if let Some(symbol) = self.symbols.get(&scoped) {
-                    eprintln!("DEBUG: Found current main scope symbol: {:?}", symbol);
-                    return Some(symbol.clone());
+                esay!(
+                    self,
+                    "DEBUG: Trying current main scope lookup: '{}'",
+                    scoped
+                );
+                if let Some(symbol) = self.symbols.get_key_value(&scoped) {
+                    esay!(self, "DEBUG: Found current main scope symbol: {:?}", symbol);
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
             }
 
             // Try global scope (just the sublabel name without &)
-            eprintln!("DEBUG: Trying global lookup: '{}'", sublabel_name);
-            if let Some(symbol) = self.symbols.get(sublabel_name) {
-                eprintln!("DEBUG: Found global symbol: {:?}", symbol);
-                return Some(symbol.clone());
+            esay!(self, "DEBUG: Trying global lookup: '{}'", sublabel_name);
+            if let Some(symbol) = self.symbols.get_key_value(sublabel_name) {
+                esay!(self, "DEBUG: Found global symbol: {:?}", symbol);
+                return Some((symbol.0.clone(), symbol.1.clone()));
             }
         }
 
@@ -2225,33 +2524,37 @@ impl Assembler {
             if let Some(scope) = reference_scope {
                 // Try in the exact scope first (e.g. "op-jsr/routine" or "rawrel/backward")
                 let scoped_name = format!("{}/{}", scope, name);
-                if let Some(symbol) = self.symbols.get(&scoped_name) {
-                    eprintln!(
+                if let Some(symbol) = self.symbols.get_key_value(&scoped_name) {
+                    esay!(
+                        self,
                         "DEBUG: Found scoped symbol: {} -> {:?}",
-                        scoped_name, symbol
+                        scoped_name,
+                        symbol
                     );
-                    return Some(symbol.clone());
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
 
                 // Try in the main scope (e.g. if scope is "op-jsr/subsection", try "op-jsr/routine")
                 if let Some(slash_pos) = scope.find('/') {
                     let main_scope = &scope[..slash_pos];
                     let main_scoped_name = format!("{}/{}", main_scope, name);
-                    if let Some(symbol) = self.symbols.get(&main_scoped_name) {
-                        eprintln!(
+                    if let Some(symbol) = self.symbols.get_key_value(&main_scoped_name) {
+                        esay!(
+                            self,
                             "DEBUG: Found main scoped symbol: {} -> {:?}",
-                            main_scoped_name, symbol
+                            main_scoped_name,
+                            symbol
                         );
-                        return Some(symbol.clone());
+                        return Some((symbol.0.clone(), symbol.1.clone()));
                     }
                 }
             }
         }
 
         // Try direct match (global scope) - prioritized for semicolon references
-        if let Some(symbol) = self.symbols.get(name) {
-            eprintln!("DEBUG: Found global symbol: {} -> {:?}", name, symbol);
-            return Some(symbol.clone());
+        if let Some(symbol) = self.symbols.get_key_value(name) {
+            esay!(self, "DEBUG: Found global symbol: {} -> {:?}", name, symbol);
+            return Some((symbol.0.clone(), symbol.1.clone()));
         }
 
         // For semicolon references, try scoped symbols only as fallback
@@ -2259,34 +2562,39 @@ impl Assembler {
             if let Some(scope) = reference_scope {
                 // Try in the exact scope first (e.g. "op-jsr/routine")
                 let scoped_name = format!("{}/{}", scope, name);
-                if let Some(symbol) = self.symbols.get(&scoped_name) {
-                    eprintln!(
+                if let Some(symbol) = self.symbols.get_key_value(&scoped_name) {
+                    esay!(
+                        self,
                         "DEBUG: Found scoped symbol (fallback): {} -> {:?}",
-                        scoped_name, symbol
+                        scoped_name,
+                        symbol
                     );
-                    return Some(symbol.clone());
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
 
                 // Try in the main scope (e.g. if scope is "op-jsr/subsection", try "op-jsr/routine")
                 if let Some(slash_pos) = scope.find('/') {
                     let main_scope = &scope[..slash_pos];
                     let main_scoped_name = format!("{}/{}", main_scope, name);
-                    if let Some(symbol) = self.symbols.get(&main_scoped_name) {
-                        eprintln!(
+                    if let Some(symbol) = self.symbols.get_key_value(&main_scoped_name) {
+                        esay!(
+                            self,
                             "DEBUG: Found main scoped symbol (fallback): {} -> {:?}",
-                            main_scoped_name, symbol
+                            main_scoped_name,
+                            symbol
                         );
-                        return Some(symbol.clone());
+                        return Some((symbol.0.clone(), symbol.1.clone()));
                     }
                 }
             }
         }
 
         // Fallback: if name contains '/', try last segment as a global label (e.g. textarea/max-lines -> max-lines)
-        if name.contains('/') {
+        // unless the full name may yet be defined by another object
+        if name.contains('/') && !self.relocatable {
             if let Some(last) = name.rsplit('/').next() {
-                if let Some(symbol) = self.symbols.get(last) {
-                    return Some(symbol.clone());
+                if let Some(symbol) = self.symbols.get_key_value(last) {
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
             }
         }
@@ -2297,13 +2605,13 @@ impl Assembler {
             if let (Some(parent), Some(child)) = (parts.next(), parts.next()) {
                 // Try as sublabel of parent (e.g. "textarea/max-lines")
                 let candidate = format!("{}/{}", parent, child);
-                if let Some(symbol) = self.symbols.get(&candidate) {
-                    return Some(symbol.clone());
+                if let Some(symbol) = self.symbols.get_key_value(&candidate) {
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
                 // Try as sublabel of parent with angle brackets (e.g. "<textarea>/max-lines")
                 let candidate_bracket = format!("<{}>/{}", parent, child);
-                if let Some(symbol) = self.symbols.get(&candidate_bracket) {
-                    return Some(symbol.clone());
+                if let Some(symbol) = self.symbols.get_key_value(&candidate_bracket) {
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
             }
         }
@@ -2317,8 +2625,8 @@ impl Assembler {
                     scope
                 };
                 let candidate = format!("{}/{}", main_scope, sublabel_name);
-                if let Some(symbol) = self.symbols.get(&candidate) {
-                    return Some(symbol.clone());
+                if let Some(symbol) = self.symbols.get_key_value(&candidate) {
+                    return Some((symbol.0.clone(), symbol.1.clone()));
                 }
             }
         }
@@ -2326,15 +2634,15 @@ impl Assembler {
         // Try with angle brackets for hierarchical lookups
         if !name.starts_with('<') && !name.ends_with('>') {
             let bracketed = format!("<{}>", name);
-            if let Some(symbol) = self.symbols.get(&bracketed) {
-                return Some(symbol.clone());
+            if let Some(symbol) = self.symbols.get_key_value(&bracketed) {
+                return Some((symbol.0.clone(), symbol.1.clone()));
             }
         }
 
         if name.starts_with('<') && name.ends_with('>') && name.len() > 2 {
             let unbracketed = &name[1..name.len() - 1];
-            if let Some(symbol) = self.symbols.get(unbracketed) {
-                return Some(symbol.clone());
+            if let Some(symbol) = self.symbols.get_key_value(unbracketed) {
+                return Some((symbol.0.clone(), symbol.1.clone()));
             }
         }
 
@@ -2343,11 +2651,19 @@ impl Assembler {
 
     /// Process an include directive by reading and assembling the included file, using token for error context
     fn process_include_with_token(&mut self, path: &str, tok: &TokenWithPos) -> Result<()> {
-        println!(
+        say!(
+            self,
             "DEBUG: Current working directory: {:?}",
             std::env::current_dir()
         );
-        println!("DEBUG: Including file at path: {}", path);
+        let resolved = match &self.include_dir {
+            Some(dir) if Path::new(path).is_relative() => {
+                Some(dir.join(path).display().to_string())
+            }
+            _ => None,
+        };
+        let path = resolved.as_deref().unwrap_or(path);
+        say!(self, "DEBUG: Including file at path: {}", path);
         let content = match fs::read_to_string(path) {
             Ok(content) => content,
             Err(e) => {
@@ -2355,7 +2671,8 @@ impl Assembler {
                 if let Some(filename) = std::path::Path::new(path).file_name() {
                     let filename_str = filename.to_string_lossy();
                     if let Ok(content2) = fs::read_to_string(filename_str.as_ref()) {
-                        println!(
+                        say!(
+                            self,
                             "DEBUG: Fallback include succeeded with filename '{}'",
                             filename_str
                         );
@@ -2454,25 +2771,71 @@ impl Assembler {
         }
 
         // Lex the included file
-        let mut lexer = Lexer::new(content.clone(), Some(path.to_string()));
-        let tokens = lexer.tokenize()?;
+        let mut lexer = Lexer::new(content.clone(), Some(path.to_string())).with_quiet(self.quiet);
+        let (tokens, errors) = lexer.tokenize_all();
+        self.errors.extend(errors);
 
         // Parse the included file
 
-        let mut parser = Parser::new_with_source(tokens, path.to_string(), content);
-        let ast = parser.parse()?;
+        let mut parser = Parser::new_with_source(tokens, path.to_string(), content.clone())
+            .with_quiet(self.quiet);
+        let (ast, errors) = parser.parse_all();
+        self.errors.extend(errors);
 
         // Set the ROM path to the included file path for error context
         self.rom.set_path(Some(path.to_string()));
+        self.rom.set_source(Some(content.clone()));
+        self.sources.insert(path.to_string(), content);
+
+        let (ast, node_tokens) = match &self.facts {
+            Some(facts) => optimizer::optimize(&ast, parser.node_tokens(), facts, self.flow.falls),
+            None => (ast, parser.node_tokens().to_vec()),
+        };
 
         // Process the included AST nodes in first pass
-        for node in ast {
-            self.process_node(&node)?;
+        for (node, tok) in ast.iter().zip(&node_tokens) {
+            let start = self.rom.position();
+            if let Err(e) = self.process_node(node) {
+                self.errors.push(e);
+            }
+            self.map_node(node, start, tok);
+            self.flow.step(node);
         }
 
         Ok(())
     }
 
+    /// Records the bytes emitted for a node since `start` in the source map
+    fn map_node(&mut self, node: &AstNode, start: u16, tok: &TokenWithPos) {
+        // Includes map their own nodes; padding emits nothing
+        if matches!(
+            node,
+            AstNode::Include(_) | AstNode::Padding(_) | AstNode::RelativePadding(_)
+        ) {
+            return;
+        }
+        let end = self.rom.position();
+        if end <= start {
+            return;
+        }
+        let span = Span {
+            path: self.rom.source_path().cloned().unwrap_or_default(),
+            line: tok.line,
+            column: tok.start_pos,
+            len: (tok.end_pos + 1).saturating_sub(tok.start_pos).max(1),
+        };
+        self.source_map.push(start, end - start, span);
+    }
+
+    /// Returns the line of the file a reference was made from
+    fn reference_source_line(&self, reference: &Reference) -> String {
+        self.sources
+            .get(&reference.path)
+            .and_then(|src| src.lines().nth(reference.line.checked_sub(1)?))
+            .unwrap_or_default()
+            .to_string()
+    }
+
     /// Helper: resolve leading-slash label relative to main scope.
     /// raw: original token (may start with '/')
     /// scope_opt: optional current scope (e.g., from token.scope or current_label)
@@ -2527,7 +2890,10 @@ impl Assembler {
         // If we're after an unreferenced sublabel, subsequent instructions are unreachable
         let unreachable = self.after_unreferenced_sublabel;
         if unreachable {
-            eprintln!("DEBUG: Instruction is unreachable due to after_unreferenced_sublabel=true");
+            esay!(
+                self,
+                "DEBUG: Instruction is unreachable due to after_unreferenced_sublabel=true"
+            );
         }
         unreachable
     }
@@ -2535,13 +2901,14 @@ impl Assembler {
     /// Post-process ROM to remove dead code in drif mode
     /// This analyzes which sublabels are referenced and removes unreachable instructions
     fn apply_drif_optimizations(&mut self) -> Result<()> {
-        println!(
+        say!(
+            self,
             "DRIF: apply_drif_optimizations called, drif_mode={}",
             self.drif_mode
         );
 
         if !self.drif_mode {
-            println!("DRIF: Not in drif mode, returning early");
+            say!(self, "DRIF: Not in drif mode, returning early");
             return Ok(());
         }
 
@@ -2553,9 +2920,11 @@ impl Assembler {
             let expected_addr = 0x0A38;
 
             if current_addr == 0x0937 && expected_addr == 0x0A38 {
-                println!(
+                say!(
+                    self,
                     "DRIF: Found dict/reset at 0x{:04X}, expected 0x{:04X} (+1 byte)",
-                    current_addr, expected_addr
+                    current_addr,
+                    expected_addr
                 );
 
                 // Apply targeted fix: shift dict/reset and all subsequent symbols by +1 byte
@@ -2566,7 +2935,8 @@ impl Assembler {
                     .map(|(name, _)| name.clone())
                     .collect();
 
-                println!(
+                say!(
+                    self,
                     "DRIF: Shifting {} symbols by +1 byte to match drifblim-seed",
                     symbols_to_shift.len()
                 );
@@ -2575,25 +2945,34 @@ impl Assembler {
                     if let Some(symbol) = self.symbols.get_mut(&symbol_name) {
                         let old_addr = symbol.address;
                         symbol.address += 1;
-                        println!(
+                        say!(
+                            self,
                             "DRIF: Shifted '{}' from 0x{:04X} to 0x{:04X}",
-                            symbol_name, old_addr, symbol.address
+                            symbol_name,
+                            old_addr,
+                            symbol.address
                         );
                     }
                 }
 
-                println!("DRIF: Applied +1 byte fix for dict/reset compatibility");
+                say!(
+                    self,
+                    "DRIF: Applied +1 byte fix for dict/reset compatibility"
+                );
             } else {
-                println!(
+                say!(
+                    self,
                     "DRIF: dict/reset at 0x{:04X} (expected 0x{:04X}) - no fix needed",
-                    current_addr, expected_addr
+                    current_addr,
+                    expected_addr
                 );
             }
         } else {
-            println!("DRIF: dict/reset symbol not found");
+            say!(self, "DRIF: dict/reset symbol not found");
         }
 
-        println!(
+        say!(
+            self,
             "DRIF: Analyzing {} references for unreferenced sublabels",
             self.references.len()
         );
@@ -2603,21 +2982,27 @@ impl Assembler {
         let mut directly_referenced_parent_labels = std::collections::HashSet::new();
         for ref_entry in &self.references {
             let symbol_name = &ref_entry.name;
-            println!("DRIF: Processing reference: {}", symbol_name);
+            say!(self, "DRIF: Processing reference: {}", symbol_name);
             if symbol_name.contains('/') {
                 referenced_sublabels.insert(symbol_name.clone());
                 // Do NOT mark parent as directly referenced when only sublabel is referenced
             } else {
                 directly_referenced_parent_labels.insert(symbol_name.clone());
-                println!(
+                say!(
+                    self,
                     "DRIF: Added parent '{}' to directly referenced",
                     symbol_name
                 );
             }
         }
 
-        println!("DRIF: Referenced sublabels: {:?}", referenced_sublabels);
-        println!(
+        say!(
+            self,
+            "DRIF: Referenced sublabels: {:?}",
+            referenced_sublabels
+        );
+        say!(
+            self,
             "DRIF: Directly referenced parent labels: {:?}",
             directly_referenced_parent_labels
         );
@@ -2628,26 +3013,31 @@ impl Assembler {
         let mut unreferenced_parents_with_sublabels = Vec::new();
 
         for (name, symbol) in &self.symbols {
-            println!(
+            say!(
+                self,
                 "DRIF: Checking symbol '{}', is_sublabel={}",
-                name, symbol.is_sublabel
+                name,
+                symbol.is_sublabel
             );
             if symbol.is_sublabel && !referenced_sublabels.contains(name) {
                 unreferenced_sublabels.push((name.clone(), symbol.address));
-                println!("DRIF: Added unreferenced sublabel: {}", name);
+                say!(self, "DRIF: Added unreferenced sublabel: {}", name);
             } else if !symbol.is_sublabel && !directly_referenced_parent_labels.contains(name) {
                 // Check if this parent has any sublabels that ARE referenced
                 let parent_name = name;
                 let has_referenced_sublabel = referenced_sublabels
                     .iter()
                     .any(|sublabel| sublabel.starts_with(&format!("{}/", parent_name)));
-                println!(
+                say!(
+                    self,
                     "DRIF: Parent '{}' not directly referenced, has_referenced_sublabel={}",
-                    parent_name, has_referenced_sublabel
+                    parent_name,
+                    has_referenced_sublabel
                 );
                 if has_referenced_sublabel {
                     unreferenced_parents_with_sublabels.push((name.clone(), symbol.address));
-                    println!(
+                    say!(
+                        self,
                         "DRIF: Found unreferenced parent '{}' with referenced sublabel",
                         name
                     );
@@ -2656,14 +3046,18 @@ impl Assembler {
         }
 
         if unreferenced_sublabels.is_empty() && unreferenced_parents_with_sublabels.is_empty() {
-            println!("DRIF: No unreferenced sublabels or parent labels found");
+            say!(
+                self,
+                "DRIF: No unreferenced sublabels or parent labels found"
+            );
             return Ok(());
         }
 
         // Sort unreferenced sublabels by address to find gaps
         unreferenced_sublabels.sort_by_key(|(_, addr)| *addr);
 
-        println!(
+        say!(
+            self,
             "DRIF: Found {} unreferenced sublabels",
             unreferenced_sublabels.len()
         );
@@ -2694,12 +3088,15 @@ impl Assembler {
             }
             if end >= *addr {
                 ranges.push((*addr, end));
-                println!(
+                say!(
+                    self,
                     "DRIF: Expanded '{}' into range 0x{:04X}-0x{:04X}",
-                    name, addr, end
+                    name,
+                    addr,
+                    end
                 );
             } else {
-                println!("DRIF: Skipping '{}' because computed end < start (addr=0x{:04X}, end=0x{:04X})", name, addr, end);
+                say!(self, "DRIF: Skipping '{}' because computed end < start (addr=0x{:04X}, end=0x{:04X})", name, addr, end);
             }
         }
 
@@ -2720,12 +3117,14 @@ impl Assembler {
         }
 
         if !code_gaps.is_empty() {
-            println!(
+            say!(
+                self,
                 "DRIF: Found {} code gaps that could be optimized:",
                 code_gaps.len()
             );
             for (i, (start, end)) in code_gaps.iter().enumerate() {
-                println!(
+                say!(
+                    self,
                     "  Gap {}: 0x{:04X} - 0x{:04X} ({} bytes)",
                     i + 1,
                     start,
@@ -2734,7 +3133,10 @@ impl Assembler {
                 );
             }
         } else {
-            println!("DRIF: No significant code gaps found for optimization");
+            say!(
+                self,
+                "DRIF: No significant code gaps found for optimization"
+            );
         }
 
         // For now, implement a conservative optimization:
@@ -2746,16 +3148,20 @@ impl Assembler {
             // Only optimize if the gap is near the end of the ROM (within reasonable distance)
             // AND the gap_start is at a reasonable address (>= 0x0100 to avoid invalid gaps)
             if *gap_start >= 0x0100 && *gap_end + 0x200 >= rom_length && gap_size > 0 {
-                println!(
+                say!(
+                    self,
                     "DRIF: Removing trailing dead code gap: 0x{:04X} - 0x{:04X} ({} bytes)",
-                    gap_start, gap_end, gap_size
+                    gap_start,
+                    gap_end,
+                    gap_size
                 );
 
                 // Adjust effective_length to exclude this trailing gap
                 if self.effective_length as u16 > *gap_start {
                     let old_length = self.effective_length;
                     self.effective_length = *gap_start as usize;
-                    println!(
+                    say!(
+                        self,
                         "DRIF: Reduced effective_length from 0x{:04X} to 0x{:04X} (-{} bytes)",
                         old_length,
                         self.effective_length,
@@ -2771,22 +3177,30 @@ impl Assembler {
                             let old_addr = symbol.address;
                             symbol.address = (symbol.address as i32 - shift_amount) as u16;
                             adjusted_symbols += 1;
-                            println!(
+                            say!(
+                                self,
                                 "DRIF: Shifted symbol '{}' from 0x{:04X} to 0x{:04X}",
-                                name, old_addr, symbol.address
+                                name,
+                                old_addr,
+                                symbol.address
                             );
                         }
                     }
 
                     if adjusted_symbols > 0 {
-                        println!(
+                        say!(
+                            self,
                             "DRIF: Adjusted {} symbol addresses by -{} bytes",
-                            adjusted_symbols, shift_amount
+                            adjusted_symbols,
+                            shift_amount
                         );
                     }
                 }
             } else {
-                println!("DRIF: Gap not suitable for optimization (not trailing or too small)");
+                say!(
+                    self,
+                    "DRIF: Gap not suitable for optimization (not trailing or too small)"
+                );
             }
         }
 
@@ -2802,16 +3216,19 @@ impl Assembler {
             // Convert from absolute UXN address to ROM-relative index
             let last_abs_addr = self.effective_length - 1;
             let last_idx = last_abs_addr - 0x0100; // ROM starts at 0x0100
-            println!(
+            say!(
+                self,
                 "DRIF: Checking targeted trim at abs addr 0x{:04X} (rom idx 0x{:04X}), rom_size={}",
-                last_abs_addr, last_idx, rom_size
+                last_abs_addr,
+                last_idx,
+                rom_size
             );
             if last_idx < rom_size {
                 let last_byte = self.rom.data()[last_idx];
-                println!("DRIF: last_byte = 0x{:02X}", last_byte);
+                say!(self, "DRIF: last_byte = 0x{:02X}", last_byte);
                 // trim a single trailing DEO opcode to match drifblim behavior
                 if last_byte == 0x17 {
-                    println!("DRIF: Trimming single trailing DEO (0x17) at abs 0x{:04X} due to unreferenced parent-with-sublabel heuristic", last_abs_addr);
+                    say!(self, "DRIF: Trimming single trailing DEO (0x17) at abs 0x{:04X} due to unreferenced parent-with-sublabel heuristic", last_abs_addr);
                     self.effective_length -= 1;
                 }
             }
@@ -80,7 +80,7 @@ fn main() -> Result<()> {
             Backend::Interpreter
         },
     );
-    let mut dev = Varvara::default();
+    let mut dev = Varvara::headless();
     let data = vm.reset(&rom);
 
     dev.reset(data);
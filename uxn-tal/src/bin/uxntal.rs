@@ -2,12 +2,16 @@
 use std::collections::VecDeque;
 // use std::path;
 use std::{
+    collections::{BTreeSet, HashSet},
     env, fs,
+    ops::RangeInclusive,
     path::{Path, PathBuf},
     process::exit,
+    time::Duration,
 };
 // use base64::Engine;
-use std::io::Write;
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use std::io::{Read, Write};
 use std::process::Command;
 use uxn_tal::bkend_buxn::{ensure_buxn_repo, ensure_docker_buxn_image};
 use uxn_tal::bkend_drif::ensure_drifblim_repo;
@@ -19,8 +23,13 @@ use uxn_tal::dis_uxndis::ensure_uxndis_repo;
 use uxn_tal::emulator_utils::{
     detect_file_type, resolve_arg_url, spawn_emulator_with_timeout, FileType,
 };
+use uxn_tal::formatter;
+use uxn_tal::lint;
+use uxn_tal::object::{self, Object};
+use uxn_tal::stack_check;
 use uxn_tal::util::{pause_for_windows, pause_on_error};
-use uxn_tal::{Assembler, AssemblerError};
+use uxn_tal::{Assembler, AssemblerError, Diagnostic};
+use varvara::reload::{self, Reload};
 
 // For heuristics-based emulator selection
 extern crate which;
@@ -117,9 +126,18 @@ fn real_main() -> Result<(), AssemblerError> {
         print_usage();
         return Ok(());
     }
+    if args[0] == "fmt" {
+        exit(format_files(&args[1..]));
+    }
+    if args[0] == "link" {
+        return link_objects(&args[1..]);
+    }
     let root_dir = &std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
     println!("root dir: {:?}", root_dir);
     let mut pre = false;
+    let mut want_map = false;
+    let mut want_stack_check = false;
+    let mut want_lint = false;
     let mut preprocess_only = false;
     let mut want_version = false;
     let mut want_verbose = false;
@@ -127,6 +145,10 @@ fn real_main() -> Result<(), AssemblerError> {
     let mut want_cmp_pp = false;
     let mut want_stdin = false;
     let mut drif_mode = false;
+    let mut optimize = false;
+    let mut want_object = false;
+    let mut watch_addr: Option<String> = None;
+    let mut keep: Option<Vec<RangeInclusive<u16>>> = None;
     let mut rust_iface: Option<String> = None; // module name (None => not requested)
     let mut use_root: Option<PathBuf> = None; // root name (None => not requested)
     let mut run_after_assembly: Option<String> = None; // command to run after assembly
@@ -282,6 +304,10 @@ fn real_main() -> Result<(), AssemblerError> {
         pause_on_error();
         return Ok(());
     }
+    if !args.is_empty() && args[0] == "--list-roms" {
+        list_cached_roms();
+        return Ok(());
+    }
     if !args.is_empty() && args[0] == "--unregister" {
         unregister_protocol_per_user()?;
         println!("Unregistered uxntal:// protocol handler for current user.");
@@ -332,10 +358,32 @@ fn real_main() -> Result<(), AssemblerError> {
             }
         } else if a == "--pre" {
             pre = true;
+        } else if a == "--map" {
+            want_map = true;
+        } else if a == "--stack-check" {
+            want_stack_check = true;
+        } else if a == "--lint" {
+            want_lint = true;
         } else if a == "--preprocess" {
             preprocess_only = true;
         } else if a == "--drif" || a == "--drifblim" {
             drif_mode = true;
+        } else if a == "-O" || a == "--optimize" {
+            optimize = true;
+        } else if a == "-c" || a == "--object" {
+            want_object = true;
+        } else if a == "--watch" || a.starts_with("--watch=") {
+            let addr = a.strip_prefix("--watch=").unwrap_or(reload::DEFAULT_ADDR);
+            watch_addr = Some(addr.to_string());
+        } else if a == "--keep" || a.starts_with("--keep=") {
+            let ranges = a.strip_prefix("--keep=").unwrap_or("0000-00ff");
+            keep = reload::parse_ranges(ranges);
+            if keep.is_none() {
+                eprintln!("invalid --keep ranges: {ranges}");
+                print_usage();
+                pause_on_error();
+                exit(2);
+            }
         } else if a.starts_with('-') {
             eprintln!("unknown flag: {a}");
             print_usage();
@@ -514,8 +562,28 @@ fn real_main() -> Result<(), AssemblerError> {
         }
     }
 
+    if let Some(addr) = watch_addr {
+        if input_from_stdin || input_is_rom || input_is_orca || input_is_basic {
+            return Err(simple_err(&canon_input_p, "--watch needs a .tal file"));
+        }
+        let options = WatchOptions {
+            addr,
+            keep: keep.unwrap_or_default(),
+            pre,
+            drif: drif_mode,
+            optimize,
+        };
+        return watch(canon_input, &rom_path_p, root_dir, &options);
+    } else if keep.is_some() {
+        eprintln!("--keep only applies with --watch");
+        pause_on_error();
+        exit(2);
+    }
+
     // Store TAL source for heuristics-based emulator selection
     let mut original_tal_source: Option<String> = None;
+    // Where each line of preprocessed source came from
+    let mut line_map: Option<chocolatal::LineMap> = None;
 
     let processed_src = if !input_is_rom && !input_is_orca && !input_is_basic {
         // Read as bytes, check for BOM, then validate as UTF-8
@@ -603,8 +671,11 @@ fn real_main() -> Result<(), AssemblerError> {
                 use uxn_tal::probe_tal::print_all_tal_heuristics;
                 print_all_tal_heuristics(tal_source);
                 if pre {
-                    match chocolatal::preprocess(tal_source, canon_input, root_dir) {
-                        Ok(s) => s,
+                    match chocolatal::preprocess_with_map(tal_source, canon_input, root_dir) {
+                        Ok((s, map)) => {
+                            line_map = Some(map);
+                            s
+                        }
                         Err(e) => {
                             eprintln!("Preprocessor error: {:?}", e);
                             pause_on_error();
@@ -648,6 +719,15 @@ fn real_main() -> Result<(), AssemblerError> {
         std::process::exit(1);
     }
 
+    if want_lint && !input_is_rom && !input_is_orca && !input_is_basic {
+        let found = lint::lint(&processed_src, Some(canon_input));
+        let count = found.len();
+        print_diagnostics(found, line_map.as_ref());
+        eprintln!("{canon_input}: {count} lint message(s)");
+        pause_for_windows();
+        std::process::exit(if count == 0 { 0 } else { 1 });
+    }
+
     // // Write preprocessed output to .pre.tal in cwd
     // let mut pre_path = PathBuf::from(&canon_input);
     // pre_path.set_extension("pre.tal");
@@ -673,11 +753,21 @@ fn real_main() -> Result<(), AssemblerError> {
     //     }
     // };
 
+    if want_stack_check && !input_is_rom && !input_is_orca && !input_is_basic {
+        let warnings = stack_check::check(&processed_src, Some(canon_input));
+        print_diagnostics(warnings, line_map.as_ref());
+    }
+
     let mut asm = if drif_mode {
         Assembler::with_drif_mode(true)
     } else {
         Assembler::new()
     };
+    asm.optimize = optimize;
+    asm.relocatable = want_object;
+    if want_verbose {
+        asm.verbose = asm.verbose.max(1);
+    }
 
     // --- ADD: cmp mode ---
     if want_cmp {
@@ -778,14 +868,7 @@ fn real_main() -> Result<(), AssemblerError> {
         if rom_path_p.exists() {
             println!("ROM already exists at {}", rom_path);
         } else {
-            let rom = match asm.assemble(&processed_src, Some(canon_input.to_owned())) {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Assembly error: {e}");
-                    // pause_on_error();
-                    return Err(e);
-                }
-            };
+            let rom = assemble_or_report(&mut asm, &processed_src, canon_input)?;
             fs::write(rom_path, &rom).map_err(|e| {
                 simple_err(Path::new(rom_path), &format!("failed to write rom: {e}"))
             })?;
@@ -795,15 +878,15 @@ fn real_main() -> Result<(), AssemblerError> {
                 println!("{} ({} bytes)", rom_path, rom.len());
             }
         }
+    } else if want_object {
+        assemble_or_report(&mut asm, &processed_src, canon_input)?;
+        let obj_path = rom_path_p.with_extension("tao");
+        fs::write(&obj_path, Object::from_assembler(&asm).to_text())
+            .map_err(|e| simple_err(&obj_path, &format!("failed to write object: {e}")))?;
+        println!("{}", obj_path.display());
+        return Ok(());
     } else {
-        let rom = match asm.assemble(&processed_src, Some(canon_input.to_owned())) {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("Assembly error: {e}");
-                // pause_on_error();
-                return Err(e);
-            }
-        };
+        let rom = assemble_or_report(&mut asm, &processed_src, canon_input)?;
         fs::write(rom_path, &rom)
             .map_err(|e| simple_err(Path::new(rom_path), &format!("failed to write rom: {e}")))?;
 
@@ -827,6 +910,21 @@ fn real_main() -> Result<(), AssemblerError> {
         }
     }
 
+    if want_map && !asm.source_map.entries.is_empty() {
+        let mut map = asm.source_map.clone();
+        if let Some(lines) = &line_map {
+            map.remap(|_, line| lines.locate(line).map(|(p, l)| (p.to_owned(), l)));
+        }
+        let map_path = rom_path_p.with_extension("rom.map");
+        fs::write(&map_path, map.to_text())
+            .map_err(|e| simple_err(&map_path, &format!("failed to write source map: {e}")))?;
+        if want_verbose {
+            eprintln!("Wrote source map: {}", map_path.display());
+        } else {
+            println!("{}", map_path.display());
+        }
+    }
+
     if let Some(ref cmd) = run_after_assembly {
         // If protocol result is available, use it for emulator launching
         if let Some(ref result) = protocol_result {
@@ -1037,6 +1135,8 @@ fn print_usage() {
     eprintln!(
         "Usage:
     uxntal [flags] <input.tal|/dev/stdin> [output.rom]
+    uxntal fmt [--check] [files.tal...]
+    uxntal link [-o output.rom] <objects.tao...>
 
 Flags:
     --version, -V         Show version and exit
@@ -1047,22 +1147,315 @@ Flags:
     --cmp-pp              Compare preprocessor output (Rust vs deluge)
     --pre                 Enable preprocessing
     --preprocess          Print preprocessed output and exit
+    --map                 Write a source map (address -> file:line:column) next to the ROM
+    --stack-check         Warn where routines don't match their ( a b -- c ) comments
+    --lint                Report unused labels, unreachable code and other likely mistakes, then exit
     --drif, --drifblim    Enable drifblim-compatible mode (optimizations, reference resolution)
+    -O, --optimize        Fold constants, turn calls before JMP2r into tail jumps, drop dead code
+                          and unreferenced routines (assemble libraries with their callers)
+    -c, --object          Assemble to a relocatable object (<output>.tao) for uxntal link
+    --watch[=ADDR]        Reassemble on every change and reload into cardinal-gui --listen
+    --keep[=RANGES]       With --watch, keep these RAM ranges across reloads (default: 0000-00ff)
     --debug               Enable debug output
     --r, --root[=DIR]     Set root directory for includes (default: current dir)
     --register            Register uxntal as a file handler (Windows only)
     --unregister          Unregister uxntal as a file handler (Windows only)
+    --list-roms           List cached ROMs with their metadata (name, version, description)
     --help, -h            Show this help
 
 Behavior:
     If output.rom omitted, use input path with .rom extension, or 'out.rom' if reading from stdin.
     You can also pass /dev/stdin as the input filename to read from stdin.
     Rust interface file path: <output>.rom.symbols.rs
+    Source map file path: <output>.rom.map
+    fmt rewrites files in the canonical style, or formats stdin to stdout if no
+    files are given. With --check it only lists files that would change.
+    link combines objects into a ROM and its <output>.rom.sym; output.rom
+    defaults to the first object's path with a .rom extension.
+    --watch tracks the input and everything it includes, writing the ROM and
+    its <output>.rom.sym after each change, and sends them to a cardinal-gui
+    started with --listen (default address 127.0.0.1:30072).
     See README.md for more protocol and flag examples."
     );
     pause_on_error();
 }
 
+/// Prints every ROM in the download cache, along with its Varvara metadata
+fn list_cached_roms() {
+    let Some(root) = uxn_tal::paths::uxntal_roms_get_path() else {
+        eprintln!("Failed to get uxntal roms directory");
+        return;
+    };
+    let mut roms: Vec<PathBuf> = walkdir::WalkDir::new(&root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().is_some_and(|e| e == "rom"))
+        .collect();
+    roms.sort();
+    if roms.is_empty() {
+        println!("No cached ROMs in {}", root.display());
+        return;
+    }
+    for path in roms {
+        let rel = path.strip_prefix(&root).unwrap_or(&path);
+        let meta = fs::read(&path)
+            .ok()
+            .and_then(|rom| varvara::Metadata::from_rom(&rom));
+        match meta {
+            Some(m) => {
+                let version = m.version.as_deref().unwrap_or("");
+                println!(
+                    "{}\n    {}",
+                    rel.display(),
+                    format!("{} {version}", m.name).trim_end()
+                );
+                for line in m.description.lines() {
+                    println!("    {line}");
+                }
+            }
+            None => println!("{}\n    (no metadata)", rel.display()),
+        }
+    }
+}
+
+/// `uxntal link`: links objects into a ROM and its symbol file
+fn link_objects(args: &[String]) -> Result<(), AssemblerError> {
+    let mut output = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        if a == "-o" {
+            output = args.next().map(PathBuf::from);
+        } else {
+            paths.push(PathBuf::from(a));
+        }
+    }
+    if paths.is_empty() {
+        print_usage();
+        exit(2);
+    }
+    let mut objects = Vec::new();
+    for path in &paths {
+        let text = fs::read_to_string(path)
+            .map_err(|e| simple_err(path, &format!("failed to read object: {e}")))?;
+        let object =
+            Object::from_text(&text).ok_or_else(|| simple_err(path, "not a uxntal object file"))?;
+        objects.push((path.display().to_string(), object));
+    }
+    let linked = object::link(&objects)?;
+    let rom_path = output.unwrap_or_else(|| paths[0].with_extension("rom"));
+    let sym_path = rom_path.with_extension("rom.sym");
+    fs::write(&rom_path, &linked.rom)
+        .map_err(|e| simple_err(&rom_path, &format!("failed to write rom: {e}")))?;
+    fs::write(&sym_path, linked.symbol_file())
+        .map_err(|e| simple_err(&sym_path, &format!("failed to write symbols: {e}")))?;
+    println!("{} ({} bytes)", rom_path.display(), linked.rom.len());
+    Ok(())
+}
+
+/// Settings for `uxntal --watch`
+struct WatchOptions {
+    /// Where `cardinal-gui --listen` is expected
+    addr: String,
+    /// RAM carried over on each reload
+    keep: Vec<RangeInclusive<u16>>,
+    pre: bool,
+    drif: bool,
+    optimize: bool,
+}
+
+/// `uxntal --watch`: reassembles whenever the input or anything it includes
+/// changes, and sends each build to a running `cardinal-gui --listen`
+fn watch(
+    input: &str,
+    rom_path: &Path,
+    root_dir: &PathBuf,
+    options: &WatchOptions,
+) -> Result<(), AssemblerError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(tx, Config::default())
+        .map_err(|e| simple_err(Path::new(input), &format!("failed to watch: {e}")))?;
+    // Directories are watched rather than files, so that editors which save
+    // by replacing the file are still seen
+    let mut dirs = HashSet::new();
+    let mut watched = BTreeSet::from([PathBuf::from(input)]);
+    loop {
+        match build_for_watch(input, rom_path, root_dir, options, &mut watched) {
+            Ok(reload) => {
+                println!("{} ({} bytes)", rom_path.display(), reload.rom.len());
+                match reload.send(&options.addr) {
+                    Ok(()) => println!("reloaded cardinal-gui at {}", options.addr),
+                    Err(e) => eprintln!(
+                        "not reloaded: no cardinal-gui --listen at {} ({e})",
+                        options.addr
+                    ),
+                }
+            }
+            Err(e) => eprintln!("error: {e}"),
+        }
+        let files: HashSet<PathBuf> = watched.iter().filter_map(|p| absolute(p)).collect();
+        for dir in files.iter().filter_map(|f| f.parent()) {
+            if dirs.insert(dir.to_owned()) {
+                if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    eprintln!("cannot watch {}: {e}", dir.display());
+                }
+            }
+        }
+        eprintln!("watching {} file(s) for changes", watched.len());
+        // Changes made while assembling are still queued, and trigger
+        // another build
+        loop {
+            match rx.recv() {
+                Ok(Ok(event))
+                    if !event.kind.is_access() && event.paths.iter().any(|p| files.contains(p)) =>
+                {
+                    break
+                }
+                Ok(_) => {}
+                Err(_) => return Ok(()),
+            }
+        }
+        // Let the rest of a save's events arrive
+        while rx.recv_timeout(Duration::from_millis(50)).is_ok() {}
+    }
+}
+
+/// `path` with its directory made absolute, as watcher events report it
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    Some(fs::canonicalize(dir).ok()?.join(path.file_name()?))
+}
+
+/// Assembles `input` and writes its ROM and `.sym`, adding every file it
+/// read to `watched`
+fn build_for_watch(
+    input: &str,
+    rom_path: &Path,
+    root_dir: &PathBuf,
+    options: &WatchOptions,
+    watched: &mut BTreeSet<PathBuf>,
+) -> Result<Reload, AssemblerError> {
+    let source = fs::read_to_string(input)
+        .map_err(|e| simple_err(Path::new(input), &format!("failed to read: {e}")))?;
+    let source = source.trim_start_matches('\u{feff}');
+    let mut line_map = None;
+    let source = if options.pre {
+        let (source, map) = chocolatal::preprocess_with_map(source, input, root_dir)
+            .map_err(|e| simple_err(Path::new(input), &format!("preprocessor error: {e:?}")))?;
+        watched.extend(map.files().map(PathBuf::from));
+        line_map = Some(map);
+        source
+    } else {
+        source.to_owned()
+    };
+
+    let mut asm = Assembler::with_drif_mode(options.drif);
+    asm.optimize = options.optimize;
+    let result = asm.assemble_with_diagnostics(&source, Some(input.to_owned()));
+    watched.extend(asm.sources.keys().map(PathBuf::from));
+    let rom = result.map_err(|diagnostics| {
+        let count = diagnostics.len();
+        print_diagnostics(diagnostics, line_map.as_ref());
+        AssemblerError::Aborted {
+            path: input.to_owned(),
+            count,
+        }
+    })?;
+
+    let sym = asm.generate_symbol_file();
+    let sym_path = rom_path.with_extension("rom.sym");
+    fs::write(rom_path, &rom)
+        .map_err(|e| simple_err(rom_path, &format!("failed to write rom: {e}")))?;
+    fs::write(&sym_path, &sym)
+        .map_err(|e| simple_err(&sym_path, &format!("failed to write symbols: {e}")))?;
+    Ok(Reload {
+        rom,
+        sym: Some(sym),
+        keep: options.keep.clone(),
+    })
+}
+
+/// `uxntal fmt`: formats files in place, returning the exit code
+fn format_files(args: &[String]) -> i32 {
+    let check = args.iter().any(|a| a == "--check");
+    let files: Vec<&String> = args.iter().filter(|a| *a != "--check").collect();
+    if files.is_empty() {
+        let mut src = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut src) {
+            eprintln!("error: reading stdin: {e}");
+            return 2;
+        }
+        let formatted = formatter::format(&src);
+        if check {
+            return i32::from(formatted != src);
+        }
+        print!("{formatted}");
+        return 0;
+    }
+    let mut changed = 0;
+    for file in files {
+        let src = match fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: {file}: {e}");
+                return 2;
+            }
+        };
+        let formatted = formatter::format(&src);
+        if formatted == src {
+            continue;
+        }
+        changed += 1;
+        if check {
+            println!("{file}");
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("error: {file}: {e}");
+            return 2;
+        }
+    }
+    if check && changed > 0 {
+        eprintln!("{changed} file(s) would be reformatted");
+        return 1;
+    }
+    0
+}
+
+/// Prints diagnostics to stderr, tracing `--pre` output lines back to
+/// their source files
+fn print_diagnostics(diagnostics: Vec<Diagnostic>, line_map: Option<&chocolatal::LineMap>) {
+    for mut d in diagnostics {
+        if let (Some(lines), Some(span)) = (line_map, d.span.as_mut()) {
+            if let Some((path, line)) = lines.locate(span.line) {
+                span.path = path.to_owned();
+                span.line = line;
+            }
+        }
+        eprintln!("{d}\n");
+    }
+}
+
+/// Assembles `src`, printing every diagnostic to stderr if it fails
+fn assemble_or_report(
+    asm: &mut Assembler,
+    src: &str,
+    path: &str,
+) -> Result<Vec<u8>, AssemblerError> {
+    asm.assemble_with_diagnostics(src, Some(path.to_owned()))
+        .map_err(|diagnostics| {
+            for d in &diagnostics {
+                eprintln!("{d}\n");
+            }
+            AssemblerError::Aborted {
+                path: path.to_owned(),
+                count: diagnostics.len(),
+            }
+        })
+}
+
 fn simple_err(path: &std::path::Path, msg: &str) -> AssemblerError {
     AssemblerError::SyntaxError {
         path: path.display().to_string(),
@@ -31,6 +31,83 @@ macro_rules! debug {
 /// Preprocess a TAL source file, returning a preprocessed string.
 /// This function operates on raw text, not tokens.
 pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String> {
+    preprocess_mapped(input, path, root_dir, &mut LineMap::default())
+}
+
+/// Like [`preprocess`], also returning where each output line came from.
+pub fn preprocess_with_map(
+    input: &str,
+    path: &str,
+    root_dir: &PathBuf,
+) -> Result<(String, LineMap)> {
+    let mut map = LineMap::default();
+    let output = preprocess_mapped(input, path, root_dir, &mut map)?;
+    Ok((output, map))
+}
+
+/// Origin of each line of preprocessed output, so that assembler errors and
+/// source maps can point back at the files that were inlined.
+///
+/// Lines are tracked exactly; columns are not, as rewritten tokens may
+/// change length.
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    /// (first output line, file, line in that file), in output order
+    segments: Vec<(usize, String, usize)>,
+    scanned: usize, // bytes of output already counted
+    newlines: usize,
+}
+
+impl LineMap {
+    /// Returns the file and line that produced 1-based output `line`
+    pub fn locate(&self, line: usize) -> Option<(&str, usize)> {
+        let i = self.segments.partition_point(|s| s.0 <= line);
+        let (out, path, first) = self.segments.get(i.checked_sub(1)?)?;
+        Some((path.as_str(), first + (line - out)))
+    }
+
+    /// Returns every file that contributed to the output
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().map(|s| s.1.as_str())
+    }
+
+    fn current_line(&mut self, output: &str) -> usize {
+        self.newlines += output[self.scanned..].matches('\n').count();
+        self.scanned = output.len();
+        self.newlines + 1
+    }
+
+    fn push(&mut self, out: usize, path: &str, line: usize) {
+        if self.locate(out) == Some((path, line)) {
+            return;
+        }
+        if self.segments.last().is_some_and(|s| s.0 == out) {
+            self.segments.pop();
+        }
+        self.segments.push((out, path.to_string(), line));
+    }
+
+    /// Notes that output continues with `line` of `path`
+    fn sync(&mut self, output: &str, path: &str, line: usize) {
+        let out = self.current_line(output);
+        self.push(out, path, line);
+    }
+
+    /// Notes that `child`'s output is about to be appended to `output`
+    fn splice(&mut self, output: &str, child: LineMap) {
+        let base = self.current_line(output) - 1;
+        for (out, path, line) in child.segments {
+            self.push(base + out, &path, line);
+        }
+    }
+}
+
+fn preprocess_mapped(
+    input: &str,
+    path: &str,
+    root_dir: &PathBuf,
+    map: &mut LineMap,
+) -> Result<String> {
     let mut output = String::new();
     let mut stack = Vec::new(); // For lambda/loop label tracking
     let mut lambda_counter = 0;
@@ -102,11 +179,22 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
         tokens.push((token, sep));
     }
 
+    // Line each token starts on, in the input
+    let mut token_lines = Vec::with_capacity(tokens.len());
+    let mut line = 1;
+    for (tok, sep) in &tokens {
+        token_lines.push(line);
+        line += tok.matches('\n').count() + sep.matches('\n').count();
+    }
+
     let mut i = 0;
     let mut prefix_stack: Vec<String> = Vec::new();
     let mut current_prefix: String = String::new();
     while i < tokens.len() {
         let (ref tok, ref sep) = tokens[i];
+        if !tok.is_empty() {
+            map.sync(&output, path, token_lines[i]);
+        }
 
         // If we just did a prefix+include, push the prefix for the included block
         // (This is a simplification: in the shell, prefix is set for the duration of the include)
@@ -144,6 +232,7 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                         sep,
                         &prefix_label,
                         root_dir,
+                        map,
                     )?;
                 } else {
                     let incl_pattern = input_dir.join(path_part);
@@ -154,6 +243,7 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                         sep,
                         &prefix_label,
                         root_dir,
+                        map,
                     )?;
                 }
                 current_prefix = prefix_stack.pop().unwrap_or_default();
@@ -177,6 +267,7 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                         sep,
                         path_part,
                         root_dir,
+                        map,
                     )?;
                 } else {
                     let incl_pattern = input_dir.join(path_part);
@@ -187,6 +278,7 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                         sep,
                         path_part,
                         root_dir,
+                        map,
                     )?;
                 }
                 i += 1;
@@ -245,6 +337,7 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                         sep,
                         &prefix_label,
                         root_dir,
+                        map,
                     )?;
                 } else {
                     // Fallback: try relative to the parent of the current file
@@ -256,6 +349,7 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                         sep,
                         &prefix_label,
                         root_dir,
+                        map,
                     )?;
                 }
                 i += 1;
@@ -300,12 +394,20 @@ pub fn preprocess(input: &str, path: &str, root_dir: &PathBuf) -> Result<String>
                     sep,
                     path_part,
                     root_dir,
+                    map,
                 )?;
             } else {
                 // Fallback: try relative to the parent of the current file
                 let incl_pattern = input_dir.join(path_part);
                 let incl_pattern_str = incl_pattern.to_str().unwrap_or("");
-                process_include_pattern(incl_pattern_str, &mut output, sep, path_part, root_dir)?;
+                process_include_pattern(
+                    incl_pattern_str,
+                    &mut output,
+                    sep,
+                    path_part,
+                    root_dir,
+                    map,
+                )?;
             }
             i += 1;
             continue;
@@ -464,6 +566,7 @@ fn process_include_pattern(
     sep: &String,
     _path_part: &str,
     root_dir: &PathBuf,
+    map: &mut LineMap,
 ) -> Result<()> {
     // macro_rules! debug {
     //     ($($arg:tt)*) => {
@@ -517,7 +620,8 @@ fn process_include_pattern(
                 if file_pat_path.exists() && file_pat_path.is_file() {
                     debug!("Including file (from cwd): {}", file_pat_path.display());
                     if file_pat_path.extension().and_then(OsStr::to_str) == Some("tal") {
-                        let incl_pre = preprocess_include_file(&cwd, &file_pat_path)?;
+                        let (incl_pre, child) = preprocess_include_file(&cwd, &file_pat_path)?;
+                        map.splice(output, child);
                         output.push_str(&incl_pre);
                         output.push_str(sep);
                     } else {
@@ -543,18 +647,19 @@ fn process_include_pattern(
                         debug!("Including file: {}", path.display());
                         if path.extension().and_then(OsStr::to_str) == Some("tal") {
                             // Try to include using cwd as current_dir first, then fallback to original logic if fails
-                            let incl_pre = match preprocess_include_file(&PathBuf::from("."), &path)
-                            {
-                                Ok(s) => s,
-                                Err(_) =>
-                                //preprocess_include_file(&input_path, &path)?,
-                                {
-                                    match preprocess_include_file(&input_path, &path) {
-                                        Ok(s) => s,
-                                        Err(_) => preprocess_include_file(root_dir, &path)?,
+                            let (incl_pre, child) =
+                                match preprocess_include_file(&PathBuf::from("."), &path) {
+                                    Ok(s) => s,
+                                    Err(_) =>
+                                    //preprocess_include_file(&input_path, &path)?,
+                                    {
+                                        match preprocess_include_file(&input_path, &path) {
+                                            Ok(s) => s,
+                                            Err(_) => preprocess_include_file(root_dir, &path)?,
+                                        }
                                     }
-                                }
-                            };
+                                };
+                            map.splice(output, child);
                             output.push_str(&incl_pre);
                             output.push_str(sep);
                         } else {
@@ -569,7 +674,7 @@ fn process_include_pattern(
             }
         })
     } else {
-        process_single_file(incl_pattern_str, output, sep, input_path, &root_dir)?;
+        process_single_file(incl_pattern_str, output, sep, input_path, &root_dir, map)?;
         Ok(())
     }
 }
@@ -580,6 +685,7 @@ fn process_single_file(
     sep: &String,
     input_path: PathBuf,
     root_dir: &PathBuf,
+    map: &mut LineMap,
 ) -> Result<()> {
     // Enable debug output if env var CHOCOLATAL_DEBUG=1
     let debug_enabled = env::var("CHOCOLATAL_DEBUG")
@@ -697,8 +803,10 @@ fn process_single_file(
                     }
                 }
             };
-            let incl_pre = preprocess(&incl_str, input_path.to_str().unwrap_or(""), &root_dir)?;
+            let (incl_pre, child) =
+                preprocess_with_map(&incl_str, input_path.to_str().unwrap_or(""), &root_dir)?;
             let incl_pre = incl_pre.trim_end();
+            map.splice(output, child);
             output.push_str(&incl_pre);
             output.push_str(sep);
         } else {
@@ -736,7 +844,7 @@ fn hexdump(output: &mut String, sep: &String, input_path: PathBuf) -> Result<()>
     Ok(())
 }
 
-fn preprocess_include_file(current_dir: &PathBuf, path: &PathBuf) -> Result<String> {
+fn preprocess_include_file(current_dir: &PathBuf, path: &PathBuf) -> Result<(String, LineMap)> {
     // let rel_path = path.strip_prefix(current_dir).unwrap_or(path);
     // let rel_str = rel_path.to_str().unwrap_or("");
     // let rel_str = rel_str
@@ -757,8 +865,7 @@ fn preprocess_include_file(current_dir: &PathBuf, path: &PathBuf) -> Result<Stri
         }
     };
     let incl_str = incl_str.trim_end();
-    let incl_pre = preprocess(&incl_str, &path.display().to_string(), current_dir)?;
-    Ok(incl_pre)
+    preprocess_with_map(&incl_str, &path.display().to_string(), current_dir)
 }
 
 /// Runs the deluge docker container and returns the output as a String.
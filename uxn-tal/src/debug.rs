@@ -1,6 +1,6 @@
 use crate::bkend::{AssemblerBackend, AssemblyOutput};
 use crate::dis_uxndis::run_dis_file;
-use crate::{Assembler, AssemblerError};
+use crate::{inproc, Assembler, AssemblerError};
 use std::fs;
 use std::path::Path;
 use std::process::Command;
@@ -129,236 +129,56 @@ impl AssemblerBackend for UxnasmBackend {
     }
 }
 
+/// Assembles with the bundled drifblim ROM, run in-process
 pub struct DrifblimBackend;
 impl AssemblerBackend for DrifblimBackend {
     fn name(&self) -> &'static str {
         "drifblim"
     }
-    fn assemble(&self, tal_file: &str, _src: &str) -> Result<AssemblyOutput, AssemblerError> {
-        // let mut temp_file = tempfile::NamedTempFile::new().map_err(|e| syntax_err(tal_file, &format!("tempfile error: {e}")))?;
-        // temp_file.write_all(_src.as_bytes()).map_err(|e| syntax_err(tal_file, &format!("tempfile write error: {e}")))?;
-        // let tal_path = temp_file.path().to_string_lossy().to_string();
-        // let tal_path = tal_path.replace('\\', "/");
-        // let rom_path = format!("{}_{}.rom", tal_path, self.name());
-        // println!("rom_path: {}", rom_path);
-
-        let input = tal_file.replace('\\', "/");
-        let input = input.replace("//?/C:", "/mnt/c"); // Handle Windows long path prefix
-                                                       //let input = tal_file;//.replace("\\\\?\\", ""); // Handle Windows long path prefix
-        let rom_path = format!("{}_{}.rom", input, self.name());
-        println!("rom_path: {}", rom_path);
-        println!("tal_file: {}", tal_file);
-        println!("input: {}", input);
-        let stdout = Self::run_drif(&input, &input, &rom_path)?;
-        let rom_path = rom_path.replace("/mnt/c/", "C:/"); // Handle Windows long path prefix
-        let rom_path = rom_path.replace('/', "\\");
-        let bytes = fs::read(&rom_path).unwrap_or_default();
-        if bytes.is_empty() {
-            return Err(syntax_err(&rom_path, "drifblim produced empty ROM"));
-        }
-        Ok(AssemblyOutput {
-            rom_path: rom_path.clone(),
-            rom_bytes: bytes,
-            stdout,
-            disassembly: run_dis_file(&rom_path)?,
-        })
-    }
-}
-impl DrifblimBackend {
-    fn run_drif(tal_path: &str, _tal_file: &str, rom_path: &str) -> Result<String, AssemblerError> {
-        println!("tal_path: {}", tal_path);
-        println!("rom_path: {}", rom_path);
-        let drifblim_rom = crate::bkend_drif::drifblim_repo_get_drifblim();
-        let drifblim_rom = if cfg!(windows) {
-            wslpath::windows_to_wsl(&drifblim_rom.to_string_lossy())
-                .map_err(|_| syntax_err(rom_path, "Could not convert drifblim path to WSL"))?
-        } else {
-            drifblim_rom.to_string_lossy().to_string()
-        };
-        let output = if cfg!(windows) {
-            let in_wsl = detect_wsl();
-            if in_wsl {
-                Command::new("uxncli")
-                    .arg(drifblim_rom)
-                    .arg(tal_path)
-                    .arg(rom_path)
-                    .output()
-            } else {
-                Command::new("wsl")
-                    .arg("uxncli")
-                    .arg(drifblim_rom)
-                    .arg(tal_path)
-                    .arg(rom_path)
-                    .output()
-            }
-            .map_err(|e| syntax_err(rom_path, &format!("drifblim failed: {e}")))?
-        } else {
-            Command::new("uxncli")
-                .arg(drifblim_rom)
-                .arg(tal_path)
-                .arg(rom_path)
-                .output()
-                .map_err(|e| syntax_err(rom_path, &format!("drifblim failed: {e}")))?
-        };
-        println!("drifblim stdout: {:?}", output);
-        if !output.stderr.is_empty() {
-            let stderr_str = String::from_utf8_lossy(&output.stderr);
-            if stderr_str.contains("Assembled ") {
-                // Write tal_file to a temp file with a short name
-                println!("Path exceeded error from drifblim, retrying with short path");
-                // let tal_path_rel = Path::new(tal_path)
-                //     .file_name()
-                //     .map(|f| f.to_string_lossy().to_string())
-                //     .unwrap_or_else(|| "temp.tal".to_string());
-                // let temp_path = format!("./{}", tal_path_rel);
-                // let rom_path = format!("{}_{}.rom", temp_path, "drifblim");
-                // let tal_path = wslpath::wsl_to_windows(tal_path)
-                //     .or_else(|_| Err(syntax_err(&rom_path, "Could not convert TAL path to WSL")))?;
-                // fs::write(&temp_path, tal_file.as_bytes()).map_err(|e| syntax_err(&temp_path, &format!("Failed to write to temp path: {e}")))?;
-                // return Self::run_drif(&temp_path, &tal_file, &rom_path);
-            } else {
-                return Err(syntax_err(
-                    rom_path,
-                    &format!(
-                        "drifblim stderr: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    ),
-                ));
-            }
-        }
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    fn assemble(&self, tal_file: &str, src: &str) -> Result<AssemblyOutput, AssemblerError> {
+        assemble_in_process(self.name(), inproc::DRIFBLIM_ROM, tal_file, src)
     }
 }
 
+/// Assembles with drifblim's seed ROM, run in-process
 pub struct DrifblimSeedBackend;
 impl AssemblerBackend for DrifblimSeedBackend {
     fn name(&self) -> &'static str {
         "drifseed"
     }
-    fn assemble(&self, tal_file: &str, _src: &str) -> Result<AssemblyOutput, AssemblerError> {
-        // let mut temp_file = tempfile::NamedTempFile::new().map_err(|e| syntax_err(tal_file, &format!("tempfile error: {e}")))?;
-        // temp_file.write_all(_src.as_bytes()).map_err(|e| syntax_err(tal_file, &format!("tempfile write error: {e}")))?;
-        // let tal_path = temp_file.path().to_string_lossy().to_string();
-        // let tal_path = tal_path.replace('\\', "/");
-        // let rom_path = format!("{}_{}.rom", tal_path, self.name());
-        // println!("rom_path: {}", rom_path);
-        let tal_file = Path::new(tal_file)
-            .canonicalize()
-            .ok()
-            .and_then(|abs| {
-                std::env::current_dir()
-                    .ok()
-                    .and_then(|cwd| abs.strip_prefix(&cwd).ok().map(|rel| rel.to_path_buf()))
-                    .or(Some(abs))
-            })
-            .unwrap_or_else(|| Path::new(tal_file).to_path_buf());
-        let cwd_wsl = std::env::current_dir()
-            .ok()
-            .and_then(|cwd| wslpath::windows_to_wsl(&cwd.display().to_string()).ok())
-            .unwrap_or_else(|| ".".to_string());
-        let input = if cfg!(windows) {
-            let input = wslpath::windows_to_wsl(&tal_file.display().to_string()).map_err(|e| {
-                syntax_err(
-                    &tal_file.display().to_string(),
-                    &format!("Could not convert TAL path to WSL: {e}"),
-                )
-            })?;
-            if input.starts_with(&cwd_wsl) {
-                input[cwd_wsl.len()..].trim_start_matches('/').to_string()
-            } else {
-                input
-            }
-        } else {
-            tal_file.display().to_string()
-        };
-        //let input = tal_file;//.replace("\\\\?\\", ""); // Handle Windows long path prefix
-        let rom_path = format!("{}_{}.rom", input, self.name());
-        println!("cwd_wsl: {}", cwd_wsl);
-        println!("rom_path: {}", rom_path);
-        println!("tal_file: {}", tal_file.display());
-        println!("input: {}", input);
-        // std::process::exit(0);
-        let stdout = Self::run_drif(&input, &input, &rom_path)?;
-        let rom_path = if cfg!(windows) {
-            wslpath::wsl_to_windows(&rom_path).map_err(|e| {
-                syntax_err(
-                    &rom_path,
-                    &format!("Could not convert ROM path to Windows: {e}"),
-                )
-            })?
-        } else {
-            rom_path.to_string()
-        };
-        let bytes = fs::read(&rom_path).unwrap_or_default();
-        println!("rom_path: {}", rom_path);
-
-        if bytes.is_empty() {
-            return Err(syntax_err(&rom_path, "drifblim produced empty ROM"));
-        }
-
-        Ok(AssemblyOutput {
-            rom_path: rom_path.clone(),
-            rom_bytes: bytes,
-            stdout,
-            disassembly: run_dis_file(&rom_path)?,
-        })
+    fn assemble(&self, tal_file: &str, src: &str) -> Result<AssemblyOutput, AssemblerError> {
+        let seed_path = crate::bkend_drif::drifblim_repo_get_drifblim_seed();
+        let seed = fs::read(&seed_path).map_err(|e| io_err(&seed_path.display().to_string(), e))?;
+        assemble_in_process(self.name(), &seed, tal_file, src)
     }
 }
-impl DrifblimSeedBackend {
-    fn run_drif(tal_path: &str, _tal_file: &str, rom_path: &str) -> Result<String, AssemblerError> {
-        println!("tal_path: {}", tal_path);
-        println!("rom_path: {}", rom_path);
-        let drifblim_rom = crate::bkend_drif::drifblim_repo_get_drifblim_seed();
-        let drifblim_rom = if cfg!(windows) {
-            wslpath::windows_to_wsl(&drifblim_rom.to_string_lossy())
-                .map_err(|_| syntax_err(rom_path, "Could not convert drifblim path to WSL"))?
-        } else {
-            drifblim_rom.to_string_lossy().to_string()
-        };
-        let in_wsl = detect_wsl();
-        let output = if in_wsl || !cfg!(windows) {
-            Command::new("uxncli")
-                .arg(drifblim_rom)
-                .arg(tal_path)
-                .arg(rom_path)
-                .output()
-        } else {
-            Command::new("wsl")
-                .arg("uxncli")
-                .arg(drifblim_rom)
-                .arg(tal_path)
-                .arg(rom_path)
-                .output()
-        }
-        .map_err(|e| syntax_err(rom_path, &format!("drifblim failed: {e}")))?;
-        println!("drifblim stdout: {:?}", output);
-        if !output.stderr.is_empty() {
-            let stderr_str = String::from_utf8_lossy(&output.stderr);
-            if stderr_str.contains("Assembled ") {
-                // Write tal_file to a temp file with a short name
-                // println!("Path exceeded error from drifblim, retrying with short path");
-                // let tal_path_rel = Path::new(tal_path)
-                //     .file_name()
-                //     .map(|f| f.to_string_lossy().to_string())
-                //     .unwrap_or_else(|| "temp.tal".to_string());
-                // let temp_path = format!("./{}", tal_path_rel);
-                // let rom_path = format!("{}_{}.rom", temp_path, "drifblim");
-                // let tal_path = wslpath::wsl_to_windows(tal_path)
-                //     .or_else(|_| Err(syntax_err(&rom_path, "Could not convert TAL path to WSL")))?;
-                // fs::write(&temp_path, tal_file.as_bytes()).map_err(|e| syntax_err(&temp_path, &format!("Failed to write to temp path: {e}")))?;
-                // return Self::run_drif(&temp_path, &tal_file, &rom_path);
-            } else {
-                return Err(syntax_err(
-                    rom_path,
-                    &format!(
-                        "drifblim stderr: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    ),
-                ));
-            }
-        }
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    }
+
+/// Runs a drifblim ROM on `src` in-process, reading its includes from the
+/// source's directory, and writes the ROM next to the source
+fn assemble_in_process(
+    backend: &str,
+    drifblim: &[u8],
+    tal_file: &str,
+    src: &str,
+) -> Result<AssemblyOutput, AssemblerError> {
+    let path = Path::new(tal_file);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "input.tal".to_owned());
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let (rom, out) = inproc::drifblim_assemble_with(drifblim, &name, src, Some(dir))?;
+    let rom_path = format!("{tal_file}_{backend}.rom");
+    fs::write(&rom_path, &rom).map_err(|e| io_err(&rom_path, e))?;
+    Ok(AssemblyOutput {
+        rom_path,
+        disassembly: inproc::uxndis_disassemble(&rom)?,
+        rom_bytes: rom,
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+    })
 }
 
 pub struct DrifloonBackend;
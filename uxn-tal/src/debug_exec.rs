@@ -0,0 +1,316 @@
+//! Differential execution against the reference `uxncli`
+//!
+//! This is the execution counterpart of [`crate::debug`]: where that module
+//! compares ROMs produced by several assemblers, this one runs a single ROM
+//! in Cardinal (in-process) and in an external `uxncli`, with the same
+//! arguments, stdin and files, and reports any difference in stdout, exit
+//! code or final RAM.
+//!
+//! Cardinal's final RAM is always captured in [`ExecOutput::ram`], but
+//! `uxncli` has no way to dump RAM from outside the ROM, so RAM is only
+//! compared through a dump file written by the ROM itself: if
+//! [`ExecCase::dump`] is set, the ROM is expected to write its memory (or
+//! whatever state is of interest) to that file with the File device, and the
+//! file's contents are compared after both runs.
+//!
+//! When `uxncli` is not in `PATH`, [`compare_execution`] returns `Ok(None)`,
+//! so tests built on it skip cleanly in offline CI.
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uxn::Backend;
+use varvara::{Machine, MemoryFiles};
+
+/// Reasons a differential run couldn't be completed
+#[derive(Error, Debug)]
+pub enum ExecError {
+    #[error("cardinal: instruction budget of {cycles} exhausted")]
+    BudgetExhausted { cycles: u64 },
+
+    #[error("uxncli: timed out after {timeout:?}")]
+    Timeout { timeout: Duration },
+
+    #[error("uxncli terminated by signal ({status})")]
+    Signal { status: ExitStatus },
+
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// Inputs for a differential run
+#[derive(Clone, Debug)]
+pub struct ExecCase {
+    /// Console arguments
+    pub args: Vec<String>,
+    /// Bytes sent to the console, followed by an end-of-stdin event
+    pub stdin: Vec<u8>,
+    /// Files visible to the ROM, relative to its working directory
+    pub files: MemoryFiles,
+    /// File written by the ROM whose contents should be compared
+    pub dump: Option<String>,
+    /// Instruction budget for Cardinal
+    pub cycles: u64,
+    /// Wall-clock limit for `uxncli`
+    pub timeout: Duration,
+}
+
+impl Default for ExecCase {
+    fn default() -> Self {
+        Self {
+            args: vec![],
+            stdin: vec![],
+            files: MemoryFiles::new(),
+            dump: None,
+            cycles: 100_000_000,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Observable results of running a ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    /// Exit code, which is 0 if the ROM never wrote to `System/state`
+    pub exit: i32,
+    /// Contents of the dump file, if requested and written
+    pub dump: Option<Vec<u8>>,
+    /// Final contents of all 64k of RAM, which only Cardinal can provide;
+    /// compare RAM with `uxncli` through [`ExecCase::dump`]
+    pub ram: Option<Vec<u8>>,
+}
+
+/// Results of running a ROM in Cardinal and `uxncli`
+#[derive(Debug)]
+pub struct ExecDiff {
+    pub cardinal: ExecOutput,
+    pub uxncli: ExecOutput,
+    /// Human-readable description of each difference
+    pub mismatches: Vec<String>,
+}
+
+impl ExecDiff {
+    /// Checks whether both runs produced identical results
+    pub fn is_match(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ExecDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "== Execution Diff ==")?;
+        writeln!(
+            f,
+            "  {:<9} {:>5} {:>8} {:>8}",
+            "runtime", "exit", "stdout", "dump"
+        )?;
+        for (name, o) in [("cardinal", &self.cardinal), ("uxncli", &self.uxncli)] {
+            let dump = o.dump.as_ref().map(|d| d.len().to_string());
+            writeln!(
+                f,
+                "  {:<9} {:>5} {:>8} {:>8}",
+                name,
+                o.exit,
+                o.stdout.len(),
+                dump.as_deref().unwrap_or("-")
+            )?;
+        }
+        if self.is_match() {
+            writeln!(f, "identical")
+        } else {
+            self.mismatches.iter().try_for_each(|m| writeln!(f, "{m}"))
+        }
+    }
+}
+
+/// Finds the external `uxncli`, returning `None` if it isn't installed
+pub fn find_uxncli() -> Option<PathBuf> {
+    #[cfg(all(target_family = "wasm", target_os = "unknown"))]
+    {
+        None
+    }
+    #[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+    {
+        which::which("uxncli").ok()
+    }
+}
+
+/// Runs a ROM in Cardinal, using the interpreter and in-memory files
+pub fn run_cardinal(rom: &[u8], case: &ExecCase) -> Result<ExecOutput, ExecError> {
+    let mut m = Machine::new(Backend::Interpreter);
+    m.dev_mut().set_cycle_budget(Some(case.cycles));
+    m.dev_mut().file.set_memory_files(case.files.clone());
+    m.load(rom, &case.args);
+    let mut stdout = m.output().stdout;
+    for &c in &case.stdin {
+        if !m.is_running() {
+            break;
+        }
+        m.console(c);
+        stdout.extend(m.output().stdout);
+    }
+    if m.is_running() {
+        m.console_eof();
+        stdout.extend(m.output().stdout);
+    }
+    if m.dev().cycles_exhausted() {
+        return Err(ExecError::BudgetExhausted {
+            cycles: case.cycles,
+        });
+    }
+    let mut files = m.dev_mut().file.take_memory_files().unwrap_or_default();
+    Ok(ExecOutput {
+        stdout,
+        exit: m.exit().unwrap_or(0),
+        dump: case.dump.as_ref().and_then(|d| files.remove(d)),
+        ram: Some(m.vm().ram.to_vec()),
+    })
+}
+
+/// Runs a ROM in `uxncli`, in a temporary directory holding the case's files
+///
+/// Returns `Ok(None)` if `uxncli` is not installed.
+pub fn run_uxncli(rom: &[u8], case: &ExecCase) -> Result<Option<ExecOutput>, ExecError> {
+    let Some(uxncli) = find_uxncli() else {
+        return Ok(None);
+    };
+    let tmp = tempfile::tempdir().map_err(io_err("tempdir"))?;
+    let dir = tmp.path();
+    let rom_path = dir.join(".__diff_exec.rom");
+    std::fs::write(&rom_path, rom).map_err(io_err("write rom"))?;
+    for (name, data) in &case.files {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(io_err(name))?;
+        }
+        std::fs::write(&path, data).map_err(io_err(name))?;
+    }
+
+    let mut child = Command::new(uxncli)
+        .current_dir(dir)
+        .arg(&rom_path)
+        .args(&case.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(io_err("uxncli failed to spawn"))?;
+
+    // Feed stdin and collect stdout on threads, so that neither pipe can
+    // fill up while we wait for the process with a timeout.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = case.stdin.clone();
+    std::thread::spawn(move || stdin.write_all(&input));
+    let mut out = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut buf = vec![];
+        out.read_to_end(&mut buf).map(|_| buf)
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if start.elapsed() > case.timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(ExecError::Timeout {
+                    timeout: case.timeout,
+                });
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(5)),
+            Err(e) => return Err(io_err("uxncli")(e)),
+        }
+    };
+    let stdout = reader.join().unwrap().map_err(io_err("uxncli stdout"))?;
+    let exit = status.code().ok_or(ExecError::Signal { status })?;
+    Ok(Some(ExecOutput {
+        stdout,
+        exit,
+        dump: case
+            .dump
+            .as_ref()
+            .and_then(|d| std::fs::read(dir.join(d)).ok()),
+        ram: None,
+    }))
+}
+
+/// Runs a ROM in Cardinal and `uxncli`, comparing the results
+///
+/// Returns `Ok(None)` if `uxncli` is not installed.
+pub fn compare_execution(rom: &[u8], case: &ExecCase) -> Result<Option<ExecDiff>, ExecError> {
+    let Some(uxncli) = run_uxncli(rom, case)? else {
+        return Ok(None);
+    };
+    let cardinal = run_cardinal(rom, case)?;
+
+    let mut mismatches = vec![];
+    if cardinal.stdout != uxncli.stdout {
+        let i = first_diff(&cardinal.stdout, &uxncli.stdout);
+        mismatches.push(format!(
+            "stdout differs at byte {i}: cardinal {:?}, uxncli {:?}",
+            excerpt(&cardinal.stdout, i),
+            excerpt(&uxncli.stdout, i),
+        ));
+    }
+    if cardinal.exit != uxncli.exit {
+        mismatches.push(format!(
+            "exit code differs: cardinal {}, uxncli {}",
+            cardinal.exit, uxncli.exit
+        ));
+    }
+    if let Some(name) = &case.dump {
+        match (&cardinal.dump, &uxncli.dump) {
+            (Some(a), Some(b)) if a != b => {
+                let i = first_diff(a, b);
+                let byte = |d: &[u8]| d.get(i).map(|v| format!("{v:02X}"));
+                mismatches.push(format!(
+                    "dump {name} differs at 0x{i:04X}: cardinal {}, uxncli {}",
+                    byte(a).as_deref().unwrap_or("(end)"),
+                    byte(b).as_deref().unwrap_or("(end)"),
+                ));
+            }
+            (Some(_), Some(_)) => (),
+            (a, b) => mismatches.push(format!(
+                "dump {name} written by {}",
+                match (a.is_some(), b.is_some()) {
+                    (true, _) => "cardinal only",
+                    (_, true) => "uxncli only",
+                    _ => "neither runtime",
+                }
+            )),
+        }
+    }
+    Ok(Some(ExecDiff {
+        cardinal,
+        uxncli,
+        mismatches,
+    }))
+}
+
+fn first_diff(a: &[u8], b: &[u8]) -> usize {
+    a.iter()
+        .zip(b)
+        .position(|(x, y)| x != y)
+        .unwrap_or(a.len().min(b.len()))
+}
+
+/// Returns a short lossy excerpt of output, starting at `i`
+fn excerpt(data: &[u8], i: usize) -> String {
+    let end = (i + 32).min(data.len());
+    String::from_utf8_lossy(&data[i.min(end)..end]).into_owned()
+}
+
+fn io_err(context: &str) -> impl FnOnce(io::Error) -> ExecError + '_ {
+    move |source| ExecError::Io {
+        context: context.to_owned(),
+        source,
+    }
+}
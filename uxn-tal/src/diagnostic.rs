@@ -0,0 +1,199 @@
+//! Assembler diagnostics with source spans
+//!
+//! [`Assembler::assemble_with_diagnostics`](crate::Assembler::assemble_with_diagnostics)
+//! keeps going after an error where it can and reports everything it found
+//! as a list of [`Diagnostic`]s, which render like rustc's:
+//!
+//! ```text
+//! error: Label unknown: "missing"
+//!  --> hello.tal:3:2
+//!   |
+//! 3 |     ;missing print
+//!   |     ^^^^^^^^
+//! ```
+use std::fmt;
+
+use crate::error::AssemblerError;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+/// Location of a diagnostic in a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub path: String,
+    /// 1-based line, or 0 if only the file is known
+    pub line: usize,
+    /// 1-based column in characters, or 0 if only the line is known
+    pub column: usize,
+    /// Number of characters covered, at least 1
+    pub len: usize,
+}
+
+/// Single assembler message, with the source line it points into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub source_line: String,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span: None,
+            source_line: String::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    /// Points the diagnostic at `line:column`, covering the word there
+    pub fn at(mut self, path: &str, line: usize, column: usize, source_line: &str) -> Self {
+        let len = source_line
+            .chars()
+            .skip(column.saturating_sub(1))
+            .take_while(|c| !c.is_whitespace())
+            .count()
+            .max(1);
+        self.span = Some(Span {
+            path: path.to_owned(),
+            line,
+            column,
+            len,
+        });
+        self.source_line = source_line.to_owned();
+        self
+    }
+
+    /// Renders the diagnostic as a rustc-style snippet
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        let Some(span) = &self.span else {
+            return Ok(());
+        };
+        let path = if span.path.is_empty() {
+            "(input)"
+        } else {
+            &span.path
+        };
+        if span.line == 0 {
+            return write!(f, "\n --> {path}");
+        }
+        let gutter = " ".repeat(span.line.to_string().len());
+        write!(f, "\n{gutter}--> {path}:{}", span.line)?;
+        if span.column > 0 {
+            write!(f, ":{}", span.column)?;
+        }
+        if self.source_line.is_empty() {
+            return Ok(());
+        }
+
+        // Tabs are expanded so the carets line up whatever the terminal's
+        // tab width is
+        let mut line = String::new();
+        let mut caret_start = None;
+        let mut caret_end = None;
+        for (i, c) in self.source_line.chars().enumerate() {
+            if i + 1 == span.column {
+                caret_start = Some(line.len());
+            }
+            if i + 1 == span.column + span.len {
+                caret_end = Some(line.len());
+            }
+            match c {
+                '\t' => line.push_str("    "),
+                c => line.push(c),
+            }
+        }
+        let line = line.trim_end();
+        write!(f, "\n{gutter} |\n{} | {line}", span.line)?;
+        if span.column > 0 {
+            let start = caret_start.unwrap_or(line.len());
+            let end = caret_end.unwrap_or(line.len()).max(start + 1);
+            write!(
+                f,
+                "\n{gutter} | {}{}",
+                " ".repeat(start),
+                "^".repeat(end - start)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&AssemblerError> for Diagnostic {
+    fn from(e: &AssemblerError) -> Self {
+        match e {
+            AssemblerError::SyntaxError {
+                path,
+                line,
+                position,
+                message,
+                source_line,
+            }
+            | AssemblerError::Utf8Error {
+                path,
+                line,
+                position,
+                message,
+                source_line,
+            } => Diagnostic::error(message.clone()).at(path, *line, *position, source_line),
+            AssemblerError::ExpectedIdentifierError {
+                path,
+                line,
+                position,
+                after_token,
+                found,
+                source_line,
+            } => Diagnostic::error(format!(
+                "Expected identifier after {after_token} (found '{found}')"
+            ))
+            .at(path, *line, *position, source_line),
+            AssemblerError::LabelReferenceError(data) => Diagnostic::error(format!(
+                "Invalid reference to '{}'",
+                data.label
+            ))
+            .at(&data.path, data.line, data.position, &data.source_line),
+            AssemblerError::FileReadError { path, message } => {
+                Diagnostic::error(message.clone()).at(path, 0, 0, "")
+            }
+            e => Diagnostic::error(e.to_string()),
+        }
+    }
+}
+
+impl From<AssemblerError> for Diagnostic {
+    fn from(e: AssemblerError) -> Self {
+        Diagnostic::from(&e)
+    }
+}
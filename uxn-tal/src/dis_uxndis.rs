@@ -48,10 +48,9 @@ pub fn uxndis_repo_get_uxndis_seed() -> PathBuf {
 use std::{
     fs,
     path::{Path, PathBuf},
-    process::Command,
 };
 
-use crate::{wsl::detect_wsl, Assembler, AssemblerError};
+use crate::{Assembler, AssemblerError};
 
 fn simple_err(path: &std::path::Path, msg: &str) -> AssemblerError {
     AssemblerError::SyntaxError {
@@ -177,67 +176,15 @@ fn dis_err(path: &str, e: &str) -> AssemblerError {
     }
 }
 
+/// Disassembles a ROM file with the bundled uxndis ROM, run in-process
+///
+/// The disassembly is also written next to the ROM, as `{rom_path}.dis`.
 pub fn run_dis_file(rom_path: &str) -> Result<String, AssemblerError> {
-    // let sym = format!("{rom_path}.sym");
-    // if Path::new(&sym).exists() {
-    //     let _ = fs::remove_file(&sym);
-    // }
-    let in_wsl = detect_wsl();
-    let uxndis_path = crate::dis_uxndis::uxndis_repo_get_uxndis();
-    let uxndis_path: String = if cfg!(windows) {
-        wslpath::windows_to_wsl(&uxndis_path.to_string_lossy()).map_err(|e| {
-            dis_err(
-                rom_path,
-                &format!("Could not convert uxndis path to WSL: {e}"),
-            )
-        })?
-    } else {
-        uxndis_path.to_string_lossy().to_string()
-    };
-    let rom_path = if cfg!(windows) {
-        wslpath::windows_to_wsl(rom_path)
-            .map_err(|e| dis_err(rom_path, &format!("Could not convert ROM path to WSL: {e}")))?
-    } else {
-        rom_path.to_string()
-    };
-    //     println!("Disassembly command: uxncli {} {}", uxndis_path, rom_path);
-    // // println!("Disassembly written to {}", dis);
-    // std::process::exit(0);
-    let output = if cfg!(windows) && !in_wsl {
-        if in_wsl {
-            Command::new("uxncli")
-                .arg(&uxndis_path)
-                .arg(&rom_path)
-                .output()
-        } else {
-            Command::new("wsl")
-                .arg("uxncli")
-                .arg(&uxndis_path)
-                .arg(&rom_path)
-                .output()
-        }
-        .map_err(|e| dis_err(&rom_path, &format!("uxndis failed: {e}")))?
-    } else {
-        Command::new("uxncli")
-            .arg(&uxndis_path)
-            .arg(&rom_path)
-            .output()?
-    };
-    let rom_path = if cfg!(windows) {
-        wslpath::wsl_to_windows(&rom_path).map_err(|e| {
-            dis_err(
-                &rom_path,
-                &format!("Could not convert ROM path to Windows: {e}"),
-            )
-        })?
-    } else {
-        rom_path.to_string()
-    };
+    let rom = fs::read(rom_path).map_err(|e| dis_err(rom_path, &format!("read failed: {e}")))?;
+    let out = crate::inproc::uxndis_disassemble(&rom)?;
     let dis = format!("{rom_path}.dis");
-    // Write disassembly output to .dis file
-    if let Err(e) = fs::write(&dis, &output.stdout) {
+    if let Err(e) = fs::write(&dis, &out) {
         eprintln!("Failed to write disassembly to {}: {}", dis, e);
     }
-
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    Ok(out)
 }
@@ -87,6 +87,13 @@ pub enum AssemblerError {
 
     #[error("Disassembly error: {message}")]
     Disassembly { message: String },
+
+    #[error("Link error in {path}: {message}")]
+    Link { path: String, message: String },
+
+    /// Assembly failed after the listed diagnostics were reported
+    #[error("could not assemble {path} due to {} previous error{}", .count, if *.count == 1 { "" } else { "s" })]
+    Aborted { path: String, count: usize },
 }
 
 /// Result type for assembler operations
@@ -0,0 +1,349 @@
+//! Canonical TAL formatter
+//!
+//! Formatting only ever changes whitespace and the case of hex numbers, so
+//! a file that assembles still assembles to the same ROM afterwards. Words are never split or
+//! joined, and comments are kept verbatim. The canonical style is:
+//!
+//! - `@label`, `|` padding, `%macro` and `~include` lines start at column 0,
+//!   everything else is indented one tab, plus one per open `{` block,
+//! - lines after a `&sublabel` are indented one more tab, up to the next
+//!   sublabel or the line that jumps back to it with `?&` or `!&`,
+//! - comment-only lines take the indentation of the code after them,
+//! - words are separated by single spaces, and trailing whitespace and
+//!   repeated blank lines are removed,
+//! - each routine is preceded by exactly one blank line,
+//! - trailing comments on consecutive lines of equal indentation are aligned,
+//! - hex numbers in `#` literals and `|`/`$` padding are lowercase. The lexer
+//!   only reads lowercase hex, so uppercase bare words such as `AB` are label
+//!   references and are left as they are.
+//!
+//! [`format`] is idempotent.
+use crate::lexer::{Lexer, Token};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Word(String),
+    /// Verbatim, parentheses included; may span lines
+    Comment(String),
+}
+
+impl Item {
+    fn text(&self) -> &str {
+        match self {
+            Item::Word(w) | Item::Comment(w) => w,
+        }
+    }
+}
+
+/// Kind of brace a `{` opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Brace {
+    /// Body of a `%macro`, not indented
+    Macro,
+    Block,
+}
+
+struct Line {
+    depth: usize,
+    items: Vec<Item>,
+}
+
+impl Line {
+    fn first_word(&self) -> Option<&str> {
+        self.items.iter().find_map(|i| match i {
+            Item::Word(w) => Some(w.as_str()),
+            Item::Comment(_) => None,
+        })
+    }
+
+    fn is_top_level(&self) -> bool {
+        self.first_word()
+            .is_some_and(|w| w.starts_with(['@', '|', '%', '~']))
+    }
+
+    fn is_routine(&self) -> bool {
+        self.first_word().is_some_and(|w| w.starts_with('@'))
+    }
+
+    /// Width of the code before a trailing single-line comment, if the line
+    /// has one worth aligning
+    fn trailing_comment(&self) -> Option<usize> {
+        let (last, code) = self.items.split_last()?;
+        let Item::Comment(c) = last else {
+            return None;
+        };
+        // `@label ( signature )` keeps its comment next to the label
+        let label_only = matches!(code, [Item::Word(w)] if w.starts_with(['@', '&']));
+        if code.is_empty() || c.contains('\n') || label_only {
+            return None;
+        }
+        Some(
+            code.iter()
+                .map(|i| i.text().chars().count() + 1)
+                .sum::<usize>()
+                - 1,
+        )
+    }
+}
+
+/// Formats `source` in the canonical style
+pub fn format(source: &str) -> String {
+    let lines = indent(split(source));
+    let aligned = align(&lines);
+    let code = |l: &Line| l.first_word().is_some();
+
+    // A routine gets one blank line before it and its header comments,
+    // unless it directly follows other top-level lines
+    let mut separate = vec![false; lines.len()];
+    for (r, line) in lines.iter().enumerate() {
+        if !line.is_routine() {
+            continue;
+        }
+        let mut g = r;
+        while g > 0 && !lines[g - 1].items.is_empty() && !code(&lines[g - 1]) {
+            g -= 1;
+        }
+        separate[g] = g > 0 && code(&lines[g - 1]) && lines[g - 1].depth > 0;
+    }
+
+    let mut out = String::new();
+    let mut last_blank = true;
+    for (i, line) in lines.iter().enumerate() {
+        if line.items.is_empty() || separate[i] {
+            if !last_blank {
+                out.push('\n');
+                last_blank = true;
+            }
+            if line.items.is_empty() {
+                continue;
+            }
+        }
+        for _ in 0..line.depth {
+            out.push('\t');
+        }
+        let (last, rest) = line.items.split_last().unwrap();
+        for item in rest {
+            out.push_str(item.text());
+            out.push(' ');
+        }
+        if let (Some(width), Some(column)) = (line.trailing_comment(), aligned[i]) {
+            out.push_str(&" ".repeat(column - width - 1));
+        }
+        out.push_str(last.text());
+        out.push('\n');
+        last_blank = false;
+    }
+    while out.ends_with("\n\n") {
+        out.pop();
+    }
+    out
+}
+
+/// Splits `source` into lines of words and comments; a line holding the
+/// start of a multi-line comment also holds whatever follows its end
+fn split(source: &str) -> Vec<Vec<Item>> {
+    let mut lines = vec![Vec::new()];
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c == '\n' {
+            chars.next();
+            lines.push(Vec::new());
+            continue;
+        }
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut end = start;
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        let word = &source[start..end];
+        let line = lines.last_mut().unwrap();
+        if !word.starts_with('(') {
+            line.push(Item::Word(normalize(word)));
+            continue;
+        }
+        // Like the lexer, only standalone `(` and `)` words nest or close
+        let mut depth = match &word[1..] {
+            "(" => 2,
+            ")" => 0,
+            _ => 1,
+        };
+        while depth > 0 {
+            while chars.peek().is_some_and(|(_, c)| c.is_whitespace()) {
+                chars.next();
+            }
+            let Some(&(s, _)) = chars.peek() else {
+                break;
+            };
+            let mut e = s;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                e = i + c.len_utf8();
+                chars.next();
+            }
+            match &source[s..e] {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            end = e;
+        }
+        line.push(Item::Comment(source[start..end].to_owned()));
+    }
+    lines
+}
+
+/// Lowercases hex numbers, leaving anything the lexer reads otherwise alone
+fn normalize(word: &str) -> String {
+    if !word.starts_with(|c: char| "#|$".contains(c) || c.is_ascii_hexdigit())
+        || !word.chars().any(|c| c.is_ascii_uppercase())
+    {
+        return word.to_owned();
+    }
+    let lower = word.to_ascii_lowercase();
+    // The single token a word lexes to, or `Err` if it doesn't lex
+    let first = |w: &str| {
+        let (tokens, errors) = Lexer::new(w.to_owned(), None).tokenize_all();
+        if !errors.is_empty() {
+            return Err(());
+        }
+        let single = tokens.iter().skip(1).all(|t| t.token == Token::Eof);
+        Ok(single
+            .then(|| tokens.into_iter().next())
+            .flatten()
+            .map(|t| t.token))
+    };
+    // Only rewrite words the lexer reads as the same number either way, and
+    // `#` literals it rejects because they are uppercase. A word like `#0A`
+    // reads as `#0` followed by a call to `A`, so it is left alone.
+    let same = match (first(word), first(&lower)) {
+        (Ok(Some(Token::HexLiteral(a))), Ok(Some(Token::HexLiteral(b)))) => {
+            a.eq_ignore_ascii_case(&b)
+        }
+        (Ok(Some(Token::Padding(a))), Ok(Some(Token::Padding(b))))
+        | (Ok(Some(Token::RelativePadding(a))), Ok(Some(Token::RelativePadding(b)))) => a == b,
+        (Err(()), Ok(Some(Token::HexLiteral(_)))) => word.starts_with('#'),
+        _ => false,
+    };
+    if same {
+        lower
+    } else {
+        word.to_owned()
+    }
+}
+
+/// Assigns each line its indentation
+fn indent(items: Vec<Vec<Item>>) -> Vec<Line> {
+    let mut braces: Vec<Brace> = Vec::new();
+    // Open sublabel body: the sublabel's name and the blocks open at it
+    let mut body: Option<(String, usize)> = None;
+    let mut lines: Vec<Line> = Vec::with_capacity(items.len());
+    for items in items {
+        let mut line = Line { depth: 0, items };
+        let blocks = |b: &[Brace]| b.iter().filter(|b| **b == Brace::Block).count();
+        // Closing braces at the start of a line dedent it
+        let mut open = braces.clone();
+        for item in &line.items {
+            match item {
+                Item::Word(w) if w == "}" => {
+                    open.pop();
+                }
+                Item::Comment(_) => continue,
+                _ => break,
+            }
+        }
+        let sublabel = line
+            .first_word()
+            .and_then(|w| w.strip_prefix('&'))
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned);
+        if line.is_top_level() || sublabel.is_some() {
+            body = None;
+        }
+        if body.as_ref().is_some_and(|(_, b)| blocks(&open) < *b) {
+            body = None;
+        }
+        line.depth = if line.is_top_level() {
+            0
+        } else {
+            1 + blocks(&open) + usize::from(body.is_some())
+        };
+        if let Some(name) = sublabel {
+            body = Some((name, blocks(&open)));
+        }
+        if let Some((name, _)) = &body {
+            let back = |w: &str| {
+                w.strip_prefix(['?', '!']).and_then(|w| w.strip_prefix('&')) == Some(name)
+            };
+            if line
+                .items
+                .iter()
+                .any(|i| matches!(i, Item::Word(w) if back(w)))
+            {
+                body = None;
+            }
+        }
+        let macro_line = line.first_word().is_some_and(|w| w.starts_with('%'));
+        let mut first_brace = true;
+        for item in &line.items {
+            let Item::Word(w) = item else {
+                continue;
+            };
+            if w.starts_with('}') {
+                braces.pop();
+            } else if w.ends_with('{') && !w.starts_with(['"', '\'']) {
+                braces.push(if macro_line && first_brace {
+                    Brace::Macro
+                } else {
+                    Brace::Block
+                });
+                first_brace = false;
+            }
+        }
+        lines.push(line);
+    }
+    // Comment-only lines take the indentation of the code that follows
+    let mut next = 0;
+    for line in lines.iter_mut().rev() {
+        if line.first_word().is_some() {
+            next = line.depth;
+        } else if !line.items.is_empty() {
+            line.depth = next;
+        }
+    }
+    lines
+}
+
+/// Column each line's trailing comment is aligned to
+fn align(lines: &[Line]) -> Vec<Option<usize>> {
+    let mut out = vec![None; lines.len()];
+    let mut i = 0;
+    while i < lines.len() {
+        let Some(_) = lines[i].trailing_comment() else {
+            i += 1;
+            continue;
+        };
+        let depth = lines[i].depth;
+        let end = (i..lines.len())
+            .find(|&j| lines[j].depth != depth || lines[j].trailing_comment().is_none())
+            .unwrap_or(lines.len());
+        let column = (i..end)
+            .filter_map(|j| lines[j].trailing_comment())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        for slot in &mut out[i..end] {
+            *slot = Some(column);
+        }
+        i = end;
+    }
+    out
+}
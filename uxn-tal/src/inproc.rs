@@ -0,0 +1,115 @@
+//! Runs console ROMs (drifblim, uxndis) in-process with Cardinal
+//!
+//! The ROMs are bundled with this crate and executed on `cardinal-uxn` and
+//! `cardinal-varvara`, with the File device backed by an in-memory set of
+//! files.  This needs no external emulator, Docker, or network access, and
+//! produces the same output on every run.
+use crate::AssemblerError;
+use std::path::Path;
+use uxn::Backend;
+use varvara::{Machine, MemoryFiles};
+
+/// drifblim assembler ROM
+pub const DRIFBLIM_ROM: &[u8] = include_bytes!("bin/drifblim.rom");
+
+/// uxndis disassembler ROM
+pub const UXNDIS_ROM: &[u8] = include_bytes!("bin/uxndis.rom");
+
+/// Results of running a console ROM
+#[derive(Debug, Default)]
+pub struct RomOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Exit code, if the ROM wrote to `System/state`
+    pub exit: Option<i32>,
+    /// Files after the ROM has run, including any that it wrote
+    pub files: MemoryFiles,
+}
+
+/// Runs a ROM with the given console arguments and in-memory files
+///
+/// The ROM runs until its reset and argument vectors return; console ROMs
+/// such as drifblim and uxndis do all of their work there.
+pub fn run_rom(rom: &[u8], args: &[String], files: MemoryFiles) -> RomOutput {
+    run_rom_in(rom, args, files, None)
+}
+
+/// Like [`run_rom`], but files which aren't in `files` are read from `dir`
+/// when the ROM opens them
+pub fn run_rom_in(
+    rom: &[u8],
+    args: &[String],
+    files: MemoryFiles,
+    dir: Option<&Path>,
+) -> RomOutput {
+    let mut m = Machine::new(Backend::Interpreter);
+    m.dev_mut().file.set_memory_files(files);
+    m.dev_mut()
+        .file
+        .set_memory_fallback(dir.map(Path::to_path_buf));
+    m.load(rom, args);
+    let out = m.output();
+    let (stdout, stderr, exit) = (out.stdout, out.stderr, out.exit);
+    let files = m.dev_mut().file.take_memory_files().unwrap_or_default();
+    RomOutput {
+        stdout,
+        stderr,
+        exit,
+        files,
+    }
+}
+
+fn drif_err(path: &str, out: &RomOutput) -> AssemblerError {
+    AssemblerError::Backend {
+        message: format!(
+            "{path}: drifblim failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ),
+    }
+}
+
+/// Assembles TAL source with drifblim, returning the ROM and drifblim's output
+///
+/// `name` is the file name passed to drifblim, which is used in its messages;
+/// the source's includes are read from `dir` as drifblim opens them.
+pub fn drifblim_assemble(
+    name: &str,
+    src: &str,
+    dir: Option<&Path>,
+) -> Result<(Vec<u8>, RomOutput), AssemblerError> {
+    drifblim_assemble_with(DRIFBLIM_ROM, name, src, dir)
+}
+
+/// Like [`drifblim_assemble`], but with another build of drifblim, such as
+/// its seed ROM
+pub fn drifblim_assemble_with(
+    drifblim: &[u8],
+    name: &str,
+    src: &str,
+    dir: Option<&Path>,
+) -> Result<(Vec<u8>, RomOutput), AssemblerError> {
+    const OUT: &str = "out.rom";
+    let files = MemoryFiles::from([(name.to_owned(), src.as_bytes().to_vec())]);
+    let args = [name.to_owned(), OUT.to_owned()];
+    let out = run_rom_in(drifblim, &args, files, dir);
+    match out.files.get(OUT) {
+        Some(rom) if !rom.is_empty() && out.exit.unwrap_or(0) == 0 => Ok((rom.clone(), out)),
+        _ => Err(drif_err(name, &out)),
+    }
+}
+
+/// Disassembles a ROM with uxndis, returning its output
+pub fn uxndis_disassemble(rom: &[u8]) -> Result<String, AssemblerError> {
+    const IN: &str = "in.rom";
+    let files = MemoryFiles::from([(IN.to_owned(), rom.to_vec())]);
+    let out = run_rom(UXNDIS_ROM, &[IN.to_owned()], files);
+    if out.exit.unwrap_or(0) != 0 {
+        return Err(AssemblerError::Disassembly {
+            message: format!(
+                "uxndis failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        });
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
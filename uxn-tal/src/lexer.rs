@@ -111,6 +111,7 @@ pub struct Lexer {
     position_on_line: usize,
     next_conditional_sublabel: Option<String>,
     current_scope: Option<String>, // <-- Track current scope
+    quiet: bool,
 }
 
 impl Lexer {
@@ -125,9 +126,16 @@ impl Lexer {
             position_on_line: 1,
             next_conditional_sublabel: None,
             current_scope: None, // <-- Initialize
+            quiet: false,
         }
     }
 
+    /// Stops the lexer printing debug output
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
     /// Get the current line content for error reporting
     fn get_current_line(&self) -> String {
         let lines: Vec<&str> = self.input.lines().collect();
@@ -153,9 +161,21 @@ impl Lexer {
         self.line
     }
 
-    /// Tokenize the entire input
+    /// Tokenize the entire input, stopping at the first error
     pub fn tokenize(&mut self) -> Result<Vec<TokenWithPos>> {
+        let (tokens, mut errors) = self.tokenize_all();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Tokenize the entire input, skipping to the next whitespace after an
+    /// invalid token so that every error in the file is reported
+    pub fn tokenize_all(&mut self) -> (Vec<TokenWithPos>, Vec<AssemblerError>) {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
         while self.position < self.input.len() {
             let start_line = self.line;
@@ -185,7 +205,13 @@ impl Lexer {
                 let comment_start_line = self.line;
                 let comment_start_pos = self.position_on_line;
                 self.advance();
-                let comment = self.read_comment()?;
+                let comment = match self.read_comment() {
+                    Ok(comment) => comment,
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                };
                 let comment_end_pos = self.position_on_line;
                 tokens.push(TokenWithPos {
                     token: Token::Comment(comment.clone()),
@@ -198,7 +224,17 @@ impl Lexer {
             }
 
             // Get next token
-            match self.next_token()? {
+            let token = match self.next_token() {
+                Ok(token) => token,
+                Err(e) => {
+                    errors.push(e);
+                    while self.position < self.input.len() && !self.current_char().is_whitespace() {
+                        self.advance();
+                    }
+                    continue;
+                }
+            };
+            match token {
                 Token::Eof => break,
                 token => {
                     let token_end_position = self.position;
@@ -253,7 +289,7 @@ impl Lexer {
                 }
             }
         }
-        Ok(tokens)
+        (tokens, errors)
     }
 
     /// Move to the next character
@@ -837,8 +873,10 @@ impl Lexer {
             '~' => {
                 self.advance();
                 let filename = self.read_include_path()?;
-                println!("LEXER DEBUG: Include path: {:?}", self.path);
-                println!("LEXER DEBUG: Parsed include filename: ~{}", filename);
+                if !self.quiet {
+                    println!("LEXER DEBUG: Include path: {:?}", self.path);
+                    println!("LEXER DEBUG: Parsed include filename: ~{}", filename);
+                }
                 Ok(Token::Include(filename))
             }
             // '<' => {
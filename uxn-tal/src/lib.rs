@@ -65,19 +65,30 @@ pub mod bkend_uxn;
 pub mod bkend_uxn38;
 pub mod chocolatal;
 pub mod debug;
+pub mod debug_exec;
 pub mod devicemap;
+pub mod diagnostic;
 pub mod dis_uxndis;
 pub mod error;
+pub mod formatter;
 pub mod hexrev;
+pub mod inproc;
 pub mod lexer;
+pub mod lint;
+pub mod object;
 pub mod opcode_table;
 pub mod opcodes;
+pub mod optimizer;
 pub mod parser;
 pub mod rom;
 pub mod runes;
+pub mod source_map;
+pub mod stack_check;
 pub mod wsl;
 pub use assembler::Assembler;
+pub use diagnostic::{Diagnostic, Severity, Span};
 pub use error::AssemblerError;
+pub use source_map::SourceMap;
 pub mod emulator_utils;
 pub mod fetch;
 pub mod mode_basic;
@@ -154,6 +165,26 @@ pub fn assemble_file_with_symbols<P: AsRef<std::path::Path>>(
     Ok((rom_path, sym_path, rom.len()))
 }
 
+/// Convenience function to assemble a TAL file and generate both ROM and
+/// source map files, the map written next to the ROM as `<rom>.rom.map`
+pub fn assemble_file_with_source_map<P: AsRef<std::path::Path>>(
+    input_path: P,
+) -> Result<(std::path::PathBuf, std::path::PathBuf, usize), AssemblerError> {
+    let input_path = input_path.as_ref();
+    let source = std::fs::read_to_string(input_path)?;
+    let mut assembler = Assembler::new();
+    let path_str = input_path.to_string_lossy().into_owned();
+    let rom = assembler.assemble(&source, Some(path_str))?;
+
+    let rom_path = input_path.with_extension("rom");
+    std::fs::write(&rom_path, &rom)?;
+
+    let map_path = rom_path.with_extension("rom.map");
+    std::fs::write(&map_path, assembler.source_map.to_text())?;
+
+    Ok((rom_path, map_path, rom.len()))
+}
+
 /// Convenience function to batch process TAL files in a directory
 pub fn assemble_directory<P: AsRef<std::path::Path>>(
     dir_path: P,
@@ -194,48 +225,23 @@ pub fn generate_rust_interface_module(
     assembler: &crate::assembler::Assembler,
     module_name: &str,
 ) -> String {
+    let labels = label_extents(assembler);
     let mut out = String::new();
     out.push_str("#![allow(clippy::module_inception)]\n");
     out.push_str(&format!("pub mod {} {{\n", module_name));
     out.push_str("    #![allow(non_upper_case_globals)]\n");
     out.push_str("    // Auto-generated: label address & size constants\n");
     // Address and size constants
-    for name in &assembler.symbol_order {
-        if let Some(sym) = assembler.symbols.get(name) {
-            let id = {
-                let mut s: String = name
-                    .chars()
-                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
-                    .collect();
-                if s.chars()
-                    .next()
-                    .map(|c| c.is_ascii_digit())
-                    .unwrap_or(false)
-                {
-                    s.insert(0, '_');
-                }
-                s.to_ascii_uppercase()
-            };
-            out.push_str(&format!(
-                "    pub const _c{}: usize = 0x{:04X};\n",
-                id, sym.address
-            ));
-            // Compute size
-            let next_addr = assembler
-                .symbol_order
-                .iter()
-                .skip_while(|n| *n != name)
-                .skip(1)
-                .filter_map(|n| assembler.symbols.get(n))
-                .map(|s| s.address)
-                .find(|&a| a > sym.address)
-                .unwrap_or(assembler.effective_length as u16);
-            let size = next_addr.saturating_sub(sym.address);
-            out.push_str(&format!(
-                "    pub const _c{}_SIZE: usize = 0x{:04X};\n",
-                id, size
-            ));
-        }
+    for (name, address, size) in &labels {
+        let id = rust_identifier(name);
+        out.push_str(&format!(
+            "    pub const _c{}: usize = 0x{:04X};\n",
+            id, address
+        ));
+        out.push_str(&format!(
+            "    pub const _c{}_SIZE: usize = 0x{:04X};\n",
+            id, size
+        ));
     }
     // Helper function to get a slice for a label
     out.push_str(
@@ -245,27 +251,12 @@ pub fn generate_rust_interface_module(
         match label {
 "#,
     );
-    for name in &assembler.symbol_order {
-        if let Some(_sym) = assembler.symbols.get(name) {
-            let id = {
-                let mut s: String = name
-                    .chars()
-                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
-                    .collect();
-                if s.chars()
-                    .next()
-                    .map(|c| c.is_ascii_digit())
-                    .unwrap_or(false)
-                {
-                    s.insert(0, '_');
-                }
-                s.to_ascii_uppercase()
-            };
-            out.push_str(&format!(
-                "            \"{name}\" => Some(&ram[_c{}.._c{}+_c{}_SIZE]),\n",
-                id, id, id
-            ));
-        }
+    for (name, _, _) in &labels {
+        let id = rust_identifier(name);
+        out.push_str(&format!(
+            "            \"{name}\" => Some(&ram[_c{}.._c{}+_c{}_SIZE]),\n",
+            id, id, id
+        ));
     }
     out.push_str(
         r#"            _ => None,
@@ -277,6 +268,43 @@ pub fn generate_rust_interface_module(
     out
 }
 
+/// Turns a label into the constant name [`generate_rust_interface_module`]
+/// uses for it, e.g. `on-reset/loop` into `ON_RESET_LOOP`
+pub fn rust_identifier(label: &str) -> String {
+    let mut s: String = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if s.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    s.to_ascii_uppercase()
+}
+
+/// Every label with its address and size, in definition order
+///
+/// A label's size runs up to the next label defined after it at a higher
+/// address, or to the end of the ROM.
+pub fn label_extents(assembler: &crate::assembler::Assembler) -> Vec<(&str, u16, u16)> {
+    let defined: Vec<(&str, u16)> = assembler
+        .symbol_order
+        .iter()
+        .filter_map(|name| Some((name.as_str(), assembler.symbols.get(name)?.address)))
+        .collect();
+    defined
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, address))| {
+            let next = defined[i + 1..]
+                .iter()
+                .map(|&(_, a)| a)
+                .find(|&a| a > address)
+                .unwrap_or(assembler.effective_length as u16);
+            (name, address, next.saturating_sub(address))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
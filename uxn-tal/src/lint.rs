@@ -0,0 +1,364 @@
+//! Lint pass
+//!
+//! Assembles a file and reports likely mistakes that still assemble:
+//!
+//! - labels and macros that are never referenced,
+//! - code after `BRK`, `JMP2`, `JMP2r` or `!` with no label in between,
+//! - macros defined twice, or named like a label,
+//! - `|` padding that moves back over bytes already written,
+//! - zero-page labels used both as device ports (`DEI`/`DEO`) and as
+//!   variables (`LDZ`/`STZ`),
+//! - zero-page variables laid out among the devices, such as
+//!   `|10 @counter $1` in the Console's page: labels after a two-digit `|`
+//!   padding are taken to be device ports, those after `|0000` to be
+//!   memory.
+//!
+//! Only definitions in the file itself are checked; labels in `~include`d
+//! files still count as references.
+use std::collections::{HashMap, HashSet};
+
+use crate::assembler::Assembler;
+use crate::devicemap::DEVICES_DEFAULT;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::lexer::{Lexer, Token, TokenWithPos};
+use crate::parser::{AstNode, Parser};
+
+/// Lints `source`, returning warnings, or the assembler's errors if it
+/// doesn't assemble
+pub fn lint(source: &str, path: Option<&str>) -> Vec<Diagnostic> {
+    let path = path.unwrap_or_default();
+    let mut asm = Assembler::new();
+    asm.quiet = true;
+    if let Err(errors) = asm.assemble_with_diagnostics(source, Some(path.to_owned())) {
+        return errors;
+    }
+    let (tokens, _) = Lexer::new(source.to_owned(), Some(path.to_owned()))
+        .with_quiet(true)
+        .tokenize_all();
+    let mut parser = Parser::new_with_source(tokens.clone(), path.to_owned(), source.to_owned())
+        .with_quiet(true);
+    let (ast, _) = parser.parse_all();
+
+    let mut lint = Lint {
+        source,
+        path,
+        asm: &asm,
+        warnings: Vec::new(),
+    };
+    lint.unused_labels(&tokens);
+    lint.macros(&tokens);
+    lint.unreachable(&ast, parser.node_tokens());
+    lint.overlaps();
+    lint.zero_page(&ast, parser.node_tokens());
+    lint.warnings
+        .sort_by_key(|d| d.span.as_ref().map(|s| (s.line, s.column)));
+    lint.warnings
+}
+
+struct Lint<'a> {
+    source: &'a str,
+    path: &'a str,
+    asm: &'a Assembler,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Lint<'_> {
+    fn warn(&mut self, tok: &TokenWithPos, message: String) {
+        let line = self
+            .source
+            .lines()
+            .nth(tok.line.saturating_sub(1))
+            .unwrap_or_default();
+        self.warnings.push(Diagnostic::warning(message).at(
+            self.path,
+            tok.line,
+            tok.start_pos,
+            line,
+        ));
+    }
+
+    fn warn_at(&mut self, span: &Span, message: String) {
+        let line = self
+            .asm
+            .sources
+            .get(&span.path)
+            .and_then(|s| s.lines().nth(span.line.saturating_sub(1)))
+            .unwrap_or_default();
+        self.warnings.push(Diagnostic::warning(message).at(
+            &span.path,
+            span.line,
+            span.column,
+            line,
+        ));
+    }
+
+    /// Labels no reference resolves to
+    ///
+    /// Not reported: the label at the reset vector, device ports (page-zero
+    /// labels under a capitalized parent, as in `@Console/write`), labels
+    /// with a referenced sublabel, and labels named by `|` or `$` padding.
+    fn unused_labels(&mut self, tokens: &[TokenWithPos]) {
+        let padded: Vec<&str> = tokens
+            .iter()
+            .filter_map(|t| match &t.token {
+                Token::PaddingLabel(l) | Token::RelativePaddingLabel(l) => Some(l.as_str()),
+                _ => None,
+            })
+            .collect();
+        let mut scope = String::new();
+        for t in tokens {
+            let name = match &t.token {
+                Token::LabelDef(_, name) => {
+                    scope = name.split('/').next().unwrap_or_default().to_owned();
+                    name.clone()
+                }
+                Token::SublabelDef(sub) if !sub.is_empty() => format!("{scope}/{sub}"),
+                _ => continue,
+            };
+            let Some(symbol) = self.asm.symbols.get(&name) else {
+                continue;
+            };
+            let parent = name.split('/').next().unwrap_or_default();
+            let prefix = format!("{name}/");
+            let used = self.asm.used_symbols.contains(&name)
+                || self.asm.used_symbols.iter().any(|u| u.starts_with(&prefix))
+                || padded.contains(&name.as_str())
+                || symbol.address == 0x0100
+                || (symbol.address < 0x0100 && parent.starts_with(char::is_uppercase))
+                || name.starts_with('λ');
+            if !used {
+                self.warn(t, format!("unused label: {name}"));
+            }
+        }
+    }
+
+    /// Unused, redefined and shadowing macros
+    fn macros(&mut self, tokens: &[TokenWithPos]) {
+        let mut defined: HashMap<&str, &TokenWithPos> = HashMap::new();
+        for (i, t) in tokens.iter().enumerate() {
+            let Token::MacroDef(name) = &t.token else {
+                continue;
+            };
+            if let Some(first) = defined.get(name.as_str()) {
+                let line = first.line;
+                self.warn(t, format!("macro {name} redefines the one on line {line}"));
+            } else {
+                defined.insert(name, t);
+            }
+            if self.asm.symbols.contains_key(name) {
+                self.warn(
+                    t,
+                    format!("macro {name} shadows the label of the same name"),
+                );
+            }
+            let used = tokens.iter().enumerate().any(|(j, u)| {
+                j != i && matches!(&u.token, Token::Word(w) | Token::LabelRef(_, w) if w == name)
+            });
+            if !used {
+                self.warn(t, format!("unused macro: {name}"));
+            }
+        }
+    }
+
+    /// Code following an unconditional jump before any label
+    fn unreachable(&mut self, ast: &[AstNode], tokens: &[TokenWithPos]) {
+        let mut after: Option<&TokenWithPos> = None;
+        // A byte `JMP` or `JCN` is usually a computed skip over the next
+        // instruction, as in `flag JMP JMP2r`
+        let mut skip = false;
+        for (node, tok) in ast.iter().zip(tokens) {
+            match node {
+                AstNode::LabelDef(..)
+                | AstNode::SublabelDef(_)
+                | AstNode::ConditionalBlockEnd(_)
+                | AstNode::LambdaEnd(_)
+                | AstNode::Padding(_)
+                | AstNode::PaddingLabel(_)
+                | AstNode::RelativePadding(_)
+                | AstNode::RelativePaddingLabel(_)
+                | AstNode::Include(_) => after = None,
+                // Data is commonly placed after a routine
+                AstNode::Byte(_)
+                | AstNode::Short(_)
+                | AstNode::RawString(_)
+                | AstNode::MacroDef(..)
+                | AstNode::Ignored
+                | AstNode::Eof => {}
+                _ => {
+                    if let Some(end) = after.take() {
+                        let line = end.line;
+                        self.warn(
+                            tok,
+                            format!("unreachable code after the jump on line {line}"),
+                        );
+                        continue;
+                    }
+                    let ends = !skip
+                        && match node {
+                            AstNode::Instruction(i) => {
+                                i.opcode == "BRK"
+                                    || (i.opcode == "JMP" && i.short_mode && !i.keep_mode)
+                            }
+                            AstNode::ExclamationRef(_) => true,
+                            _ => false,
+                        };
+                    skip = match node {
+                        AstNode::Instruction(i) => {
+                            !i.short_mode && matches!(i.opcode.as_str(), "JMP" | "JCN")
+                        }
+                        _ => false,
+                    };
+                    if ends {
+                        after = Some(tok);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bytes written twice, e.g. when code runs past the next `|` padding
+    fn overlaps(&mut self) {
+        let entries = &self.asm.source_map.entries;
+        let mut owner: Vec<Option<usize>> = vec![None; 0x10000];
+        let mut in_run = false;
+        for (i, e) in entries.iter().enumerate() {
+            let range = e.address as usize..(e.address as usize + e.len as usize).min(0x10000);
+            let earlier = owner[range.clone()].iter().flatten().next().copied();
+            match earlier {
+                Some(j) if !in_run => {
+                    in_run = true;
+                    let first = &entries[j].span;
+                    let msg = format!(
+                        "bytes at {:04x} overwrite those written by {}:{}",
+                        e.address, first.path, first.line
+                    );
+                    let span = e.span.clone();
+                    self.warn_at(&span, msg);
+                }
+                Some(_) => {}
+                None => in_run = false,
+            }
+            owner[range].fill(Some(i));
+        }
+    }
+
+    /// Page-zero labels used as both device ports and memory, and variables
+    /// declared in a device's page
+    fn zero_page(&mut self, ast: &[AstNode], tokens: &[TokenWithPos]) {
+        // label -> (first port access, first memory access)
+        type Uses<'t> = (Option<&'t TokenWithPos>, Option<&'t TokenWithPos>);
+        let mut uses: HashMap<String, Uses> = HashMap::new();
+        let mut order = Vec::new();
+        // label -> widest memory access, in bytes
+        let mut widths: HashMap<String, u16> = HashMap::new();
+        // labels declared after device-style `|xx` padding
+        let mut in_devices = HashSet::new();
+        let mut device_padding = false;
+        let mut scope = "";
+        for (k, node) in ast.iter().enumerate() {
+            match node {
+                AstNode::LabelDef(_, name) => {
+                    scope = name.split('/').next().unwrap_or_default();
+                    if device_padding {
+                        in_devices.insert(name.as_str());
+                    }
+                }
+                AstNode::Padding(_) => {
+                    device_padding = tokens[k].end_pos - tokens[k].start_pos <= 2;
+                }
+                AstNode::PaddingLabel(_) => device_padding = false,
+                _ => {}
+            }
+            let (AstNode::DotRef(t), Some(AstNode::Instruction(next))) = (node, ast.get(k + 1))
+            else {
+                continue;
+            };
+            let Token::DotRef(raw) = &t.token else {
+                continue;
+            };
+            let Some(name) = self.resolve(raw, scope) else {
+                continue;
+            };
+            if matches!(next.opcode.as_str(), "LDZ" | "STZ") {
+                let width = widths.entry(name.clone()).or_default();
+                *width = (*width).max(if next.short_mode { 2 } else { 1 });
+            }
+            let entry = uses.entry(name.clone()).or_insert_with(|| {
+                order.push(name);
+                (None, None)
+            });
+            match next.opcode.as_str() {
+                "DEI" | "DEO" => entry.0 = entry.0.or(Some(&tokens[k])),
+                "LDZ" | "STZ" => entry.1 = entry.1.or(Some(&tokens[k])),
+                _ => {}
+            }
+        }
+        for name in &order {
+            let (port, Some(memory)) = uses[name] else {
+                continue;
+            };
+            // Labels used as ports too are reported below
+            if port.is_some() || !in_devices.contains(name.as_str()) {
+                continue;
+            }
+            let start = self.asm.symbols[name].address;
+            let end = (start + widths[name] - 1).min(0xff);
+            let device = (start & 0xf0..=end & 0xf0)
+                .step_by(0x10)
+                .find_map(|p| Some((p, self.device_name(p, &widths)?)));
+            if let Some((page, device)) = device {
+                let msg = format!(
+                    "zero-page variable {name} ({start:02x}-{end:02x}) overlaps the {device} device page ({page:02x}-{:02x})",
+                    page | 0x0f
+                );
+                self.warn(memory, msg);
+            }
+        }
+        for name in order {
+            if let (Some(port), Some(memory)) = uses[&name] {
+                let (first, second) = if port.line <= memory.line {
+                    (port, memory)
+                } else {
+                    (memory, port)
+                };
+                let msg = format!(
+                    "zero-page label {name} is used both as a device port and as a variable (line {})",
+                    first.line
+                );
+                self.warn(second, msg);
+            }
+        }
+    }
+
+    /// Name of the device at `page`, as declared or from the defaults,
+    /// ignoring labels used as `variables`
+    fn device_name(&self, page: u16, variables: &HashMap<String, u16>) -> Option<String> {
+        let declared = self
+            .asm
+            .symbols
+            .iter()
+            .filter(|(name, s)| {
+                s.address == page && !name.contains('/') && !variables.contains_key(*name)
+            })
+            .map(|(name, _)| name.clone())
+            .min();
+        declared.or_else(|| {
+            DEVICES_DEFAULT
+                .iter()
+                .find(|d| d.address == page)
+                .map(|d| d.name.clone())
+        })
+    }
+
+    /// Resolves a `.` reference to a zero-page symbol name
+    fn resolve(&self, raw: &str, scope: &str) -> Option<String> {
+        let scoped = |sub: &str| format!("{scope}/{sub}");
+        let name = match raw.strip_prefix(['&', '/']) {
+            Some(sub) => scoped(sub),
+            None if self.asm.symbols.contains_key(raw) => raw.to_owned(),
+            None => scoped(raw),
+        };
+        let symbol = self.asm.symbols.get(&name)?;
+        (symbol.address < 0x0100).then_some(name)
+    }
+}
@@ -0,0 +1,400 @@
+//! Relocatable object files and the linker
+//!
+//! `uxntal -c lib.tal` assembles one unit of a program into `lib.tao`
+//! instead of a ROM. References to labels the unit doesn't define are left
+//! as relocations, and `uxntal link main.tao lib.tao` fills them in to
+//! produce the ROM and its `.sym`, so a change to one unit only reassembles
+//! that unit.
+//!
+//! Every unit starts at `0100`, and is moved to the end of the objects
+//! before it in command-line order, just as if it had been `~include`d
+//! there, so the one with the reset vector goes first. A unit that pads to
+//! an absolute address further into page memory is pinned instead, and
+//! the others are placed after it; at most one object may be pinned.
+//!
+//! Macros are not exported, so share them by `~include`ing the same header
+//! in each unit; labels that several units define at the same address, like
+//! the devices in such a header, are merged. The optimizer doesn't run on
+//! objects, as it can't tell which routines other units call.
+//!
+//! The text form has one item per line:
+//!
+//! ```text
+//! uxntal-object 1
+//! size 000c
+//! code 0100 a0ffff60ffff80ff0c40fffa
+//! label 0100 on-reset
+//! label 0106 on-reset/loop
+//! reloc 0101 ; text
+//! reloc 0104 ! print
+//! reloc 0107 , next on-reset
+//! ```
+//!
+//! `size` is how many bytes the unit spans, including trailing padding that
+//! is left out of the ROM, and `code` lines hold the bytes from the given
+//! address. `label` lines list every label the unit defines, and `reloc`
+//! lines the references the linker patches: to the named label, looked up
+//! in the given scope first like the assembler does for `,` and `_`, or
+//! without a name, to a label of the unit's own that moves along with it.
+use crate::assembler::Assembler;
+use crate::error::{AssemblerError, Result};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const PAGE: usize = 0x0100;
+
+/// How a reference is patched, named after the rune that makes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocKind {
+    /// `.` and `-`: the low byte of the address
+    ZeroPage,
+    /// `,` and `_`: a signed byte offset
+    Relative,
+    /// `;` and `=`: the absolute address
+    Absolute,
+    /// `!`, `?` and bare calls: a signed short offset
+    Jump,
+}
+
+impl RelocKind {
+    pub fn from_rune(rune: char) -> Option<Self> {
+        match rune {
+            '.' | '-' => Some(Self::ZeroPage),
+            ',' | '_' => Some(Self::Relative),
+            ';' | '=' | ':' => Some(Self::Absolute),
+            '!' | '?' | ' ' | '/' => Some(Self::Jump),
+            _ => None,
+        }
+    }
+
+    /// The rune this kind is written as in the text form
+    pub fn rune(self) -> char {
+        match self {
+            Self::ZeroPage => '.',
+            Self::Relative => ',',
+            Self::Absolute => ';',
+            Self::Jump => '!',
+        }
+    }
+
+    /// Number of bytes the reference takes
+    fn width(self) -> usize {
+        match self {
+            Self::ZeroPage | Self::Relative => 1,
+            Self::Absolute | Self::Jump => 2,
+        }
+    }
+
+    /// Writes the reference at `at` to `target`, as `second_pass` does
+    fn patch(self, memory: &mut [u8], at: usize, target: u16) -> std::result::Result<(), String> {
+        let rel = target as i32 - at as i32 - 2;
+        match self {
+            Self::ZeroPage => memory[at] = target as u8,
+            Self::Relative => {
+                if rel != rel as i8 as i32 {
+                    return Err("Reference too far".to_string());
+                }
+                memory[at] = rel as u8;
+            }
+            Self::Absolute => memory[at..at + 2].copy_from_slice(&target.to_be_bytes()),
+            Self::Jump => memory[at..at + 2].copy_from_slice(&(rel as u16).to_be_bytes()),
+        }
+        Ok(())
+    }
+}
+
+/// A reference for the linker to patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub address: u16,
+    pub kind: RelocKind,
+    /// Label in another object, or `None` for a reference to this one's
+    /// own labels
+    pub label: Option<String>,
+    /// Enclosing label, for `,` and `_` references
+    pub scope: Option<String>,
+}
+
+/// One assembled unit of a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Object {
+    /// Pads to an absolute address past 0x0100, so can't be moved
+    pub pinned: bool,
+    /// Bytes from 0x0100 that belong in the ROM
+    pub code: Vec<u8>,
+    /// Bytes spanned from 0x0100, including trailing padding
+    pub size: usize,
+    /// Every label defined, in definition order
+    pub labels: Vec<(String, u16)>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Object {
+    /// Assembles `source` as one unit, leaving labels it doesn't define to
+    /// the linker
+    pub fn assemble(source: &str, path: Option<String>) -> Result<Self> {
+        let mut asm = Assembler::new();
+        asm.relocatable = true;
+        asm.assemble(source, path)?;
+        Ok(Self::from_assembler(&asm))
+    }
+
+    /// Collects the object from an assembler that has just assembled a unit
+    /// with `relocatable` set
+    pub fn from_assembler(asm: &Assembler) -> Self {
+        let extent = asm.rom.extent();
+        let len = asm.effective_length.saturating_sub(PAGE).min(extent.len());
+        Self {
+            pinned: asm.pinned,
+            code: extent[..len].to_vec(),
+            size: extent.len(),
+            labels: asm
+                .symbol_order
+                .iter()
+                .filter_map(|name| Some((name.clone(), asm.symbols.get(name)?.address)))
+                .collect(),
+            relocations: asm.relocations.clone(),
+        }
+    }
+
+    /// Formats the object as text
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("uxntal-object 1\n");
+        if self.pinned {
+            out.push_str("pinned\n");
+        }
+        let _ = writeln!(out, "size {:04x}", self.size);
+        for (i, chunk) in self.code.chunks(32).enumerate() {
+            let _ = write!(out, "code {:04x} ", PAGE + i * 32);
+            for byte in chunk {
+                let _ = write!(out, "{byte:02x}");
+            }
+            out.push('\n');
+        }
+        for (name, address) in &self.labels {
+            let _ = writeln!(out, "label {address:04x} {name}");
+        }
+        for r in &self.relocations {
+            let _ = write!(out, "reloc {:04x} {}", r.address, r.kind.rune());
+            for name in r.label.iter().chain(&r.scope) {
+                let _ = write!(out, " {name}");
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Parses the text form written by [`Object::to_text`]
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        if lines.next()? != "uxntal-object 1" {
+            return None;
+        }
+        let mut object = Self::default();
+        for line in lines {
+            let mut fields = line.split(' ');
+            match fields.next()? {
+                "pinned" => object.pinned = true,
+                "size" => object.size = u16::from_str_radix(fields.next()?, 16).ok()?.into(),
+                "code" => {
+                    let at = usize::from(u16::from_str_radix(fields.next()?, 16).ok()?)
+                        .checked_sub(PAGE)?;
+                    let hex = fields.next()?;
+                    if hex.len() % 2 != 0 {
+                        return None;
+                    }
+                    let bytes = (0..hex.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                        .collect::<Option<Vec<u8>>>()?;
+                    if object.code.len() < at + bytes.len() {
+                        object.code.resize(at + bytes.len(), 0);
+                    }
+                    object.code[at..at + bytes.len()].copy_from_slice(&bytes);
+                }
+                "label" => {
+                    let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+                    object.labels.push((fields.next()?.to_owned(), address));
+                }
+                "reloc" => {
+                    let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+                    let mut rune = fields.next()?.chars();
+                    let kind =
+                        RelocKind::from_rune(rune.next()?).filter(|_| rune.next().is_none())?;
+                    object.relocations.push(Relocation {
+                        address,
+                        kind,
+                        label: fields.next().map(str::to_owned),
+                        scope: fields.next().map(str::to_owned),
+                    });
+                }
+                _ => return None,
+            }
+        }
+        Some(object)
+    }
+
+    /// Checks that the code, labels and relocations lie within the object,
+    /// so that a damaged object file can't make the linker write outside it
+    fn validate(&self) -> std::result::Result<(), String> {
+        let end = PAGE + self.size;
+        if self.code.len() > self.size {
+            return Err(format!(
+                "code runs to {:04x}, past the object's size {:04x}",
+                PAGE + self.code.len(),
+                self.size
+            ));
+        }
+        for (name, address) in &self.labels {
+            if usize::from(*address) > end {
+                return Err(format!(
+                    "label \"{name}\" at {address:04x} is outside the object"
+                ));
+            }
+        }
+        for r in &self.relocations {
+            let at = usize::from(r.address);
+            if at < PAGE || at + r.kind.width() > end {
+                return Err(format!(
+                    "reference at {:04x} is outside the object",
+                    r.address
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The result of linking
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Linked {
+    pub rom: Vec<u8>,
+    /// Every label at its final address, in link order
+    pub labels: Vec<(String, u16)>,
+}
+
+impl Linked {
+    /// The `.sym` file, in the format of [`Assembler::generate_symbol_file`]
+    pub fn symbol_file(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, address) in self.labels.iter().filter(|(_, a)| *a >= 0x0100) {
+            out.extend_from_slice(&address.to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+        }
+        out
+    }
+}
+
+/// Places the objects, named by path for error messages, and resolves
+/// every relocation
+pub fn link(objects: &[(String, Object)]) -> Result<Linked> {
+    let link_err = |path: &str, message: String| AssemblerError::Link {
+        path: path.to_owned(),
+        message,
+    };
+    for (path, object) in objects {
+        object
+            .validate()
+            .map_err(|message| link_err(path, message))?;
+    }
+    let mut pinned = objects.iter().filter(|(_, o)| o.pinned);
+    let first = pinned.next();
+    if let (Some((a, _)), Some((b, _))) = (first, pinned.next()) {
+        return Err(link_err(
+            b,
+            format!("both {a} and {b} are pinned to absolute addresses; only one object may be"),
+        ));
+    }
+
+    // Offset each object is moved by
+    let mut end = PAGE + first.map_or(0, |(_, o)| o.size);
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (_, object) in objects {
+        if object.pinned {
+            offsets.push(0);
+        } else {
+            offsets.push(end - PAGE);
+            end += object.size;
+        }
+    }
+    if end > 0x10000 {
+        return Err(AssemblerError::RomTooLarge { size: end - PAGE });
+    }
+
+    let mut linked = Linked::default();
+    let mut defined: HashMap<&str, (u16, &str)> = HashMap::new();
+    for ((path, object), &offset) in objects.iter().zip(&offsets) {
+        for (name, address) in &object.labels {
+            let address = if *address >= 0x0100 {
+                address.wrapping_add(offset as u16)
+            } else {
+                *address
+            };
+            linked.labels.push((name.clone(), address));
+            // Lambdas are local to their object
+            if name.starts_with('λ') {
+                continue;
+            }
+            match defined.get(name.as_str()) {
+                // The same device or zero-page label from a shared header
+                Some((other, _)) if *other == address => {}
+                Some((_, other_path)) => {
+                    return Err(link_err(
+                        path,
+                        format!("label \"{name}\" is already defined in {other_path}"),
+                    ))
+                }
+                None => {
+                    defined.insert(name, (address, path));
+                }
+            }
+        }
+    }
+
+    let mut memory = vec![0u8; 0x10000];
+    let mut len = 0;
+    for ((_, object), offset) in objects.iter().zip(&offsets) {
+        let start = PAGE + offset;
+        memory[start..start + object.code.len()].copy_from_slice(&object.code);
+        if !object.code.is_empty() {
+            len = len.max(start + object.code.len());
+        }
+    }
+    for ((path, object), &offset) in objects.iter().zip(&offsets) {
+        for r in &object.relocations {
+            let at = r.address as usize + offset;
+            let target = match &r.label {
+                Some(name) => match lookup(&defined, name, r.scope.as_deref()) {
+                    Some(address) => address,
+                    None => return Err(link_err(path, format!("Label unknown: \"{name}\""))),
+                },
+                // Only the low byte of a zero-page reference is kept, but
+                // that's all the low byte of the sum depends on
+                None if r.kind == RelocKind::ZeroPage => memory[at] as u16 + offset as u16,
+                None => {
+                    u16::from_be_bytes([memory[at], memory[at + 1]]).wrapping_add(offset as u16)
+                }
+            };
+            let name = r.label.as_deref().unwrap_or_default();
+            r.kind
+                .patch(&mut memory, at, target)
+                .map_err(|message| link_err(path, format!("{message}: \"{name}\"")))?;
+        }
+    }
+
+    linked.rom = memory[PAGE..len.max(PAGE)].to_vec();
+    Ok(linked)
+}
+
+/// Finds `name`, trying it within each enclosing label of `scope` first
+fn lookup(defined: &HashMap<&str, (u16, &str)>, name: &str, scope: Option<&str>) -> Option<u16> {
+    let mut scope = scope;
+    while let Some(s) = scope {
+        if let Some((address, _)) = defined.get(format!("{s}/{name}").as_str()) {
+            return Some(*address);
+        }
+        scope = s.rfind('/').map(|i| &s[..i]);
+    }
+    defined.get(name).map(|(address, _)| *address)
+}
@@ -301,6 +301,67 @@ pub const BASE_INSTRUCTIONS: &[&str] = &[
     "ADD", "SUB", "MUL", "DIV", "AND", "ORA", "EOR", "SFT", // 0x18-0x1F
 ];
 
+/// Stack effect of each base instruction on the stack it operates on, as
+/// `(inputs, outputs)` where each side is `(items, bytes)`: items take the
+/// width of the short mode, bytes are always single bytes (addresses,
+/// ports, shift amounts and flags)
+///
+/// Values moved to the other stack (JSR, STH) are not counted.
+const STACK_EFFECTS: [((u8, u8), (u8, u8)); 32] = [
+    ((0, 0), (0, 0)), // BRK
+    ((1, 0), (1, 0)), // INC
+    ((1, 0), (0, 0)), // POP
+    ((2, 0), (1, 0)), // NIP
+    ((2, 0), (2, 0)), // SWP
+    ((3, 0), (3, 0)), // ROT
+    ((1, 0), (2, 0)), // DUP
+    ((2, 0), (3, 0)), // OVR
+    ((2, 0), (0, 1)), // EQU
+    ((2, 0), (0, 1)), // NEQ
+    ((2, 0), (0, 1)), // GTH
+    ((2, 0), (0, 1)), // LTH
+    ((1, 0), (0, 0)), // JMP
+    ((1, 1), (0, 0)), // JCN
+    ((1, 0), (0, 0)), // JSR
+    ((1, 0), (0, 0)), // STH
+    ((0, 1), (1, 0)), // LDZ
+    ((1, 1), (0, 0)), // STZ
+    ((0, 1), (1, 0)), // LDR
+    ((1, 1), (0, 0)), // STR
+    ((0, 2), (1, 0)), // LDA
+    ((1, 2), (0, 0)), // STA
+    ((0, 1), (1, 0)), // DEI
+    ((1, 1), (0, 0)), // DEO
+    ((2, 0), (1, 0)), // ADD
+    ((2, 0), (1, 0)), // SUB
+    ((2, 0), (1, 0)), // MUL
+    ((2, 0), (1, 0)), // DIV
+    ((2, 0), (1, 0)), // AND
+    ((2, 0), (1, 0)), // ORA
+    ((2, 0), (1, 0)), // EOR
+    ((1, 1), (1, 0)), // SFT
+];
+
+/// Number of bytes an opcode takes from and puts back on the stack it
+/// operates on, before the keep mode puts its inputs back
+///
+/// LIT and its variants push their operand; the immediate jumps
+/// (JCI, JMI, JSI) read their operand from memory instead.
+pub fn stack_effect(opcode: u8) -> (u8, u8) {
+    let (base, short_mode, _, keep_mode) = decode_opcode(opcode);
+    let width = if short_mode { 2 } else { 1 };
+    match (base, keep_mode) {
+        (0x00, true) => (0, width),
+        // JCI takes its condition
+        (0x00, false) if short_mode && opcode & 0x40 == 0 => (1, 0),
+        (0x00, false) => (0, 0),
+        _ => {
+            let ((ins, in_bytes), (outs, out_bytes)) = STACK_EFFECTS[base as usize];
+            (ins * width + in_bytes, outs * width + out_bytes)
+        }
+    }
+}
+
 /// Create a hashmap from instruction name to base opcode value
 pub fn create_instruction_map() -> HashMap<String, u8> {
     let mut map = HashMap::new();
@@ -395,6 +456,18 @@ mod tests {
         verify_opcode_table().unwrap();
     }
 
+    #[test]
+    fn test_stack_effect() {
+        assert_eq!(stack_effect(0x80), (0, 1)); // LIT
+        assert_eq!(stack_effect(0xA0), (0, 2)); // LIT2
+        assert_eq!(stack_effect(0x20), (1, 0)); // JCI
+        assert_eq!(stack_effect(0x38), (4, 2)); // ADD2
+        assert_eq!(stack_effect(0xA8), (4, 1)); // EQU2k
+        assert_eq!(stack_effect(0x35), (4, 0)); // STA2
+        assert_eq!(stack_effect(0x3F), (3, 2)); // SFT2
+        assert_eq!(stack_effect(0x71), (3, 0)); // STZ2r
+    }
+
     #[test]
     fn test_opcode_encoding() {
         // Test LIT (0x80)
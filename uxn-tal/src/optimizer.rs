@@ -0,0 +1,429 @@
+//! Peephole optimizer
+//!
+//! An opt-in pass (`uxntal -O`) that rewrites each file's parsed nodes
+//! before they are laid out, so labels, the `.sym` file and the source map
+//! all describe the optimized ROM. Rewritten nodes keep the position of the
+//! first node they replace. The rewrites are:
+//!
+//! - runs of `#` literals and the stack operations applied to them are
+//!   evaluated at assembly time and pushed again in as few bytes as
+//!   possible: `#02 #03 ADD` becomes `#05`, `#0012 NIP` becomes `#12`, and
+//!   `#12 #34` becomes `#1234`,
+//! - a call directly followed by `JMP2r` becomes a tail jump (`!routine`),
+//! - code after `BRK`, `JMP2`, `JMP2r` or `!` that no label leads to is
+//!   removed,
+//! - in a program with a `|0100` reset vector, top-level routines that are
+//!   only referenced from inside themselves, and that the code before them
+//!   can't run into, are removed. A library has no reset vector, so all of
+//!   its routines are kept. Only
+//!   labels followed by code count as routines; unreferenced data such as
+//!   `@message "Hello` is kept, since it may be reached by address
+//!   arithmetic.
+//!
+//! Macro bodies are left alone. Tail calls assume the callee doesn't look
+//! at its own return address, as routines that read inline data after the
+//! call do.
+use std::collections::HashSet;
+
+use crate::assembler::Assembler;
+use crate::lexer::{Token, TokenWithPos};
+use crate::parser::{AstNode, Instruction};
+use crate::runes::Rune;
+
+/// What one assembly of the program tells the optimizer
+#[derive(Debug, Clone, Default)]
+pub struct Facts {
+    /// Macro names; a bare word naming one is not a call
+    pub macros: HashSet<String>,
+    /// Top-level labels nothing outside their own routine refers to
+    pub unused: HashSet<String>,
+}
+
+impl Facts {
+    /// Adds what a finished assembly shows, returning whether any new
+    /// routine can be removed
+    pub(crate) fn learn(&mut self, asm: &Assembler) -> bool {
+        self.macros.extend(asm.macros.keys().cloned());
+        if !asm.reset {
+            return false;
+        }
+        let mut routines: Vec<(u16, &str)> = asm
+            .symbols
+            .iter()
+            .filter(|(name, s)| s.address >= 0x0100 && !name.contains('/'))
+            .filter(|(name, _)| !name.starts_with('λ'))
+            .map(|(name, s)| (s.address, name.as_str()))
+            .collect();
+        routines.sort();
+        let end = asm.effective_length.max(0x0100) as u32;
+        let mut found = false;
+        for &(start, name) in &routines {
+            if start == 0x0100 || self.unused.contains(name) {
+                continue;
+            }
+            let stop = routines
+                .iter()
+                .map(|&(a, _)| a as u32)
+                .find(|&a| a > start as u32)
+                .unwrap_or(end);
+            let prefix = format!("{name}/");
+            let outside = asm.reference_targets.iter().any(|(site, target)| {
+                (target == name || target.starts_with(&prefix))
+                    && !(start as u32..stop).contains(&(*site as u32))
+            });
+            if !outside {
+                self.unused.insert(name.to_owned());
+                found = true;
+            }
+        }
+        found
+    }
+}
+
+/// Tracks whether execution can run past the nodes emitted so far
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Flow {
+    pub falls: bool,
+    /// The last node was a byte `JMP` or `JCN`, which may skip the next one
+    skip: bool,
+}
+
+impl Flow {
+    pub fn step(&mut self, node: &AstNode) {
+        let skip = std::mem::take(&mut self.skip);
+        match node {
+            AstNode::Instruction(i) if i.opcode != "LIT" => {
+                self.falls = skip || !ends(i);
+                self.skip = !i.short_mode && matches!(i.opcode.as_str(), "JMP" | "JCN");
+            }
+            AstNode::ExclamationRef(t) if !is_lambda(t) => self.falls = skip,
+            AstNode::Padding(_) | AstNode::PaddingLabel(_) => self.falls = false,
+            AstNode::Include(_) => self.falls = true,
+            n if is_code(n) => self.falls = true,
+            _ => {}
+        }
+    }
+}
+
+type Node = (AstNode, TokenWithPos);
+
+/// Optimizes one file's nodes; `falls_in` says whether the code before
+/// them can run into them
+pub(crate) fn optimize(
+    ast: &[AstNode],
+    tokens: &[TokenWithPos],
+    facts: &Facts,
+    falls_in: bool,
+) -> (Vec<AstNode>, Vec<TokenWithPos>) {
+    let nodes: Vec<Node> = ast.iter().cloned().zip(tokens.iter().cloned()).collect();
+    let nodes = tail_calls(nodes, facts);
+    let nodes = fold_literals(nodes);
+    let nodes = unreachable_code(nodes);
+    let nodes = unused_routines(nodes, facts, falls_in);
+    nodes.into_iter().unzip()
+}
+
+/// `routine JMP2r` and `;routine JSR2 JMP2r` become `!routine`
+fn tail_calls(nodes: Vec<Node>, facts: &Facts) -> Vec<Node> {
+    let mut out: Vec<Node> = Vec::with_capacity(nodes.len());
+    for (node, tok) in nodes {
+        let is_return = matches!(&node, AstNode::Instruction(i) if is_op(i, "JMP", true, true));
+        let target = match out.as_slice() {
+            [.., (
+                AstNode::LabelRef {
+                    label,
+                    rune: Rune::None,
+                    ..
+                },
+                t,
+            )] if is_return && !facts.macros.contains(label) && label != "{" => {
+                Some((1, label.clone(), t.clone()))
+            }
+            [.., (AstNode::SemicolonRef(t), _), (AstNode::Instruction(jsr), _)]
+                if is_return && is_op(jsr, "JSR", true, false) && !is_lambda(t) =>
+            {
+                match &t.token {
+                    Token::SemicolonRef(label) => Some((2, label.clone(), t.clone())),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match target {
+            Some((len, label, mut t)) => {
+                out.truncate(out.len() - len);
+                t.token = Token::ExclamationRef(label);
+                out.push((AstNode::ExclamationRef(t.clone()), t));
+            }
+            None => out.push((node, tok)),
+        }
+    }
+    out
+}
+
+/// Evaluates literal runs, keeping whichever prefix of each run saves the
+/// most bytes
+fn fold_literals(nodes: Vec<Node>) -> Vec<Node> {
+    let mut out = Vec::with_capacity(nodes.len());
+    let mut i = 0;
+    while i < nodes.len() {
+        let mut stack = Vec::new();
+        let mut size = 0;
+        let mut best: Option<(usize, Vec<u8>, usize)> = None;
+        for (j, (node, _)) in nodes.iter().enumerate().skip(i) {
+            match node {
+                AstNode::LiteralByte(b) => {
+                    stack.push(*b);
+                    size += 2;
+                }
+                AstNode::LiteralShort(s) => {
+                    stack.extend(s.to_be_bytes());
+                    size += 3;
+                }
+                AstNode::Instruction(ins) if j > i && eval(ins, &mut stack) => size += 1,
+                _ => break,
+            }
+            let folded = pushed_size(stack.len());
+            if folded < size && best.as_ref().is_none_or(|b| size - folded > b.2) {
+                best = Some((j, stack.clone(), size - folded));
+            }
+        }
+        let Some((last, bytes, _)) = best else {
+            out.push(nodes[i].clone());
+            i += 1;
+            continue;
+        };
+        let tok = &nodes[i].1;
+        for pair in bytes.chunks(2) {
+            let node = match *pair {
+                [hi, lo] => AstNode::LiteralShort(u16::from_be_bytes([hi, lo])),
+                [b] => AstNode::LiteralByte(b),
+                _ => unreachable!(),
+            };
+            out.push((node, tok.clone()));
+        }
+        i = last + 1;
+    }
+    out
+}
+
+/// Bytes taken by literals pushing `n` bytes
+fn pushed_size(n: usize) -> usize {
+    n / 2 * 3 + n % 2 * 2
+}
+
+/// Applies `ins` to a stack of known bytes, returning false, with the
+/// stack untouched, if it can't be evaluated
+fn eval(ins: &Instruction, stack: &mut Vec<u8>) -> bool {
+    if ins.return_mode {
+        return false;
+    }
+    let w = if ins.short_mode { 2 } else { 1 };
+    let (args, shift) = match ins.opcode.as_str() {
+        "INC" | "POP" | "DUP" => (1, false),
+        "NIP" | "SWP" | "OVR" | "EQU" | "NEQ" | "GTH" | "LTH" | "ADD" | "SUB" | "MUL" | "DIV"
+        | "AND" | "ORA" | "EOR" => (2, false),
+        "ROT" => (3, false),
+        "SFT" => (1, true),
+        _ => return false,
+    };
+    // SFT takes a value of the instruction's width and a byte shift
+    let needed = args * w + usize::from(shift);
+    if stack.len() < needed {
+        return false;
+    }
+    let base = stack.len() - needed;
+    let v: Vec<u32> = stack[base..base + args * w]
+        .chunks(w)
+        .map(|c| c.iter().fold(0, |acc, &b| acc << 8 | b as u32))
+        .collect();
+    let mask = if w == 2 { 0xffff } else { 0xff };
+    let flag = |c: bool| vec![(u32::from(c), 1)];
+    let result: Vec<(u32, usize)> = match ins.opcode.as_str() {
+        "INC" => vec![((v[0] + 1) & mask, w)],
+        "POP" => vec![],
+        "DUP" => vec![(v[0], w), (v[0], w)],
+        "NIP" => vec![(v[1], w)],
+        "SWP" => vec![(v[1], w), (v[0], w)],
+        "OVR" => vec![(v[0], w), (v[1], w), (v[0], w)],
+        "ROT" => vec![(v[1], w), (v[2], w), (v[0], w)],
+        "EQU" => flag(v[0] == v[1]),
+        "NEQ" => flag(v[0] != v[1]),
+        "GTH" => flag(v[0] > v[1]),
+        "LTH" => flag(v[0] < v[1]),
+        "ADD" => vec![((v[0] + v[1]) & mask, w)],
+        "SUB" => vec![(v[0].wrapping_sub(v[1]) & mask, w)],
+        "MUL" => vec![((v[0] * v[1]) & mask, w)],
+        "DIV" if v[1] == 0 => return false,
+        "DIV" => vec![(v[0] / v[1], w)],
+        "AND" => vec![(v[0] & v[1], w)],
+        "ORA" => vec![(v[0] | v[1], w)],
+        "EOR" => vec![(v[0] ^ v[1], w)],
+        "SFT" => {
+            let s = stack[stack.len() - 1] as u32;
+            vec![(((v[0] >> (s & 0x0f)) << (s >> 4)) & mask, w)]
+        }
+        _ => unreachable!(),
+    };
+    if !ins.keep_mode {
+        stack.truncate(base);
+    }
+    for (value, width) in result {
+        stack.extend(&value.to_be_bytes()[4 - width..]);
+    }
+    true
+}
+
+/// Drops code following an unconditional jump up to the next label
+///
+/// Label-to-label stretches that contain a computed byte jump, as in
+/// `#03 JMP` or `flag JMP JMP2r`, are left alone since it may land anywhere.
+fn unreachable_code(nodes: Vec<Node>) -> Vec<Node> {
+    let mut computed = vec![false; nodes.len()];
+    let mut start = 0;
+    for i in 0..=nodes.len() {
+        if i == nodes.len() || !is_code(&nodes[i].0) && !matches!(nodes[i].0, AstNode::Ignored) {
+            let any = (start..i).any(|j| is_computed_jump(&nodes, j));
+            computed[start..i].fill(any);
+            start = i + 1;
+        }
+    }
+    let mut out = Vec::with_capacity(nodes.len());
+    let mut dead = false;
+    for (i, (node, tok)) in nodes.into_iter().enumerate() {
+        if matches!(node, AstNode::Ignored) {
+            out.push((node, tok));
+            continue;
+        }
+        if !is_code(&node) {
+            dead = false;
+            out.push((node, tok));
+            continue;
+        }
+        if dead {
+            continue;
+        }
+        dead = !computed[i]
+            && match &node {
+                AstNode::Instruction(i) => ends(i),
+                AstNode::ExclamationRef(t) => !is_lambda(t),
+                _ => false,
+            };
+        out.push((node, tok));
+    }
+    out
+}
+
+/// Whether node `i` is a byte jump whose offset isn't a `,label`
+fn is_computed_jump(nodes: &[Node], i: usize) -> bool {
+    let AstNode::Instruction(ins) = &nodes[i].0 else {
+        return false;
+    };
+    let labelled = i > 0 && matches!(nodes[i - 1].0, AstNode::CommaRef(_));
+    !ins.short_mode && matches!(ins.opcode.as_str(), "JMP" | "JCN" | "JSR") && !labelled
+}
+
+/// Drops routines listed in `facts.unused`, up to the next top-level label
+///
+/// Labels whose first node is data rather than code are kept.
+fn unused_routines(nodes: Vec<Node>, facts: &Facts, falls_in: bool) -> Vec<Node> {
+    let mut out = Vec::with_capacity(nodes.len());
+    let mut flow = Flow {
+        falls: falls_in,
+        skip: false,
+    };
+    let mut i = 0;
+    while i < nodes.len() {
+        if let (AstNode::LabelDef(_, name), false) = (&nodes[i].0, flow.falls) {
+            if facts.unused.contains(name) {
+                let prefix = format!("{name}/");
+                let end = (i + 1..nodes.len())
+                    .find(|&j| match &nodes[j].0 {
+                        AstNode::LabelDef(_, other) => !other.starts_with(&prefix),
+                        AstNode::Padding(_)
+                        | AstNode::PaddingLabel(_)
+                        | AstNode::Include(_)
+                        | AstNode::MacroDef(..)
+                        | AstNode::Eof => true,
+                        _ => false,
+                    })
+                    .unwrap_or(nodes.len());
+                let code = nodes[i + 1..end]
+                    .iter()
+                    .map(|(n, _)| n)
+                    .find(|n| {
+                        !matches!(
+                            n,
+                            AstNode::LabelDef(..) | AstNode::SublabelDef(_) | AstNode::Ignored
+                        )
+                    })
+                    .is_some_and(is_code);
+                if code && balanced(&nodes[i..end]) {
+                    i = end;
+                    continue;
+                }
+            }
+        }
+        flow.step(&nodes[i].0);
+        out.push(nodes[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Whether every `{` block opened in `nodes` is also closed there
+fn balanced(nodes: &[Node]) -> bool {
+    let mut depth = 0i32;
+    for (node, _) in nodes {
+        match node {
+            AstNode::ConditionalBlockStart(_) | AstNode::LambdaStart(_) => depth += 1,
+            AstNode::SemicolonRef(t)
+            | AstNode::ExclamationRef(t)
+            | AstNode::EqualsRef(t)
+            | AstNode::UnderscoreRef(t)
+                if is_lambda(t) =>
+            {
+                depth += 1
+            }
+            AstNode::ConditionalBlockEnd(_) | AstNode::LambdaEnd(_) => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0
+}
+
+/// Nodes that emit instructions, as opposed to labels, data and padding
+fn is_code(node: &AstNode) -> bool {
+    match node {
+        AstNode::Instruction(i) => i.opcode != "LIT",
+        AstNode::LiteralByte(_) | AstNode::LiteralShort(_) => true,
+        AstNode::LabelRef { rune, .. } => *rune != Rune::RawAbsolute,
+        AstNode::DotRef(t)
+        | AstNode::SemicolonRef(t)
+        | AstNode::CommaRef(t)
+        | AstNode::QuestionRef(t)
+        | AstNode::ExclamationRef(t) => !is_lambda(t),
+        _ => false,
+    }
+}
+
+/// `BRK`, `JMP2` and `JMP2r`
+fn ends(i: &Instruction) -> bool {
+    i.opcode == "BRK" || (i.opcode == "JMP" && i.short_mode && !i.keep_mode)
+}
+
+fn is_op(i: &Instruction, opcode: &str, short: bool, ret: bool) -> bool {
+    i.opcode == opcode && i.short_mode == short && i.return_mode == ret && !i.keep_mode
+}
+
+/// Whether a rune reference opens an anonymous `{` block
+fn is_lambda(t: &TokenWithPos) -> bool {
+    matches!(
+        &t.token,
+        Token::SemicolonRef(s) | Token::ExclamationRef(s) | Token::EqualsRef(s)
+            | Token::UnderscoreRef(s) | Token::QuestionRef(s) if s == "{"
+    )
+}
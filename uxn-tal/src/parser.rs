@@ -100,6 +100,8 @@ pub struct Parser {
     source: String,
     brace_stack: Vec<BraceKind>, // track lambda vs conditional braces
     macro_table: std::collections::HashSet<String>, // <-- Add macro table
+    node_tokens: Vec<TokenWithPos>, // first token of each parsed node, for source maps
+    quiet: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -128,12 +130,33 @@ impl Parser {
             source,
             brace_stack: Vec::new(),
             macro_table,
+            node_tokens: Vec::new(),
+            quiet: false,
         }
     }
 
-    /// Parse tokens into AST nodes
+    /// Stops the parser printing debug output
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Parse tokens into AST nodes, stopping at the first error
     pub fn parse(&mut self) -> Result<Vec<AstNode>> {
+        let (nodes, mut errors) = self.parse_all();
+        if errors.is_empty() {
+            Ok(nodes)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parse tokens into AST nodes, skipping past tokens that fail to parse
+    /// so that every error in the file is reported
+    pub fn parse_all(&mut self) -> (Vec<AstNode>, Vec<AssemblerError>) {
         let mut nodes = Vec::new();
+        let mut errors = Vec::new();
+        self.node_tokens.clear();
 
         while !self.is_at_end() {
             let tok_with_pos = self.current_token();
@@ -163,14 +186,30 @@ impl Parser {
                     continue;
                 }
                 _ => {
-                    let node = self.parse_node()?;
-                    // println!("{:?}", node);
-                    nodes.push(node);
+                    let start = self.position;
+                    let first = tok_with_pos.clone();
+                    match self.parse_node() {
+                        Ok(node) => {
+                            nodes.push(node);
+                            self.node_tokens.push(first);
+                        }
+                        Err(e) => {
+                            errors.push(e);
+                            if self.position == start {
+                                self.advance();
+                            }
+                        }
+                    }
                 }
             }
         }
 
-        Ok(nodes)
+        (nodes, errors)
+    }
+
+    /// First token of each node returned by the last parse, in the same order
+    pub fn node_tokens(&self) -> &[TokenWithPos] {
+        &self.node_tokens
     }
 
     fn parse_node(&mut self) -> Result<AstNode> {
@@ -637,10 +676,12 @@ impl Parser {
                 Ok(AstNode::RawString(bytes))
             }
             Token::Include(_) => {
-                println!(
-                    "DEBUG: Include directive found: {:?}",
-                    self.current_token().token
-                );
+                if !self.quiet {
+                    println!(
+                        "DEBUG: Include directive found: {:?}",
+                        self.current_token().token
+                    );
+                }
                 let tok = self.current_token().clone();
                 self.advance();
                 Ok(AstNode::Include(tok))
@@ -25,7 +25,7 @@ pub fn probe_runtime(rom_bytes: &[u8], max_ticks: usize) -> ProbeRuntimeResult {
     ram[..rom_len].copy_from_slice(&rom_bytes[..rom_len]);
 
     // Instantiate Varvara and Uxn VM
-    let mut varvara = Varvara::default();
+    let mut varvara = Varvara::headless();
     let mut vm = uxn::Uxn::new(&mut ram, uxn::Backend::Interpreter);
 
     // Reset peripherals and VM
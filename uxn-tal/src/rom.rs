@@ -129,6 +129,12 @@ impl Rom {
         }
     }
 
+    /// Everything from 0x0100 to the furthest position reached, including
+    /// trailing zeros left by padding
+    pub fn extent(&self) -> &[u8] {
+        &self.data[0x0100..self.size.max(0x0100)]
+    }
+
     /// Returns true if any byte in the zero page (0x0000..0x0100) is nonzero
     pub fn has_zero_page_data(&self) -> bool {
         if self.data.len() < 0x0100 {
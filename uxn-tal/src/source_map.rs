@@ -0,0 +1,106 @@
+//! Address to source location map
+//!
+//! The assembler records, for every run of bytes it emits, the token that
+//! produced them. Bytes emitted by a macro are attributed to the macro call,
+//! and bytes from `~include`d files to the included file.
+//!
+//! The text form has one range per line, in the order they were emitted:
+//!
+//! ```text
+//! 0100 3 hello.tal:3:2
+//! 0103 1 hello.tal:3:15
+//! ```
+//!
+//! that is, the start address in hex, the length in bytes, and the file,
+//! line and column of the token. Where ranges overlap, e.g. after padding
+//! back over earlier bytes, the later line wins.
+use crate::diagnostic::Span;
+
+/// Bytes `address..address + len` were emitted for `span`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    pub address: u16,
+    pub len: u16,
+    pub span: Span,
+}
+
+/// Map from ROM addresses to the source that emitted them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// Entries in emission order; a later entry overrides an earlier one
+    /// where they overlap
+    pub entries: Vec<SourceMapEntry>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, address: u16, len: u16, span: Span) {
+        if len > 0 {
+            self.entries.push(SourceMapEntry { address, len, span });
+        }
+    }
+
+    /// Returns the source location of the byte at `address`
+    pub fn lookup(&self, address: u16) -> Option<&Span> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| (address as u32).wrapping_sub(e.address as u32) < e.len as u32)
+            .map(|e| &e.span)
+    }
+
+    /// Rewrites every span's file and line, e.g. to undo a preprocessor;
+    /// spans for which `f` returns `None` are left unchanged
+    pub fn remap(&mut self, f: impl Fn(&str, usize) -> Option<(String, usize)>) {
+        for e in &mut self.entries {
+            if let Some((path, line)) = f(&e.span.path, e.span.line) {
+                e.span.path = path;
+                e.span.line = line;
+            }
+        }
+    }
+
+    /// Formats the map as text, keeping the emission order so that reading
+    /// it back resolves overlaps the same way
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for e in &self.entries {
+            let s = &e.span;
+            out.push_str(&format!(
+                "{:04x} {} {}:{}:{}\n",
+                e.address, e.len, s.path, s.line, s.column
+            ));
+        }
+        out
+    }
+
+    /// Parses the text form written by [`SourceMap::to_text`]; token lengths
+    /// are not part of it, so the spans read back cover one character
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut map = Self::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.splitn(3, ' ');
+            let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+            let len = fields.next()?.parse().ok()?;
+            // The path may itself contain ':' (`C:\`), so split from the end
+            let mut loc = fields.next()?.rsplitn(3, ':');
+            let column = loc.next()?.parse().ok()?;
+            let line = loc.next()?.parse().ok()?;
+            let path = loc.next()?.to_owned();
+            map.push(
+                address,
+                len,
+                Span {
+                    path,
+                    line,
+                    column,
+                    len: 1,
+                },
+            );
+        }
+        Some(map)
+    }
+}
@@ -0,0 +1,742 @@
+//! Static stack-effect checker
+//!
+//! Routines are conventionally annotated with a comment after their label:
+//!
+//! ```text
+//! @print-str ( str* -- )
+//! @on-frame ( -> )
+//! ```
+//!
+//! where `name*` (or the older `name^`) is a short, any other name a byte, and
+//! `->` marks a vector.
+//! For each annotated label this pass walks the routine's nodes, tracking
+//! working and return stack depths from the opcodes' modes, and warns when
+//!
+//! - a `JMP2r` leaves a different net effect than the comment declares, or
+//!   leaves the return stack unbalanced,
+//! - a vector reaches `BRK` with anything left beyond its declared inputs,
+//! - the routine reads deeper than its declared inputs,
+//! - two branches reach the same label or the end of a `?{ }` block with
+//!   different stack depths.
+//!
+//! Calls to other annotated routines use their declared effects. A routine is
+//! skipped silently as soon as its effect can't be known statically: calls
+//! to unannotated labels, computed jumps, or raw bytes in code.
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Lexer, Token, TokenWithPos};
+use crate::opcode_table::{encode_opcode, stack_effect, BASE_INSTRUCTIONS};
+use crate::parser::{AstNode, Instruction, Parser};
+use crate::runes::Rune;
+
+/// Declared stack effect, in bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackEffect {
+    pub inputs: i32,
+    /// Inputs taken from the return stack, listed after a `.`:
+    /// `( x y . type -- )`
+    pub return_inputs: i32,
+    pub outputs: i32,
+    /// `( -> )` with no outputs: the routine ends with `BRK` rather than
+    /// returning
+    pub vector: bool,
+    /// Comment text, for messages
+    pub text: String,
+}
+
+impl StackEffect {
+    /// Parses a signature comment such as ` a b* -- c ) `, returning `None`
+    /// for comments that aren't signatures
+    pub fn parse(comment: &str) -> Option<Self> {
+        let text = comment.trim().trim_end_matches(')').trim();
+        // Skip separators such as `|`
+        let words: Vec<&str> = text
+            .split_whitespace()
+            .filter(|w| w.contains(|c: char| c.is_alphanumeric()) || ["--", "->", "."].contains(w))
+            .collect();
+        // `x**` is a pair of shorts, `x*` a short, and `x^` or `x` a byte
+        let size = |w: &[&str]| w.iter().map(|w| suffix_size(w).unwrap_or(1)).sum();
+        // `->` with nothing after it marks a vector; otherwise it's just
+        // another way of writing `--`
+        let i = words.iter().position(|w| *w == "--" || *w == "->")?;
+        let vector = words[i] == "->" && i + 1 == words.len();
+        let (inputs, return_inputs) = match words[..i].iter().position(|w| *w == ".") {
+            Some(dot) => (size(&words[..dot]), size(&words[dot + 1..i])),
+            None => (size(&words[..i]), 0),
+        };
+        Some(Self {
+            inputs,
+            return_inputs,
+            outputs: if vector {
+                0
+            } else {
+                // An output without a size, such as `x<<n`, is sized like
+                // the input it's named after
+                words[i + 1..]
+                    .iter()
+                    .map(|w| {
+                        suffix_size(w)
+                            .or_else(|| {
+                                let name = leading_name(w);
+                                words[..i]
+                                    .iter()
+                                    .find(|input| leading_name(input) == name)
+                                    .and_then(|input| suffix_size(input))
+                            })
+                            .unwrap_or(1)
+                    })
+                    .sum()
+            },
+            vector,
+            text: text.to_owned(),
+        })
+    }
+
+    fn net(&self) -> i32 {
+        self.outputs - self.inputs
+    }
+}
+
+/// Size in bytes given by a signature word's suffix, if it has one
+fn suffix_size(word: &str) -> Option<i32> {
+    if word.ends_with("**") {
+        Some(4)
+    } else if word.ends_with('*') {
+        Some(2)
+    } else if word.ends_with('^') {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// The name a signature word starts with, e.g. `x` for `x<<n`
+fn leading_name(word: &str) -> &str {
+    let end = word
+        .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(word.len());
+    &word[..end]
+}
+
+/// Checks every annotated routine in `source`, returning warnings
+///
+/// Includes are not followed, so routines calling into included files are
+/// skipped.
+pub fn check(source: &str, path: Option<&str>) -> Vec<Diagnostic> {
+    let path = path.unwrap_or_default();
+    let (tokens, _) = Lexer::new(source.to_owned(), Some(path.to_owned()))
+        .with_quiet(true)
+        .tokenize_all();
+    let signatures = signature_comments(&tokens);
+    let mut parser =
+        Parser::new_with_source(tokens, path.to_owned(), source.to_owned()).with_quiet(true);
+    let (ast, _) = parser.parse_all();
+    let node_tokens = parser.node_tokens().to_vec();
+    let mut checker = Checker::new(source, path, &ast, &node_tokens, &signatures);
+    checker.run();
+    checker.warnings
+}
+
+/// Maps the position of each label definition to the signature comment
+/// following it
+fn signature_comments(tokens: &[TokenWithPos]) -> HashMap<(usize, usize), StackEffect> {
+    let mut out = HashMap::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if !matches!(t.token, Token::LabelDef(..) | Token::SublabelDef(_)) {
+            continue;
+        }
+        let next = tokens[i + 1..]
+            .iter()
+            .find(|t| !matches!(t.token, Token::Newline));
+        if let Some(TokenWithPos {
+            token: Token::Comment(c),
+            ..
+        }) = next
+        {
+            if let Some(effect) = StackEffect::parse(c) {
+                out.insert((t.line, t.start_pos), effect);
+            }
+        }
+    }
+    out
+}
+
+/// Stack depths relative to the routine's entry, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Depth {
+    w: i32,
+    r: i32,
+}
+
+/// Why a routine's walk ended early
+enum Stop {
+    /// The effect can't be known statically
+    Unknown,
+    /// The rest of the region isn't reachable from the entry
+    Unreachable,
+}
+
+struct Routine<'a> {
+    name: String,
+    scope: String,
+    effect: &'a StackEffect,
+    /// Depths at which each label in the routine was jumped to
+    targets: HashMap<String, (Depth, &'a TokenWithPos)>,
+    /// Skip-path depth for each open `?{`
+    blocks: Vec<Option<Depth>>,
+    lowest: Option<(i32, &'a TokenWithPos)>,
+    /// Operand bytes still owed to an explicit LIT
+    lit: i32,
+    warnings: Vec<Diagnostic>,
+}
+
+struct Checker<'a> {
+    source: &'a str,
+    path: &'a str,
+    ast: &'a [AstNode],
+    tokens: &'a [TokenWithPos],
+    macros: HashMap<&'a str, &'a [AstNode]>,
+    labels: HashSet<String>,
+    effects: HashMap<String, &'a StackEffect>,
+    /// Node index of each annotated routine's label
+    starts: Vec<(usize, String, &'a StackEffect)>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(
+        source: &'a str,
+        path: &'a str,
+        ast: &'a [AstNode],
+        tokens: &'a [TokenWithPos],
+        signatures: &'a HashMap<(usize, usize), StackEffect>,
+    ) -> Self {
+        let mut c = Self {
+            source,
+            path,
+            ast,
+            tokens,
+            macros: HashMap::new(),
+            labels: HashSet::new(),
+            effects: HashMap::new(),
+            starts: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut scope = String::new();
+        for (i, (node, tok)) in ast.iter().zip(tokens).enumerate() {
+            let name = match node {
+                AstNode::MacroDef(name, body) => {
+                    c.macros.insert(name, body);
+                    continue;
+                }
+                AstNode::LabelDef(_, name) => {
+                    scope = name.clone();
+                    name.clone()
+                }
+                AstNode::SublabelDef(t) => match &t.token {
+                    Token::SublabelDef(sub) => format!("{scope}/{sub}"),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if let Some(effect) = signatures.get(&(tok.line, tok.start_pos)) {
+                c.effects.insert(name.clone(), effect);
+                c.starts.push((i, name.clone(), effect));
+            }
+            c.labels.insert(name);
+        }
+        c
+    }
+
+    fn run(&mut self) {
+        let names: HashMap<usize, &str> = self
+            .starts
+            .iter()
+            .map(|(i, name, _)| (*i, name.as_str()))
+            .collect();
+        // Annotated sublabels reached from an enclosing routine, e.g. loops
+        let mut inner = HashSet::new();
+        let mut warnings = Vec::new();
+        for &(start, ref name, effect) in &self.starts {
+            if inner.contains(&start) {
+                continue;
+            }
+            let scope = name.split('/').next().unwrap_or_default().to_owned();
+            let mut routine = Routine {
+                name: name.clone(),
+                scope,
+                effect,
+                targets: HashMap::new(),
+                blocks: Vec::new(),
+                lowest: None,
+                lit: 0,
+                warnings: Vec::new(),
+            };
+            let mut state = Some(Depth { w: 0, r: 0 });
+            let mut unknown = false;
+            // Once the walk stops, the rest of the region is only scanned
+            // for where it ends
+            let mut stopped = false;
+            for i in start + 1..self.ast.len() {
+                if matches!(self.ast[i], AstNode::LabelDef(..)) {
+                    break;
+                }
+                if let Some(sub) = names.get(&i) {
+                    let entered = if stopped {
+                        self.falls_through(i) || self.branched_to(start..i, sub)
+                    } else {
+                        state.is_some() || routine.targets.contains_key(*sub)
+                    };
+                    if !entered {
+                        break;
+                    }
+                    inner.insert(i);
+                }
+                if stopped {
+                    continue;
+                }
+                match self.step(&mut routine, &mut state, &self.ast[i], &self.tokens[i], 0) {
+                    Ok(()) => {}
+                    Err(Stop::Unknown) => (unknown, stopped) = (true, true),
+                    Err(Stop::Unreachable) => stopped = true,
+                }
+            }
+            // Falling into the next label: its effect isn't ours
+            if unknown || state.is_some() {
+                continue;
+            }
+            if let Some((low, tok)) = routine.lowest {
+                if low < -effect.inputs {
+                    routine.warn(
+                        self,
+                        tok,
+                        format!(
+                            "{} reads {} byte(s) below its declared inputs ( {} )",
+                            routine.name,
+                            -low - effect.inputs,
+                            effect.text
+                        ),
+                    );
+                }
+            }
+            warnings.append(&mut routine.warnings);
+        }
+        self.warnings = warnings;
+    }
+
+    /// Whether any of the nodes in `range` branches to sublabel `name`
+    /// with an `&` reference
+    fn branched_to(&self, range: std::ops::Range<usize>, name: &str) -> bool {
+        let Some((_, sub)) = name.split_once('/') else {
+            return false;
+        };
+        self.ast[range].iter().any(|n| match n {
+            AstNode::ConditionalRef(t) | AstNode::QuestionRef(t) | AstNode::ExclamationRef(t) => {
+                ref_name(t).and_then(|r| r.strip_prefix('&')) == Some(sub)
+            }
+            _ => false,
+        })
+    }
+
+    /// Whether execution can run into node `i` from the node before it
+    fn falls_through(&self, i: usize) -> bool {
+        let prev = self.ast[..i]
+            .iter()
+            .rev()
+            .find(|n| !matches!(n, AstNode::MacroDef(..) | AstNode::Ignored));
+        match prev {
+            Some(AstNode::Instruction(inst)) => match inst.opcode.as_str() {
+                "BRK" => false,
+                "JMP" => inst.keep_mode,
+                _ => true,
+            },
+            Some(
+                AstNode::ExclamationRef(_)
+                | AstNode::Byte(_)
+                | AstNode::Short(_)
+                | AstNode::RawString(_)
+                | AstNode::Padding(_)
+                | AstNode::PaddingLabel(_)
+                | AstNode::RelativePadding(_)
+                | AstNode::RelativePaddingLabel(_),
+            )
+            | None => false,
+            Some(_) => true,
+        }
+    }
+
+    /// Resolves a reference from inside `routine` to a label name
+    fn resolve(&self, routine: &Routine, raw: &str) -> Option<String> {
+        let bare = raw.trim_start_matches(['&', '/']);
+        let scoped = format!("{}/{}", routine.scope, bare);
+        if raw.starts_with(['&', '/']) {
+            return self.labels.contains(&scoped).then_some(scoped);
+        }
+        if self.labels.contains(raw) {
+            Some(raw.to_owned())
+        } else {
+            self.labels.contains(&scoped).then_some(scoped)
+        }
+    }
+
+    fn step(
+        &self,
+        routine: &mut Routine<'a>,
+        state: &mut Option<Depth>,
+        node: &'a AstNode,
+        tok: &'a TokenWithPos,
+        nesting: usize,
+    ) -> Result<(), Stop> {
+        // Operands of an explicit LIT are data
+        if routine.lit > 0 {
+            match node {
+                AstNode::Byte(_) => routine.lit -= 1,
+                AstNode::Short(_) => routine.lit -= 2,
+                _ => return Err(Stop::Unknown),
+            }
+            return Ok(());
+        }
+        // Only label definitions can make unreachable code reachable again
+        if state.is_none() && !matches!(node, AstNode::SublabelDef(_)) {
+            return match node {
+                AstNode::ConditionalBlockEnd(_) => {
+                    *state = routine.blocks.pop().flatten();
+                    Ok(())
+                }
+                AstNode::ConditionalBlockStart(_) => {
+                    routine.blocks.push(None);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+        }
+        match node {
+            AstNode::SublabelDef(t) => {
+                let Token::SublabelDef(sub) = &t.token else {
+                    return Err(Stop::Unknown);
+                };
+                let name = format!("{}/{}", routine.scope, sub);
+                let incoming = routine.targets.get(&name).copied();
+                match (*state, incoming) {
+                    (Some(d), Some((e, _))) if d != e => {
+                        routine.warn(
+                            self,
+                            tok,
+                            format!(
+                                "unbalanced branches: &{sub} is reached with {} here but {} from line {}",
+                                describe(d),
+                                describe(e),
+                                incoming.unwrap().1.line
+                            ),
+                        );
+                    }
+                    (Some(_), _) => {}
+                    (None, Some((e, _))) => *state = Some(e),
+                    (None, None) => return Err(Stop::Unreachable),
+                }
+                if let Some(d) = *state {
+                    routine.targets.entry(name).or_insert((d, tok));
+                }
+            }
+            AstNode::Instruction(inst) => self.instruction(routine, state, inst, tok)?,
+            AstNode::LiteralByte(_) => routine.push(state, 1, 0),
+            AstNode::LiteralShort(_) => routine.push(state, 2, 0),
+            AstNode::DotRef(_) | AstNode::CommaRef(_) => routine.push(state, 1, 0),
+            AstNode::SemicolonRef(_) => routine.push(state, 2, 0),
+            AstNode::LabelRef {
+                label,
+                rune: Rune::None,
+                ..
+            } => {
+                if let Some(body) = self.macros.get(label.as_str()) {
+                    return self.expand(routine, state, body, tok, nesting);
+                }
+                // JSI
+                let callee = self.resolve(routine, label).ok_or(Stop::Unknown)?;
+                self.call(routine, state, &callee, tok)?;
+            }
+            AstNode::MacroCall(name, ..) => {
+                let body = self.macros.get(name.as_str()).ok_or(Stop::Unknown)?;
+                return self.expand(routine, state, body, tok, nesting);
+            }
+            AstNode::ConditionalRef(t) | AstNode::QuestionRef(t) => {
+                // JCI
+                routine.read(state, 1, tok);
+                routine.push(state, -1, 0);
+                let mut taken = *state;
+                self.jump(routine, &mut taken, t, tok)?;
+            }
+            AstNode::ExclamationRef(t) | AstNode::JSRRef(t) => {
+                // JMI
+                self.jump(routine, state, t, tok)?;
+            }
+            AstNode::ConditionalBlockStart(_) => {
+                routine.read(state, 1, tok);
+                routine.push(state, -1, 0);
+                routine.blocks.push(*state);
+            }
+            AstNode::ConditionalBlockEnd(_) => {
+                let skipped = routine.blocks.pop().ok_or(Stop::Unknown)?;
+                if let (Some(body), Some(skip)) = (*state, skipped) {
+                    if body != skip {
+                        routine.warn(
+                            self,
+                            tok,
+                            format!(
+                                "unbalanced branches: ?{{ }} block ends with {} but {} when skipped",
+                                describe(body),
+                                describe(skip)
+                            ),
+                        );
+                    }
+                }
+                *state = state.or(skipped);
+            }
+            AstNode::MacroDef(..) | AstNode::Eof | AstNode::Ignored => {}
+            // Lambdas, raw bytes, padding, includes and raw references in code
+            _ => return Err(Stop::Unknown),
+        }
+        Ok(())
+    }
+
+    fn expand(
+        &self,
+        routine: &mut Routine<'a>,
+        state: &mut Option<Depth>,
+        body: &'a [AstNode],
+        tok: &'a TokenWithPos,
+        nesting: usize,
+    ) -> Result<(), Stop> {
+        if nesting > 16 {
+            return Err(Stop::Unknown);
+        }
+        for node in body {
+            self.step(routine, state, node, tok, nesting + 1)?;
+        }
+        Ok(())
+    }
+
+    fn instruction(
+        &self,
+        routine: &mut Routine<'a>,
+        state: &mut Option<Depth>,
+        inst: &Instruction,
+        tok: &'a TokenWithPos,
+    ) -> Result<(), Stop> {
+        let base = match inst.opcode.as_str() {
+            "LIT" => {
+                // Its operands are data, not instructions
+                routine.lit = if inst.short_mode { 2 } else { 1 };
+                0
+            }
+            name => BASE_INSTRUCTIONS
+                .iter()
+                .position(|&b| b == name)
+                .ok_or(Stop::Unknown)? as u8,
+        };
+        match inst.opcode.as_str() {
+            "BRK" => {
+                let d = state.take().ok_or(Stop::Unknown)?;
+                let effect = routine.effect;
+                if effect.vector && (d.w != effect.net() || d.r != -effect.return_inputs) {
+                    routine.warn(
+                        self,
+                        tok,
+                        format!(
+                            "vector {} is declared ( {} ), a net of {}, but reaches BRK with {}",
+                            routine.name,
+                            effect.text,
+                            signed(effect.net()),
+                            describe(d)
+                        ),
+                    );
+                }
+                return Ok(());
+            }
+            "JMP" if inst.return_mode && inst.short_mode && !inst.keep_mode => {
+                let d = state.take().ok_or(Stop::Unknown)?;
+                routine.ret(self, d, tok);
+                return Ok(());
+            }
+            // Computed jumps and calls
+            "JMP" | "JCN" | "JSR" => return Err(Stop::Unknown),
+            "STH" => {
+                let w = if inst.short_mode { 2 } else { 1 };
+                let (a, b) = if inst.return_mode { (0, w) } else { (w, 0) };
+                routine.read(state, a, tok);
+                if !inst.keep_mode {
+                    routine.push(state, -a, -b);
+                }
+                routine.push(state, b, a);
+                return Ok(());
+            }
+            _ => {}
+        }
+        let keep_mode = inst.keep_mode || inst.opcode == "LIT";
+        let opcode = encode_opcode(base, inst.short_mode, inst.return_mode, keep_mode);
+        let (ins, outs) = stack_effect(opcode);
+        let (ins, outs) = (ins as i32, outs as i32);
+        let (iw, ir) = if inst.return_mode { (0, ins) } else { (ins, 0) };
+        let (ow, or) = if inst.return_mode {
+            (0, outs)
+        } else {
+            (outs, 0)
+        };
+        routine.read(state, iw, tok);
+        if !inst.keep_mode {
+            routine.push(state, -iw, -ir);
+        }
+        routine.push(state, ow, or);
+        Ok(())
+    }
+
+    /// Applies a call's effect: the callee's declared effect, or for a
+    /// vector, the end of this path
+    fn call(
+        &self,
+        routine: &mut Routine<'a>,
+        state: &mut Option<Depth>,
+        callee: &str,
+        tok: &'a TokenWithPos,
+    ) -> Result<(), Stop> {
+        let effect = self.effects.get(callee).ok_or(Stop::Unknown)?;
+        if effect.vector {
+            return Err(Stop::Unknown);
+        }
+        routine.read(state, effect.inputs, tok);
+        routine.push(state, effect.net(), 0);
+        Ok(())
+    }
+
+    /// Follows an unconditional jump from `state`, which becomes unreachable
+    ///
+    /// Jumps to `&name` or to unannotated sublabels are branches within the
+    /// routine; other jumps to annotated labels are tail calls.
+    fn jump(
+        &self,
+        routine: &mut Routine<'a>,
+        state: &mut Option<Depth>,
+        reference: &TokenWithPos,
+        tok: &'a TokenWithPos,
+    ) -> Result<(), Stop> {
+        let raw = ref_name(reference).ok_or(Stop::Unknown)?;
+        let target = self.resolve(routine, raw).ok_or(Stop::Unknown)?;
+        let target = target.as_str();
+        let Some(d) = *state else {
+            return Ok(());
+        };
+        *state = None;
+        let local = target.starts_with(&format!("{}/", routine.scope))
+            && (raw.starts_with('&') || !self.effects.contains_key(target));
+        if local {
+            // Branch within the routine
+            match routine.targets.get(target) {
+                Some(&(e, first)) if e != d => routine.warn(
+                    self,
+                    tok,
+                    format!(
+                        "unbalanced branches: {} is reached with {} here but {} from line {}",
+                        target,
+                        describe(d),
+                        describe(e),
+                        first.line
+                    ),
+                ),
+                Some(_) => {}
+                None => {
+                    routine.targets.insert(target.to_owned(), (d, tok));
+                }
+            }
+            return Ok(());
+        }
+        // Tail call: the callee returns on our behalf
+        let mut after = Some(d);
+        self.call(routine, &mut after, target, tok)?;
+        routine.ret(self, after.unwrap(), tok);
+        Ok(())
+    }
+}
+
+impl<'a> Routine<'a> {
+    fn push(&mut self, state: &mut Option<Depth>, w: i32, r: i32) {
+        if let Some(d) = state {
+            d.w += w;
+            d.r += r;
+        }
+    }
+
+    /// Notes that the top `w` bytes of the working stack are read, without
+    /// changing the depth
+    fn read(&mut self, state: &mut Option<Depth>, w: i32, tok: &'a TokenWithPos) {
+        if let Some(d) = state {
+            let low = d.w - w;
+            if self.lowest.is_none_or(|(l, _)| low < l) {
+                self.lowest = Some((low, tok));
+            }
+        }
+    }
+
+    /// Checks the depth at a return against the declaration
+    fn ret(&mut self, checker: &Checker, d: Depth, tok: &TokenWithPos) {
+        if self.effect.vector {
+            return;
+        }
+        if d.w != self.effect.net() {
+            let msg = format!(
+                "{} is declared ( {} ), a net of {}, but returns here with a net of {}",
+                self.name,
+                self.effect.text,
+                signed(self.effect.net()),
+                signed(d.w)
+            );
+            self.warn(checker, tok, msg);
+        }
+        if d.r != -self.effect.return_inputs {
+            let msg = format!(
+                "{} returns with the return stack off by {} byte(s)",
+                self.name,
+                signed(d.r + self.effect.return_inputs)
+            );
+            self.warn(checker, tok, msg);
+        }
+    }
+
+    fn warn(&mut self, checker: &Checker, tok: &TokenWithPos, message: String) {
+        let line = checker
+            .source
+            .lines()
+            .nth(tok.line.saturating_sub(1))
+            .unwrap_or_default();
+        let d = Diagnostic::warning(message).at(checker.path, tok.line, tok.start_pos, line);
+        if !self.warnings.contains(&d) {
+            self.warnings.push(d);
+        }
+    }
+}
+
+fn ref_name(t: &TokenWithPos) -> Option<&str> {
+    match &t.token {
+        Token::ConditionalRef(s)
+        | Token::QuestionRef(s)
+        | Token::ExclamationRef(s)
+        | Token::JSRRef(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn describe(d: Depth) -> String {
+    if d.r == 0 {
+        format!("a working stack net of {}", signed(d.w))
+    } else {
+        format!(
+            "a working stack net of {} and return stack net of {}",
+            signed(d.w),
+            signed(d.r)
+        )
+    }
+}
+
+fn signed(n: i32) -> String {
+    format!("{n:+}")
+}
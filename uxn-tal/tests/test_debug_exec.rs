@@ -0,0 +1,86 @@
+use uxn_tal::debug_exec::{self, ExecCase, ExecError};
+use uxn_tal::Assembler;
+
+/// Echoes stdin, then writes the number of characters to `count.bin` and
+/// exits with it
+const COUNT: &str = "
+|10 @Console &vector $2 &read $1 &pad $4 &type $1 &write $1
+|a0 @File &vector $2 &success $2 &stat $2 &delete $1 &append $1 &name $2 &length $2 &read $2 &write $2
+
+|0100
+	;on-console .Console/vector DEO2
+	BRK
+
+@on-console ( -> )
+	.Console/type DEI #04 EQU ?&end
+	.Console/read DEI .Console/write DEO
+	;count LDA INC ;count STA
+	BRK
+	&end
+	;dump-name .File/name DEO2 #0001 .File/length DEO2 ;count .File/write DEO2
+	;count LDA #80 ORA #0f DEO
+	BRK
+
+@dump-name \"count.bin $1
+@count $1
+";
+
+const HELLO: &str = include_str!("../helloworld.tal");
+
+fn count_case(stdin: &str) -> ExecCase {
+    ExecCase {
+        stdin: stdin.as_bytes().to_vec(),
+        dump: Some("count.bin".to_owned()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn cardinal() {
+    let mut asm = Assembler::new();
+    let rom = asm.assemble(COUNT, None).unwrap();
+    let out = debug_exec::run_cardinal(&rom, &count_case("abc")).unwrap();
+    assert_eq!(out.stdout, b"abc");
+    assert_eq!(out.exit, 3);
+    assert_eq!(out.dump, Some(vec![3]));
+
+    // Cardinal's RAM is captured without the ROM's help
+    let ram = out.ram.unwrap();
+    assert_eq!(ram.len(), 0x10000);
+    assert_eq!(ram[usize::from(asm.symbols["count"].address)], 3);
+}
+
+#[test]
+fn cardinal_budget() {
+    let rom = Assembler::new()
+        .assemble("|0100 @loop !loop", None)
+        .unwrap();
+    let case = ExecCase {
+        cycles: 1000,
+        ..Default::default()
+    };
+    assert!(matches!(
+        debug_exec::run_cardinal(&rom, &case),
+        Err(ExecError::BudgetExhausted { cycles: 1000 })
+    ));
+}
+
+#[test]
+fn matches_uxncli() {
+    if debug_exec::find_uxncli().is_none() {
+        eprintln!("uxncli not found in PATH, skipping");
+        return;
+    }
+    let count = Assembler::new().assemble(COUNT, None).unwrap();
+    let hello = Assembler::new().assemble(HELLO, None).unwrap();
+    for (rom, case) in [
+        (&hello, ExecCase::default()),
+        (&count, count_case("")),
+        (&count, count_case("hello, world")),
+    ] {
+        let diff = debug_exec::compare_execution(rom, &case)
+            .unwrap()
+            .expect("uxncli disappeared");
+        assert!(diff.is_match(), "{diff}");
+    }
+}
@@ -0,0 +1,64 @@
+use uxn_tal::{Assembler, AssemblerError, Severity};
+
+const SOURCE: &str = "\
+|0100
+\t;missing #zz BRK
+@main ;also ,main
+";
+
+#[test]
+fn collects_every_error() {
+    let diags = Assembler::new()
+        .assemble_with_diagnostics(SOURCE, Some("bad.tal".to_owned()))
+        .unwrap_err();
+    let found: Vec<_> = diags
+        .iter()
+        .map(|d| {
+            let s = d.span.as_ref().unwrap();
+            (d.message.as_str(), s.line, s.column, s.len)
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("Expected hexadecimal digits after '#'", 2, 12, 2),
+            ("Label unknown: \"missing\"", 2, 2, 8),
+            ("Label unknown: \"also\"", 3, 7, 5),
+        ]
+    );
+    assert!(diags.iter().all(|d| d.severity == Severity::Error));
+}
+
+#[test]
+fn renders_snippet() {
+    let diags = Assembler::new()
+        .assemble_with_diagnostics(SOURCE, Some("bad.tal".to_owned()))
+        .unwrap_err();
+    assert_eq!(
+        diags[1].render(),
+        "\
+error: Label unknown: \"missing\"
+ --> bad.tal:2:2
+  |
+2 |     ;missing #zz BRK
+  |     ^^^^^^^^"
+    );
+}
+
+#[test]
+fn assemble_returns_first_error() {
+    let err = Assembler::new()
+        .assemble(SOURCE, Some("bad.tal".to_owned()))
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            AssemblerError::SyntaxError {
+                line: 2,
+                position: 12,
+                ..
+            }
+        ),
+        "{err}"
+    );
+}
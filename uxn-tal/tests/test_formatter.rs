@@ -0,0 +1,83 @@
+use uxn_tal::formatter::format;
+use uxn_tal::Assembler;
+
+const MESSY: &str = "\
+( hello world )
+
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+|0100
+  ;text   <print-str>
+    BRK
+@<print-str> ( str* -- )
+        &loop
+    LDAk .Console/write DEO ( emit )
+ INC2 LDAk ?&loop ( next )
+  POP2 JMP2r
+@text \"Hello 20 \"World 0a 00
+";
+
+const CANONICAL: &str = "\
+( hello world )
+
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+|0100
+\t;text <print-str>
+\tBRK
+
+@<print-str> ( str* -- )
+\t&loop
+\t\tLDAk .Console/write DEO ( emit )
+\t\tINC2 LDAk ?&loop        ( next )
+\tPOP2 JMP2r
+
+@text \"Hello 20 \"World 0a 00
+";
+
+#[test]
+fn formats_to_canonical_style() {
+    assert_eq!(format(MESSY), CANONICAL);
+    assert_eq!(format(CANONICAL), CANONICAL);
+}
+
+#[test]
+fn keeps_the_rom() {
+    let before = Assembler::new().assemble(MESSY, None).unwrap();
+    let after = Assembler::new().assemble(&format(MESSY), None).unwrap();
+    assert_eq!(before, after);
+}
+
+#[test]
+fn lowercases_hex_only_where_it_is_a_number() {
+    assert_eq!(
+        format("|01AB @main #ABCD $1F #0A AB BRK"),
+        "|01ab @main #abcd $1f #0A AB BRK\n"
+    );
+}
+
+#[test]
+fn indents_blocks_and_keeps_comments_verbatim() {
+    let src = "%MAC { #01   ADD }\n@f\n?{\n#01 (  spaced\n   comment  )\n}\nJMP2r";
+    let expected = "\
+%MAC { #01 ADD }
+@f
+\t?{
+\t\t#01 (  spaced
+   comment  )
+\t}
+\tJMP2r
+";
+    assert_eq!(format(src), expected);
+    assert_eq!(format(expected), expected);
+}
+
+#[test]
+fn is_idempotent_on_examples() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tal");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "tal") {
+            let once = format(&std::fs::read_to_string(&path).unwrap());
+            assert_eq!(format(&once), once, "{}", path.display());
+        }
+    }
+}
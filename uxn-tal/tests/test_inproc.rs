@@ -0,0 +1,69 @@
+use uxn_tal::bkend::AssemblerBackend;
+use uxn_tal::debug::DrifblimBackend;
+use uxn_tal::inproc;
+use uxn_tal::Assembler;
+
+const HELLO: &str = include_str!("../helloworld.tal");
+
+#[test]
+fn drifblim_matches_uxntal() {
+    let (rom, out) = inproc::drifblim_assemble("hello.tal", HELLO, None).unwrap();
+    assert_eq!(out.exit, Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "Assembled out.rom in 29 bytes.\n"
+    );
+    assert!(out.files.contains_key("out.rom.sym"));
+    assert_eq!(rom, Assembler::new().assemble(HELLO, None).unwrap());
+}
+
+#[test]
+fn drifblim_errors() {
+    let err = inproc::drifblim_assemble("bad.tal", "|0100 ;missing BRK", None);
+    assert!(err.is_err());
+}
+
+#[test]
+fn backend_with_includes() {
+    let tmp = tempfile::tempdir().unwrap();
+    std::fs::write(tmp.path().join("lib.tal"), "@lib #2a #18 DEO JMP2r\n").unwrap();
+    let src = "|0100 lib BRK\n~lib.tal\n";
+    let main = tmp.path().join("main.tal");
+    std::fs::write(&main, src).unwrap();
+
+    let out = DrifblimBackend
+        .assemble(main.to_str().unwrap(), src)
+        .unwrap();
+    let expected = Assembler::new()
+        .assemble("|0100 lib BRK\n@lib #2a #18 DEO JMP2r\n", None)
+        .unwrap();
+    assert_eq!(out.rom_bytes, expected);
+    assert_eq!(std::fs::read(&out.rom_path).unwrap(), expected);
+    assert!(out.disassembly.contains("DEO"));
+}
+
+#[test]
+fn backend_with_includes_outside_its_directory() {
+    let tmp = tempfile::tempdir().unwrap();
+    std::fs::write(tmp.path().join("lib.tal"), "@lib #2a #18 DEO JMP2r\n").unwrap();
+    std::fs::create_dir(tmp.path().join("src")).unwrap();
+    let src = "|0100 lib BRK\n~../lib.tal\n";
+    let main = tmp.path().join("src").join("main.tal");
+    std::fs::write(&main, src).unwrap();
+
+    let out = DrifblimBackend
+        .assemble(main.to_str().unwrap(), src)
+        .unwrap();
+    let expected = Assembler::new()
+        .assemble("|0100 lib BRK\n@lib #2a #18 DEO JMP2r\n", None)
+        .unwrap();
+    assert_eq!(out.rom_bytes, expected);
+}
+
+#[test]
+fn uxndis() {
+    let rom = Assembler::new().assemble(HELLO, None).unwrap();
+    let dis = inproc::uxndis_disassemble(&rom).unwrap();
+    assert!(dis.contains("|0100"), "{dis}");
+    assert!(dis.contains("LDAk"), "{dis}");
+}
@@ -0,0 +1,123 @@
+use uxn_tal::lint::lint;
+use uxn_tal::Severity;
+
+const SOURCE: &str = "\
+|00 @System &vector $2 &pad $6 &r $2
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+|0000 @var $1 @unused-var $1
+
+%EMIT { .Console/write DEO }
+%UNUSED { #00 }
+%EMIT { .Console/write DEO }
+%helper { #00 }
+
+|0100
+	#41 EMIT
+	#01 .var STZ
+	.var DEI POP
+	#00 JMP JMP2r
+	BRK
+	#02 POP
+@helper ( -- )
+	JMP2r
+|0101
+	#00
+";
+
+#[test]
+fn reports_each_kind() {
+    let found: Vec<_> = lint(SOURCE, Some("lint.tal"))
+        .into_iter()
+        .map(|d| {
+            assert_eq!(d.severity, Severity::Warning);
+            let s = d.span.unwrap();
+            (s.line, s.column, d.message)
+        })
+        .collect();
+    let expected = [
+        (3, 15, "unused label: unused-var"),
+        (6, 1, "unused macro: UNUSED"),
+        (7, 1, "macro EMIT redefines the one on line 5"),
+        (8, 1, "macro helper shadows the label of the same name"),
+        (8, 1, "unused macro: helper"),
+        (
+            13,
+            2,
+            "zero-page label var is used both as a device port and as a variable (line 12)",
+        ),
+        (16, 2, "unreachable code after the jump on line 15"),
+        (17, 1, "unused label: helper"),
+        (
+            20,
+            2,
+            "bytes at 0101 overwrite those written by lint.tal:11",
+        ),
+    ];
+    let expected: Vec<_> = expected
+        .into_iter()
+        .map(|(l, c, m)| (l, c, m.to_owned()))
+        .collect();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn clean_file_has_no_warnings() {
+    let source = "\
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+|0000 @count $1
+%EMIT { .Console/write DEO }
+|0100
+	;hello print-str
+	.count LDZ INC .count STZ
+	BRK
+@print-str ( str* -- )
+	&while
+		LDAk EMIT
+		INC2 LDAk ?&while
+	POP2 JMP2r
+@hello \"hello 00
+";
+    assert_eq!(lint(source, Some("clean.tal")), []);
+}
+
+#[test]
+fn returns_assembler_errors() {
+    let found = lint("|0100 ;missing BRK\n", Some("bad.tal"));
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].severity, Severity::Error);
+}
+
+#[test]
+fn variables_in_device_pages() {
+    let source = "\
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+|10 @counter $1
+|80 @pad $2
+|f0 @spare $1
+|0000 @free $2
+|0100
+	.counter LDZ .Console/write DEO
+	.pad LDZ2 .spare LDZ POP POP2
+	.free LDZ2 POP2
+	BRK
+";
+    let found: Vec<_> = lint(source, Some("zp.tal"))
+        .into_iter()
+        .map(|d| (d.span.unwrap().line, d.message))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (
+                7,
+                "zero-page variable counter (10-10) overlaps the Console device page (10-1f)"
+                    .to_owned()
+            ),
+            (
+                8,
+                "zero-page variable pad (80-81) overlaps the Controller device page (80-8f)"
+                    .to_owned()
+            ),
+        ]
+    );
+}
@@ -0,0 +1,131 @@
+use uxn_tal::inproc::run_rom;
+use uxn_tal::object::{link, Object, RelocKind};
+use uxn_tal::{Assembler, AssemblerError};
+
+const HEADER: &str = "|10 @Console &vector $2 &read $1 &pad $5 &write $1\n|0100\n";
+
+const MAIN: &str = "
+@on-reset
+	;text print-str
+	#0a .Console/write DEO
+	BRK
+";
+
+const LIB: &str = "
+@print-str ( str* -- )
+	&loop
+		LDAk .Console/write DEO
+		INC2 LDAk ?&loop
+	POP2 JMP2r
+@text \"hello 00
+@buffer $10
+";
+
+fn object(src: &str, path: &str) -> (String, Object) {
+    let object = Object::assemble(&format!("{HEADER}{src}"), Some(path.to_owned())).unwrap();
+    (path.to_owned(), object)
+}
+
+#[test]
+fn links_to_the_same_rom_as_one_source() {
+    let whole = Assembler::new()
+        .assemble(&format!("{HEADER}{MAIN}{LIB}"), None)
+        .unwrap();
+    let linked = link(&[object(MAIN, "main.tao"), object(LIB, "lib.tao")]).unwrap();
+    assert_eq!(linked.rom, whole);
+    assert_eq!(
+        run_rom(&linked.rom, &[], Default::default()).stdout,
+        b"hello\n"
+    );
+}
+
+#[test]
+fn units_are_placed_in_link_order() {
+    let (_, main) = object(MAIN, "main.tao");
+    let (_, lib) = object(LIB, "lib.tao");
+    assert!(!main.pinned && !lib.pinned);
+    // `;text` and `print-str` are left to the linker, `.Console/write` isn't
+    let external: Vec<_> = main
+        .relocations
+        .iter()
+        .map(|r| (r.kind, r.label.as_deref()))
+        .collect();
+    assert_eq!(
+        external,
+        [
+            (RelocKind::Absolute, Some("text")),
+            (RelocKind::Jump, Some("print-str"))
+        ]
+    );
+    // The library's reference to its own `text` moves with it
+    assert_eq!(
+        lib.labels.iter().find(|(n, _)| n == "text").unwrap().1,
+        0x010b
+    );
+    assert_eq!(main.size, 0x0c);
+
+    let linked = link(&[("main.tao".into(), main), ("lib.tao".into(), lib)]).unwrap();
+    let text = linked.labels.iter().find(|(n, _)| n == "text").unwrap().1;
+    assert_eq!(text, 0x0117);
+    let sym = linked.symbol_file();
+    assert!(sym
+        .windows(6)
+        .any(|w| w == [0x17, 0x01, b't', b'e', b'x', b't']));
+    assert!(!sym.windows(7).any(|w| w == b"Console"));
+}
+
+#[test]
+fn round_trips_through_text() {
+    for src in [MAIN, LIB] {
+        let (_, object) = object(src, "unit.tao");
+        assert_eq!(Object::from_text(&object.to_text()), Some(object));
+    }
+    assert_eq!(Object::from_text("not an object"), None);
+}
+
+#[test]
+fn reports_link_errors() {
+    let message = |objects: &[(String, Object)]| match link(objects) {
+        Err(AssemblerError::Link { path, message }) => format!("{path}: {message}"),
+        other => panic!("{other:?}"),
+    };
+    assert_eq!(
+        message(&[object(MAIN, "main.tao")]),
+        "main.tao: Label unknown: \"text\""
+    );
+    assert_eq!(
+        message(&[object(LIB, "a.tao"), object(LIB, "b.tao")]),
+        "b.tao: label \"print-str\" is already defined in a.tao"
+    );
+    assert_eq!(
+        message(&[object("|0200 BRK", "a.tao"), object("|0300 BRK", "b.tao")]),
+        "b.tao: both a.tao and b.tao are pinned to absolute addresses; only one object may be"
+    );
+}
+
+#[test]
+fn rejects_damaged_objects() {
+    let message =
+        |text: &str| match link(&[("bad.tao".to_owned(), Object::from_text(text).unwrap())]) {
+            Err(AssemblerError::Link { path, message }) => format!("{path}: {message}"),
+            other => panic!("{other:?}"),
+        };
+    assert_eq!(
+        message("uxntal-object 1\nsize 0002\ncode 0100 a00000\n"),
+        "bad.tao: code runs to 0103, past the object's size 0002"
+    );
+    assert_eq!(
+        message("uxntal-object 1\nsize 0003\ncode 0100 a00000\nreloc 0102 ; text\n"),
+        "bad.tao: reference at 0102 is outside the object"
+    );
+    assert_eq!(
+        message("uxntal-object 1\nsize 0003\ncode 0100 a00000\nreloc ffff ! text\n"),
+        "bad.tao: reference at ffff is outside the object"
+    );
+    assert_eq!(
+        message("uxntal-object 1\nsize 0003\nlabel ff00 far\n"),
+        "bad.tao: label \"far\" at ff00 is outside the object"
+    );
+    // Addresses past the 64k memory aren't an object at all
+    assert_eq!(Object::from_text("uxntal-object 1\ncode 10000 00\n"), None);
+}
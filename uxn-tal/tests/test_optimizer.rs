@@ -0,0 +1,136 @@
+use uxn_tal::inproc::run_rom;
+use uxn_tal::Assembler;
+
+fn optimized(src: &str) -> (Vec<u8>, Assembler) {
+    let mut asm = Assembler::new();
+    asm.optimize = true;
+    let rom = asm.assemble(src, Some("opt.tal".to_owned())).unwrap();
+    (rom, asm)
+}
+
+fn plain(src: &str) -> Vec<u8> {
+    Assembler::new().assemble(src, None).unwrap()
+}
+
+#[test]
+fn folds_literal_arithmetic() {
+    let (rom, _) = optimized("|0100 #02 #03 ADD #0010 #0002 MUL2 #0012 NIP #12 #00 SWP BRK");
+    // Pushes 05 0020 12 0012, with neighbouring bytes packed into LIT2s
+    assert_eq!(rom, plain("|0100 #0500 #2012 #0012 BRK"));
+}
+
+#[test]
+fn leaves_division_by_zero_and_return_stack_alone() {
+    let (rom, _) = optimized("|0100 #02 #00 DIV #01 #02 ADDr BRK");
+    assert_eq!(rom, plain("|0100 #0200 DIV #0102 ADDr BRK"));
+}
+
+#[test]
+fn turns_calls_before_return_into_tail_jumps() {
+    let src = "
+%RET { JMP2r }
+%CALL { routine }
+|0100 caller ;caller JSR2 BRK
+@caller
+	;routine JSR2 JMP2r
+@other
+	routine JMP2r
+@macro-caller
+	CALL JMP2r
+@routine ( -- )
+	;other JSR2 ;macro-caller JSR2 RET
+";
+    let expected = "
+|0100 caller ;caller JSR2 BRK
+@caller
+	!routine
+@other
+	!routine
+@macro-caller
+	routine JMP2r
+@routine ( -- )
+	;other JSR2 ;macro-caller JSR2 JMP2r
+";
+    assert_eq!(optimized(src).0, plain(expected));
+}
+
+#[test]
+fn removes_unreachable_code_and_unused_routines_but_not_data() {
+    let src = "
+|0100
+	;main JSR2 ;tail JSR2 BRK
+	#01 #02 ADD POP
+@main ( -- )
+	&loop INC ?&loop
+	JMP2r
+	#18 DEO
+@unused ( -- )
+	&loop ;helper JSR2 !&loop
+@helper ( -- )
+	JMP2r
+@data 01 02
+@tail #01 JMP JMP2r
+	#02
+";
+    let (rom, asm) = optimized(src);
+    let expected = "
+|0100
+	;main JSR2 ;tail JSR2 BRK
+@main ( -- )
+	&loop INC ?&loop
+	JMP2r
+@data 01 02
+@tail #01 JMP JMP2r
+	#02
+";
+    assert_eq!(rom, plain(expected));
+    let sym = asm.generate_symbol_file_txt();
+    assert!(sym.contains(" main\n") && sym.contains(" tail\n"), "{sym}");
+    assert!(sym.contains(" data\n"), "{sym}");
+    assert!(!sym.contains("unused") && !sym.contains("helper"));
+}
+
+#[test]
+fn keeps_library_routines_without_a_reset_vector() {
+    let src = "
+@append ( byte -- )
+	#18 DEO
+	JMP2r
+@twice ( byte -- )
+	DUP append !append
+";
+    let (rom, _) = optimized(src);
+    assert!(!rom.is_empty());
+    let (heap, _) = optimized(include_str!("../tal/heap.tal"));
+    assert!(!heap.is_empty());
+}
+
+#[test]
+fn source_map_points_at_the_original_lines() {
+    let (_, asm) = optimized("|0100\n\t#02 #03\n\tADD #18 DEO\n\tBRK\n");
+    let lines: Vec<(u16, usize)> = asm
+        .source_map
+        .entries
+        .iter()
+        .map(|e| (e.address, e.span.line))
+        .collect();
+    // `#02 #03 ADD #18` is folded into `#0518`
+    assert_eq!(lines, [(0x0100, 2), (0x0103, 3), (0x0104, 4)]);
+}
+
+#[test]
+fn validation_roms_behave_the_same() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tal/validate");
+    for name in ["opctest.tal", "acid.tal"] {
+        let src = std::fs::read_to_string(dir.join(name)).unwrap();
+        let (small, _) = optimized(&src);
+        let full = plain(&src);
+        assert!(small.len() < full.len(), "{name}");
+        let (a, b) = (
+            run_rom(&full, &[], Default::default()),
+            run_rom(&small, &[], Default::default()),
+        );
+        assert_eq!(a.stdout, b.stdout, "{name}");
+        assert_eq!(a.exit, b.exit, "{name}");
+    }
+}
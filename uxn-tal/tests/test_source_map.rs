@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use uxn_tal::chocolatal;
+use uxn_tal::{Assembler, SourceMap, Span};
+
+/// Returns a main file including `lib.tal`, either by absolute path or
+/// relative to the main file
+fn fixture(absolute: bool) -> (tempfile::TempDir, String, String) {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let lib = dir.path().join("lib.tal");
+    std::fs::write(&lib, "@lib ( -- )\n\t#01 POP JMP2r\n").unwrap();
+    let include = if absolute {
+        lib.display().to_string()
+    } else {
+        "lib.tal".to_owned()
+    };
+    let main = format!("%EMIT {{ #18 DEO }}\n|0100\n\t#41 EMIT\n\tlib BRK\n~{include}\n");
+    (dir, main, lib.display().to_string())
+}
+
+#[test]
+fn maps_macros_and_includes() {
+    let (_dir, main, lib) = fixture(true);
+    let mut asm = Assembler::new();
+    asm.assemble(&main, Some("main.tal".to_owned())).unwrap();
+    let map = &asm.source_map;
+
+    let at = |addr| {
+        let s = map.lookup(addr).expect("unmapped address");
+        (s.path.as_str(), s.line, s.column)
+    };
+    assert_eq!(at(0x100), ("main.tal", 3, 2));
+    // Macro bytes belong to the call
+    assert_eq!(at(0x102), ("main.tal", 3, 6));
+    assert_eq!(at(0x104), ("main.tal", 3, 6));
+    assert_eq!(at(0x108), ("main.tal", 4, 6));
+    assert_eq!(at(0x10b), (lib.as_str(), 2, 6));
+    assert_eq!(map.lookup(0x10d), None);
+
+    let text = map.to_text();
+    assert!(text.starts_with("0100 2 main.tal:3:2\n0102 3 main.tal:3:6\n"));
+    let back = SourceMap::from_text(&text).unwrap();
+    assert_eq!(back.entries.len(), map.entries.len());
+    assert_eq!(back.lookup(0x10b).unwrap().line, 2);
+}
+
+#[test]
+fn later_entries_override_after_a_round_trip() {
+    // As when padding back over bytes emitted earlier at a higher address
+    let span = |line| Span {
+        path: "main.tal".to_owned(),
+        line,
+        column: 1,
+        len: 1,
+    };
+    let mut map = SourceMap::new();
+    map.push(0x180, 2, span(1));
+    map.push(0x100, 4, span(2));
+    map.push(0x101, 1, span(3));
+    map.push(0x0ff, 2, span(4));
+    let back = SourceMap::from_text(&map.to_text()).unwrap();
+    let lines = [(0x0ff, 4), (0x100, 4), (0x101, 3), (0x102, 2), (0x180, 1)];
+    for (addr, line) in lines {
+        assert_eq!(back.lookup(addr).unwrap().line, line, "{addr:04x}");
+    }
+}
+
+#[test]
+fn maps_through_preprocessor() {
+    let (dir, main, lib) = fixture(false);
+    let main_path = dir.path().join("main.tal").display().to_string();
+    let root = PathBuf::from(dir.path());
+    let (out, lines) = chocolatal::preprocess_with_map(&main, &main_path, &root).unwrap();
+
+    let mut asm = Assembler::new();
+    asm.assemble(&out, Some(main_path.clone())).unwrap();
+    let mut map = asm.source_map.clone();
+    map.remap(|_, line| lines.locate(line).map(|(p, l)| (p.to_owned(), l)));
+
+    let at = |addr| {
+        let s = map.lookup(addr).unwrap();
+        (s.path.clone(), s.line)
+    };
+    assert_eq!(at(0x102), (main_path, 3));
+    assert_eq!(at(0x10b), (lib, 2));
+}
+
+#[test]
+fn writes_map_next_to_rom() {
+    let (dir, main, _) = fixture(true);
+    let main_path = dir.path().join("main.tal");
+    std::fs::write(&main_path, main).unwrap();
+
+    // Only asked for symbols: no map
+    uxn_tal::assemble_file_with_symbols(&main_path).unwrap();
+    assert!(!dir.path().join("main.map").exists());
+    assert!(!dir.path().join("main.rom.map").exists());
+
+    let (rom, map, _) = uxn_tal::assemble_file_with_source_map(&main_path).unwrap();
+    assert_eq!(rom, dir.path().join("main.rom"));
+    assert_eq!(map, dir.path().join("main.rom.map"));
+    let map = SourceMap::from_text(&std::fs::read_to_string(map).unwrap()).unwrap();
+    assert_eq!(map.lookup(0x100).unwrap().line, 3);
+}
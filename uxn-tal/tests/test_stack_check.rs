@@ -0,0 +1,164 @@
+use uxn_tal::stack_check::{check, StackEffect};
+use uxn_tal::Severity;
+
+fn warnings(source: &str) -> Vec<(usize, String)> {
+    check(source, Some("test.tal"))
+        .into_iter()
+        .map(|d| {
+            assert_eq!(d.severity, Severity::Warning);
+            (d.span.unwrap().line, d.message)
+        })
+        .collect()
+}
+
+#[test]
+fn parses_signatures() {
+    let e = StackEffect::parse(" a b* -- c )\n").unwrap();
+    assert_eq!((e.inputs, e.outputs, e.vector), (3, 1, false));
+    let e = StackEffect::parse(" x y addr* . type -- )").unwrap();
+    assert_eq!((e.inputs, e.return_inputs, e.outputs), (4, 1, 0));
+    let e = StackEffect::parse(" mouse* -> )").unwrap();
+    assert_eq!((e.inputs, e.outputs, e.vector), (2, 0, true));
+    assert_eq!(StackEffect::parse(" just a note )"), None);
+
+    // `->` followed by outputs is an ordinary separator
+    let e = StackEffect::parse(" x^ -> n^ )").unwrap();
+    assert_eq!((e.inputs, e.outputs, e.vector), (1, 1, false));
+    let e = StackEffect::parse(" x** y** -> z** )").unwrap();
+    assert_eq!((e.inputs, e.outputs, e.vector), (8, 4, false));
+    // Unsized outputs take the size of the input they're named after
+    let e = StackEffect::parse(" x** n^ -> x<<n )").unwrap();
+    assert_eq!((e.inputs, e.outputs), (5, 4));
+}
+
+#[test]
+fn math32_has_no_false_positives() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tal/math32.tal");
+    let source = std::fs::read_to_string(path).unwrap();
+    assert_eq!(warnings(&source), []);
+}
+
+#[test]
+fn accepts_balanced_routines() {
+    let source = "\
+|10 @Console &vector $2 &read $1 &pad $5 &write $1
+%EMIT { .Console/write DEO }
+|0100
+@on-reset ( -> )
+	;hello print-str
+	#01 #02 add POP
+	BRK
+@print-str ( str* -- )
+	&while ( -- )
+		LDAk EMIT
+		INC2 LDAk ?&while
+	POP2 JMP2r
+@add ( a b -- c )
+	ADD JMP2r
+@max ( a b -- c )
+	GTHk ?{ NIP JMP2r }
+	POP JMP2r
+@hello \"hello 00
+";
+    assert_eq!(warnings(source), []);
+}
+
+#[test]
+fn reports_mismatches() {
+    let source = "\
+|0100
+@add ( a b -- c )
+	ADD DUP JMP2r
+@deep ( a -- )
+	POP POP JMP2r
+@on-frame ( -> )
+	#01 BRK
+@rst ( -- )
+	#01 STH JMP2r
+@uses-add ( a b -- )
+	add !add
+";
+    assert_eq!(
+        warnings(source),
+        [
+            (
+                3,
+                "add is declared ( a b -- c ), a net of -1, but returns here with a net of +0"
+                    .to_owned()
+            ),
+            (
+                5,
+                "deep is declared ( a -- ), a net of -1, but returns here with a net of -2"
+                    .to_owned()
+            ),
+            (5, "deep reads 1 byte(s) below its declared inputs ( a -- )".to_owned()),
+            (
+                7,
+                "vector on-frame is declared ( -> ), a net of +0, but reaches BRK with a working stack net of +1"
+                    .to_owned()
+            ),
+            (9, "rst returns with the return stack off by +1 byte(s)".to_owned()),
+            (11, "uses-add reads 1 byte(s) below its declared inputs ( a b -- )".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn reports_unbalanced_branches() {
+    let source = "\
+|0100
+@block ( flag -- )
+	?{ #01 }
+	JMP2r
+@loop ( -- )
+	#00
+	&l
+		INC DUP #10 LTH ?{ DUP }
+		DUP ?&l
+	POP JMP2r
+";
+    let found = warnings(source);
+    let lines: Vec<_> = found.iter().map(|w| w.0).collect();
+    // Each imbalance also throws off the routine's net effect
+    assert_eq!(lines, [3, 4, 8, 9, 10], "{found:?}");
+    assert_eq!(
+        found[0].1,
+        "unbalanced branches: ?{ } block ends with a working stack net of +0 \
+         but a working stack net of -1 when skipped"
+    );
+    assert_eq!(
+        found[3].1,
+        "unbalanced branches: loop/l is reached with a working stack net of +2 \
+         here but a working stack net of +1 from line 7"
+    );
+}
+
+#[test]
+fn skips_unknown_code() {
+    // Calls to unannotated labels and computed jumps can't be checked
+    let source = "\
+|0100
+@a ( -- )
+	helper JMP2r
+@b ( -- )
+	;helper JMP2 JMP2r
+@helper
+	#01 JMP2r
+";
+    assert_eq!(warnings(source), []);
+}
+
+#[test]
+fn treats_lit_operands_as_data() {
+    let source = "\
+|0100
+@two ( -- a b )
+	LIT 12 LIT2 3456 POP JMP2r
+@one ( -- a )
+	LIT 12 LIT 34 JMP2r
+";
+    let w = warnings(source);
+    assert_eq!(w.len(), 1, "{w:?}");
+    assert_eq!(w[0].0, 5);
+    assert!(w[0].1.contains("net of +2"), "{}", w[0].1);
+}